@@ -8,9 +8,12 @@ mod bindings {
 
 use bindings::exports::provider::common::ingress::Guest;
 use bindings::greentic::secrets_store::secrets_store;
-use hmac::{Hmac, Mac};
+use provider_common::ProviderError;
+use provider_common::form_urlencoded;
+use provider_common::webhook_auth::{
+    self, SLACK_SIGNATURE_HEADER, SLACK_TIMESTAMP_HEADER, TimestampWindow,
+};
 use serde_json::{Map, Value, json};
-use sha2::Sha256;
 
 const SIGNING_SECRET_KEY: &str = "SLACK_SIGNING_SECRET";
 
@@ -26,12 +29,29 @@ impl Guest for Component {
             verify_signature(&headers, &body_json, &signing_secret)?;
         }
 
-        let body_val: Value = serde_json::from_str(&body_json)
-            .map_err(|_| "validation error: invalid body json".to_string())?;
-        let normalized = json!({
-            "ok": true,
-            "event": body_val,
-        });
+        let normalized = match (
+            form_urlencoded::json_payload(&body_json),
+            slash_command(&body_json),
+        ) {
+            (Some(payload), _) => json!({
+                "ok": true,
+                "interaction_type": payload.get("type"),
+                "event": payload,
+            }),
+            (None, Some(command)) => json!({
+                "ok": true,
+                "command": command.get("command"),
+                "event": command,
+            }),
+            (None, None) => {
+                let body_val: Value = serde_json::from_str(&body_json)
+                    .map_err(|_| "validation error: invalid body json".to_string())?;
+                json!({
+                    "ok": true,
+                    "event": body_val,
+                })
+            }
+        };
         serde_json::to_string(&normalized)
             .map_err(|_| "other error: serialization failed".to_string())
     }
@@ -41,6 +61,17 @@ bindings::exports::provider::common::ingress::__export_provider_common_ingress_0
     Component with_types_in bindings::exports::provider::common::ingress
 );
 
+/// Slash commands post their fields as a form.
+fn slash_command(body: &str) -> Option<Value> {
+    let fields = form_urlencoded::slash_command_fields(body)?;
+    Some(Value::Object(
+        fields
+            .into_iter()
+            .map(|(name, value)| (name, Value::String(value)))
+            .collect(),
+    ))
+}
+
 fn get_optional_secret(key: &str) -> Option<Result<String, String>> {
     match secrets_store::get(key) {
         Ok(Some(bytes)) => {
@@ -52,23 +83,15 @@ fn get_optional_secret(key: &str) -> Option<Result<String, String>> {
 }
 
 fn verify_signature(headers: &Map<String, Value>, body: &str, secret: &str) -> Result<(), String> {
-    let signature = header_value(headers, "x-slack-signature")
-        .ok_or_else(|| "validation error: missing signature".to_string())?;
-    let timestamp = header_value(headers, "x-slack-request-timestamp")
-        .ok_or_else(|| "validation error: missing timestamp".to_string())?;
-
-    let basestring = format!("v0:{timestamp}:{body}");
-    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
-        .map_err(|_| "validation error: invalid secret".to_string())?;
-    mac.update(basestring.as_bytes());
-    let signature_bytes = mac.finalize().into_bytes();
-    let computed = format!("v0={}", hex_encode(&signature_bytes));
-
-    if computed == signature {
-        Ok(())
-    } else {
-        Err("validation error: invalid signature".to_string())
-    }
+    webhook_auth::verify_slack(
+        secret.as_bytes(),
+        header_value(headers, SLACK_SIGNATURE_HEADER).as_deref(),
+        header_value(headers, SLACK_TIMESTAMP_HEADER).as_deref(),
+        body.as_bytes(),
+        &TimestampWindow::default(),
+        webhook_auth::now_unix_secs(),
+    )
+    .map_err(|err| ProviderError::from(err).to_string())
 }
 
 fn header_value(headers: &Map<String, Value>, key: &str) -> Option<String> {
@@ -84,11 +107,3 @@ fn header_value(headers: &Map<String, Value>, key: &str) -> Option<String> {
                 .map(|s| s.to_string())
         })
 }
-
-fn hex_encode(bytes: &[u8]) -> String {
-    let mut out = String::with_capacity(bytes.len() * 2);
-    for b in bytes {
-        out.push_str(&format!("{:02x}", b));
-    }
-    out
-}
@@ -11,8 +11,12 @@ use bindings::exports::provider::common::subscriptions::Guest as SubscriptionsGu
 use bindings::greentic::http::http_client as client;
 use bindings::greentic::secrets_store::secrets_store;
 use bindings::greentic::state::state_store;
+use provider_common::ProviderError;
+use provider_common::state::HostStateAdapter;
+use provider_common::token_cache::{AccessToken, TokenCache, TokenCacheKey};
+use provider_common::webhook_auth::{self, TEAMS_AUTHORIZATION_HEADER};
 use serde::Deserialize;
-use serde_json::{Value, json};
+use serde_json::{Map, Value, json};
 use urlencoding::encode;
 
 const DEFAULT_GRAPH_BASE: &str = "https://graph.microsoft.com/v1.0";
@@ -21,6 +25,7 @@ const DEFAULT_TOKEN_SCOPE: &str = "https://graph.microsoft.com/.default";
 const DEFAULT_CLIENT_SECRET_KEY: &str = "MS_GRAPH_CLIENT_SECRET";
 const DEFAULT_REFRESH_TOKEN_KEY: &str = "MS_GRAPH_REFRESH_TOKEN";
 const STATE_KEY: &str = "messaging.teams.subscriptions";
+const OUTGOING_WEBHOOK_SECRET_KEY: &str = "TEAMS_OUTGOING_WEBHOOK_SECRET";
 
 #[derive(Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -55,7 +60,20 @@ struct ExistingSubscription {
 struct Component;
 
 impl IngressGuest for Component {
-    fn handle_webhook(_headers_json: String, body_json: String) -> Result<String, String> {
+    fn handle_webhook(headers_json: String, body_json: String) -> Result<String, String> {
+        let headers: Map<String, Value> = serde_json::from_str(&headers_json)
+            .map_err(|_| "validation error: invalid headers".to_string())?;
+
+        if let Some(secret_result) = get_optional_secret(OUTGOING_WEBHOOK_SECRET_KEY) {
+            let security_token = secret_result.map_err(|e| format!("transport error: {e}"))?;
+            webhook_auth::verify_teams_outgoing(
+                &security_token,
+                header_value(&headers, TEAMS_AUTHORIZATION_HEADER).as_deref(),
+                body_json.as_bytes(),
+            )
+            .map_err(|err| ProviderError::from(err).to_string())?;
+        }
+
         let parsed: Value = serde_json::from_str(&body_json)
             .map_err(|_| "validation error: invalid body".to_string())?;
         let normalized = json!({ "ok": true, "event": parsed });
@@ -217,15 +235,39 @@ fn find_matching<'a>(
 }
 
 fn acquire_token(cfg: &ProviderConfig) -> Result<String, String> {
+    TokenCache::default().get_or_fetch(
+        &mut host_state(),
+        &token_cache_key(cfg),
+        webhook_auth::now_unix_secs(),
+        || fetch_token(cfg),
+    )
+}
+
+fn token_cache_key(cfg: &ProviderConfig) -> TokenCacheKey {
+    TokenCacheKey::new(&cfg.tenant_id, &cfg.client_id, token_scope(cfg))
+}
+
+fn token_scope(cfg: &ProviderConfig) -> String {
+    cfg.token_scope
+        .clone()
+        .unwrap_or_else(|| DEFAULT_TOKEN_SCOPE.to_string())
+}
+
+/// Drops the cached token when Graph rejected it, so the next sync exchanges
+/// credentials again.
+fn forget_rejected_token(cfg: &ProviderConfig, status: u16) {
+    if status == 401 {
+        TokenCache::default().invalidate(&mut host_state(), &token_cache_key(cfg));
+    }
+}
+
+fn fetch_token(cfg: &ProviderConfig) -> Result<AccessToken, String> {
     let auth_base = cfg
         .auth_base_url
         .clone()
         .unwrap_or_else(|| DEFAULT_AUTH_BASE.to_string());
     let token_url = format!("{}/{}/oauth2/v2.0/token", auth_base, cfg.tenant_id);
-    let scope = cfg
-        .token_scope
-        .clone()
-        .unwrap_or_else(|| DEFAULT_TOKEN_SCOPE.to_string());
+    let scope = token_scope(cfg);
 
     if let Ok(refresh_token) = get_secret(DEFAULT_REFRESH_TOKEN_KEY) {
         let mut form = format!(
@@ -250,7 +292,7 @@ fn acquire_token(cfg: &ProviderConfig) -> Result<String, String> {
     send_token_request(&token_url, &form)
 }
 
-fn send_token_request(url: &str, form: &str) -> Result<String, String> {
+fn send_token_request(url: &str, form: &str) -> Result<AccessToken, String> {
     let request = client::Request {
         method: "POST".into(),
         url: url.to_string(),
@@ -269,11 +311,8 @@ fn send_token_request(url: &str, form: &str) -> Result<String, String> {
     let body = resp.body.unwrap_or_default();
     let json: Value =
         serde_json::from_slice(&body).map_err(|e| format!("invalid token response: {e}"))?;
-    let token = json
-        .get("access_token")
-        .and_then(Value::as_str)
-        .ok_or_else(|| "token response missing access_token".to_string())?;
-    Ok(token.to_string())
+    AccessToken::from_response(&json)
+        .ok_or_else(|| "token response missing access_token".to_string())
 }
 
 fn list_subscriptions(
@@ -293,6 +332,7 @@ fn list_subscriptions(
     };
     let resp = client::send(&request, None, None)
         .map_err(|e| format!("transport error: {}", e.message))?;
+    forget_rejected_token(cfg, resp.status);
     if resp.status < 200 || resp.status >= 300 {
         return Err(format!("graph returned status {}", resp.status));
     }
@@ -368,6 +408,7 @@ fn create_subscription(
     };
     let resp = client::send(&request, None, None)
         .map_err(|e| format!("transport error: {}", e.message))?;
+    forget_rejected_token(cfg, resp.status);
     if resp.status < 200 || resp.status >= 300 {
         return Err(format!("create subscription status {}", resp.status));
     }
@@ -414,6 +455,7 @@ fn renew_subscription(
     };
     let resp = client::send(&request, None, None)
         .map_err(|e| format!("transport error: {}", e.message))?;
+    forget_rejected_token(cfg, resp.status);
     if resp.status < 200 || resp.status >= 300 {
         return Err(format!("renew subscription status {}", resp.status));
     }
@@ -427,6 +469,22 @@ fn write_state(state: &Value) -> Result<(), String> {
         .map(|_| ())
 }
 
+fn host_state() -> HostStateAdapter {
+    HostStateAdapter::new(
+        |key| state_store::read(key, None).map_err(|err| err.message),
+        |key, value| {
+            state_store::write(key, value, None)
+                .map(|_| ())
+                .map_err(|err| err.message)
+        },
+        |key| {
+            state_store::delete(key, None)
+                .map(|_| ())
+                .map_err(|err| err.message)
+        },
+    )
+}
+
 fn get_secret(key: &str) -> Result<String, String> {
     match secrets_store::get(key) {
         Ok(Some(bytes)) => String::from_utf8(bytes).map_err(|_| format!("secret {key} not utf-8")),
@@ -434,3 +492,21 @@ fn get_secret(key: &str) -> Result<String, String> {
         Err(e) => Err(format!("secret store error: {e:?}")),
     }
 }
+
+fn get_optional_secret(key: &str) -> Option<Result<String, String>> {
+    match secrets_store::get(key) {
+        Ok(Some(bytes)) => {
+            Some(String::from_utf8(bytes).map_err(|_| "secret not valid utf-8".into()))
+        }
+        Ok(None) => None,
+        Err(e) => Some(Err(format!("secret store error: {e:?}"))),
+    }
+}
+
+fn header_value(headers: &Map<String, Value>, key: &str) -> Option<String> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .and_then(|(_, v)| v.as_str())
+        .map(|s| s.to_string())
+}
@@ -7,12 +7,30 @@ mod bindings {
 }
 
 use bindings::exports::provider::common::ingress::Guest;
-use serde_json::{Value, json};
+use bindings::greentic::secrets_store::secrets_store;
+use provider_common::ProviderError;
+use provider_common::webhook_auth::{self, TELEGRAM_SECRET_TOKEN_HEADER};
+use serde_json::{Map, Value, json};
+
+const WEBHOOK_SECRET_KEY: &str = "TELEGRAM_WEBHOOK_SECRET";
 
 struct Component;
 
 impl Guest for Component {
-    fn handle_webhook(_headers_json: String, body_json: String) -> Result<String, String> {
+    fn handle_webhook(headers_json: String, body_json: String) -> Result<String, String> {
+        let headers: Map<String, Value> = serde_json::from_str(&headers_json)
+            .map_err(|_| "validation error: invalid headers".to_string())?;
+
+        if let Some(secret_result) = get_optional_secret(WEBHOOK_SECRET_KEY) {
+            let secret = secret_result.map_err(|e| format!("transport error: {e}"))?;
+            webhook_auth::verify_shared_token(
+                &secret,
+                header_value(&headers, TELEGRAM_SECRET_TOKEN_HEADER).as_deref(),
+                TELEGRAM_SECRET_TOKEN_HEADER,
+            )
+            .map_err(|err| ProviderError::from(err).to_string())?;
+        }
+
         let parsed: Value = serde_json::from_str(&body_json)
             .map_err(|_| "validation error: invalid body".to_string())?;
         let normalized = json!({ "ok": true, "event": parsed });
@@ -24,3 +42,21 @@ impl Guest for Component {
 bindings::exports::provider::common::ingress::__export_provider_common_ingress_0_0_2_cabi!(
     Component with_types_in bindings::exports::provider::common::ingress
 );
+
+fn get_optional_secret(key: &str) -> Option<Result<String, String>> {
+    match secrets_store::get(key) {
+        Ok(Some(bytes)) => {
+            Some(String::from_utf8(bytes).map_err(|_| "secret not valid utf-8".into()))
+        }
+        Ok(None) => None,
+        Err(e) => Some(Err(format!("secret store error: {e:?}"))),
+    }
+}
+
+fn header_value(headers: &Map<String, Value>, key: &str) -> Option<String> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .and_then(|(_, v)| v.as_str())
+        .map(|s| s.to_string())
+}
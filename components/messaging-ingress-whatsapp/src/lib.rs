@@ -8,15 +8,18 @@ mod bindings {
 
 use bindings::exports::provider::common::ingress::Guest;
 use bindings::greentic::secrets_store::secrets_store;
-use serde_json::{Value, json};
+use provider_common::ProviderError;
+use provider_common::webhook_auth::{self, META_SIGNATURE_HEADER};
+use serde_json::{Map, Value, json};
 
 const VERIFY_TOKEN_KEY: &str = "WHATSAPP_VERIFY_TOKEN";
+const APP_SECRET_KEY: &str = "WHATSAPP_APP_SECRET";
 
 struct Component;
 
 impl Guest for Component {
     fn handle_webhook(headers_json: String, body_json: String) -> Result<String, String> {
-        let _headers: Value = serde_json::from_str(&headers_json)
+        let headers: Map<String, Value> = serde_json::from_str(&headers_json)
             .map_err(|_| "validation error: invalid headers".to_string())?;
 
         let parsed: Value = serde_json::from_str(&body_json)
@@ -28,9 +31,17 @@ impl Guest for Component {
             .and_then(Value::as_str)
         {
             let expected = get_secret(VERIFY_TOKEN_KEY)?;
-            if token != expected {
+            if !webhook_auth::constant_time_eq(token.as_bytes(), expected.as_bytes()) {
                 return Err("validation error: verify token mismatch".into());
             }
+        } else if let Some(secret_result) = get_optional_secret(APP_SECRET_KEY) {
+            let app_secret = secret_result.map_err(|e| format!("transport error: {e}"))?;
+            webhook_auth::verify_meta(
+                app_secret.as_bytes(),
+                header_value(&headers, META_SIGNATURE_HEADER).as_deref(),
+                body_json.as_bytes(),
+            )
+            .map_err(|err| ProviderError::from(err).to_string())?;
         }
 
         let normalized = json!({ "ok": true, "event": parsed });
@@ -50,3 +61,21 @@ fn get_secret(key: &str) -> Result<String, String> {
         Err(e) => Err(format!("secret store error: {e:?}")),
     }
 }
+
+fn get_optional_secret(key: &str) -> Option<Result<String, String>> {
+    match secrets_store::get(key) {
+        Ok(Some(bytes)) => {
+            Some(String::from_utf8(bytes).map_err(|_| "secret not valid utf-8".into()))
+        }
+        Ok(None) => None,
+        Err(e) => Some(Err(format!("secret store error: {e:?}"))),
+    }
+}
+
+fn header_value(headers: &Map<String, Value>, key: &str) -> Option<String> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .and_then(|(_, v)| v.as_str())
+        .map(|s| s.to_string())
+}
@@ -1,6 +1,10 @@
 use super::bindings::greentic::http::http_client as client;
 use super::bindings::greentic::secrets_store::secrets_store;
-use super::{AuthUserRefV1, ProviderConfig};
+use super::{AuthUserRefV1, ProviderConfig, host_state};
+use provider_common::ProviderError;
+use provider_common::http_retry::{RetryPolicy, retry_after, send_http};
+use provider_common::token_cache::{AccessToken, TokenCache, TokenCacheKey};
+use provider_common::webhook_auth::now_unix_secs;
 use serde_json::Value;
 use urlencoding::encode as url_encode;
 
@@ -9,26 +13,109 @@ const DEFAULT_GRAPH_SCOPE: &str = "https://graph.microsoft.com/.default offline_
 const MS_GRAPH_CLIENT_ID_KEY: &str = "MS_GRAPH_CLIENT_ID";
 const MS_GRAPH_CLIENT_SECRET_KEY: &str = "MS_GRAPH_CLIENT_SECRET";
 
+/// Access token for Graph calls. `refresh_token_rotated` is set when the
+/// exchange returned a new refresh token: `true` once it replaced the stored
+/// secret, `false` when the secret still holds the retired one.
+pub(crate) struct GraphToken {
+    pub access_token: String,
+    pub refresh_token_rotated: Option<bool>,
+}
+
 pub(crate) fn acquire_graph_token(
     cfg: &ProviderConfig,
     user: &AuthUserRefV1,
-) -> Result<String, String> {
-    let refresh_token = get_secret(&user.token_key)?;
+) -> Result<GraphToken, ProviderError> {
     let client_id = get_secret(MS_GRAPH_CLIENT_ID_KEY)?;
-    let client_secret = get_secret(MS_GRAPH_CLIENT_SECRET_KEY)?;
     let endpoint = graph_token_endpoint(cfg, user)?;
     let scope = cfg.graph_scope.as_deref().unwrap_or(DEFAULT_GRAPH_SCOPE);
-    let form = format!(
-        "client_id={}&client_secret={}&grant_type=refresh_token&refresh_token={}&scope={}",
-        url_encode(&client_id),
-        url_encode(&client_secret),
-        url_encode(&refresh_token),
-        url_encode(scope)
+    let mut refresh_token_rotated = None;
+    let access_token = TokenCache::default().get_or_fetch(
+        &mut host_state(),
+        &token_cache_key(&endpoint, &client_id, scope, user),
+        now_unix_secs(),
+        || {
+            let refresh_token = get_secret(&user.token_key)?;
+            let client_secret = get_secret(MS_GRAPH_CLIENT_SECRET_KEY)?;
+            let form = format!(
+                "client_id={}&client_secret={}&grant_type=refresh_token&refresh_token={}&scope={}",
+                url_encode(&client_id),
+                url_encode(&client_secret),
+                url_encode(&refresh_token),
+                url_encode(scope)
+            );
+            let response = request_token(&endpoint, form.as_bytes())?;
+            refresh_token_rotated = persist_rotated_refresh_token(user, &refresh_token, &response);
+            AccessToken::from_response(&response)
+                .ok_or_else(|| ProviderError::other("token response missing access_token"))
+        },
+    )?;
+    Ok(GraphToken {
+        access_token,
+        refresh_token_rotated,
+    })
+}
+
+/// Microsoft identity may return a new refresh token with every exchange and
+/// retire the old one, so a rotated token replaces the stored secret. `put`
+/// reports nothing, so the secret is read back to tell whether it stuck;
+/// `None` when the response carried no new token.
+fn persist_rotated_refresh_token(
+    user: &AuthUserRefV1,
+    current: &str,
+    response: &Value,
+) -> Option<bool> {
+    let rotated = rotated_refresh_token(current, response)?;
+    secrets_store::put(&user.token_key, rotated.as_bytes());
+    let stored = secrets_store::get(&user.token_key).ok().flatten();
+    Some(stored.as_deref() == Some(rotated.as_bytes()))
+}
+
+fn rotated_refresh_token<'a>(current: &str, response: &'a Value) -> Option<&'a str> {
+    response
+        .get("refresh_token")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|token| !token.is_empty() && *token != current)
+}
+
+/// Drops the user's cached token when a Graph call failed authorization
+/// (401/403), so the next call exchanges the refresh token again.
+pub(crate) fn forget_rejected_token(
+    cfg: &ProviderConfig,
+    user: &AuthUserRefV1,
+    err: &ProviderError,
+) {
+    if !matches!(err, ProviderError::AuthFailed(_)) {
+        return;
+    }
+    let (Ok(client_id), Ok(endpoint)) = (
+        get_secret(MS_GRAPH_CLIENT_ID_KEY),
+        graph_token_endpoint(cfg, user),
+    ) else {
+        return;
+    };
+    let scope = cfg.graph_scope.as_deref().unwrap_or(DEFAULT_GRAPH_SCOPE);
+    TokenCache::default().invalidate(
+        &mut host_state(),
+        &token_cache_key(&endpoint, &client_id, scope, user),
     );
-    request_token(&endpoint, form.as_bytes())
 }
 
-fn graph_token_endpoint(cfg: &ProviderConfig, user: &AuthUserRefV1) -> Result<String, String> {
+/// Delegated tokens are cached per user; the token endpoint identifies the
+/// tenant.
+fn token_cache_key(
+    endpoint: &str,
+    client_id: &str,
+    scope: &str,
+    user: &AuthUserRefV1,
+) -> TokenCacheKey {
+    TokenCacheKey::new(endpoint, client_id, scope).with_subject(&user.token_key)
+}
+
+fn graph_token_endpoint(
+    cfg: &ProviderConfig,
+    user: &AuthUserRefV1,
+) -> Result<String, ProviderError> {
     if let Some(endpoint) = cfg.graph_token_endpoint.as_ref() {
         return Ok(endpoint.clone());
     }
@@ -36,7 +123,7 @@ fn graph_token_endpoint(cfg: &ProviderConfig, user: &AuthUserRefV1) -> Result<St
         .tenant_id
         .as_deref()
         .or(cfg.graph_tenant_id.as_deref())
-        .ok_or_else(|| "missing Graph tenant id".to_string())?;
+        .ok_or_else(|| ProviderError::config_invalid("missing Graph tenant id"))?;
     let authority = cfg
         .graph_authority
         .as_deref()
@@ -48,15 +135,16 @@ fn graph_token_endpoint(cfg: &ProviderConfig, user: &AuthUserRefV1) -> Result<St
     ))
 }
 
-fn get_secret(key: &str) -> Result<String, String> {
+fn get_secret(key: &str) -> Result<String, ProviderError> {
     match secrets_store::get(key) {
-        Ok(Some(bytes)) => String::from_utf8(bytes).map_err(|_| format!("secret {key} not utf-8")),
-        Ok(None) => Err(format!("missing secret: {key}")),
-        Err(err) => Err(format!("secret store error: {err:?}")),
+        Ok(Some(bytes)) => String::from_utf8(bytes)
+            .map_err(|_| ProviderError::config_invalid(format!("secret {key} not utf-8"))),
+        Ok(None) => Err(ProviderError::missing_secret(key)),
+        Err(err) => Err(ProviderError::other(format!("secret store error: {err:?}"))),
     }
 }
 
-fn request_token(url: &str, body: &[u8]) -> Result<String, String> {
+fn request_token(url: &str, body: &[u8]) -> Result<Value, ProviderError> {
     let request = client::Request {
         method: "POST".into(),
         url: url.to_string(),
@@ -66,17 +154,82 @@ fn request_token(url: &str, body: &[u8]) -> Result<String, String> {
         )],
         body: Some(body.to_vec()),
     };
-    let resp = client::send(&request, None, None)
-        .map_err(|e| format!("token exchange error: {}", e.message))?;
+    let resp = send_http(&request.method, &RetryPolicy::default(), || {
+        client::send(&request, None, None)
+    })
+    .result
+    .map_err(|e| ProviderError::transport(format!("token exchange error: {}", e.message)))?;
     if resp.status < 200 || resp.status >= 300 {
-        return Err(format!("token endpoint returned status {}", resp.status));
+        return Err(token_status_error(&resp));
     }
     let body = resp.body.unwrap_or_default();
-    let parsed: Value =
-        serde_json::from_slice(&body).map_err(|e| format!("invalid token response: {e}"))?;
-    parsed
-        .get("access_token")
-        .and_then(Value::as_str)
-        .map(|token| token.to_string())
-        .ok_or_else(|| "token response missing access_token".to_string())
+    serde_json::from_slice(&body)
+        .map_err(|e| ProviderError::other(format!("invalid token response: {e}")))
+}
+
+/// OAuth error codes meaning the user has to consent again.
+const REAUTH_ERROR_CODES: &[&str] = &["invalid_grant", "interaction_required", "consent_required"];
+
+/// A rejected grant (400/401) means the stored refresh token or client
+/// credentials need attention, so it is reported as an auth failure. A
+/// revoked or expired grant is reported as `reauth_required`.
+fn token_status_error(resp: &client::Response) -> ProviderError {
+    let body: Value = resp
+        .body
+        .as_deref()
+        .and_then(|body| serde_json::from_slice(body).ok())
+        .unwrap_or(Value::Null);
+    let code = body.get("error").and_then(Value::as_str);
+    let message = match code {
+        Some(code) => format!("token endpoint returned status {}: {code}", resp.status),
+        None => format!("token endpoint returned status {}", resp.status),
+    };
+    match resp.status {
+        400 | 401 if code.is_some_and(|code| REAUTH_ERROR_CODES.contains(&code)) => {
+            ProviderError::reauth_required(message)
+        }
+        400 | 401 => ProviderError::auth_failed(message),
+        status => ProviderError::from_http_response(
+            status,
+            retry_after(resp).map(|delay| delay.as_secs()),
+            code.map(str::to_string),
+            message,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn token_error(status: u16, body: Value) -> ProviderError {
+        token_status_error(&client::Response {
+            status,
+            headers: Vec::new(),
+            body: Some(serde_json::to_vec(&body).unwrap()),
+        })
+    }
+
+    #[test]
+    fn revoked_grant_requires_reauth() {
+        let err = token_error(
+            400,
+            json!({"error": "invalid_grant", "error_description": "AADSTS700082"}),
+        );
+        assert!(matches!(err, ProviderError::ReauthRequired(_)));
+        let err = token_error(401, json!({"error": "invalid_client"}));
+        assert!(matches!(err, ProviderError::AuthFailed(_)));
+    }
+
+    #[test]
+    fn only_new_refresh_tokens_are_rotated() {
+        let response = json!({"access_token": "a", "refresh_token": "r2"});
+        assert_eq!(rotated_refresh_token("r1", &response), Some("r2"));
+        assert_eq!(rotated_refresh_token("r2", &response), None);
+        assert_eq!(
+            rotated_refresh_token("r1", &json!({"access_token": "a"})),
+            None
+        );
+    }
 }
@@ -7,10 +7,18 @@ use greentic_types::messaging::universal_dto::{
     SubscriptionRenewInV1, SubscriptionRenewOutV1,
 };
 use provider_common::component_v0_6::{
-    DescribePayload, I18nText, OperationDescriptor, QaQuestionSpec, QaSpec, RedactionRule,
-    SchemaField, SchemaIr, canonical_cbor_bytes, decode_cbor, default_en_i18n_messages,
-    schema_hash,
+    DescribePayload, EnumValue, I18nText, OperationDescriptor, QaQuestionSpec, QaSpec,
+    RedactionRule, SchemaField, SchemaIr, canonical_cbor_bytes, decode_cbor,
+    default_en_i18n_messages, schema_hash,
 };
+use provider_common::http_retry::{
+    RetryPolicy, RetryableResponse, find_header, retry_after, send_http, send_payload_result_bytes,
+};
+use provider_common::ingress_dedup::{DUPLICATE_SUPPRESSED_FLAG, DedupWindow, duplicate_ack_body};
+use provider_common::outbound_idempotency::send_idempotent;
+use provider_common::state::HostStateAdapter;
+use provider_common::webhook_auth;
+use provider_common::{ProviderError, ProviderErrorDetail};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use sha2::{Digest, Sha256};
@@ -28,6 +36,8 @@ mod bindings {
 mod auth;
 
 use bindings::greentic::http::http_client as client;
+use bindings::greentic::secrets_store::secrets_store;
+use bindings::greentic::state::state_store;
 use greentic_types::{
     Actor, ChannelMessageEnvelope, Destination, EnvId, MessageMetadata, TenantCtx, TenantId,
 };
@@ -37,6 +47,7 @@ const PROVIDER_TYPE: &str = "messaging.email.smtp";
 const WORLD_ID: &str = "component-v0-v6-v0";
 const DEFAULT_GRAPH_BASE: &str = "https://graph.microsoft.com/v1.0";
 const GRAPH_MAX_EXPIRATION_MINUTES: u32 = 4230;
+const CLIENT_STATE_KEY: &str = "MS_GRAPH_CLIENT_STATE";
 const I18N_KEYS: &[&str] = &[
     "email.op.run.title",
     "email.op.run.description",
@@ -84,6 +95,9 @@ const I18N_KEYS: &[&str] = &[
     "email.schema.config.from_address.description",
     "email.schema.config.tls_mode.title",
     "email.schema.config.tls_mode.description",
+    "email.schema.config.tls_mode.starttls",
+    "email.schema.config.tls_mode.implicit",
+    "email.schema.config.tls_mode.none",
     "email.schema.config.default_to_address.title",
     "email.schema.config.default_to_address.description",
     "email.schema.config.password.title",
@@ -365,12 +379,12 @@ bindings::export!(Component with_types_in bindings);
 
 fn dispatch_json_invoke(op: &str, input_json: &[u8]) -> Vec<u8> {
     match op {
-        "send" => handle_send(input_json),
-        "reply" => handle_reply(input_json),
+        "send" => send_idempotent(PROVIDER_ID, &mut host_state(), input_json, handle_send),
+        "reply" => send_idempotent(PROVIDER_ID, &mut host_state(), input_json, handle_reply),
         "ingest_http" => ingest_http(input_json),
         "render_plan" => render_plan(input_json),
         "encode" => encode_op(input_json),
-        "send_payload" => send_payload(input_json),
+        "send_payload" => send_idempotent(PROVIDER_ID, &mut host_state(), input_json, send_payload),
         "subscription_ensure" => subscription_ensure(input_json),
         "subscription_renew" => subscription_renew(input_json),
         "subscription_delete" => subscription_delete(input_json),
@@ -589,11 +603,11 @@ fn config_schema() -> SchemaIr {
     insert(
         "port",
         true,
-        SchemaIr::String {
+        SchemaIr::Integer {
             title: i18n("email.schema.config.port.title"),
             description: i18n("email.schema.config.port.description"),
-            format: None,
-            secret: false,
+            minimum: Some(1),
+            maximum: Some(i64::from(u16::MAX)),
         },
     );
     insert(
@@ -619,11 +633,16 @@ fn config_schema() -> SchemaIr {
     insert(
         "tls_mode",
         true,
-        SchemaIr::String {
+        SchemaIr::Enum {
             title: i18n("email.schema.config.tls_mode.title"),
             description: i18n("email.schema.config.tls_mode.description"),
-            format: None,
-            secret: false,
+            values: ["starttls", "implicit", "none"]
+                .into_iter()
+                .map(|mode| EnumValue {
+                    value: mode.to_string(),
+                    label: i18n(&format!("email.schema.config.tls_mode.{mode}")),
+                })
+                .collect(),
         },
     );
     insert(
@@ -678,16 +697,16 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
     let parsed: Value = match serde_json::from_slice(input_json) {
         Ok(val) => val,
         Err(err) => {
-            return json_bytes(&json!({"ok": false, "error": format!("invalid json: {err}")}));
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
         }
     };
 
     let cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
     if !cfg.enabled {
-        return json_bytes(&json!({"ok": false, "error": "provider disabled by config"}));
+        return error_bytes(ProviderError::config_invalid("provider disabled by config"));
     }
 
     let envelope = match serde_json::from_slice::<ChannelMessageEnvelope>(input_json) {
@@ -702,7 +721,9 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
     };
 
     if !envelope.attachments.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "attachments not supported"}));
+        return error_bytes(ProviderError::unsupported_content(
+            "attachments not supported",
+        ));
     }
 
     let body = envelope
@@ -713,7 +734,7 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
         .map(ToOwned::to_owned);
     let body = match body {
         Some(value) => value,
-        None => return json_bytes(&json!({"ok": false, "error": "text required"})),
+        None => return error_bytes(ProviderError::validation("text required")),
     };
 
     let destination = envelope.to.first().cloned().or_else(|| {
@@ -724,19 +745,18 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
     });
     let destination = match destination {
         Some(dest) => dest,
-        None => return json_bytes(&json!({"ok": false, "error": "destination required"})),
+        None => return error_bytes(ProviderError::validation("destination required")),
     };
 
     let dest_id = destination.id.trim();
     if dest_id.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "destination id required"}));
+        return error_bytes(ProviderError::validation("destination id required"));
     }
     let kind = destination.kind.as_deref().unwrap_or("email");
     if kind != "email" && !kind.is_empty() {
-        return json_bytes(&json!({
-            "ok": false,
-            "error": format!("unsupported destination kind: {kind}"),
-        }));
+        return error_bytes(ProviderError::validation(format!(
+            "unsupported destination kind: {kind}"
+        )));
     }
 
     let subject = envelope
@@ -774,21 +794,21 @@ fn handle_reply(_input_json: &[u8]) -> Vec<u8> {
     let parsed: Value = match serde_json::from_slice(_input_json) {
         Ok(val) => val,
         Err(err) => {
-            return json_bytes(&json!({"ok": false, "error": format!("invalid json: {err}")}));
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
         }
     };
 
     let cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
     if !cfg.enabled {
-        return json_bytes(&json!({"ok": false, "error": "provider disabled by config"}));
+        return error_bytes(ProviderError::config_invalid("provider disabled by config"));
     }
 
     let to = match parsed.get("to").and_then(|v| v.as_str()) {
         Some(addr) if !addr.is_empty() => addr.to_string(),
-        _ => return json_bytes(&json!({"ok": false, "error": "to required"})),
+        _ => return error_bytes(ProviderError::validation("to required")),
     };
     let subject = parsed
         .get("subject")
@@ -924,16 +944,20 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
     let send_in = match serde_json::from_slice::<SendPayloadInV1>(input_json) {
         Ok(value) => value,
         Err(err) => {
-            return send_payload_error(&format!("invalid send_payload input: {err}"), false);
+            return send_payload_error(ProviderError::validation(format!(
+                "invalid send_payload input: {err}"
+            )));
         }
     };
     if send_in.provider_type != PROVIDER_TYPE {
-        return send_payload_error("provider type mismatch", false);
+        return send_payload_error(ProviderError::validation("provider type mismatch"));
     }
     let payload_bytes: Vec<u8> = match STANDARD.decode(&send_in.payload.body_b64) {
         Ok(bytes) => bytes,
         Err(err) => {
-            return send_payload_error(&format!("payload decode failed: {err}"), false);
+            return send_payload_error(ProviderError::validation(format!(
+                "payload decode failed: {err}"
+            )));
         }
     };
     let payload: Value = serde_json::from_slice(&payload_bytes).unwrap_or(Value::Null);
@@ -955,14 +979,14 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
         .unwrap_or("")
         .to_string();
     if to.is_empty() {
-        return send_payload_error("missing email target", false);
+        return send_payload_error(ProviderError::validation("missing email target"));
     }
     if subject.is_empty() {
-        return send_payload_error("subject required", false);
+        return send_payload_error(ProviderError::validation("subject required"));
     }
     let auth_user = match send_in.auth_user {
         Some(user) => user,
-        None => return send_payload_error("auth_user missing", false),
+        None => return send_payload_error(ProviderError::validation("auth_user missing")),
     };
     let mut config_value = serde_json::Map::new();
     for key in [
@@ -987,14 +1011,16 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
     let cfg = if !config_value.is_empty() {
         match parse_config_value(&Value::Object(config_value)) {
             Ok(cfg) => cfg,
-            Err(err) => return send_payload_error(&err, false),
+            Err(err) => return send_payload_error(err),
         }
     } else {
-        return send_payload_error("config metadata required for send_payload", false);
+        return send_payload_error(ProviderError::config_invalid(
+            "config metadata required for send_payload",
+        ));
     };
     let token = match auth::acquire_graph_token(&cfg, &auth_user) {
         Ok(value) => value,
-        Err(err) => return send_payload_error(&err, true),
+        Err(err) => return send_payload_error(err),
     };
     let mail_body = json!({
         "message": {
@@ -1007,10 +1033,16 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
         "saveToSentItems": false
     });
     let url = format!("{}/me/sendMail", graph_base_url(&cfg));
-    if let Err(err) = graph_post(&token, &url, &mail_body) {
-        return send_payload_error(&err, true);
+    let policy = serde_json::from_slice::<Value>(input_json)
+        .map(|input| RetryPolicy::from_input(&input))
+        .unwrap_or_default();
+    let (result, attempts) =
+        graph_request_with_retry(&token.access_token, "POST", &url, Some(&mail_body), &policy);
+    if let Err(err) = result {
+        auth::forget_rejected_token(&cfg, &auth_user, &err);
+        return send_payload_error_after(err, attempts);
     }
-    send_payload_success()
+    send_payload_success_after(attempts, None, &token)
 }
 
 /// Serialize HttpOutV1 with "v":1 for operator v0.4.x compatibility.
@@ -1040,47 +1072,81 @@ fn encode_error(message: &str) -> Vec<u8> {
     json_bytes(&json!({"ok": false, "error": message}))
 }
 
-fn send_payload_error(message: &str, retryable: bool) -> Vec<u8> {
+fn error_bytes(err: ProviderError) -> Vec<u8> {
+    json_bytes(&err.to_result_json())
+}
+
+fn send_payload_error(err: ProviderError) -> Vec<u8> {
+    send_payload_error_after(err, 0)
+}
+
+fn send_payload_error_after(err: ProviderError, attempts: u32) -> Vec<u8> {
+    send_payload_failure(&err.to_string(), &err.detail(), attempts)
+}
+
+fn send_payload_failure(message: &str, detail: &ProviderErrorDetail, attempts: u32) -> Vec<u8> {
     let result = SendPayloadResultV1 {
         ok: false,
         message: Some(message.to_string()),
-        retryable,
+        retryable: detail.retryable,
     };
-    json_bytes(&result)
+    send_payload_result_bytes(&result, attempts, Some(detail), &[])
 }
 
-fn send_payload_success() -> Vec<u8> {
+fn send_payload_success_after(
+    attempts: u32,
+    provider_message_id: Option<&str>,
+    token: &auth::GraphToken,
+) -> Vec<u8> {
     let result = SendPayloadResultV1 {
         ok: true,
         message: None,
         retryable: false,
     };
-    json_bytes(&result)
+    let mut value = serde_json::to_value(&result).unwrap_or(Value::Null);
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+        if let Some(id) = provider_message_id {
+            map.insert("provider_message_id".into(), json!(id));
+        }
+    }
+    with_refresh_rotation(&mut value, token);
+    json_bytes(&value)
+}
+
+impl RetryableResponse for client::Response {
+    fn status(&self) -> u16 {
+        self.status
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
 }
 
 fn subscription_ensure(input_json: &[u8]) -> Vec<u8> {
     let parsed = match serde_json::from_slice::<Value>(input_json) {
         Ok(value) => value,
         Err(err) => {
-            return subscription_error(&format!("invalid subscription input: {err}"));
+            return subscription_error(format!("invalid subscription input: {err}"));
         }
     };
     let dto = match serde_json::from_value::<SubscriptionEnsureInV1>(parsed.clone()) {
         Ok(value) => value,
         Err(err) => {
-            return subscription_error(&format!("invalid subscription payload: {err}"));
+            return subscription_error(format!("invalid subscription payload: {err}"));
         }
     };
     if let Err(err) = ensure_provider(&dto.provider) {
-        return subscription_error(&err);
+        return subscription_error(err);
     }
     let cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return subscription_error(&err),
+        Err(err) => return subscription_error(err),
     };
     let token = match auth::acquire_graph_token(&cfg, &dto.user) {
         Ok(value) => value,
-        Err(err) => return subscription_error(&err),
+        Err(err) => return error_bytes(err),
     };
     let change_types = if dto.change_types.is_empty() {
         vec!["created".to_string()]
@@ -1103,9 +1169,12 @@ fn subscription_ensure(input_json: &[u8]) -> Vec<u8> {
         body["metadata"] = metadata.clone();
     }
     let url = format!("{}/subscriptions", graph_base_url(&cfg));
-    let resp = match graph_post(&token, &url, &body) {
+    let resp = match graph_post(&token.access_token, &url, &body) {
         Ok(value) => value,
-        Err(err) => return subscription_error(&err),
+        Err(err) => {
+            auth::forget_rejected_token(&cfg, &dto.user, &err);
+            return subscription_error(err);
+        }
     };
     let subscription_id = resp
         .get("id")
@@ -1132,32 +1201,32 @@ fn subscription_ensure(input_json: &[u8]) -> Vec<u8> {
         binding_id: dto.binding_id.clone(),
         user: dto.user,
     };
-    json_bytes(&json!({"ok": true, "subscription": out}))
+    subscription_ok(&out, &token)
 }
 
 fn subscription_renew(input_json: &[u8]) -> Vec<u8> {
     let parsed = match serde_json::from_slice::<Value>(input_json) {
         Ok(value) => value,
         Err(err) => {
-            return subscription_error(&format!("invalid subscription input: {err}"));
+            return subscription_error(format!("invalid subscription input: {err}"));
         }
     };
     let dto = match serde_json::from_value::<SubscriptionRenewInV1>(parsed.clone()) {
         Ok(value) => value,
         Err(err) => {
-            return subscription_error(&format!("invalid subscription payload: {err}"));
+            return subscription_error(format!("invalid subscription payload: {err}"));
         }
     };
     if let Err(err) = ensure_provider(&dto.provider) {
-        return subscription_error(&err);
+        return subscription_error(err);
     }
     let cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return subscription_error(&err),
+        Err(err) => return subscription_error(err),
     };
     let token = match auth::acquire_graph_token(&cfg, &dto.user) {
         Ok(value) => value,
-        Err(err) => return subscription_error(&err),
+        Err(err) => return error_bytes(err),
     };
     let expiration = target_expiration(dto.expiration_minutes, dto.expiration_target_unix_ms);
     let expiration = clamp_expiration(expiration);
@@ -1170,9 +1239,12 @@ fn subscription_renew(input_json: &[u8]) -> Vec<u8> {
         graph_base_url(&cfg),
         dto.subscription_id
     );
-    let resp = match graph_patch(&token, &url, &body) {
+    let resp = match graph_patch(&token.access_token, &url, &body) {
         Ok(value) => value,
-        Err(err) => return subscription_error(&err),
+        Err(err) => {
+            auth::forget_rejected_token(&cfg, &dto.user, &err);
+            return subscription_error(err);
+        }
     };
     let expiration_ms = resp
         .get("expirationDateTime")
@@ -1187,51 +1259,67 @@ fn subscription_renew(input_json: &[u8]) -> Vec<u8> {
         metadata: dto.metadata.clone(),
         user: dto.user,
     };
-    json_bytes(&json!({"ok": true, "subscription": out}))
+    subscription_ok(&out, &token)
 }
 
 fn subscription_delete(input_json: &[u8]) -> Vec<u8> {
     let parsed = match serde_json::from_slice::<Value>(input_json) {
         Ok(value) => value,
         Err(err) => {
-            return subscription_error(&format!("invalid subscription input: {err}"));
+            return subscription_error(format!("invalid subscription input: {err}"));
         }
     };
     let dto = match serde_json::from_value::<SubscriptionDeleteInV1>(parsed.clone()) {
         Ok(value) => value,
         Err(err) => {
-            return subscription_error(&format!("invalid subscription payload: {err}"));
+            return subscription_error(format!("invalid subscription payload: {err}"));
         }
     };
     if let Err(err) = ensure_provider(&dto.provider) {
-        return subscription_error(&err);
+        return subscription_error(err);
     }
     let cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return subscription_error(&err),
+        Err(err) => return subscription_error(err),
     };
     let token = match auth::acquire_graph_token(&cfg, &dto.user) {
         Ok(value) => value,
-        Err(err) => return subscription_error(&err),
+        Err(err) => return error_bytes(err),
     };
     let url = format!(
         "{}/subscriptions/{}",
         graph_base_url(&cfg),
         dto.subscription_id
     );
-    if let Err(err) = graph_delete(&token, &url) {
-        return subscription_error(&err);
+    if let Err(err) = graph_delete(&token.access_token, &url) {
+        auth::forget_rejected_token(&cfg, &dto.user, &err);
+        return subscription_error(err);
     }
     let out = SubscriptionDeleteOutV1 {
         v: 1,
         subscription_id: dto.subscription_id,
         user: dto.user,
     };
-    json_bytes(&json!({"ok": true, "subscription": out}))
+    subscription_ok(&out, &token)
 }
 
-fn subscription_error(message: &str) -> Vec<u8> {
-    json_bytes(&json!({"ok": false, "error": message}))
+fn subscription_ok<T: serde::Serialize>(out: &T, token: &auth::GraphToken) -> Vec<u8> {
+    let mut value = json!({"ok": true, "subscription": out});
+    with_refresh_rotation(&mut value, token);
+    json_bytes(&value)
+}
+
+/// Reports a refresh token rotation in an operation result, so a rotation
+/// the secret store did not keep is visible before the old token stops
+/// working.
+fn with_refresh_rotation(value: &mut Value, token: &auth::GraphToken) {
+    if let (Some(map), Some(rotated)) = (value.as_object_mut(), token.refresh_token_rotated) {
+        map.insert("refresh_token_rotated".into(), json!(rotated));
+    }
+}
+
+fn subscription_error(message: impl std::fmt::Display) -> Vec<u8> {
+    json_bytes(&json!({"ok": false, "error": message.to_string()}))
 }
 
 fn ensure_provider(provider: &str) -> Result<(), String> {
@@ -1286,19 +1374,19 @@ fn graph_base_url(cfg: &ProviderConfig) -> String {
         .to_string()
 }
 
-fn graph_post(token: &str, url: &str, body: &Value) -> Result<Value, String> {
+fn graph_post(token: &str, url: &str, body: &Value) -> Result<Value, ProviderError> {
     graph_request(token, "POST", url, Some(body))
 }
 
-fn graph_patch(token: &str, url: &str, body: &Value) -> Result<Value, String> {
+fn graph_patch(token: &str, url: &str, body: &Value) -> Result<Value, ProviderError> {
     graph_request(token, "PATCH", url, Some(body))
 }
 
-fn graph_delete(token: &str, url: &str) -> Result<Value, String> {
+fn graph_delete(token: &str, url: &str) -> Result<Value, ProviderError> {
     graph_request(token, "DELETE", url, None)
 }
 
-fn graph_get(token: &str, url: &str) -> Result<Value, String> {
+fn graph_get(token: &str, url: &str) -> Result<Value, ProviderError> {
     graph_request(token, "GET", url, None)
 }
 
@@ -1307,10 +1395,30 @@ fn graph_request(
     method: &str,
     url: &str,
     body: Option<&Value>,
-) -> Result<Value, String> {
+) -> Result<Value, ProviderError> {
+    graph_request_with_retry(token, method, url, body, &RetryPolicy::default()).0
+}
+
+fn graph_request_with_retry(
+    token: &str,
+    method: &str,
+    url: &str,
+    body: Option<&Value>,
+    policy: &RetryPolicy,
+) -> (Result<Value, ProviderError>, u32) {
     let mut headers = vec![("Authorization".into(), format!("Bearer {token}"))];
     let (body_vec, _needs_content) = if let Some(value) = body {
-        let bytes = serde_json::to_vec(value).map_err(|e| format!("invalid graph body: {e}"))?;
+        let bytes = match serde_json::to_vec(value) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                return (
+                    Err(ProviderError::validation(format!(
+                        "invalid graph body: {e}"
+                    ))),
+                    0,
+                );
+            }
+        };
         headers.push(("Content-Type".into(), "application/json".into()));
         (Some(bytes), true)
     } else {
@@ -1322,16 +1430,47 @@ fn graph_request(
         headers,
         body: body_vec,
     };
-    let resp = client::send(&request, None, None)
-        .map_err(|e| format!("graph request error: {}", e.message))?;
+    let outcome = send_http(&request.method, policy, || {
+        client::send(&request, None, None)
+    });
+    let attempts = outcome.attempts;
+    let resp = match outcome.result {
+        Ok(resp) => resp,
+        Err(e) => return (Err(ProviderError::transport(e.message)), attempts),
+    };
     if resp.status < 200 || resp.status >= 300 {
-        return Err(format!("graph request returned {}", resp.status));
+        return (Err(graph_status_error(&resp)), attempts);
     }
     let body = match resp.body {
         Some(body) if !body.is_empty() => body,
-        _ => return Ok(Value::Null),
+        _ => return (Ok(Value::Null), attempts),
+    };
+    let parsed = serde_json::from_slice(&body)
+        .map_err(|e| ProviderError::other(format!("graph response decode failed: {e}")));
+    (parsed, attempts)
+}
+
+/// Classifies a non-2xx Graph response using the `error.code` from the body.
+fn graph_status_error(resp: &client::Response) -> ProviderError {
+    let body: Value = resp
+        .body
+        .as_deref()
+        .and_then(|body| serde_json::from_slice(body).ok())
+        .unwrap_or(Value::Null);
+    let error = body.get("error");
+    let code = error
+        .and_then(|err| err.get("code"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    let message = match error
+        .and_then(|err| err.get("message"))
+        .and_then(Value::as_str)
+    {
+        Some(detail) => format!("graph request returned {}: {detail}", resp.status),
+        None => format!("graph request returned {}", resp.status),
     };
-    serde_json::from_slice(&body).map_err(|e| format!("graph response decode failed: {e}"))
+    let retry_after_secs = retry_after(resp).map(|delay| delay.as_secs());
+    ProviderError::from_http_response(resp.status, retry_after_secs, code, message)
 }
 
 fn handle_validation(http: &HttpInV1) -> Vec<u8> {
@@ -1363,43 +1502,140 @@ fn handle_graph_notifications(http: &HttpInV1) -> Vec<u8> {
     };
     let cfg = match parse_config_value(config_value) {
         Ok(cfg) => cfg,
-        Err(err) => return http_out_error(400, &err),
+        Err(err) => return http_out_error(400, &err.to_string()),
     };
+    if let Err(out) = verify_client_state(http) {
+        return out;
+    }
     let user = match binding_to_user(http.binding_id.as_ref()) {
         Ok(value) => value,
         Err(err) => return http_out_error(400, &err),
     };
-    let token = match auth::acquire_graph_token(&cfg, &user) {
-        Ok(value) => value,
-        Err(err) => return http_out_error(500, &err),
-    };
     let notifications = match parse_graph_notifications(&http.body_b64) {
         Ok(value) => value,
         Err(err) => return http_out_error(400, &err),
     };
+    // Keys are recorded only after every message was fetched, so a failed
+    // delivery is not suppressed when Graph retries it.
+    let window = DedupWindow::default();
+    let now = webhook_auth::now_unix_secs();
+    let total = notifications.len();
+    let fresh: Vec<(String, String)> = notifications
+        .into_iter()
+        .filter(|(_, message_id)| {
+            !window.seen(
+                &mut host_state(),
+                PROVIDER_ID,
+                &dedup_key(&user, message_id),
+                now,
+            )
+        })
+        .collect();
+    if total > 0 && fresh.is_empty() {
+        return duplicate_ack(&user.user_id);
+    }
+    let token = match auth::acquire_graph_token(&cfg, &user) {
+        Ok(value) => value,
+        Err(err) => return http_out_error(500, &err.to_string()),
+    };
     let mut events = Vec::new();
-    for (resource, message_id) in notifications {
-        match fetch_graph_message(&token, &cfg, &message_id) {
+    for (resource, message_id) in &fresh {
+        match fetch_graph_message(&token.access_token, &cfg, message_id) {
             Ok(message) => {
                 events.push(channel_message_envelope(
-                    &message,
-                    &user,
-                    &message_id,
-                    &resource,
+                    &message, &user, message_id, resource,
                 ));
             }
-            Err(err) => return http_out_error(500, &err),
+            Err(err) => {
+                auth::forget_rejected_token(&cfg, &user, &err);
+                return http_out_error(500, &err.to_string());
+            }
         }
     }
+    for (_, message_id) in &fresh {
+        window.record(
+            &mut host_state(),
+            PROVIDER_ID,
+            &dedup_key(&user, message_id),
+            now,
+        );
+    }
+    let body_b64 = if fresh.len() < total {
+        STANDARD.encode(json_bytes(&json!({
+            "ok": true,
+            DUPLICATE_SUPPRESSED_FLAG: true,
+            "suppressed": total - fresh.len(),
+        })))
+    } else {
+        String::new()
+    };
     let out = HttpOutV1 {
         status: 200,
         headers: Vec::new(),
-        body_b64: String::new(),
+        body_b64,
         events,
     };
     http_out_v1_bytes(&out)
 }
 
+fn host_state() -> HostStateAdapter {
+    HostStateAdapter::new(
+        |key| state_store::read(key, None).map_err(|err| err.message),
+        |key, value| {
+            state_store::write(key, value, None)
+                .map(|_| ())
+                .map_err(|err| err.message)
+        },
+        |key| {
+            state_store::delete(key, None)
+                .map(|_| ())
+                .map_err(|err| err.message)
+        },
+    )
+}
+
+/// Acknowledges a redelivered notification batch without emitting events.
+fn duplicate_ack(key: &str) -> Vec<u8> {
+    let out = HttpOutV1 {
+        status: 200,
+        headers: Vec::new(),
+        body_b64: STANDARD.encode(duplicate_ack_body(PROVIDER_ID, key)),
+        events: Vec::new(),
+    };
+    http_out_v1_bytes(&out)
+}
+
+/// Graph message ids are unique per mailbox.
+fn dedup_key(user: &AuthUserRefV1, message_id: &str) -> String {
+    format!("{}:{message_id}", user.user_id)
+}
+
+/// Rejects the request with a 401 when `MS_GRAPH_CLIENT_STATE` is configured
+/// and any notification carries a different `clientState`.
+fn verify_client_state(http: &HttpInV1) -> Result<(), Vec<u8>> {
+    let expected = match secrets_store::get(CLIENT_STATE_KEY) {
+        Ok(Some(bytes)) => String::from_utf8_lossy(&bytes).into_owned(),
+        Ok(None) => return Ok(()),
+        Err(err) => return Err(http_out_error(500, &format!("secret store error: {err:?}"))),
+    };
+    let body: Value = STANDARD
+        .decode(&http.body_b64)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or(Value::Null);
+    let entries = body
+        .get("value")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    for entry in entries {
+        let provided = entry.get("clientState").and_then(Value::as_str);
+        webhook_auth::verify_shared_token(&expected, provided, "clientState")
+            .map_err(|err| http_out_error(401, &ProviderError::from(err).to_string()))?;
+    }
+    Ok(())
+}
+
 fn query_param_value(query: &str, key: &str) -> Option<String> {
     for part in query.split('&') {
         let mut kv = part.splitn(2, '=');
@@ -1468,7 +1704,7 @@ fn fetch_graph_message(
     token: &str,
     cfg: &ProviderConfig,
     message_id: &str,
-) -> Result<Value, String> {
+) -> Result<Value, ProviderError> {
     let base = graph_base_url(cfg);
     let url = format!(
         "{}/me/messages/{}?$select=subject,bodyPreview,receivedDateTime,from,toRecipients,webLink,internetMessageId",
@@ -1545,19 +1781,19 @@ fn default_tenant() -> TenantId {
 }
 
 #[cfg(test)]
-fn parse_config_bytes(bytes: &[u8]) -> Result<ProviderConfig, String> {
+fn parse_config_bytes(bytes: &[u8]) -> Result<ProviderConfig, ProviderError> {
     let cfg = serde_json::from_slice::<ProviderConfig>(bytes)
-        .map_err(|e| format!("invalid config: {e}"))?;
+        .map_err(|e| ProviderError::config_invalid(e.to_string()))?;
     validate_provider_config(cfg)
 }
 
-fn parse_config_value(val: &Value) -> Result<ProviderConfig, String> {
+fn parse_config_value(val: &Value) -> Result<ProviderConfig, ProviderError> {
     let cfg = serde_json::from_value::<ProviderConfig>(val.clone())
-        .map_err(|e| format!("invalid config: {e}"))?;
+        .map_err(|e| ProviderError::config_invalid(e.to_string()))?;
     validate_provider_config(cfg)
 }
 
-fn load_config(input: &Value) -> Result<ProviderConfig, String> {
+fn load_config(input: &Value) -> Result<ProviderConfig, ProviderError> {
     if let Some(cfg) = input.get("config") {
         return parse_config_value(cfg);
     }
@@ -1585,7 +1821,7 @@ fn load_config(input: &Value) -> Result<ProviderConfig, String> {
     if !partial.is_empty() {
         return parse_config_value(&Value::Object(partial));
     }
-    Err("config required".into())
+    Err(ProviderError::config_invalid("config required"))
 }
 
 fn existing_config_from_answers(answers: &Value) -> Option<ProviderConfigOut> {
@@ -1662,18 +1898,22 @@ fn validate_config_out(config: &ProviderConfigOut) -> Result<(), String> {
     Ok(())
 }
 
-fn validate_provider_config(cfg: ProviderConfig) -> Result<ProviderConfig, String> {
+fn validate_provider_config(cfg: ProviderConfig) -> Result<ProviderConfig, ProviderError> {
     if cfg.public_base_url.trim().is_empty() {
-        return Err("invalid config: public_base_url cannot be empty".to_string());
+        return Err(ProviderError::config_invalid(
+            "public_base_url cannot be empty",
+        ));
     }
     if cfg.host.trim().is_empty() {
-        return Err("invalid config: host cannot be empty".to_string());
+        return Err(ProviderError::config_invalid("host cannot be empty"));
     }
     if cfg.username.trim().is_empty() {
-        return Err("invalid config: username cannot be empty".to_string());
+        return Err(ProviderError::config_invalid("username cannot be empty"));
     }
     if cfg.from_address.trim().is_empty() {
-        return Err("invalid config: from_address cannot be empty".to_string());
+        return Err(ProviderError::config_invalid(
+            "from_address cannot be empty",
+        ));
     }
     if let Some(password) = cfg.password.as_deref() {
         let _ = password.trim();
@@ -1754,8 +1994,48 @@ fn json_bytes<T: serde::Serialize>(value: &T) -> Vec<u8> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use provider_common::ProviderErrorCode;
     use std::collections::BTreeSet;
 
+    #[test]
+    fn results_report_refresh_token_rotation() {
+        let token = |rotated| auth::GraphToken {
+            access_token: "at".into(),
+            refresh_token_rotated: rotated,
+        };
+        let result = |rotated| -> Value {
+            serde_json::from_slice(&subscription_ok(&json!({"v": 1}), &token(rotated))).unwrap()
+        };
+        assert_eq!(result(Some(true))["refresh_token_rotated"], json!(true));
+        assert_eq!(result(Some(false))["refresh_token_rotated"], json!(false));
+        assert!(result(None).get("refresh_token_rotated").is_none());
+
+        let sent: Value =
+            serde_json::from_slice(&send_payload_success_after(1, None, &token(Some(false))))
+                .unwrap();
+        assert_eq!(sent["ok"], json!(true));
+        assert_eq!(sent["refresh_token_rotated"], json!(false));
+    }
+
+    #[test]
+    fn graph_status_error_classifies_mail_failures() {
+        let resp = client::Response {
+            status: 404,
+            headers: Vec::new(),
+            body: Some(br#"{"error":{"code":"ErrorItemNotFound","message":"not found"}}"#.to_vec()),
+        };
+        let err = graph_status_error(&resp);
+        assert_eq!(err.code(), ProviderErrorCode::DestinationNotFound);
+        assert!(!err.retryable());
+
+        let resp = client::Response {
+            status: 503,
+            headers: Vec::new(),
+            body: None,
+        };
+        assert!(graph_status_error(&resp).retryable());
+    }
+
     #[test]
     fn parse_config_requires_new_fields() {
         let cfg = br#"{"enabled":true,"public_base_url":"https://example.com","host":"smtp.example.com","port":587,"username":"u","from_address":"from@example.com","tls_mode":"starttls"}"#;
@@ -1767,7 +2047,7 @@ mod tests {
     fn parse_config_rejects_unknown() {
         let cfg = br#"{"enabled":true,"public_base_url":"https://example.com","host":"smtp","port":587,"username":"u","from_address":"f","tls_mode":"starttls","unknown":true}"#;
         let err = parse_config_bytes(cfg).unwrap_err();
-        assert!(err.contains("unknown field"));
+        assert!(err.to_string().contains("unknown field"));
     }
 
     #[test]
@@ -1794,10 +2074,34 @@ mod tests {
         let describe = build_describe_payload();
         assert_eq!(
             describe.schema_hash,
-            "a022076adb33dab084ad655fb83b4857a9d4aa7fd81b1d4d694a509789a63890"
+            "facb4dba464976adf0b8eb866483552c8a665175143c8bb90f608e0574aaaba4"
         );
     }
 
+    #[test]
+    fn config_schema_agrees_with_checked_in_json_schema() {
+        let checked_in: Value = serde_json::from_str(include_str!(
+            "../../../schemas/messaging/email/config.schema.json"
+        ))
+        .expect("schema json");
+        let generated = provider_common::json_schema::to_json_schema(&config_schema());
+        let required = |schema: &Value| {
+            schema["required"]
+                .as_array()
+                .map(|names| names.iter().filter_map(Value::as_str).map(String::from))
+                .into_iter()
+                .flatten()
+                .collect::<BTreeSet<_>>()
+        };
+        assert_eq!(required(&generated), required(&checked_in));
+        let properties = generated["properties"].as_object().expect("properties");
+        for (name, property) in properties {
+            let expected = &checked_in["properties"][name];
+            assert_eq!(property["type"], expected["type"], "{name}");
+            assert_eq!(property.get("enum"), expected.get("enum"), "{name}");
+        }
+    }
+
     #[test]
     fn describe_passes_strict_rules() {
         let describe = build_describe_payload();
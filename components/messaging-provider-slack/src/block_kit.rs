@@ -0,0 +1,970 @@
+//! Adaptive Card to Block Kit.
+//!
+//! The card in the `adaptive_card` metadata is converted element by element:
+//! text becomes `section`/`header`/`rich_text` blocks, facts and simple
+//! columns become section `fields`, images `image` blocks, actions a row of
+//! buttons and inputs `input` blocks. `Action.ShowCard` becomes a button
+//! whose value is the modal view of the nested card; ingest opens it when the
+//! button is clicked. Whatever Block Kit cannot show, or what exceeds its
+//! limits, is dropped with a warning (`{code, message, path}`, path being a
+//! JSON pointer into the card), and a card with warnings renders as TierB.
+
+use provider_common::formatting::{MarkdownDialect, render as render_markdown};
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+
+/// Blocks per message (and per modal view here).
+pub(crate) const MAX_BLOCKS: usize = 50;
+/// Characters of a section or context text.
+pub(crate) const MAX_TEXT: usize = 3000;
+/// Elements of an `actions` block.
+pub(crate) const MAX_ACTIONS: usize = 25;
+/// Prefix of the `action_id` of show-card buttons; their value is the view.
+pub(crate) const SHOW_CARD_ACTION_PREFIX: &str = "ac_show_card:";
+const MAX_FIELDS: usize = 10;
+const MAX_FIELD_TEXT: usize = 2000;
+const MAX_CONTEXT_ELEMENTS: usize = 10;
+const MAX_HEADER_TEXT: usize = 150;
+const MAX_BUTTON_TEXT: usize = 75;
+const MAX_VALUE: usize = 2000;
+const MAX_LABEL: usize = 2000;
+const MAX_OPTIONS: usize = 100;
+const MAX_OPTION_TEXT: usize = 75;
+const MAX_VIEW_TITLE: usize = 24;
+const MAX_PLACEHOLDER: usize = 150;
+
+/// Blocks of a card and what was lost on the way.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Conversion {
+    pub blocks: Vec<Value>,
+    pub warnings: Vec<Value>,
+    /// Button titles in display order.
+    pub actions: Vec<String>,
+}
+
+impl Conversion {
+    pub fn is_lossless(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+/// Converts the card in the `adaptive_card` metadata; `None` without a card
+/// or when it is not JSON.
+pub(crate) fn from_metadata(metadata: &BTreeMap<String, String>) -> Option<Conversion> {
+    let card: Value = serde_json::from_str(metadata.get("adaptive_card")?).ok()?;
+    Some(convert(&card))
+}
+
+pub(crate) fn convert(card: &Value) -> Conversion {
+    let mut converter = Converter::default();
+    converter.card(card, "");
+    let mut out = converter.out;
+    if out.blocks.len() > MAX_BLOCKS {
+        let dropped = out.blocks.len() - MAX_BLOCKS;
+        out.blocks.truncate(MAX_BLOCKS);
+        out.warnings.push(warning(
+            "blocks_dropped",
+            format!("{dropped} blocks beyond the {MAX_BLOCKS} block limit were dropped"),
+            "",
+        ));
+    }
+    out
+}
+
+#[derive(Default)]
+struct Converter {
+    out: Conversion,
+    /// Counter for actions and inputs without an `id`.
+    generated_ids: usize,
+}
+
+impl Converter {
+    fn card(&mut self, card: &Value, path: &str) {
+        let body = card.get("body").and_then(Value::as_array);
+        for (index, element) in body.into_iter().flatten().enumerate() {
+            self.element(element, &format!("{path}/body/{index}"));
+        }
+        if let Some(actions) = card.get("actions").and_then(Value::as_array) {
+            self.actions(actions, &format!("{path}/actions"));
+        }
+    }
+
+    fn element(&mut self, element: &Value, path: &str) {
+        match element.get("type").and_then(Value::as_str).unwrap_or("") {
+            "TextBlock" => self.text_block(element, path),
+            "RichTextBlock" => self.rich_text_block(element, path),
+            "FactSet" => self.fact_set(element, path),
+            "Image" => {
+                if let Some(block) = self.image(element, path) {
+                    self.out.blocks.push(block);
+                }
+            }
+            "ImageSet" => {
+                let images = element.get("images").and_then(Value::as_array);
+                for (index, image) in images.into_iter().flatten().enumerate() {
+                    if let Some(block) = self.image(image, &format!("{path}/images/{index}")) {
+                        self.out.blocks.push(block);
+                    }
+                }
+            }
+            "Container" => {
+                let items = element.get("items").and_then(Value::as_array);
+                for (index, item) in items.into_iter().flatten().enumerate() {
+                    self.element(item, &format!("{path}/items/{index}"));
+                }
+            }
+            "ColumnSet" => self.column_set(element, path),
+            "ActionSet" => {
+                if let Some(actions) = element.get("actions").and_then(Value::as_array) {
+                    self.actions(actions, &format!("{path}/actions"));
+                }
+            }
+            "Input.Text" | "Input.Number" | "Input.Date" | "Input.Time" | "Input.ChoiceSet"
+            | "Input.Toggle" => self.input(element, path),
+            other => self.out.warnings.push(warning(
+                "element_unsupported",
+                format!("{} has no Block Kit counterpart", type_name(other)),
+                path,
+            )),
+        }
+    }
+
+    fn text_block(&mut self, element: &Value, path: &str) {
+        let Some(text) = non_empty_str(element, "text") else {
+            return;
+        };
+        let heading = element.get("style").and_then(Value::as_str) == Some("heading")
+            || matches!(
+                element.get("size").and_then(Value::as_str),
+                Some("large" | "extraLarge")
+            );
+        if heading && text.chars().count() <= MAX_HEADER_TEXT && !text.contains('\n') {
+            self.out.blocks.push(json!({
+                "type": "header",
+                "text": plain_text(text),
+            }));
+            return;
+        }
+        let text = self.limit(&mrkdwn(text), MAX_TEXT, &format!("{path}/text"));
+        self.out.blocks.push(json!({
+            "type": "section",
+            "text": {"type": "mrkdwn", "text": text},
+        }));
+    }
+
+    fn rich_text_block(&mut self, element: &Value, path: &str) {
+        let mut elements = Vec::new();
+        let inlines = element.get("inlines").and_then(Value::as_array);
+        for (index, inline) in inlines.into_iter().flatten().enumerate() {
+            let (text, run) = match inline {
+                Value::String(text) => (text.as_str(), &Value::Null),
+                run => match run.get("text").and_then(Value::as_str) {
+                    Some(text) => (text, run),
+                    None => continue,
+                },
+            };
+            if text.is_empty() {
+                continue;
+            }
+            let text = self.limit(text, MAX_TEXT, &format!("{path}/inlines/{index}"));
+            let mut rich = match run.pointer("/selectAction/url").and_then(Value::as_str) {
+                Some(url) if is_http_url(url) => {
+                    json!({"type": "link", "url": url, "text": text})
+                }
+                _ => json!({"type": "text", "text": text}),
+            };
+            let style = text_run_style(run);
+            if !style.is_empty() {
+                rich["style"] = Value::Object(style);
+            }
+            elements.push(rich);
+        }
+        if elements.is_empty() {
+            return;
+        }
+        self.out.blocks.push(json!({
+            "type": "rich_text",
+            "elements": [{"type": "rich_text_section", "elements": elements}],
+        }));
+    }
+
+    /// Ten fields per section; longer fact sets continue in the next one.
+    fn fact_set(&mut self, element: &Value, path: &str) {
+        let facts = element.get("facts").and_then(Value::as_array);
+        let mut fields = Vec::new();
+        for (index, fact) in facts.into_iter().flatten().enumerate() {
+            let title = fact
+                .get("title")
+                .and_then(Value::as_str)
+                .unwrap_or("")
+                .trim();
+            let value = fact
+                .get("value")
+                .and_then(Value::as_str)
+                .unwrap_or("")
+                .trim();
+            let text = match (title.is_empty(), value.is_empty()) {
+                (true, true) => continue,
+                (false, true) => format!("*{title}*"),
+                (true, false) => mrkdwn(value),
+                (false, false) => format!("*{title}*\n{}", mrkdwn(value)),
+            };
+            let text = self.limit(&text, MAX_FIELD_TEXT, &format!("{path}/facts/{index}"));
+            fields.push(json!({"type": "mrkdwn", "text": text}));
+        }
+        for chunk in fields.chunks(MAX_FIELDS) {
+            self.out
+                .blocks
+                .push(json!({"type": "section", "fields": chunk}));
+        }
+    }
+
+    /// Slack fetches images itself, so only http(s) urls can be shown.
+    fn image(&mut self, element: &Value, path: &str) -> Option<Value> {
+        let url = element.get("url").and_then(Value::as_str).unwrap_or("");
+        if !is_http_url(url) || url.chars().count() > MAX_TEXT {
+            self.out.warnings.push(warning(
+                "image_dropped",
+                "images need an http(s) url Slack can fetch".to_string(),
+                &format!("{path}/url"),
+            ));
+            return None;
+        }
+        let alt = non_empty_str(element, "altText").unwrap_or("image");
+        Some(json!({
+            "type": "image",
+            "image_url": url,
+            "alt_text": self.limit(alt, MAX_LABEL, &format!("{path}/altText")),
+        }))
+    }
+
+    /// Columns of plain text become the fields of one section, columns of
+    /// text and images one context block; anything else is laid out one
+    /// column after the other.
+    fn column_set(&mut self, element: &Value, path: &str) {
+        let columns: Vec<&Vec<Value>> = element
+            .get("columns")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .map(|column| column.get("items").and_then(Value::as_array))
+            .map(|items| items.map_or(&EMPTY, |items| items))
+            .collect();
+        let simple = columns.iter().all(|items| {
+            items.iter().all(|item| {
+                matches!(
+                    item.get("type").and_then(Value::as_str),
+                    Some("TextBlock" | "Image")
+                )
+            })
+        });
+        let has_image = columns.iter().any(|items| {
+            items
+                .iter()
+                .any(|item| item.get("type").and_then(Value::as_str) == Some("Image"))
+        });
+        if simple && !has_image && columns.len() <= MAX_FIELDS {
+            let mut fields = Vec::new();
+            for (index, items) in columns.iter().enumerate() {
+                let text = column_text(items);
+                if text.is_empty() {
+                    continue;
+                }
+                let text = self.limit(&text, MAX_FIELD_TEXT, &format!("{path}/columns/{index}"));
+                fields.push(json!({"type": "mrkdwn", "text": text}));
+            }
+            if !fields.is_empty() {
+                self.out
+                    .blocks
+                    .push(json!({"type": "section", "fields": fields}));
+            }
+            return;
+        }
+        if simple {
+            let mut elements = Vec::new();
+            for (column, items) in columns.iter().enumerate() {
+                for (index, item) in items.iter().enumerate() {
+                    let item_path = format!("{path}/columns/{column}/items/{index}");
+                    if item.get("type").and_then(Value::as_str) == Some("Image") {
+                        // Image blocks and context image elements share a shape.
+                        elements.extend(self.image(item, &item_path));
+                    } else if let Some(text) = non_empty_str(item, "text") {
+                        let text = self.limit(&mrkdwn(text), MAX_TEXT, &item_path);
+                        elements.push(json!({"type": "mrkdwn", "text": text}));
+                    }
+                }
+            }
+            if elements.len() > MAX_CONTEXT_ELEMENTS {
+                self.out.warnings.push(warning(
+                    "elements_dropped",
+                    format!("a context block holds at most {MAX_CONTEXT_ELEMENTS} elements"),
+                    path,
+                ));
+                elements.truncate(MAX_CONTEXT_ELEMENTS);
+            }
+            if !elements.is_empty() {
+                self.out
+                    .blocks
+                    .push(json!({"type": "context", "elements": elements}));
+            }
+            return;
+        }
+        self.out.warnings.push(warning(
+            "columns_flattened",
+            "columns are shown one after the other".to_string(),
+            path,
+        ));
+        for (column, items) in columns.iter().enumerate() {
+            for (index, item) in items.iter().enumerate() {
+                self.element(item, &format!("{path}/columns/{column}/items/{index}"));
+            }
+        }
+    }
+
+    fn actions(&mut self, actions: &[Value], path: &str) {
+        let mut buttons = Vec::new();
+        for (index, action) in actions.iter().enumerate() {
+            if let Some(button) = self.button(action, &format!("{path}/{index}")) {
+                buttons.push(button);
+            }
+        }
+        if buttons.len() > MAX_ACTIONS {
+            self.out.warnings.push(warning(
+                "actions_dropped",
+                format!("only the first {MAX_ACTIONS} actions fit an actions block"),
+                path,
+            ));
+            buttons.truncate(MAX_ACTIONS);
+        }
+        if buttons.is_empty() {
+            return;
+        }
+        self.out.actions.extend(
+            buttons
+                .iter()
+                .filter_map(|button| button.pointer("/text/text").and_then(Value::as_str))
+                .map(ToOwned::to_owned),
+        );
+        self.out
+            .blocks
+            .push(json!({"type": "actions", "elements": buttons}));
+    }
+
+    fn button(&mut self, action: &Value, path: &str) -> Option<Value> {
+        let kind = action.get("type").and_then(Value::as_str).unwrap_or("");
+        let Some(title) = non_empty_str(action, "title") else {
+            self.out.warnings.push(warning(
+                "action_dropped",
+                "actions need a title".to_string(),
+                path,
+            ));
+            return None;
+        };
+        let mut button = json!({
+            "type": "button",
+            "text": plain_text(&self.limit(title, MAX_BUTTON_TEXT, &format!("{path}/title"))),
+        });
+        match kind {
+            "Action.OpenUrl" => {
+                let url = action.get("url").and_then(Value::as_str).unwrap_or("");
+                if !is_http_url(url) || url.chars().count() > MAX_TEXT {
+                    self.out.warnings.push(warning(
+                        "action_dropped",
+                        "link buttons need an http(s) url".to_string(),
+                        &format!("{path}/url"),
+                    ));
+                    return None;
+                }
+                button["url"] = json!(url);
+                button["action_id"] = json!(self.action_id(action));
+            }
+            "Action.Submit" | "Action.Execute" => {
+                let mut data = action
+                    .get("data")
+                    .cloned()
+                    .unwrap_or_else(|| json!(self.action_id(action)));
+                if kind == "Action.Execute"
+                    && let (Some(map), Some(verb)) = (data.as_object_mut(), action.get("verb"))
+                {
+                    map.insert("verb".to_string(), verb.clone());
+                }
+                let value = match data {
+                    Value::String(value) => value,
+                    other => other.to_string(),
+                };
+                if value.chars().count() > MAX_VALUE {
+                    self.out.warnings.push(warning(
+                        "action_dropped",
+                        format!("submit data exceeds the {MAX_VALUE} character button value"),
+                        &format!("{path}/data"),
+                    ));
+                    return None;
+                }
+                button["action_id"] = json!(self.action_id(action));
+                button["value"] = json!(value);
+            }
+            "Action.ShowCard" => {
+                let nested = action.get("card").cloned().unwrap_or(Value::Null);
+                let view_title = self.limit(title, MAX_VIEW_TITLE, &format!("{path}/title"));
+                let view = self.modal_view(&nested, &view_title, &format!("{path}/card"));
+                let value = view.to_string();
+                if value.chars().count() > MAX_VALUE {
+                    self.out.warnings.push(warning(
+                        "action_dropped",
+                        format!("the shown card exceeds the {MAX_VALUE} character button value"),
+                        &format!("{path}/card"),
+                    ));
+                    return None;
+                }
+                button["action_id"] = json!(format!(
+                    "{SHOW_CARD_ACTION_PREFIX}{}",
+                    self.action_id(action)
+                ));
+                button["value"] = json!(value);
+            }
+            other => {
+                self.out.warnings.push(warning(
+                    "action_dropped",
+                    format!("{} has no Block Kit counterpart", type_name(other)),
+                    path,
+                ));
+                return None;
+            }
+        }
+        match action.get("style").and_then(Value::as_str) {
+            Some("positive") => button["style"] = json!("primary"),
+            Some("destructive") => button["style"] = json!("danger"),
+            _ => {}
+        }
+        Some(button)
+    }
+
+    /// The nested card as a modal titled `title`, already cut to
+    /// `MAX_VIEW_TITLE`; it gets a submit button when it has inputs, whose
+    /// values then arrive as a `view_submission`.
+    fn modal_view(&mut self, card: &Value, title: &str, path: &str) -> Value {
+        let mut nested = Converter {
+            generated_ids: self.generated_ids,
+            ..Converter::default()
+        };
+        nested.card(card, path);
+        self.generated_ids = nested.generated_ids;
+        let mut blocks = nested.out.blocks;
+        self.out.warnings.append(&mut nested.out.warnings);
+        if blocks.len() > MAX_BLOCKS {
+            self.out.warnings.push(warning(
+                "blocks_dropped",
+                format!("a modal holds at most {MAX_BLOCKS} blocks here"),
+                path,
+            ));
+            blocks.truncate(MAX_BLOCKS);
+        }
+        let has_inputs = blocks
+            .iter()
+            .any(|block| block.get("type").and_then(Value::as_str) == Some("input"));
+        let mut view = json!({
+            "type": "modal",
+            "callback_id": "adaptive_card_show_card",
+            "title": plain_text(title),
+            "close": plain_text("Close"),
+            "blocks": blocks,
+        });
+        if has_inputs {
+            view["submit"] = plain_text("Submit");
+        }
+        view
+    }
+
+    fn input(&mut self, element: &Value, path: &str) {
+        let kind = element.get("type").and_then(Value::as_str).unwrap_or("");
+        let id = self.action_id(element);
+        let mut input = Map::new();
+        input.insert("action_id".to_string(), json!(id));
+        let placeholder = non_empty_str(element, "placeholder");
+        let initial = element.get("value").and_then(Value::as_str);
+        match kind {
+            "Input.Text" => {
+                input.insert("type".to_string(), json!("plain_text_input"));
+                if element.get("isMultiline").and_then(Value::as_bool) == Some(true) {
+                    input.insert("multiline".to_string(), json!(true));
+                }
+                if let Some(max) = element.get("maxLength").and_then(Value::as_u64) {
+                    input.insert("max_length".to_string(), json!(max));
+                }
+                if let Some(value) = initial.filter(|value| !value.is_empty()) {
+                    input.insert("initial_value".to_string(), json!(value));
+                }
+            }
+            "Input.Number" => {
+                input.insert("type".to_string(), json!("number_input"));
+                input.insert("is_decimal_allowed".to_string(), json!(true));
+                for (from, to) in [("min", "min_value"), ("max", "max_value")] {
+                    if let Some(bound) = element.get(from).filter(|bound| bound.is_number()) {
+                        input.insert(to.to_string(), json!(bound.to_string()));
+                    }
+                }
+                if let Some(value) = element.get("value").filter(|value| value.is_number()) {
+                    input.insert("initial_value".to_string(), json!(value.to_string()));
+                }
+            }
+            "Input.Date" => {
+                input.insert("type".to_string(), json!("datepicker"));
+                if let Some(value) = initial.filter(|value| !value.is_empty()) {
+                    input.insert("initial_date".to_string(), json!(value));
+                }
+            }
+            "Input.Time" => {
+                input.insert("type".to_string(), json!("timepicker"));
+                if let Some(value) = initial.filter(|value| !value.is_empty()) {
+                    input.insert("initial_time".to_string(), json!(value));
+                }
+            }
+            "Input.Toggle" => {
+                let title = non_empty_str(element, "title").unwrap_or(&id);
+                let on = element
+                    .get("valueOn")
+                    .and_then(Value::as_str)
+                    .unwrap_or("true");
+                let title = self.limit(title, MAX_OPTION_TEXT, &format!("{path}/title"));
+                let option = json!({
+                    "text": plain_text(&title),
+                    "value": on,
+                });
+                input.insert("type".to_string(), json!("checkboxes"));
+                if initial == Some(on) {
+                    input.insert("initial_options".to_string(), json!([option.clone()]));
+                }
+                input.insert("options".to_string(), json!([option]));
+            }
+            _ => {
+                if !self.choice_set(element, initial, &mut input, path) {
+                    return;
+                }
+            }
+        }
+        let takes_placeholder = matches!(
+            input.get("type").and_then(Value::as_str),
+            Some(
+                "plain_text_input"
+                    | "number_input"
+                    | "datepicker"
+                    | "timepicker"
+                    | "static_select"
+                    | "multi_static_select"
+            )
+        );
+        if let Some(placeholder) = placeholder.filter(|_| takes_placeholder) {
+            input.insert(
+                "placeholder".to_string(),
+                plain_text(&self.limit(
+                    placeholder,
+                    MAX_PLACEHOLDER,
+                    &format!("{path}/placeholder"),
+                )),
+            );
+        }
+        let label = non_empty_str(element, "label")
+            .or(placeholder)
+            .or_else(|| non_empty_str(element, "title"))
+            .unwrap_or(&id)
+            .to_string();
+        let label = self.limit(&label, MAX_LABEL, &format!("{path}/label"));
+        let optional = element.get("isRequired").and_then(Value::as_bool) != Some(true);
+        self.out.blocks.push(json!({
+            "type": "input",
+            "block_id": id,
+            "label": plain_text(&label),
+            "optional": optional,
+            "element": input,
+        }));
+    }
+
+    /// `compact` choice sets are selects, `expanded` ones radio buttons or
+    /// checkboxes. Returns `false` when there is nothing to choose from.
+    fn choice_set(
+        &mut self,
+        element: &Value,
+        initial: Option<&str>,
+        input: &mut Map<String, Value>,
+        path: &str,
+    ) -> bool {
+        let multi = element.get("isMultiSelect").and_then(Value::as_bool) == Some(true);
+        let expanded = element.get("style").and_then(Value::as_str) == Some("expanded");
+        let choices = element
+            .get("choices")
+            .and_then(Value::as_array)
+            .unwrap_or(&EMPTY);
+        let mut options = Vec::new();
+        for (index, choice) in choices.iter().enumerate() {
+            let (Some(title), Some(value)) = (
+                non_empty_str(choice, "title"),
+                choice.get("value").and_then(Value::as_str),
+            ) else {
+                continue;
+            };
+            let title = self.limit(
+                title,
+                MAX_OPTION_TEXT,
+                &format!("{path}/choices/{index}/title"),
+            );
+            options.push(json!({
+                "text": plain_text(&title),
+                "value": value,
+            }));
+        }
+        if options.is_empty() {
+            self.out.warnings.push(warning(
+                "element_unsupported",
+                "choice sets need static choices".to_string(),
+                path,
+            ));
+            return false;
+        }
+        if options.len() > MAX_OPTIONS {
+            self.out.warnings.push(warning(
+                "choices_dropped",
+                format!("only the first {MAX_OPTIONS} choices fit"),
+                &format!("{path}/choices"),
+            ));
+            options.truncate(MAX_OPTIONS);
+        }
+        let kind = match (expanded, multi) {
+            (true, true) => "checkboxes",
+            (true, false) => "radio_buttons",
+            (false, true) => "multi_static_select",
+            (false, false) => "static_select",
+        };
+        let selected: Vec<&str> = initial
+            .map(|value| value.split(',').map(str::trim).collect())
+            .unwrap_or_default();
+        let initial_options: Vec<Value> = options
+            .iter()
+            .filter(|option| {
+                option["value"]
+                    .as_str()
+                    .is_some_and(|value| selected.contains(&value))
+            })
+            .cloned()
+            .collect();
+        input.insert("type".to_string(), json!(kind));
+        input.insert("options".to_string(), Value::Array(options));
+        if multi && !initial_options.is_empty() {
+            input.insert("initial_options".to_string(), Value::Array(initial_options));
+        } else if let Some(first) = initial_options.into_iter().next() {
+            input.insert("initial_option".to_string(), first);
+        }
+        true
+    }
+
+    fn action_id(&mut self, value: &Value) -> String {
+        if let Some(id) = non_empty_str(value, "id") {
+            return truncate(id, 255);
+        }
+        self.generated_ids += 1;
+        format!("ac_{}", self.generated_ids)
+    }
+
+    fn limit(&mut self, text: &str, max: usize, path: &str) -> String {
+        if text.chars().count() <= max {
+            return text.to_string();
+        }
+        self.out.warnings.push(warning(
+            "text_truncated",
+            format!("text was cut to {max} characters"),
+            path,
+        ));
+        truncate(text, max)
+    }
+}
+
+static EMPTY: Vec<Value> = Vec::new();
+
+fn column_text(items: &[Value]) -> String {
+    items
+        .iter()
+        .filter_map(|item| non_empty_str(item, "text"))
+        .map(mrkdwn)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn text_run_style(run: &Value) -> Map<String, Value> {
+    let mut style = Map::new();
+    if run.get("weight").and_then(Value::as_str) == Some("bolder") {
+        style.insert("bold".to_string(), json!(true));
+    }
+    for (from, to) in [("italic", "italic"), ("strikethrough", "strike")] {
+        if run.get(from).and_then(Value::as_bool) == Some(true) {
+            style.insert(to.to_string(), json!(true));
+        }
+    }
+    if run.get("fontType").and_then(Value::as_str) == Some("monospace") {
+        style.insert("code".to_string(), json!(true));
+    }
+    style
+}
+
+fn warning(code: &str, message: String, path: &str) -> Value {
+    json!({
+        "code": code,
+        "message": message,
+        "path": if path.is_empty() { Value::Null } else { json!(path) },
+    })
+}
+
+fn type_name(kind: &str) -> String {
+    if kind.is_empty() {
+        "an element without type".to_string()
+    } else {
+        kind.to_string()
+    }
+}
+
+fn plain_text(text: &str) -> Value {
+    json!({"type": "plain_text", "text": text, "emoji": true})
+}
+
+fn mrkdwn(text: &str) -> String {
+    render_markdown(text.trim(), MarkdownDialect::SlackMrkdwn)
+}
+
+fn non_empty_str<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+/// Cuts at a character boundary and marks the cut with an ellipsis.
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(max.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(conversion: &Conversion) -> Vec<&str> {
+        conversion
+            .warnings
+            .iter()
+            .filter_map(|warning| warning["code"].as_str())
+            .collect()
+    }
+
+    #[test]
+    fn text_facts_images_and_actions_convert_losslessly() {
+        let card = json!({
+            "type": "AdaptiveCard",
+            "body": [
+                {"type": "TextBlock", "text": "Deploy", "size": "large"},
+                {"type": "TextBlock", "text": "Version **1.2** is ready"},
+                {"type": "FactSet", "facts": [{"title": "Env", "value": "prod"}]},
+                {"type": "Image", "url": "https://example.com/a.png", "altText": "graph"},
+                {"type": "RichTextBlock", "inlines": [
+                    "Logs: ",
+                    {"type": "TextRun", "text": "here", "weight": "bolder",
+                     "selectAction": {"type": "Action.OpenUrl", "url": "https://example.com/logs"}}
+                ]}
+            ],
+            "actions": [
+                {"type": "Action.Submit", "title": "Approve", "id": "approve",
+                 "data": {"ok": true}, "style": "positive"},
+                {"type": "Action.OpenUrl", "title": "Open", "url": "https://example.com"}
+            ]
+        });
+        let out = convert(&card);
+        assert!(out.is_lossless(), "{:?}", out.warnings);
+        let types: Vec<&str> = out
+            .blocks
+            .iter()
+            .filter_map(|block| block["type"].as_str())
+            .collect();
+        assert_eq!(
+            types,
+            [
+                "header",
+                "section",
+                "section",
+                "image",
+                "rich_text",
+                "actions"
+            ]
+        );
+        assert_eq!(out.blocks[1]["text"]["text"], "Version *1.2* is ready");
+        assert_eq!(out.blocks[2]["fields"][0]["text"], "*Env*\nprod");
+        assert_eq!(out.blocks[3]["alt_text"], "graph");
+        assert_eq!(
+            out.blocks[4]["elements"][0]["elements"][1],
+            json!({"type": "link", "url": "https://example.com/logs", "text": "here",
+                   "style": {"bold": true}})
+        );
+        let buttons = &out.blocks[5]["elements"];
+        assert_eq!(buttons[0]["action_id"], "approve");
+        assert_eq!(buttons[0]["value"], r#"{"ok":true}"#);
+        assert_eq!(buttons[0]["style"], "primary");
+        assert_eq!(buttons[1]["url"], "https://example.com");
+        assert_eq!(out.actions, ["Approve", "Open"]);
+        assert_eq!(convert(&card), out, "conversion is deterministic");
+    }
+
+    #[test]
+    fn columns_become_fields_context_or_flattened_blocks() {
+        let text = |text: &str| json!({"type": "TextBlock", "text": text});
+        let fields = convert(&json!({"body": [{"type": "ColumnSet", "columns": [
+            {"items": [text("Owner")]}, {"items": [text("ada")]}
+        ]}]}));
+        assert!(fields.is_lossless());
+        assert_eq!(fields.blocks[0]["fields"][1]["text"], "ada");
+
+        let context = convert(&json!({"body": [{"type": "ColumnSet", "columns": [
+            {"items": [{"type": "Image", "url": "https://example.com/u.png"}]},
+            {"items": [text("ada")]}
+        ]}]}));
+        assert_eq!(context.blocks[0]["type"], "context");
+        assert_eq!(context.blocks[0]["elements"][0]["type"], "image");
+
+        let flattened = convert(&json!({"body": [{"type": "ColumnSet", "columns": [
+            {"items": [{"type": "FactSet", "facts": [{"title": "a", "value": "b"}]}]}
+        ]}]}));
+        assert_eq!(codes(&flattened), ["columns_flattened"]);
+        assert_eq!(flattened.blocks[0]["type"], "section");
+    }
+
+    #[test]
+    fn inputs_become_input_blocks() {
+        let out = convert(&json!({"body": [
+            {"type": "Input.Text", "id": "name", "label": "Name", "isRequired": true,
+             "placeholder": "Your name"},
+            {"type": "Input.ChoiceSet", "id": "size", "value": "m", "choices": [
+                {"title": "Small", "value": "s"}, {"title": "Medium", "value": "m"}
+            ]},
+            {"type": "Input.ChoiceSet", "id": "tags", "style": "expanded",
+             "isMultiSelect": true, "choices": [{"title": "A", "value": "a"}]}
+        ]}));
+        assert!(out.is_lossless());
+        assert_eq!(out.blocks[0]["block_id"], "name");
+        assert_eq!(out.blocks[0]["optional"], false);
+        assert_eq!(out.blocks[0]["element"]["type"], "plain_text_input");
+        assert_eq!(out.blocks[0]["element"]["placeholder"]["text"], "Your name");
+        assert_eq!(out.blocks[1]["element"]["type"], "static_select");
+        assert_eq!(out.blocks[1]["element"]["initial_option"]["value"], "m");
+        assert_eq!(out.blocks[2]["element"]["type"], "checkboxes");
+        assert!(out.blocks[2]["element"].get("placeholder").is_none());
+    }
+
+    #[test]
+    fn show_card_becomes_a_modal_button() {
+        let out = convert(&json!({"actions": [{
+            "type": "Action.ShowCard",
+            "title": "Comment",
+            "card": {"body": [{"type": "Input.Text", "id": "comment", "label": "Comment"}]}
+        }]}));
+        assert!(out.is_lossless());
+        let button = &out.blocks[0]["elements"][0];
+        assert_eq!(button["action_id"], "ac_show_card:ac_1");
+        let view: Value = serde_json::from_str(button["value"].as_str().unwrap()).expect("view");
+        assert_eq!(view["type"], "modal");
+        assert_eq!(view["title"]["text"], "Comment");
+        assert_eq!(view["submit"]["text"], "Submit");
+        assert_eq!(view["blocks"][0]["block_id"], "comment");
+    }
+
+    #[test]
+    fn cut_labels_and_titles_warn() {
+        let long = "y".repeat(200);
+        let out = convert(&json!({
+            "body": [{
+                "type": "Input.ChoiceSet",
+                "id": "pick",
+                "label": "x".repeat(MAX_LABEL + 1),
+                "placeholder": long,
+                "choices": [{"title": long, "value": "a"}]
+            }],
+            "actions": [{
+                "type": "Action.ShowCard",
+                "title": long,
+                "card": {"body": [{"type": "TextBlock", "text": "hi"}]}
+            }]
+        }));
+        assert!(!out.is_lossless());
+        let paths: Vec<&str> = out
+            .warnings
+            .iter()
+            .filter(|warning| warning["code"] == "text_truncated")
+            .filter_map(|warning| warning["path"].as_str())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "/body/0/choices/0/title",
+                "/body/0/placeholder",
+                "/body/0/label",
+                "/actions/0/title",
+                "/actions/0/title"
+            ]
+        );
+        let button = &out.blocks[1]["elements"][0];
+        assert_eq!(
+            button["text"]["text"].as_str().unwrap().chars().count(),
+            MAX_BUTTON_TEXT
+        );
+        let view: Value = serde_json::from_str(button["value"].as_str().unwrap()).expect("view");
+        assert_eq!(
+            view["title"]["text"].as_str().unwrap().chars().count(),
+            MAX_VIEW_TITLE
+        );
+    }
+
+    #[test]
+    fn limits_and_unsupported_content_warn() {
+        let mut body: Vec<Value> = (0..60)
+            .map(|index| json!({"type": "TextBlock", "text": format!("line {index}")}))
+            .collect();
+        body.push(json!({"type": "Media", "sources": []}));
+        let actions: Vec<Value> = (0..30)
+            .map(|index| json!({"type": "Action.Submit", "title": format!("b{index}")}))
+            .collect();
+        body.push(json!({"type": "ActionSet", "actions": actions}));
+        body.insert(
+            0,
+            json!({"type": "TextBlock", "text": "x".repeat(MAX_TEXT + 5)}),
+        );
+        body.insert(
+            1,
+            json!({"type": "Image", "url": "data:image/png;base64,AAAA"}),
+        );
+        let out = convert(&json!({"body": body}));
+        assert_eq!(out.blocks.len(), MAX_BLOCKS);
+        assert_eq!(
+            out.blocks[0]["text"]["text"]
+                .as_str()
+                .unwrap()
+                .chars()
+                .count(),
+            MAX_TEXT
+        );
+        assert_eq!(
+            codes(&out),
+            [
+                "text_truncated",
+                "image_dropped",
+                "element_unsupported",
+                "actions_dropped",
+                "blocks_dropped"
+            ]
+        );
+        assert_eq!(out.warnings[0]["path"], "/body/0/text");
+        assert_eq!(out.actions.len(), MAX_ACTIONS);
+    }
+}
@@ -0,0 +1,108 @@
+//! Slash commands.
+//!
+//! `/ask ...` arrives as a form (`command`, `text`, `user_id`, `channel_id`,
+//! `response_url`, `trigger_id`). Slack wants an answer within three seconds:
+//! the ack is empty, or the configured `slash_command_ack` shown only to the
+//! user. The flow answers later through the `response_url`.
+
+use super::events::InboundEvent;
+use super::{interactivity, json_bytes};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use greentic_types::messaging::universal_dto::{Header, HttpOutV1};
+use provider_common::form_urlencoded;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+/// Form fields copied into envelope metadata as is.
+const METADATA_FIELDS: &[&str] = &[
+    "command",
+    "response_url",
+    "channel_name",
+    "user_name",
+    "api_app_id",
+    "enterprise_id",
+];
+
+/// The command of a slash command form; `None` for other bodies.
+pub(crate) fn slash_command(body: &[u8]) -> Option<InboundEvent> {
+    let body = std::str::from_utf8(body).ok()?;
+    let mut fields = form_urlencoded::slash_command_fields(body)?;
+    fields.retain(|_, value| !value.is_empty());
+    let command = fields.get("command")?;
+    let mut metadata: BTreeMap<String, String> = METADATA_FIELDS
+        .iter()
+        .filter_map(|key| Some((key.to_string(), fields.get(*key)?.clone())))
+        .collect();
+    metadata.insert(
+        "command_name".to_string(),
+        command.trim_start_matches('/').to_string(),
+    );
+    if let Some(team) = fields.get("team_id") {
+        metadata.insert("team".to_string(), team.clone());
+    }
+    Some(InboundEvent {
+        kind: "slash_command",
+        channel: fields.get("channel_id").cloned(),
+        user: fields.get("user_id").cloned(),
+        text: Some(fields.get("text").cloned().unwrap_or_default()),
+        trigger_id: fields.get("trigger_id").cloned(),
+        metadata,
+        ..InboundEvent::default()
+    })
+}
+
+/// Immediate answer to a command; blank `ack_text` keeps the ack empty.
+pub(crate) fn command_ack(ack_text: Option<&str>) -> HttpOutV1 {
+    let Some(text) = ack_text.map(str::trim).filter(|text| !text.is_empty()) else {
+        return interactivity::empty_ack();
+    };
+    HttpOutV1 {
+        status: 200,
+        headers: vec![Header {
+            name: "Content-Type".to_string(),
+            value: "application/json".to_string(),
+        }],
+        body_b64: STANDARD.encode(json_bytes(
+            &json!({"response_type": "ephemeral", "text": text}),
+        )),
+        events: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slash_command_form_becomes_event() {
+        let body = b"token=x&team_id=T1&channel_id=C1&channel_name=general&user_id=U1\
+&user_name=ada&command=%2Fask&text=what+is+up%3F\
+&response_url=https%3A%2F%2Fhooks.slack.com%2Fcommands%2FT1%2F1%2Fabc&trigger_id=13.42.ff";
+        let event = slash_command(body).expect("command");
+        assert_eq!(event.kind, "slash_command");
+        assert_eq!(event.text.as_deref(), Some("what is up?"));
+        assert_eq!(event.channel.as_deref(), Some("C1"));
+        assert_eq!(event.trigger_id.as_deref(), Some("13.42.ff"));
+        assert_eq!(event.metadata["command"], "/ask");
+        assert_eq!(event.metadata["command_name"], "ask");
+        assert_eq!(
+            event.metadata["response_url"],
+            "https://hooks.slack.com/commands/T1/1/abc"
+        );
+        assert!(!event.metadata.contains_key("token"));
+        assert!(slash_command(br#"{"type":"event_callback"}"#).is_none());
+    }
+
+    #[test]
+    fn ack_is_ephemeral_text_or_empty() {
+        assert!(command_ack(None).body_b64.is_empty());
+        assert!(command_ack(Some("  ")).body_b64.is_empty());
+        let ack = command_ack(Some("Working on it"));
+        let body: serde_json::Value =
+            serde_json::from_slice(&STANDARD.decode(ack.body_b64).expect("b64")).expect("json");
+        assert_eq!(
+            body,
+            json!({"response_type": "ephemeral", "text": "Working on it"})
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! Events API payloads.
+//!
+//! Slack delivers events wrapped in an `event_callback` envelope and checks
+//! the endpoint with a one-off `url_verification` challenge. Messages the bot
+//! posted itself come back as events too and are dropped so flows do not
+//! answer themselves.
+
+use super::files;
+use greentic_types::{
+    Actor, Attachment, ChannelMessageEnvelope, EnvId, MessageMetadata, ReplyScope, TenantCtx,
+    TenantId,
+};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Message subtypes that are ordinary user messages.
+const MESSAGE_SUBTYPES: &[&str] = &["file_share", "thread_broadcast", "me_message"];
+
+/// An Events API event reduced to what the envelope needs.
+#[derive(Debug, Default)]
+pub(crate) struct InboundEvent {
+    pub kind: &'static str,
+    pub event_id: Option<String>,
+    pub channel: Option<String>,
+    pub user: Option<String>,
+    pub text: Option<String>,
+    /// `ts` of the message the event is about.
+    pub ts: Option<String>,
+    pub thread_ts: Option<String>,
+    /// Interactions and slash commands; lets the flow open a modal.
+    pub trigger_id: Option<String>,
+    pub attachments: Vec<Attachment>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// The `challenge` to echo when Slack verifies the request url.
+pub(crate) fn url_verification_challenge(body: &Value) -> Option<&str> {
+    if body.get("type").and_then(Value::as_str) != Some("url_verification") {
+        return None;
+    }
+    body.get("challenge").and_then(Value::as_str)
+}
+
+/// Maps the events flows act on; `None` for ignored events and the bot's own
+/// messages. Bare event objects are accepted as well as `event_callback`
+/// envelopes.
+pub(crate) fn normalize_event(body: &Value) -> Option<InboundEvent> {
+    let event = match body.get("type").and_then(Value::as_str) {
+        Some("event_callback") => body.get("event")?,
+        _ => body,
+    };
+    if is_own_message(body, event) {
+        return None;
+    }
+    let mut inbound = match event.get("type").and_then(Value::as_str)? {
+        "message" => message_event(event)?,
+        "app_mention" => InboundEvent {
+            kind: "app_mention",
+            ..message_fields(event)
+        },
+        "reaction_added" => reaction_event("reaction_added", event)?,
+        "reaction_removed" => reaction_event("reaction_removed", event)?,
+        _ => return None,
+    };
+    inbound.event_id = str_field(body, "event_id");
+    let team = str_field(body, "team_id").or_else(|| str_field(event, "team"));
+    if let Some(team) = team {
+        inbound.metadata.insert("team".to_string(), team);
+    }
+    Some(inbound)
+}
+
+/// The installation's bot user is listed in `authorizations`; without it
+/// every bot message is treated as possibly ours.
+fn is_own_message(body: &Value, event: &Value) -> bool {
+    let message = match event.get("subtype").and_then(Value::as_str) {
+        Some("message_changed") => event.get("message").unwrap_or(event),
+        _ => event,
+    };
+    let bot_users: Vec<&str> = body
+        .get("authorizations")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|auth| auth.get("is_bot").and_then(Value::as_bool) == Some(true))
+        .filter_map(|auth| auth.get("user_id").and_then(Value::as_str))
+        .collect();
+    if bot_users.is_empty() {
+        return message.get("bot_id").is_some()
+            || message.get("subtype").and_then(Value::as_str) == Some("bot_message");
+    }
+    message
+        .get("user")
+        .and_then(Value::as_str)
+        .is_some_and(|user| bot_users.contains(&user))
+}
+
+fn message_event(event: &Value) -> Option<InboundEvent> {
+    match event.get("subtype").and_then(Value::as_str) {
+        None => Some(message_fields(event)),
+        Some(subtype) if MESSAGE_SUBTYPES.contains(&subtype) => {
+            let mut inbound = message_fields(event);
+            inbound
+                .metadata
+                .insert("subtype".to_string(), subtype.to_string());
+            Some(inbound)
+        }
+        Some("message_changed") => {
+            let message = event.get("message")?;
+            let mut inbound = InboundEvent {
+                kind: "message_edited",
+                channel: str_field(event, "channel"),
+                ..message_fields(message)
+            };
+            if let Some(previous) = event
+                .pointer("/previous_message/text")
+                .and_then(Value::as_str)
+            {
+                inbound
+                    .metadata
+                    .insert("previous_text".to_string(), previous.to_string());
+            }
+            if let Some(edited) = message.pointer("/edited/ts").and_then(Value::as_str) {
+                inbound
+                    .metadata
+                    .insert("edited_ts".to_string(), edited.to_string());
+            }
+            Some(inbound)
+        }
+        Some("message_deleted") => {
+            let previous = event.get("previous_message");
+            Some(InboundEvent {
+                kind: "message_deleted",
+                channel: str_field(event, "channel"),
+                user: previous.and_then(|message| str_field(message, "user")),
+                ts: str_field(event, "deleted_ts"),
+                thread_ts: previous.and_then(|message| str_field(message, "thread_ts")),
+                ..InboundEvent::default()
+            })
+        }
+        Some(_) => None,
+    }
+}
+
+fn message_fields(message: &Value) -> InboundEvent {
+    InboundEvent {
+        kind: "message",
+        channel: str_field(message, "channel"),
+        user: str_field(message, "user"),
+        text: str_field(message, "text"),
+        ts: str_field(message, "ts"),
+        thread_ts: str_field(message, "thread_ts"),
+        attachments: files::message_attachments(message),
+        ..InboundEvent::default()
+    }
+}
+
+fn reaction_event(kind: &'static str, event: &Value) -> Option<InboundEvent> {
+    let reaction = str_field(event, "reaction")?;
+    let item = event.get("item")?;
+    let mut metadata = BTreeMap::from([("reaction".to_string(), reaction)]);
+    if let Some(item_user) = str_field(event, "item_user") {
+        metadata.insert("item_user".to_string(), item_user);
+    }
+    Some(InboundEvent {
+        kind,
+        channel: str_field(item, "channel"),
+        user: str_field(event, "user"),
+        ts: str_field(item, "ts"),
+        metadata,
+        ..InboundEvent::default()
+    })
+}
+
+fn str_field(value: &Value, key: &str) -> Option<String> {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .filter(|field| !field.is_empty())
+        .map(ToOwned::to_owned)
+}
+
+/// Threads are their own sessions (`<channel>:<thread_ts>`); replies go to
+/// the thread the event came from.
+pub(crate) fn build_slack_envelope(event: InboundEvent) -> ChannelMessageEnvelope {
+    let env = EnvId::try_from("default").expect("env id");
+    let tenant = TenantId::try_from("default").expect("tenant id");
+    let mut metadata = MessageMetadata::new();
+    metadata.insert("universal".to_string(), "true".to_string());
+    metadata.insert("event_kind".to_string(), event.kind.to_string());
+    let fields = [
+        ("channel", &event.channel),
+        ("from", &event.user),
+        ("ts", &event.ts),
+        ("thread_ts", &event.thread_ts),
+        ("event_id", &event.event_id),
+        ("trigger_id", &event.trigger_id),
+    ];
+    for (key, value) in fields {
+        if let Some(value) = value {
+            metadata.insert(key.to_string(), value.clone());
+        }
+    }
+    metadata.extend(event.metadata);
+    let channel_name = event.channel.clone().unwrap_or_else(|| "slack".to_string());
+    let session_id = match &event.thread_ts {
+        Some(thread) => format!("{channel_name}:{thread}"),
+        None => channel_name.clone(),
+    };
+    let reply_scope = event.channel.clone().map(|conversation| ReplyScope {
+        conversation,
+        thread: event.thread_ts.clone(),
+        reply_to: event.ts.clone(),
+        correlation: None,
+    });
+    let id = match (
+        event.event_id.as_ref().or(event.trigger_id.as_ref()),
+        &event.ts,
+    ) {
+        (Some(delivery), _) => format!("slack-{delivery}"),
+        (None, Some(ts)) => format!("slack-{channel_name}-{ts}"),
+        (None, None) => format!("slack-{channel_name}"),
+    };
+    let actor = event.user.map(|id| Actor {
+        id,
+        kind: Some("user".into()),
+    });
+    ChannelMessageEnvelope {
+        id,
+        tenant: TenantCtx::new(env, tenant),
+        channel: channel_name,
+        session_id,
+        reply_scope,
+        from: actor,
+        to: Vec::new(),
+        correlation_id: None,
+        text: event.text,
+        attachments: event.attachments,
+        metadata,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn callback(event: Value) -> Value {
+        json!({
+            "type": "event_callback",
+            "team_id": "T1",
+            "event_id": "Ev1",
+            "authorizations": [{"user_id": "UBOT", "is_bot": true}],
+            "event": event,
+        })
+    }
+
+    #[test]
+    fn threaded_message_maps_into_envelope() {
+        let body = callback(json!({
+            "type": "message", "channel": "C1", "user": "U1", "text": "hi",
+            "ts": "1700000000.000200", "thread_ts": "1700000000.000100"
+        }));
+        let envelope = build_slack_envelope(normalize_event(&body).expect("event"));
+        assert_eq!(envelope.id, "slack-Ev1");
+        assert_eq!(envelope.session_id, "C1:1700000000.000100");
+        assert_eq!(envelope.text.as_deref(), Some("hi"));
+        assert_eq!(envelope.metadata["team"], "T1");
+        assert_eq!(envelope.metadata["event_kind"], "message");
+        let scope = envelope.reply_scope.expect("reply scope");
+        assert_eq!(scope.thread.as_deref(), Some("1700000000.000100"));
+        assert_eq!(scope.reply_to.as_deref(), Some("1700000000.000200"));
+    }
+
+    #[test]
+    fn own_messages_are_dropped() {
+        let own = callback(
+            json!({"type": "message", "channel": "C1", "user": "UBOT", "bot_id": "B1", "text": "echo"}),
+        );
+        assert!(normalize_event(&own).is_none());
+        let other_bot = callback(
+            json!({"type": "message", "channel": "C1", "user": "U2", "bot_id": "B2", "text": "x"}),
+        );
+        assert!(normalize_event(&other_bot).is_some());
+        let unauthorized =
+            json!({"type": "message", "subtype": "bot_message", "bot_id": "B1", "text": "x"});
+        assert!(normalize_event(&unauthorized).is_none());
+    }
+
+    #[test]
+    fn edits_deletions_and_reactions_are_typed() {
+        let edited = normalize_event(&callback(json!({
+            "type": "message", "subtype": "message_changed", "channel": "C1",
+            "message": {"user": "U1", "text": "new", "ts": "1.2", "edited": {"ts": "1.3"}},
+            "previous_message": {"text": "old"}
+        })))
+        .expect("edit");
+        assert_eq!(edited.kind, "message_edited");
+        assert_eq!(edited.text.as_deref(), Some("new"));
+        assert_eq!(edited.metadata["previous_text"], "old");
+
+        let deleted = normalize_event(&callback(json!({
+            "type": "message", "subtype": "message_deleted", "channel": "C1", "deleted_ts": "1.2"
+        })))
+        .expect("delete");
+        assert_eq!(deleted.kind, "message_deleted");
+        assert_eq!(deleted.ts.as_deref(), Some("1.2"));
+
+        let reaction = normalize_event(&callback(json!({
+            "type": "reaction_added", "user": "U1", "reaction": "thumbsup",
+            "item": {"type": "message", "channel": "C1", "ts": "1.2"}
+        })))
+        .expect("reaction");
+        assert_eq!(reaction.kind, "reaction_added");
+        assert_eq!(reaction.metadata["reaction"], "thumbsup");
+
+        let join = callback(json!({"type": "message", "subtype": "channel_join", "user": "U1"}));
+        assert!(normalize_event(&join).is_none());
+        assert_eq!(
+            url_verification_challenge(&json!({"type": "url_verification", "challenge": "abc"})),
+            Some("abc")
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Downloads of files shared in messages.
+//!
+//! Ingest keeps each file's `url_private` as the attachment url. Those urls
+//! need the bot token, so `fetch_attachment` only sends it to Slack's own file
+//! hosts.
+
+use super::{
+    PROVIDER_TYPE, client, error_bytes, error_bytes_after, json_bytes, load_config,
+    resolve_bot_token, slack_status_error,
+};
+use greentic_types::Attachment;
+use provider_common::ProviderError;
+use provider_common::attachments::{FetchLimits, FetchedAttachment, response_mime_type};
+use provider_common::http_retry::{RetryPolicy, send_http};
+use serde_json::Value;
+
+/// Hosts serving `url_private` and `url_private_download`.
+const FILE_HOSTS: &[&str] = &["files.slack.com", "slack-files.com"];
+
+/// Attachment references for the `files` of a message event.
+pub(crate) fn message_attachments(event: &Value) -> Vec<Attachment> {
+    event
+        .get("files")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|file| {
+            let url = file
+                .get("url_private_download")
+                .or_else(|| file.get("url_private"))?
+                .as_str()?;
+            Some(Attachment {
+                mime_type: file
+                    .get("mimetype")
+                    .and_then(Value::as_str)
+                    .filter(|mime| !mime.is_empty())
+                    .unwrap_or("application/octet-stream")
+                    .to_string(),
+                url: url.to_string(),
+                name: file
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .map(ToOwned::to_owned),
+                size_bytes: file.get("size").and_then(Value::as_u64),
+            })
+        })
+        .collect()
+}
+
+/// Takes the attachment `url`; `mime_type` and `name` from the envelope
+/// attachment fill in what the download does not say.
+pub(crate) fn handle_fetch_attachment(input_json: &[u8]) -> Vec<u8> {
+    let parsed: Value = match serde_json::from_slice(input_json) {
+        Ok(value) => value,
+        Err(err) => {
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
+        }
+    };
+    let cfg = match load_config(&parsed) {
+        Ok(cfg) => cfg,
+        Err(err) => return error_bytes(err),
+    };
+    if !cfg.enabled {
+        return error_bytes(ProviderError::config_invalid("provider disabled by config"));
+    }
+    let Some(url) = parsed
+        .get("url")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+    else {
+        return error_bytes(ProviderError::validation("url required"));
+    };
+    if !is_slack_file_url(url) {
+        return error_bytes(ProviderError::validation(
+            "url must be a Slack file url (url_private)",
+        ));
+    }
+    let limits = FetchLimits::from_input(&parsed);
+    let hint = parsed
+        .get("mime_type")
+        .and_then(Value::as_str)
+        .filter(|mime| !mime.trim().is_empty());
+    if let Some(hint) = hint
+        && let Err(err) = limits.check_mime(hint)
+    {
+        return error_bytes(err);
+    }
+    let token = resolve_bot_token(&cfg);
+    if token.trim().is_empty() {
+        return error_bytes(ProviderError::missing_secret("bot_token"));
+    }
+
+    let request = client::Request {
+        method: "GET".to_string(),
+        url: url.to_string(),
+        headers: vec![("Authorization".into(), format!("Bearer {token}"))],
+        body: None,
+    };
+    let outcome = send_http(&request.method, &RetryPolicy::from_input(&parsed), || {
+        client::send(&request, None, None)
+    });
+    let attempts = outcome.attempts;
+    let resp = match outcome.result {
+        Ok(resp) => resp,
+        Err(err) => return error_bytes_after(ProviderError::transport(err.message), attempts),
+    };
+    if resp.status < 200 || resp.status >= 300 {
+        return error_bytes_after(slack_status_error(&resp), attempts);
+    }
+    let served = response_mime_type(&resp.headers);
+    // Slack answers an unauthorized download with its HTML sign-in page.
+    if served.as_deref() == Some("text/html") && hint.is_none_or(|hint| hint != "text/html") {
+        return error_bytes_after(
+            ProviderError::auth_failed("file download was redirected to sign-in"),
+            attempts,
+        );
+    }
+    let fetched = FetchedAttachment {
+        mime_type: served
+            .or_else(|| hint.map(str::to_ascii_lowercase))
+            .unwrap_or_else(|| "application/octet-stream".to_string()),
+        name: parsed
+            .get("name")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned),
+        bytes: resp.body.unwrap_or_default(),
+    };
+    if let Err(err) = limits
+        .check_size(fetched.bytes.len() as u64)
+        .and_then(|()| limits.check_mime(&fetched.mime_type))
+    {
+        return error_bytes_after(err, attempts);
+    }
+    json_bytes(&fetched.to_result_json(PROVIDER_TYPE, attempts))
+}
+
+fn is_slack_file_url(url: &str) -> bool {
+    let Some(rest) = url.strip_prefix("https://") else {
+        return false;
+    };
+    let host = rest
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    FILE_HOSTS.contains(&host.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn files_become_attachment_references() {
+        let event = json!({"files": [{
+            "id": "F1",
+            "name": "report.pdf",
+            "mimetype": "application/pdf",
+            "size": 42,
+            "url_private": "https://files.slack.com/files-pri/T1-F1/report.pdf"
+        }]});
+        let attachments = message_attachments(&event);
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(
+            attachments[0].url,
+            "https://files.slack.com/files-pri/T1-F1/report.pdf"
+        );
+        assert_eq!(attachments[0].size_bytes, Some(42));
+        assert!(message_attachments(&json!({"text": "hi"})).is_empty());
+    }
+
+    #[test]
+    fn token_is_only_sent_to_slack_file_hosts() {
+        assert!(is_slack_file_url(
+            "https://files.slack.com/files-pri/T1-F1/report.pdf"
+        ));
+        assert!(!is_slack_file_url("http://files.slack.com/a"));
+        assert!(!is_slack_file_url("https://files.slack.com.evil.example/a"));
+        assert!(!is_slack_file_url("https://example.com/files.slack.com"));
+    }
+}
@@ -0,0 +1,559 @@
+//! Interactive components: button clicks, menus and modal submissions.
+//!
+//! Slack posts these as a form with a single `payload` field holding JSON and
+//! expects a 200 within three seconds. `block_actions` and `view_submission`
+//! become envelopes whose `action` metadata carries the structured data; the
+//! `response_url` of an interaction lets replies go back to where it came
+//! from, and `ack_interaction` builds the `response_action` answer modals
+//! accept in place of an empty ack.
+
+use super::block_kit::SHOW_CARD_ACTION_PREFIX;
+use super::events::InboundEvent;
+use super::{
+    MAX_TEXT_LEN, PROVIDER_TYPE, client, error_bytes, error_bytes_after, json_bytes, parse_blocks,
+    slack_api_body, slack_status_error,
+};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use greentic_types::messaging::universal_dto::{Header, HttpOutV1};
+use provider_common::ProviderError;
+use provider_common::form_urlencoded;
+use provider_common::formatting::{MarkdownDialect, render as render_markdown};
+use provider_common::http_retry::{RetryPolicy, send_http};
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+
+/// Host of the `response_url`s Slack hands out with interactions.
+const RESPONSE_URL_HOST: &str = "hooks.slack.com";
+const RESPONSE_TYPES: &[&str] = &["in_channel", "ephemeral"];
+
+/// The JSON `payload` of an interactive request; `None` for anything else,
+/// including Events API bodies.
+pub(crate) fn interaction_payload(body: &[u8]) -> Option<Value> {
+    form_urlencoded::json_payload(std::str::from_utf8(body).ok()?)
+}
+
+/// Maps `block_actions` and `view_submission`; other interaction types
+/// (`view_closed`, shortcuts, ...) are acknowledged and ignored.
+pub(crate) fn normalize_interaction(payload: &Value) -> Option<InboundEvent> {
+    let mut inbound = match payload.get("type").and_then(Value::as_str)? {
+        "block_actions" => block_actions(payload)?,
+        "view_submission" => view_submission(payload)?,
+        _ => return None,
+    };
+    inbound.user = str_field(payload, "user", "id");
+    inbound.trigger_id = payload
+        .get("trigger_id")
+        .and_then(Value::as_str)
+        .map(ToOwned::to_owned);
+    if let Some(team) = str_field(payload, "team", "id") {
+        inbound.metadata.insert("team".to_string(), team);
+    }
+    Some(inbound)
+}
+
+fn block_actions(payload: &Value) -> Option<InboundEvent> {
+    let actions: Vec<Value> = payload
+        .get("actions")?
+        .as_array()?
+        .iter()
+        .map(|action| {
+            json!({
+                "action_id": action.get("action_id"),
+                "block_id": action.get("block_id"),
+                "type": action.get("type"),
+                "value": element_value(action),
+            })
+        })
+        .collect();
+    let first = actions.first()?.clone();
+    let message = payload.get("message");
+    let ts = payload
+        .pointer("/container/message_ts")
+        .or_else(|| message.and_then(|message| message.get("ts")))
+        .and_then(Value::as_str)
+        .map(ToOwned::to_owned);
+    let mut action = json!({
+        "type": "block_actions",
+        "actions": actions,
+        "response_url": payload.get("response_url"),
+        "trigger_id": payload.get("trigger_id"),
+        "message_ts": ts,
+    });
+    if let Some(values) = payload.pointer("/state/values") {
+        action["state"] = state_values(values);
+    }
+    let mut metadata = BTreeMap::new();
+    if let Some(action_id) = first["action_id"].as_str() {
+        metadata.insert("action_id".to_string(), action_id.to_string());
+    }
+    if let Some(value) = first["value"].as_str() {
+        metadata.insert("action_value".to_string(), value.to_string());
+    }
+    if let Some(url) = payload.get("response_url").and_then(Value::as_str) {
+        metadata.insert("response_url".to_string(), url.to_string());
+    }
+    metadata.insert("action".to_string(), action.to_string());
+    Some(InboundEvent {
+        kind: "block_actions",
+        channel: str_field(payload, "channel", "id").or_else(|| {
+            payload
+                .pointer("/container/channel_id")
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned)
+        }),
+        ts,
+        thread_ts: message
+            .and_then(|message| message.get("thread_ts"))
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned),
+        metadata,
+        ..InboundEvent::default()
+    })
+}
+
+/// Submissions carry no channel; a `response_url` is only present when the
+/// modal has an input with `response_url_enabled`.
+fn view_submission(payload: &Value) -> Option<InboundEvent> {
+    let view = payload.get("view")?;
+    let response_url = payload
+        .pointer("/response_urls/0/response_url")
+        .and_then(Value::as_str);
+    let action = json!({
+        "type": "view_submission",
+        "view_id": view.get("id"),
+        "callback_id": view.get("callback_id"),
+        "private_metadata": view.get("private_metadata"),
+        "values": view
+            .pointer("/state/values")
+            .map(state_values)
+            .unwrap_or_else(|| json!({})),
+        "response_url": response_url,
+        "trigger_id": payload.get("trigger_id"),
+    });
+    let mut metadata = BTreeMap::new();
+    for key in ["id", "callback_id"] {
+        if let Some(value) = view.get(key).and_then(Value::as_str) {
+            metadata.insert(format!("view_{key}"), value.to_string());
+        }
+    }
+    if let Some(url) = response_url {
+        metadata.insert("response_url".to_string(), url.to_string());
+    }
+    metadata.insert("action".to_string(), action.to_string());
+    Some(InboundEvent {
+        kind: "view_submission",
+        channel: payload
+            .pointer("/response_urls/0/channel_id")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned),
+        metadata,
+        ..InboundEvent::default()
+    })
+}
+
+/// `state.values` (`{block_id: {action_id: element}}`) with each element
+/// reduced to its value.
+fn state_values(values: &Value) -> Value {
+    let Some(blocks) = values.as_object() else {
+        return json!({});
+    };
+    let reduced: Map<String, Value> = blocks
+        .iter()
+        .map(|(block_id, elements)| {
+            let elements: Map<String, Value> = elements
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(action_id, element)| (action_id.clone(), element_value(element)))
+                .collect();
+            (block_id.clone(), Value::Object(elements))
+        })
+        .collect();
+    Value::Object(reduced)
+}
+
+/// What the user picked or typed: a string, a list for multi-selects, or
+/// `null` when the element is empty.
+fn element_value(element: &Value) -> Value {
+    const SCALARS: &[&str] = &[
+        "value",
+        "selected_user",
+        "selected_conversation",
+        "selected_channel",
+        "selected_date",
+        "selected_time",
+        "selected_date_time",
+    ];
+    const LISTS: &[&str] = &[
+        "selected_users",
+        "selected_conversations",
+        "selected_channels",
+    ];
+    if let Some(value) = element.pointer("/selected_option/value") {
+        return value.clone();
+    }
+    if let Some(options) = element.get("selected_options").and_then(Value::as_array) {
+        return options
+            .iter()
+            .filter_map(|option| option.get("value").cloned())
+            .collect();
+    }
+    SCALARS
+        .iter()
+        .chain(LISTS)
+        .find_map(|key| element.get(*key).filter(|value| !value.is_null()).cloned())
+        .unwrap_or(Value::Null)
+}
+
+fn str_field(payload: &Value, object: &str, key: &str) -> Option<String> {
+    payload
+        .get(object)?
+        .get(key)?
+        .as_str()
+        .filter(|value| !value.is_empty())
+        .map(ToOwned::to_owned)
+}
+
+/// The modal behind a show-card button (see `block_kit`) and the trigger id
+/// that allows opening it; `None` for any other interaction.
+pub(crate) fn show_card_view(payload: &Value) -> Option<(&str, Value)> {
+    if payload.get("type").and_then(Value::as_str) != Some("block_actions") {
+        return None;
+    }
+    let action = payload.get("actions")?.as_array()?.iter().find(|action| {
+        action
+            .get("action_id")
+            .and_then(Value::as_str)
+            .is_some_and(|id| id.starts_with(SHOW_CARD_ACTION_PREFIX))
+    })?;
+    let view = serde_json::from_str::<Value>(action.get("value")?.as_str()?)
+        .ok()
+        .filter(|view| view.get("type").and_then(Value::as_str) == Some("modal"))?;
+    let trigger_id = payload.get("trigger_id")?.as_str()?;
+    Some((trigger_id, view))
+}
+
+/// `views.open`; the trigger id expires three seconds after the click, so
+/// this runs while ingest answers.
+pub(crate) fn open_view(
+    api_base: &str,
+    token: &str,
+    trigger_id: &str,
+    view: &Value,
+) -> Result<Value, ProviderError> {
+    let request = client::Request {
+        method: "POST".into(),
+        url: format!("{api_base}/views.open"),
+        headers: vec![
+            ("Content-Type".into(), "application/json".into()),
+            ("Authorization".into(), format!("Bearer {token}")),
+        ],
+        body: Some(json_bytes(&json!({"trigger_id": trigger_id, "view": view}))),
+    };
+    let resp = send_http(&request.method, &RetryPolicy::default(), || {
+        client::send(&request, None, None)
+    })
+    .result
+    .map_err(|err| ProviderError::transport(err.message))?;
+    slack_api_body(&resp).map_err(|(err, _)| err)
+}
+
+/// Empty 200: buttons need nothing more and a modal closes.
+pub(crate) fn empty_ack() -> HttpOutV1 {
+    HttpOutV1 {
+        status: 200,
+        headers: Vec::new(),
+        body_b64: String::new(),
+        events: Vec::new(),
+    }
+}
+
+/// Builds the ack for a `view_submission`: `errors` (keyed by block id)
+/// keeps the modal open with the messages shown, `update` and `push` take a
+/// `view`, `clear` closes the whole stack. Without `response_action` the ack
+/// is empty.
+pub(crate) fn handle_ack_interaction(input_json: &[u8]) -> Vec<u8> {
+    let parsed: Value = match serde_json::from_slice(input_json) {
+        Ok(value) => value,
+        Err(err) => {
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
+        }
+    };
+    match ack_body(&parsed) {
+        Ok(None) => super::http_out_v1_bytes(&empty_ack()),
+        Ok(Some(body)) => super::http_out_v1_bytes(&HttpOutV1 {
+            status: 200,
+            headers: vec![Header {
+                name: "Content-Type".to_string(),
+                value: "application/json".to_string(),
+            }],
+            body_b64: STANDARD.encode(json_bytes(&body)),
+            events: Vec::new(),
+        }),
+        Err(err) => error_bytes(err),
+    }
+}
+
+fn ack_body(parsed: &Value) -> Result<Option<Value>, ProviderError> {
+    let Some(action) = parsed.get("response_action").and_then(Value::as_str) else {
+        return Ok(None);
+    };
+    match action {
+        "errors" => {
+            let errors = parsed
+                .get("errors")
+                .and_then(Value::as_object)
+                .filter(|errors| !errors.is_empty())
+                .ok_or_else(|| {
+                    ProviderError::validation("errors must map block ids to messages")
+                })?;
+            if errors.values().any(|message| !message.is_string()) {
+                return Err(ProviderError::validation("error messages must be strings"));
+            }
+            Ok(Some(json!({"response_action": "errors", "errors": errors})))
+        }
+        "update" | "push" => {
+            let view = parsed
+                .get("view")
+                .filter(|view| view.is_object())
+                .ok_or_else(|| ProviderError::validation(format!("{action} requires a view")))?;
+            Ok(Some(json!({"response_action": action, "view": view})))
+        }
+        "clear" => Ok(Some(json!({"response_action": "clear"}))),
+        other => Err(ProviderError::validation(format!(
+            "unsupported response_action: {other}"
+        ))),
+    }
+}
+
+/// Where a send goes instead of `chat.postMessage`: an explicit
+/// `response_url`, or with `delivery: "response_url"` the one ingest put in
+/// the envelope metadata of an interaction or slash command.
+pub(crate) fn response_url(parsed: &Value) -> Result<Option<&str>, ProviderError> {
+    let explicit = non_empty(parsed.get("response_url"));
+    match parsed.get("delivery").and_then(Value::as_str) {
+        None | Some("chat") => Ok(explicit),
+        Some("response_url") => explicit
+            .or_else(|| non_empty(parsed.pointer("/metadata/response_url")))
+            .map(Some)
+            .ok_or_else(|| ProviderError::validation("delivery response_url needs a response_url")),
+        Some(other) => Err(ProviderError::validation(format!(
+            "unsupported delivery: {other}"
+        ))),
+    }
+}
+
+/// Posts `text` (and `rich` blocks) to an interaction's `response_url`. No
+/// token is needed; the url is valid for 30 minutes and five uses.
+/// `response_type` picks `in_channel` or `ephemeral`, `replace_original`
+/// edits the message the interaction came from.
+pub(crate) fn send_to_response_url(parsed: &Value, url: &str, is_reply: bool) -> Vec<u8> {
+    if !is_response_url(url) {
+        return error_bytes(ProviderError::validation(format!(
+            "response_url must be an https://{RESPONSE_URL_HOST} url"
+        )));
+    }
+    let Some(text) = parsed
+        .get("text")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+    else {
+        return error_bytes(ProviderError::validation("text required"));
+    };
+    let text = render_markdown(text, MarkdownDialect::SlackMrkdwn);
+    if text.chars().count() > MAX_TEXT_LEN {
+        return error_bytes(ProviderError::payload_too_large(format!(
+            "text exceeds {MAX_TEXT_LEN} characters"
+        )));
+    }
+    let mut payload = json!({"text": text});
+    if let Some(kind) = parsed.get("response_type").and_then(Value::as_str) {
+        if !RESPONSE_TYPES.contains(&kind) {
+            return error_bytes(ProviderError::validation(format!(
+                "unsupported response_type: {kind}"
+            )));
+        }
+        payload["response_type"] = json!(kind);
+    }
+    if let Some(replace) = parsed.get("replace_original").and_then(Value::as_bool) {
+        payload["replace_original"] = json!(replace);
+    }
+    if is_reply
+        && let Some(thread) = parsed
+            .get("thread_id")
+            .or_else(|| parsed.get("reply_to_id"))
+            .and_then(Value::as_str)
+    {
+        payload["thread_ts"] = json!(thread);
+    }
+    let (format, blocks) = parse_blocks(parsed);
+    if format.as_deref() == Some("slack_blocks")
+        && let Some(blocks) = blocks
+    {
+        payload["blocks"] = blocks;
+    }
+
+    let request = client::Request {
+        method: "POST".into(),
+        url: url.to_string(),
+        headers: vec![("Content-Type".into(), "application/json".into())],
+        body: Some(json_bytes(&payload)),
+    };
+    let outcome = send_http(&request.method, &RetryPolicy::from_input(parsed), || {
+        client::send(&request, None, None)
+    });
+    let attempts = outcome.attempts;
+    let resp = match outcome.result {
+        Ok(resp) => resp,
+        Err(err) => return error_bytes_after(ProviderError::transport(err.message), attempts),
+    };
+    if resp.status < 200 || resp.status >= 300 {
+        return error_bytes_after(slack_status_error(&resp), attempts);
+    }
+    json_bytes(&json!({
+        "ok": true,
+        "status": if is_reply { "replied" } else { "sent" },
+        "provider_type": PROVIDER_TYPE,
+        "delivery": "response_url",
+        "message_id": Value::Null,
+        "attempts": attempts,
+    }))
+}
+
+fn non_empty(value: Option<&Value>) -> Option<&str> {
+    value
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+}
+
+fn is_response_url(url: &str) -> bool {
+    url.strip_prefix("https://")
+        .and_then(|rest| rest.split(['/', '?', '#']).next())
+        .is_some_and(|host| host.eq_ignore_ascii_case(RESPONSE_URL_HOST))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn form_payload_is_decoded() {
+        let body = b"payload=%7B%22type%22%3A%22block_actions%22%7D";
+        assert_eq!(
+            interaction_payload(body),
+            Some(json!({"type": "block_actions"}))
+        );
+        assert_eq!(interaction_payload(br#"{"type":"event_callback"}"#), None);
+    }
+
+    #[test]
+    fn block_actions_carry_selected_values() {
+        let payload = json!({
+            "type": "block_actions",
+            "user": {"id": "U1"},
+            "team": {"id": "T1"},
+            "trigger_id": "13345224609.8534564800.6f8ab1f53e13d0cd15f96106292d5536",
+            "response_url": "https://hooks.slack.com/actions/T1/1/abc",
+            "channel": {"id": "C1"},
+            "container": {"type": "message", "message_ts": "1.2", "channel_id": "C1"},
+            "message": {"ts": "1.2", "thread_ts": "1.1"},
+            "actions": [
+                {"action_id": "approve", "block_id": "b1", "type": "button", "value": "yes"},
+                {"action_id": "pick", "block_id": "b2", "type": "multi_static_select",
+                 "selected_options": [{"value": "a"}, {"value": "b"}]}
+            ]
+        });
+        let inbound = normalize_interaction(&payload).expect("interaction");
+        assert_eq!(inbound.kind, "block_actions");
+        assert_eq!(inbound.channel.as_deref(), Some("C1"));
+        assert_eq!(inbound.thread_ts.as_deref(), Some("1.1"));
+        assert_eq!(inbound.metadata["action_id"], "approve");
+        assert_eq!(inbound.metadata["action_value"], "yes");
+        let action: Value = serde_json::from_str(&inbound.metadata["action"]).expect("action");
+        assert_eq!(action["actions"][1]["value"], json!(["a", "b"]));
+        assert_eq!(action["message_ts"], json!("1.2"));
+    }
+
+    #[test]
+    fn view_submission_reduces_state_values() {
+        let payload = json!({
+            "type": "view_submission",
+            "user": {"id": "U1"},
+            "view": {
+                "id": "V1",
+                "callback_id": "feedback",
+                "state": {"values": {
+                    "b1": {"comment": {"type": "plain_text_input", "value": "great"}},
+                    "b2": {"rating": {"type": "static_select", "selected_option": {"value": "5"}}},
+                    "b3": {"when": {"type": "datepicker", "selected_date": null}}
+                }}
+            }
+        });
+        let inbound = normalize_interaction(&payload).expect("interaction");
+        assert_eq!(inbound.metadata["view_callback_id"], "feedback");
+        let action: Value = serde_json::from_str(&inbound.metadata["action"]).expect("action");
+        assert_eq!(
+            action["values"],
+            json!({"b1": {"comment": "great"}, "b2": {"rating": "5"}, "b3": {"when": null}})
+        );
+        assert!(normalize_interaction(&json!({"type": "view_closed"})).is_none());
+    }
+
+    #[test]
+    fn show_card_buttons_carry_their_modal() {
+        let view =
+            json!({"type": "modal", "title": {"type": "plain_text", "text": "More"}, "blocks": []});
+        let payload = json!({
+            "type": "block_actions",
+            "trigger_id": "t-1",
+            "actions": [{
+                "action_id": format!("{SHOW_CARD_ACTION_PREFIX}more"),
+                "type": "button",
+                "value": view.to_string(),
+            }]
+        });
+        assert_eq!(show_card_view(&payload), Some(("t-1", view)));
+        let plain = json!({
+            "type": "block_actions",
+            "trigger_id": "t-1",
+            "actions": [{"action_id": "approve", "type": "button", "value": "{}"}]
+        });
+        assert_eq!(show_card_view(&plain), None);
+    }
+
+    #[test]
+    fn ack_bodies_follow_response_action() {
+        assert_eq!(ack_body(&json!({})), Ok(None));
+        assert_eq!(
+            ack_body(&json!({"response_action": "errors", "errors": {"b1": "Required"}})),
+            Ok(Some(
+                json!({"response_action": "errors", "errors": {"b1": "Required"}})
+            ))
+        );
+        assert!(ack_body(&json!({"response_action": "errors", "errors": {}})).is_err());
+        assert!(ack_body(&json!({"response_action": "push"})).is_err());
+        assert!(ack_body(&json!({"response_action": "close"})).is_err());
+    }
+
+    #[test]
+    fn response_url_delivery_falls_back_to_metadata() {
+        let url = "https://hooks.slack.com/commands/T1/1/abc";
+        assert_eq!(response_url(&json!({"text": "hi"})), Ok(None));
+        assert_eq!(response_url(&json!({"response_url": url})), Ok(Some(url)));
+        assert_eq!(
+            response_url(&json!({"delivery": "response_url", "metadata": {"response_url": url}})),
+            Ok(Some(url))
+        );
+        assert!(response_url(&json!({"delivery": "response_url"})).is_err());
+        assert!(response_url(&json!({"delivery": "email"})).is_err());
+    }
+
+    #[test]
+    fn response_urls_must_be_slack_hooks() {
+        assert!(is_response_url("https://hooks.slack.com/actions/T1/1/abc"));
+        assert!(!is_response_url("http://hooks.slack.com/actions/T1/1/abc"));
+        assert!(!is_response_url("https://hooks.slack.com.evil.example/a"));
+    }
+}
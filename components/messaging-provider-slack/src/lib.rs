@@ -1,19 +1,39 @@
 use base64::{Engine as _, engine::general_purpose::STANDARD};
 use greentic_types::messaging::universal_dto::{
-    EncodeInV1, HttpInV1, HttpOutV1, ProviderPayloadV1, RenderPlanInV1, RenderPlanOutV1,
+    EncodeInV1, Header, HttpInV1, HttpOutV1, ProviderPayloadV1, RenderPlanInV1, RenderPlanOutV1,
     SendPayloadInV1, SendPayloadResultV1,
 };
 use greentic_types::{
-    Actor, ChannelMessageEnvelope, Destination, EnvId, MessageMetadata, TenantCtx, TenantId,
+    ChannelMessageEnvelope, Destination, EnvId, MessageMetadata, TenantCtx, TenantId,
 };
 use provider_common::component_v0_6::{
     DescribePayload, I18nText, OperationDescriptor, QaQuestionSpec, QaSpec, RedactionRule,
     SchemaField, SchemaIr, canonical_cbor_bytes, decode_cbor, schema_hash,
 };
+use provider_common::formatting::{MarkdownDialect, render as render_markdown};
+use provider_common::http_retry::{
+    RetryPolicy, RetryableResponse, find_header, retry_after, send_http, send_payload_result_bytes,
+};
+use provider_common::ingress_dedup::{DedupWindow, duplicate_ack_body};
+use provider_common::message_split::{SplitOptions, split_markdown};
+use provider_common::outbound_idempotency::send_idempotent;
+use provider_common::state::{HostStateAdapter, key_fragment};
+use provider_common::webhook_auth::{
+    self, SLACK_SIGNATURE_HEADER, SLACK_TIMESTAMP_HEADER, TimestampWindow, request_header,
+};
+use provider_common::{ProviderError, ProviderErrorDetail};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use std::collections::BTreeMap;
 
+mod block_kit;
+mod commands;
+mod events;
+mod files;
+mod interactivity;
+mod message_ops;
+mod uploads;
+
 mod bindings {
     wit_bindgen::generate!({
         path: "wit/messaging-provider-slack",
@@ -24,12 +44,16 @@ mod bindings {
 
 use bindings::greentic::http::http_client as client;
 use bindings::greentic::secrets_store::secrets_store;
+use bindings::greentic::state::state_store;
 
 const PROVIDER_ID: &str = "messaging-provider-slack";
 const PROVIDER_TYPE: &str = "messaging.slack.api";
+/// `chat.postMessage` truncates text beyond this length.
+const MAX_TEXT_LEN: usize = 40_000;
 const WORLD_ID: &str = "component-v0-v6-v0";
 const DEFAULT_API_BASE: &str = "https://slack.com/api";
 const DEFAULT_BOT_TOKEN_KEY: &str = "SLACK_BOT_TOKEN";
+const SIGNING_SECRET_KEY: &str = "SLACK_SIGNING_SECRET";
 
 const I18N_KEYS: &[&str] = &[
     "slack.op.run.title",
@@ -46,6 +70,20 @@ const I18N_KEYS: &[&str] = &[
     "slack.op.encode.description",
     "slack.op.send_payload.title",
     "slack.op.send_payload.description",
+    "slack.op.fetch_attachment.title",
+    "slack.op.fetch_attachment.description",
+    "slack.op.ack_interaction.title",
+    "slack.op.ack_interaction.description",
+    "slack.op.update_message.title",
+    "slack.op.update_message.description",
+    "slack.op.delete_message.title",
+    "slack.op.delete_message.description",
+    "slack.op.add_reaction.title",
+    "slack.op.add_reaction.description",
+    "slack.op.send_ephemeral.title",
+    "slack.op.send_ephemeral.description",
+    "slack.op.schedule_message.title",
+    "slack.op.schedule_message.description",
     "slack.schema.input.title",
     "slack.schema.input.description",
     "slack.schema.input.message.title",
@@ -68,6 +106,8 @@ const I18N_KEYS: &[&str] = &[
     "slack.schema.config.api_base_url.description",
     "slack.schema.config.bot_token.title",
     "slack.schema.config.bot_token.description",
+    "slack.schema.config.slash_command_ack.title",
+    "slack.schema.config.slash_command_ack.description",
     "slack.qa.default.title",
     "slack.qa.setup.title",
     "slack.qa.upgrade.title",
@@ -90,6 +130,9 @@ struct ProviderConfig {
     #[serde(default)]
     api_base_url: Option<String>,
     bot_token: String,
+    /// Shown only to the user right after a slash command.
+    #[serde(default)]
+    slash_command_ack: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -108,6 +151,8 @@ struct ProviderConfigOut {
     public_base_url: String,
     api_base_url: String,
     bot_token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    slash_command_ack: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -141,12 +186,27 @@ impl bindings::exports::greentic::component::runtime::Guest for Component {
 
         let input_json = serde_json::to_vec(&input_value).unwrap_or_default();
         let output_json = match op.as_str() {
-            "run" | "send" => handle_send(&input_json, false),
-            "reply" => handle_send(&input_json, true),
+            "run" | "send" => {
+                send_idempotent(PROVIDER_ID, &mut host_state(), &input_json, |input| {
+                    handle_send(input, false)
+                })
+            }
+            "reply" => send_idempotent(PROVIDER_ID, &mut host_state(), &input_json, |input| {
+                handle_send(input, true)
+            }),
             "ingest_http" => ingest_http(&input_json),
             "render_plan" => render_plan(&input_json),
             "encode" => encode_op(&input_json),
-            "send_payload" => send_payload(&input_json),
+            "send_payload" => {
+                send_idempotent(PROVIDER_ID, &mut host_state(), &input_json, send_payload)
+            }
+            "fetch_attachment" => files::handle_fetch_attachment(&input_json),
+            "ack_interaction" => interactivity::handle_ack_interaction(&input_json),
+            "update_message" => message_ops::handle_update_message(&input_json),
+            "delete_message" => message_ops::handle_delete_message(&input_json),
+            "add_reaction" => message_ops::handle_add_reaction(&input_json),
+            "send_ephemeral" => message_ops::handle_send_ephemeral(&input_json),
+            "schedule_message" => message_ops::handle_schedule_message(&input_json),
             other => json_bytes(&json!({"ok": false, "error": format!("unsupported op: {other}")})),
         };
 
@@ -218,6 +278,8 @@ impl bindings::exports::greentic::component::qa::Guest for Component {
                 merged.api_base_url = DEFAULT_API_BASE.to_string();
             }
             merged.bot_token = string_or_default(&answers, "bot_token", &merged.bot_token);
+            merged.slash_command_ack = optional_string_from(&answers, "slash_command_ack")
+                .or(merged.slash_command_ack.clone());
         }
 
         if mode == bindings::exports::greentic::component::qa::Mode::Upgrade {
@@ -241,6 +303,9 @@ impl bindings::exports::greentic::component::qa::Guest for Component {
             if has("bot_token") {
                 merged.bot_token = string_or_default(&answers, "bot_token", &merged.bot_token);
             }
+            if has("slash_command_ack") {
+                merged.slash_command_ack = optional_string_from(&answers, "slash_command_ack");
+            }
             if merged.api_base_url.trim().is_empty() {
                 merged.api_base_url = DEFAULT_API_BASE.to_string();
             }
@@ -305,6 +370,41 @@ impl bindings::exports::greentic::component::component_i18n::Guest for Component
                 "slack.op.send_payload.description",
                 "Send encoded payload to Slack API",
             ),
+            ("slack.op.fetch_attachment.title", "Fetch Attachment"),
+            (
+                "slack.op.fetch_attachment.description",
+                "Download a file shared in Slack",
+            ),
+            ("slack.op.ack_interaction.title", "Acknowledge Interaction"),
+            (
+                "slack.op.ack_interaction.description",
+                "Build the HTTP answer to a Slack modal submission",
+            ),
+            ("slack.op.update_message.title", "Update Message"),
+            (
+                "slack.op.update_message.description",
+                "Replace the text or blocks of a sent Slack message",
+            ),
+            ("slack.op.delete_message.title", "Delete Message"),
+            (
+                "slack.op.delete_message.description",
+                "Delete a message the bot sent",
+            ),
+            ("slack.op.add_reaction.title", "Add Reaction"),
+            (
+                "slack.op.add_reaction.description",
+                "Add an emoji reaction to a Slack message",
+            ),
+            ("slack.op.send_ephemeral.title", "Send Ephemeral"),
+            (
+                "slack.op.send_ephemeral.description",
+                "Send a message only one user in the channel sees",
+            ),
+            ("slack.op.schedule_message.title", "Schedule Message"),
+            (
+                "slack.op.schedule_message.description",
+                "Schedule a Slack message for a later time",
+            ),
             ("slack.schema.input.title", "Slack input"),
             (
                 "slack.schema.input.description",
@@ -363,6 +463,14 @@ impl bindings::exports::greentic::component::component_i18n::Guest for Component
                 "slack.schema.config.bot_token.description",
                 "Bot token for Slack API calls",
             ),
+            (
+                "slack.schema.config.slash_command_ack.title",
+                "Slash command ack",
+            ),
+            (
+                "slack.schema.config.slash_command_ack.description",
+                "Ephemeral text sent right after a slash command",
+            ),
             ("slack.qa.default.title", "Default"),
             ("slack.qa.setup.title", "Setup"),
             ("slack.qa.upgrade.title", "Upgrade"),
@@ -400,12 +508,25 @@ impl bindings::exports::greentic::provider_schema_core::schema_core_api::Guest f
     fn invoke(op: String, input_json: Vec<u8>) -> Vec<u8> {
         let op = if op == "run" { "send" } else { op.as_str() };
         match op {
-            "send" => handle_send(&input_json, false),
-            "reply" => handle_send(&input_json, true),
+            "send" => send_idempotent(PROVIDER_ID, &mut host_state(), &input_json, |input| {
+                handle_send(input, false)
+            }),
+            "reply" => send_idempotent(PROVIDER_ID, &mut host_state(), &input_json, |input| {
+                handle_send(input, true)
+            }),
             "ingest_http" => ingest_http(&input_json),
             "render_plan" => render_plan(&input_json),
             "encode" => encode_op(&input_json),
-            "send_payload" => send_payload(&input_json),
+            "send_payload" => {
+                send_idempotent(PROVIDER_ID, &mut host_state(), &input_json, send_payload)
+            }
+            "fetch_attachment" => files::handle_fetch_attachment(&input_json),
+            "ack_interaction" => interactivity::handle_ack_interaction(&input_json),
+            "update_message" => message_ops::handle_update_message(&input_json),
+            "delete_message" => message_ops::handle_delete_message(&input_json),
+            "add_reaction" => message_ops::handle_add_reaction(&input_json),
+            "send_ephemeral" => message_ops::handle_send_ephemeral(&input_json),
+            "schedule_message" => message_ops::handle_schedule_message(&input_json),
             other => json_bytes(
                 &serde_json::json!({"ok": false, "error": format!("unsupported op: {other}")}),
             ),
@@ -451,6 +572,41 @@ fn build_describe_payload() -> DescribePayload {
                 "slack.op.send_payload.title",
                 "slack.op.send_payload.description",
             ),
+            op(
+                "fetch_attachment",
+                "slack.op.fetch_attachment.title",
+                "slack.op.fetch_attachment.description",
+            ),
+            op(
+                "ack_interaction",
+                "slack.op.ack_interaction.title",
+                "slack.op.ack_interaction.description",
+            ),
+            op(
+                "update_message",
+                "slack.op.update_message.title",
+                "slack.op.update_message.description",
+            ),
+            op(
+                "delete_message",
+                "slack.op.delete_message.title",
+                "slack.op.delete_message.description",
+            ),
+            op(
+                "add_reaction",
+                "slack.op.add_reaction.title",
+                "slack.op.add_reaction.description",
+            ),
+            op(
+                "send_ephemeral",
+                "slack.op.send_ephemeral.title",
+                "slack.op.send_ephemeral.description",
+            ),
+            op(
+                "schedule_message",
+                "slack.op.schedule_message.title",
+                "slack.op.schedule_message.description",
+            ),
         ],
         input_schema: input_schema.clone(),
         output_schema: output_schema.clone(),
@@ -669,6 +825,18 @@ fn config_schema() -> SchemaIr {
             },
         },
     );
+    fields.insert(
+        "slash_command_ack".to_string(),
+        SchemaField {
+            required: false,
+            schema: SchemaIr::String {
+                title: i18n("slack.schema.config.slash_command_ack.title"),
+                description: i18n("slack.schema.config.slash_command_ack.description"),
+                format: None,
+                secret: false,
+            },
+        },
+    );
 
     SchemaIr::Object {
         title: i18n("slack.schema.config.title"),
@@ -725,6 +893,7 @@ fn default_config_out() -> ProviderConfigOut {
         public_base_url: String::new(),
         api_base_url: DEFAULT_API_BASE.to_string(),
         bot_token: String::new(),
+        slash_command_ack: None,
     }
 }
 
@@ -754,40 +923,41 @@ fn handle_send(input_json: &[u8], is_reply: bool) -> Vec<u8> {
     let parsed: Value = match serde_json::from_slice(input_json) {
         Ok(val) => val,
         Err(err) => {
-            return json_bytes(&json!({"ok": false, "error": format!("invalid json: {err}")}));
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
         }
     };
 
     let cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
     if !cfg.enabled {
-        return json_bytes(&json!({"ok": false, "error": "provider disabled by config"}));
+        return error_bytes(ProviderError::config_invalid("provider disabled by config"));
+    }
+    match interactivity::response_url(&parsed) {
+        Ok(Some(url)) => return interactivity::send_to_response_url(&parsed, url, is_reply),
+        Ok(None) => {}
+        Err(err) => return error_bytes(err),
     }
 
     let envelope: ChannelMessageEnvelope = match serde_json::from_slice(input_json) {
         Ok(env) => env,
         Err(_) => match build_synthetic_envelope(&parsed, &cfg) {
             Ok(env) => env,
-            Err(message) => return json_bytes(&json!({"ok": false, "error": message})),
+            Err(message) => return error_bytes(ProviderError::validation(message)),
         },
     };
 
-    if !envelope.attachments.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "attachments not supported"}));
-    }
-
     let text = envelope
         .text
         .as_ref()
         .map(|value| value.trim())
         .filter(|value| !value.is_empty())
-        .map(ToOwned::to_owned);
-    let text = match text {
-        Some(value) => value,
-        None => return json_bytes(&json!({"ok": false, "error": "text required"})),
-    };
+        .map(ToOwned::to_owned)
+        .or_else(|| provider_common::extract_ac_text_summary(&envelope.metadata));
+    if text.is_none() && envelope.attachments.is_empty() {
+        return error_bytes(ProviderError::validation("text required"));
+    }
 
     let destination = envelope.to.first().cloned().or_else(|| {
         cfg.default_channel.clone().map(|channel| Destination {
@@ -797,20 +967,19 @@ fn handle_send(input_json: &[u8], is_reply: bool) -> Vec<u8> {
     });
     let destination = match destination {
         Some(dest) => dest,
-        None => return json_bytes(&json!({"ok": false, "error": "destination required"})),
+        None => return error_bytes(ProviderError::validation("destination required")),
     };
 
     let dest_id = destination.id.trim();
     if dest_id.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "destination id required"}));
+        return error_bytes(ProviderError::validation("destination id required"));
     }
     let dest_id = dest_id.to_string();
     let kind = destination.kind.as_deref().unwrap_or("channel");
     if kind != "channel" && kind != "user" && !kind.is_empty() {
-        return json_bytes(&json!({
-            "ok": false,
-            "error": format!("unsupported destination kind: {kind}")
-        }));
+        return error_bytes(ProviderError::validation(format!(
+            "unsupported destination kind: {kind}"
+        )));
     }
 
     let thread_ts = if is_reply {
@@ -830,69 +999,119 @@ fn handle_send(input_json: &[u8], is_reply: bool) -> Vec<u8> {
         .api_base_url
         .clone()
         .unwrap_or_else(|| DEFAULT_API_BASE.to_string());
-    let url = format!("{}/chat.postMessage", api_base);
-    let mut payload = json!({
-        "channel": dest_id,
-        "text": text,
-    });
-    if let Some(ts) = thread_ts {
-        payload
-            .as_object_mut()
-            .expect("payload object")
-            .insert("thread_ts".into(), Value::String(ts));
-    }
-    if format.as_deref() == Some("slack_blocks")
-        && let Some(b) = blocks
-    {
-        payload
-            .as_object_mut()
-            .expect("payload object")
-            .insert("blocks".into(), b);
+    if !envelope.attachments.is_empty() {
+        let target = uploads::UploadTarget {
+            api_base: &api_base,
+            token: &token,
+            channel: &dest_id,
+            thread_ts: thread_ts.as_deref(),
+        };
+        return uploads::send_with_attachments(
+            &target,
+            &envelope.attachments,
+            text.as_deref(),
+            &parsed,
+            is_reply,
+        );
     }
-
-    let request = client::Request {
-        method: "POST".into(),
-        url,
-        headers: vec![
-            ("Content-Type".into(), "application/json".into()),
-            ("Authorization".into(), format!("Bearer {token}")),
-        ],
-        body: Some(serde_json::to_vec(&payload).unwrap_or_else(|_| b"{}".to_vec())),
+    let text = text.unwrap_or_default();
+    let url = format!("{}/chat.postMessage", api_base);
+    let dialect = MarkdownDialect::SlackMrkdwn;
+    let chunks = split_markdown(
+        &text,
+        dialect,
+        &SplitOptions::from_metadata(MAX_TEXT_LEN, &envelope.metadata),
+    );
+    // Caller-provided Block Kit wins over a converted Adaptive Card.
+    let mut blocks = match format.as_deref() {
+        Some("slack_blocks") => blocks,
+        _ => block_kit::from_metadata(&envelope.metadata)
+            .filter(|conversion| !conversion.blocks.is_empty())
+            .map(|conversion| Value::Array(conversion.blocks)),
     };
-
-    let resp = match client::send(&request, None, None) {
-        Ok(resp) => resp,
-        Err(err) => {
-            return json_bytes(
-                &json!({"ok": false, "error": format!("transport error: {}", err.message)}),
-            );
+    let policy = RetryPolicy::from_input(&parsed);
+    let mut attempts = 0;
+    let mut message_ids = Vec::new();
+    let mut provider_message_ids = Vec::new();
+    let mut chat_message_ids = Vec::new();
+    let mut responses = Vec::new();
+    let mut diagnostics = Vec::new();
+    for chunk in &chunks {
+        let mut payload = json!({
+            "channel": dest_id,
+            "text": render_markdown(chunk, dialect),
+        });
+        let payload_obj = payload.as_object_mut().expect("payload object");
+        if let Some(ts) = &thread_ts {
+            payload_obj.insert("thread_ts".into(), Value::String(ts.clone()));
+        }
+        if let Some(b) = blocks.take() {
+            payload_obj.insert("blocks".into(), b);
         }
-    };
 
-    if resp.status < 200 || resp.status >= 300 {
-        return json_bytes(
-            &json!({"ok": false, "error": format!("slack returned status {}", resp.status)}),
-        );
-    }
+        let request = client::Request {
+            method: "POST".into(),
+            url: url.clone(),
+            headers: vec![
+                ("Content-Type".into(), "application/json".into()),
+                ("Authorization".into(), format!("Bearer {token}")),
+            ],
+            body: Some(serde_json::to_vec(&payload).unwrap_or_else(|_| b"{}".to_vec())),
+        };
 
-    let body = resp.body.unwrap_or_default();
-    let body_json: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
-    let ts = body_json
-        .get("ts")
-        .or_else(|| body_json.get("message").and_then(|m| m.get("ts")))
-        .and_then(|v| v.as_str())
-        .unwrap_or("pending-ts")
-        .to_string();
-    let provider_message_id = format!("slack:{ts}");
+        let outcome = send_http(&request.method, &policy, || {
+            client::send(&request, None, None)
+        });
+        attempts += outcome.attempts;
+        let resp = match outcome.result {
+            Ok(resp) => resp,
+            Err(err) => {
+                return error_bytes_after_parts(
+                    ProviderError::transport(err.message),
+                    attempts,
+                    &provider_message_ids,
+                    &diagnostics,
+                );
+            }
+        };
+
+        let body_json = match slack_api_body(&resp) {
+            Ok(body) => body,
+            Err((err, failure_diagnostics)) => {
+                diagnostics.extend(failure_diagnostics);
+                return error_bytes_after_parts(err, attempts, &provider_message_ids, &diagnostics);
+            }
+        };
+        diagnostics.extend(slack_diagnostics(&body_json));
+        if let Some(ts) = body_json
+            .get("ts")
+            .or_else(|| body_json.get("message").and_then(|m| m.get("ts")))
+            .and_then(|v| v.as_str())
+        {
+            let channel = body_json
+                .get("channel")
+                .and_then(Value::as_str)
+                .unwrap_or(&dest_id);
+            provider_message_ids.push(format!("slack:{ts}"));
+            chat_message_ids.push(chat_message_id(channel, ts));
+            message_ids.push(ts.to_string());
+        }
+        responses.push(body_json);
+    }
 
     let result = json!({
         "ok": true,
         "status": if is_reply {"replied"} else {"sent"},
         "provider_type": PROVIDER_TYPE,
         "public_base_url": cfg.public_base_url,
-        "message_id": ts,
-        "provider_message_id": provider_message_id,
-        "response": body_json
+        "message_id": message_ids.first(),
+        "provider_message_id": provider_message_ids.first(),
+        "provider_message_ids": provider_message_ids,
+        "chat_message_id": chat_message_ids.first(),
+        "chat_message_ids": chat_message_ids,
+        "attempts": attempts,
+        "diagnostics": diagnostics,
+        "response": responses.first()
     });
     json_bytes(&result)
 }
@@ -915,16 +1134,17 @@ fn parse_blocks(parsed: &Value) -> (Option<String>, Option<Value>) {
 }
 
 #[cfg(test)]
-fn parse_config_bytes(bytes: &[u8]) -> Result<ProviderConfig, String> {
-    serde_json::from_slice::<ProviderConfig>(bytes).map_err(|e| format!("invalid config: {e}"))
+fn parse_config_bytes(bytes: &[u8]) -> Result<ProviderConfig, ProviderError> {
+    serde_json::from_slice::<ProviderConfig>(bytes)
+        .map_err(|e| ProviderError::config_invalid(e.to_string()))
 }
 
-fn parse_config_value(val: &Value) -> Result<ProviderConfig, String> {
+fn parse_config_value(val: &Value) -> Result<ProviderConfig, ProviderError> {
     serde_json::from_value::<ProviderConfig>(val.clone())
-        .map_err(|e| format!("invalid config: {e}"))
+        .map_err(|e| ProviderError::config_invalid(e.to_string()))
 }
 
-fn load_config(input: &Value) -> Result<ProviderConfig, String> {
+fn load_config(input: &Value) -> Result<ProviderConfig, ProviderError> {
     if let Some(cfg) = input.get("config") {
         return parse_config_value(cfg);
     }
@@ -936,6 +1156,7 @@ fn load_config(input: &Value) -> Result<ProviderConfig, String> {
         "public_base_url",
         "api_base_url",
         "bot_token",
+        "slash_command_ack",
     ] {
         if let Some(v) = input.get(key) {
             partial.insert(key.to_string(), v.clone());
@@ -945,7 +1166,9 @@ fn load_config(input: &Value) -> Result<ProviderConfig, String> {
         return parse_config_value(&Value::Object(partial));
     }
 
-    Err("missing config: expected `config` or top-level config fields".to_string())
+    Err(ProviderError::config_invalid(
+        "missing config: expected `config` or top-level config fields",
+    ))
 }
 
 fn build_synthetic_envelope(
@@ -1033,28 +1256,65 @@ fn ingest_http(input_json: &[u8]) -> Vec<u8> {
         Ok(bytes) => bytes,
         Err(err) => return http_out_error(400, &format!("invalid body encoding: {err}")),
     };
+    if let Err(out) = verify_webhook(&request, &body_bytes) {
+        return out;
+    }
+    if let Some(payload) = interactivity::interaction_payload(&body_bytes) {
+        return ingest_interaction(&payload, request.config.as_ref());
+    }
+    if let Some(command) = commands::slash_command(&body_bytes) {
+        let ack_text = request
+            .config
+            .as_ref()
+            .and_then(|config| parse_config_value(config).ok())
+            .and_then(|cfg| cfg.slash_command_ack);
+        let mut out = commands::command_ack(ack_text.as_deref());
+        out.events.push(events::build_slack_envelope(command));
+        return http_out_v1_bytes(&out);
+    }
     let body_val: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
-    let payload = body_val.get("body").cloned().unwrap_or(Value::Null);
-    let text = payload
-        .get("text")
-        .and_then(Value::as_str)
-        .unwrap_or("")
-        .to_string();
-    let channel = payload
-        .get("channel")
-        .and_then(Value::as_str)
-        .map(|s| s.to_string());
-    let sender = payload
-        .get("user")
-        .or_else(|| payload.get("user_id"))
-        .and_then(Value::as_str)
-        .map(|s| s.to_string());
-    let envelope = build_slack_envelope(text, channel.clone(), sender);
+    if let Some(challenge) = events::url_verification_challenge(&body_val) {
+        let out = HttpOutV1 {
+            status: 200,
+            headers: vec![Header {
+                name: "Content-Type".to_string(),
+                value: "text/plain".to_string(),
+            }],
+            body_b64: STANDARD.encode(challenge.as_bytes()),
+            events: Vec::new(),
+        };
+        return http_out_v1_bytes(&out);
+    }
+    // The key is recorded once the delivery normalized, so a redelivery of one
+    // that failed part-way is processed again.
+    let dedup = dedup_key(&body_val);
+    let window = DedupWindow::default();
+    let now = webhook_auth::now_unix_secs();
+    if let Some(key) = &dedup
+        && window.seen(&mut host_state(), PROVIDER_ID, key, now)
+    {
+        return duplicate_ack(key);
+    }
+    let Some(event) = events::normalize_event(&body_val) else {
+        let ignored = json!({"ok": true, "ignored": true, "event": body_val});
+        let out = HttpOutV1 {
+            status: 200,
+            headers: Vec::new(),
+            body_b64: STANDARD.encode(json_bytes(&ignored)),
+            events: Vec::new(),
+        };
+        return http_out_v1_bytes(&out);
+    };
+    if let Some(key) = &dedup {
+        window.record(&mut host_state(), PROVIDER_ID, key, now);
+    }
     let normalized = json!({
         "ok": true,
         "event": body_val,
-        "channel": channel,
+        "event_kind": event.kind,
+        "channel": event.channel,
     });
+    let envelope = events::build_slack_envelope(event);
     let normalized_bytes = serde_json::to_vec(&normalized).unwrap_or_else(|_| b"{}".to_vec());
     let out = HttpOutV1 {
         status: 200,
@@ -1065,6 +1325,45 @@ fn ingest_http(input_json: &[u8]) -> Vec<u8> {
     http_out_v1_bytes(&out)
 }
 
+/// Interactions are acked with an empty 200 so Slack's three-second deadline
+/// holds whatever the flow does; modal answers go through `ack_interaction`.
+/// Show-card buttons of converted Adaptive Cards open their modal here, as
+/// the trigger id would expire before the flow could.
+fn ingest_interaction(payload: &Value, config: Option<&Value>) -> Vec<u8> {
+    let mut out = interactivity::empty_ack();
+    let Some(mut event) = interactivity::normalize_interaction(payload) else {
+        return http_out_v1_bytes(&out);
+    };
+    if let Some((trigger_id, view)) = interactivity::show_card_view(payload) {
+        let opened = config
+            .ok_or_else(|| ProviderError::config_invalid("config required to open a modal"))
+            .and_then(parse_config_value)
+            .and_then(|cfg| {
+                let api_base = cfg
+                    .api_base_url
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_API_BASE.to_string());
+                interactivity::open_view(&api_base, &resolve_bot_token(&cfg), trigger_id, &view)
+            });
+        match opened {
+            Ok(body) => {
+                if let Some(view_id) = body.pointer("/view/id").and_then(Value::as_str) {
+                    event
+                        .metadata
+                        .insert("view_id".to_string(), view_id.to_string());
+                }
+            }
+            Err(err) => {
+                event
+                    .metadata
+                    .insert("modal_error".to_string(), err.to_string());
+            }
+        }
+    }
+    out.events.push(events::build_slack_envelope(event));
+    http_out_v1_bytes(&out)
+}
+
 fn render_plan(input_json: &[u8]) -> Vec<u8> {
     let plan_in = match serde_json::from_slice::<RenderPlanInV1>(input_json) {
         Ok(value) => value,
@@ -1080,21 +1379,26 @@ fn render_plan(input_json: &[u8]) -> Vec<u8> {
                 .filter(|t| !t.trim().is_empty())
         })
         .unwrap_or_else(|| "slack message".to_string());
-    let mut warnings: Vec<Value> = Vec::new();
-    if plan_in.message.metadata.contains_key("adaptive_card") {
-        warnings
-            .push(json!({"code": "adaptive_cards_not_supported", "message": null, "path": null}));
-    }
+    let conversion = block_kit::from_metadata(&plan_in.message.metadata);
+    let tier = match &conversion {
+        Some(conversion) if conversion.blocks.is_empty() => "TierD",
+        Some(conversion) if conversion.is_lossless() => "TierA",
+        Some(_) => "TierB",
+        None => "TierD",
+    };
+    let (actions, warnings) = conversion
+        .map(|conversion| (conversion.actions, conversion.warnings))
+        .unwrap_or_default();
     let plan_obj = json!({
-        "tier": "TierD",
+        "tier": tier,
         "summary_text": summary,
-        "actions": [],
+        "actions": actions,
         "attachments": [],
         "warnings": warnings,
         "debug": plan_in.metadata,
     });
     let plan_json =
-        serde_json::to_string(&plan_obj).unwrap_or_else(|_| "{\"tier\":\"TierD\"}".to_string());
+        serde_json::to_string(&plan_obj).unwrap_or_else(|_| format!("{{\"tier\":\"{tier}\"}}"));
     let plan_out = RenderPlanOutV1 { plan_json };
     json_bytes(&json!({"ok": true, "plan": plan_out}))
 }
@@ -1118,12 +1422,18 @@ fn encode_op(input_json: &[u8]) -> Vec<u8> {
         .text
         .clone()
         .filter(|t| !t.trim().is_empty())
+        .or_else(|| provider_common::extract_ac_text_summary(&encode_in.message.metadata))
         .unwrap_or_else(|| "slack universal payload".to_string());
     let url = format!("{}/chat.postMessage", DEFAULT_API_BASE);
-    let body = json!({
+    let mut body = json!({
         "channel": channel,
         "text": text,
     });
+    if let Some(conversion) = block_kit::from_metadata(&encode_in.message.metadata)
+        && !conversion.blocks.is_empty()
+    {
+        body["blocks"] = Value::Array(conversion.blocks);
+    }
     let body_bytes = serde_json::to_vec(&body).unwrap_or_else(|_| b"{}".to_vec());
     let mut metadata = BTreeMap::new();
     metadata.insert("url".to_string(), Value::String(url));
@@ -1141,11 +1451,13 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
     let send_in = match serde_json::from_slice::<SendPayloadInV1>(input_json) {
         Ok(value) => value,
         Err(err) => {
-            return send_payload_error(&format!("invalid send_payload input: {err}"), false);
+            return send_payload_error(ProviderError::validation(format!(
+                "invalid send_payload input: {err}"
+            )));
         }
     };
     if send_in.provider_type != PROVIDER_TYPE {
-        return send_payload_error("provider type mismatch", false);
+        return send_payload_error(ProviderError::validation("provider type mismatch"));
     }
     let ProviderPayloadV1 {
         content_type,
@@ -1157,11 +1469,15 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
     let method = metadata_string(&metadata, "method").unwrap_or_else(|| "POST".to_string());
     let body_bytes = match STANDARD.decode(&body_b64) {
         Ok(bytes) => bytes,
-        Err(err) => return send_payload_error(&format!("payload decode failed: {err}"), false),
+        Err(err) => {
+            return send_payload_error(ProviderError::validation(format!(
+                "payload decode failed: {err}"
+            )));
+        }
     };
     let token = match get_secret_string(DEFAULT_BOT_TOKEN_KEY) {
         Ok(value) => value,
-        Err(err) => return send_payload_error(&err, false),
+        Err(err) => return send_payload_error(err),
     };
     let request = client::Request {
         method,
@@ -1172,19 +1488,37 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
         ],
         body: Some(body_bytes),
     };
-    let resp = match client::send(&request, None, None) {
+    let policy = serde_json::from_slice::<Value>(input_json)
+        .map(|input| RetryPolicy::from_input(&input))
+        .unwrap_or_default();
+    let outcome = send_http(&request.method, &policy, || {
+        client::send(&request, None, None)
+    });
+    let attempts = outcome.attempts;
+    let resp = match outcome.result {
         Ok(value) => value,
         Err(err) => {
-            return send_payload_error(&format!("transport error: {}", err.message), true);
+            return send_payload_error_after(ProviderError::transport(err.message), attempts);
         }
     };
-    if resp.status < 200 || resp.status >= 300 {
-        return send_payload_error(
-            &format!("slack returned status {}", resp.status),
-            resp.status >= 500,
-        );
-    }
-    send_payload_success()
+    let body_json = match slack_api_body(&resp) {
+        Ok(body) => body,
+        Err((err, diagnostics)) => {
+            return send_payload_failure(&err.to_string(), &err.detail(), attempts, &diagnostics);
+        }
+    };
+    let ts = body_json.get("ts").and_then(Value::as_str);
+    let channel = body_json.get("channel").and_then(Value::as_str);
+    let provider_message_id = ts.map(|ts| format!("slack:{ts}"));
+    let chat_message_id = channel
+        .zip(ts)
+        .map(|(channel, ts)| chat_message_id(channel, ts));
+    send_payload_success_after(
+        attempts,
+        provider_message_id.as_deref(),
+        chat_message_id.as_deref(),
+        &slack_diagnostics(&body_json),
+    )
 }
 
 fn metadata_string(metadata: &BTreeMap<String, Value>, key: &str) -> Option<String> {
@@ -1212,6 +1546,59 @@ fn http_out_error(status: u16, message: &str) -> Vec<u8> {
     http_out_v1_bytes(&out)
 }
 
+/// Rejects the request with a 401 when `SLACK_SIGNING_SECRET` is configured and
+/// the Slack signature or its timestamp does not check out.
+fn verify_webhook(request: &HttpInV1, body: &[u8]) -> Result<(), Vec<u8>> {
+    let secret = match secrets_store::get(SIGNING_SECRET_KEY) {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return Ok(()),
+        Err(e) => return Err(http_out_error(500, &format!("secret store error: {e:?}"))),
+    };
+    webhook_auth::verify_slack(
+        &secret,
+        request_header(request, SLACK_SIGNATURE_HEADER),
+        request_header(request, SLACK_TIMESTAMP_HEADER),
+        body,
+        &TimestampWindow::default(),
+        webhook_auth::now_unix_secs(),
+    )
+    .map_err(|err| http_out_error(401, &ProviderError::from(err).to_string()))
+}
+
+fn host_state() -> HostStateAdapter {
+    HostStateAdapter::new(
+        |key| state_store::read(key, None).map_err(|err| err.message),
+        |key, value| {
+            state_store::write(key, value, None)
+                .map(|_| ())
+                .map_err(|err| err.message)
+        },
+        |key| {
+            state_store::delete(key, None)
+                .map(|_| ())
+                .map_err(|err| err.message)
+        },
+    )
+}
+
+/// Acknowledges a redelivered webhook without emitting events.
+fn duplicate_ack(key: &str) -> Vec<u8> {
+    let out = HttpOutV1 {
+        status: 200,
+        headers: Vec::new(),
+        body_b64: STANDARD.encode(duplicate_ack_body(PROVIDER_ID, key)),
+        events: Vec::new(),
+    };
+    http_out_v1_bytes(&out)
+}
+
+/// Slack retries (`X-Slack-Retry-Num`) carry the original `event_id`.
+fn dedup_key(body: &Value) -> Option<String> {
+    key_fragment(body.get("event_id"))
+        .or_else(|| key_fragment(body.pointer("/event/event_id")))
+        .or_else(|| key_fragment(body.pointer("/event/client_msg_id")))
+}
+
 fn render_plan_error(message: &str) -> Vec<u8> {
     json_bytes(&json!({"ok": false, "error": message}))
 }
@@ -1220,64 +1607,212 @@ fn encode_error(message: &str) -> Vec<u8> {
     json_bytes(&json!({"ok": false, "error": message}))
 }
 
-fn build_slack_envelope(
-    text: String,
-    channel: Option<String>,
-    sender: Option<String>,
-) -> ChannelMessageEnvelope {
-    let env = EnvId::try_from("default").expect("env id");
-    let tenant = TenantId::try_from("default").expect("tenant id");
-    let mut metadata = MessageMetadata::new();
-    metadata.insert("universal".to_string(), "true".to_string());
-    if let Some(channel_id) = &channel {
-        metadata.insert("channel".to_string(), channel_id.clone());
-    }
-    if let Some(sender_id) = &sender {
-        metadata.insert("from".to_string(), sender_id.clone());
-    }
-    let channel_name = channel.clone().unwrap_or_else(|| "slack".to_string());
-    let actor = sender.map(|id| Actor {
-        id,
-        kind: Some("user".into()),
-    });
-    ChannelMessageEnvelope {
-        id: format!("slack-{channel_name}"),
-        tenant: TenantCtx::new(env.clone(), tenant.clone()),
-        channel: channel_name.clone(),
-        session_id: channel_name,
-        reply_scope: None,
-        from: actor,
-        to: Vec::new(),
-        correlation_id: None,
-        text: Some(text),
-        attachments: Vec::new(),
-        metadata,
+fn error_bytes(err: ProviderError) -> Vec<u8> {
+    json_bytes(&err.to_result_json())
+}
+
+fn error_bytes_after(err: ProviderError, attempts: u32) -> Vec<u8> {
+    error_bytes_after_parts(err, attempts, &[], &[])
+}
+
+/// `slack:<channel>:<ts>`. A ts is only unique within its conversation, so
+/// unlike `slack:<ts>` this id addresses the message on its own for
+/// update, delete and reaction ops.
+pub(crate) fn chat_message_id(channel: &str, ts: &str) -> String {
+    format!("slack:{channel}:{ts}")
+}
+
+/// Error result of a split send; ids of the parts already delivered are kept
+/// so callers do not resend them.
+fn error_bytes_after_parts(
+    err: ProviderError,
+    attempts: u32,
+    provider_message_ids: &[String],
+    diagnostics: &[String],
+) -> Vec<u8> {
+    let mut value = err.to_result_json();
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+        if !provider_message_ids.is_empty() {
+            map.insert("provider_message_ids".into(), json!(provider_message_ids));
+        }
+        if !diagnostics.is_empty() {
+            map.insert("diagnostics".into(), json!(diagnostics));
+        }
     }
+    json_bytes(&value)
+}
+
+fn send_payload_error(err: ProviderError) -> Vec<u8> {
+    send_payload_error_after(err, 0)
+}
+
+fn send_payload_error_after(err: ProviderError, attempts: u32) -> Vec<u8> {
+    send_payload_failure(&err.to_string(), &err.detail(), attempts, &[])
 }
 
-fn send_payload_error(message: &str, retryable: bool) -> Vec<u8> {
+fn send_payload_failure(
+    message: &str,
+    detail: &ProviderErrorDetail,
+    attempts: u32,
+    diagnostics: &[String],
+) -> Vec<u8> {
     let result = SendPayloadResultV1 {
         ok: false,
         message: Some(message.to_string()),
-        retryable,
+        retryable: detail.retryable,
     };
-    json_bytes(&result)
+    send_payload_result_bytes(&result, attempts, Some(detail), diagnostics)
 }
 
-fn send_payload_success() -> Vec<u8> {
+fn send_payload_success_after(
+    attempts: u32,
+    provider_message_id: Option<&str>,
+    chat_message_id: Option<&str>,
+    diagnostics: &[String],
+) -> Vec<u8> {
     let result = SendPayloadResultV1 {
         ok: true,
         message: None,
         retryable: false,
     };
-    json_bytes(&result)
+    let mut value = serde_json::to_value(&result).unwrap_or(Value::Null);
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+        if let Some(id) = provider_message_id {
+            map.insert("provider_message_id".into(), json!(id));
+        }
+        if let Some(id) = chat_message_id {
+            map.insert("chat_message_id".into(), json!(id));
+        }
+        if !diagnostics.is_empty() {
+            map.insert("diagnostics".into(), json!(diagnostics));
+        }
+    }
+    json_bytes(&value)
 }
 
-fn get_secret_string(key: &str) -> Result<String, String> {
+impl RetryableResponse for client::Response {
+    fn status(&self) -> u16 {
+        self.status
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+/// Classifies a non-2xx Web API response; Slack puts its error identifier in
+/// the `error` field of the body and rate-limit hints in `Retry-After`.
+fn slack_status_error(resp: &client::Response) -> ProviderError {
+    let provider_code = resp
+        .body
+        .as_deref()
+        .and_then(|bytes| serde_json::from_slice::<Value>(bytes).ok())
+        .and_then(|body| {
+            body.get("error")
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned)
+        });
+    let retry_after_secs = retry_after(resp).map(|delay| delay.as_secs());
+    ProviderError::from_http_status("slack", resp.status, retry_after_secs, provider_code)
+}
+
+/// How much of an unexpected response body goes into the error.
+const BODY_EXCERPT_CHARS: usize = 200;
+
+/// Body of a Web API call. Slack answers most failures with HTTP 200 and
+/// `ok: false`, so the body decides; failures keep the diagnostics Slack
+/// attached to them. A body that is not JSON or lacks `ok: true` (a proxy
+/// page, a truncated response) is a failure too.
+fn slack_api_body(resp: &client::Response) -> Result<Value, (ProviderError, Vec<String>)> {
+    let body: Value = resp
+        .body
+        .as_deref()
+        .and_then(|bytes| serde_json::from_slice(bytes).ok())
+        .unwrap_or(Value::Null);
+    if resp.status < 200 || resp.status >= 300 {
+        return Err((slack_status_error(resp), slack_diagnostics(&body)));
+    }
+    if body.get("ok").and_then(Value::as_bool) == Some(false) {
+        let code = body
+            .get("error")
+            .and_then(Value::as_str)
+            .unwrap_or("unknown_error");
+        // `retry_after` only trusts the header on 429/503 statuses.
+        let retry_after_secs = find_header(&resp.headers, "retry-after")
+            .and_then(|value| value.trim().parse::<u64>().ok());
+        return Err((
+            slack_api_error(code, retry_after_secs),
+            slack_diagnostics(&body),
+        ));
+    }
+    if body.get("ok").and_then(Value::as_bool) != Some(true) {
+        let excerpt: String = String::from_utf8_lossy(resp.body.as_deref().unwrap_or_default())
+            .chars()
+            .take(BODY_EXCERPT_CHARS)
+            .collect();
+        return Err((
+            ProviderError::other(format!(
+                "slack returned status {} without ok: true: {excerpt}",
+                resp.status
+            )),
+            Vec::new(),
+        ));
+    }
+    Ok(body)
+}
+
+/// Classifies a Slack `error` code. Codes without a closer fit, such as
+/// `restricted_action`, are `provider_rejected` and keep the code.
+fn slack_api_error(code: &str, retry_after_secs: Option<u64>) -> ProviderError {
+    let message = format!("slack error: {code}");
+    match code {
+        "channel_not_found" | "not_in_channel" | "is_archived" | "user_not_found"
+        | "thread_not_found" | "message_not_found" => ProviderError::destination_not_found(message),
+        "invalid_auth" | "not_authed" | "account_inactive" | "missing_scope" => {
+            ProviderError::auth_failed(message)
+        }
+        "token_revoked" | "token_expired" => ProviderError::reauth_required(message),
+        "ratelimited" | "rate_limited" => ProviderError::rate_limited(message, retry_after_secs),
+        "msg_too_long" | "msg_blocks_too_long" => ProviderError::payload_too_large(message),
+        "fatal_error" | "internal_error" | "service_unavailable" | "request_timeout" => {
+            ProviderError::transport(message)
+        }
+        _ => ProviderError::provider_rejected(Some(code.to_string()), message),
+    }
+}
+
+/// `warning` (comma separated) plus `response_metadata.warnings` and
+/// `.messages`; the latter explain `invalid_blocks` and similar failures.
+fn slack_diagnostics(body: &Value) -> Vec<String> {
+    let warnings = body
+        .get("warning")
+        .and_then(Value::as_str)
+        .into_iter()
+        .flat_map(|warning| warning.split(','));
+    let metadata = ["warnings", "messages"].into_iter().flat_map(|key| {
+        body.pointer(&format!("/response_metadata/{key}"))
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+    });
+    let mut diagnostics: Vec<String> = Vec::new();
+    for entry in warnings.chain(metadata).map(str::trim) {
+        if !entry.is_empty() && !diagnostics.iter().any(|seen| seen == entry) {
+            diagnostics.push(entry.to_string());
+        }
+    }
+    diagnostics
+}
+
+fn get_secret_string(key: &str) -> Result<String, ProviderError> {
     match secrets_store::get(key) {
-        Ok(Some(bytes)) => String::from_utf8(bytes).map_err(|_| "secret not valid utf-8".into()),
-        Ok(None) => Err(format!("missing secret: {key}")),
-        Err(e) => Err(format!("secret store error: {e:?}")),
+        Ok(Some(bytes)) => String::from_utf8(bytes)
+            .map_err(|_| ProviderError::config_invalid(format!("secret {key} not valid utf-8"))),
+        Ok(None) => Err(ProviderError::missing_secret(key)),
+        Err(e) => Err(ProviderError::other(format!("secret store error: {e:?}"))),
     }
 }
 
@@ -1286,11 +1821,125 @@ mod tests {
     use super::*;
     use std::collections::BTreeSet;
 
+    #[test]
+    fn dedup_key_uses_event_id() {
+        let body = json!({"event_id": "Ev1", "event": {"client_msg_id": "m-1"}});
+        assert_eq!(dedup_key(&body).as_deref(), Some("Ev1"));
+        let body = json!({"event": {"client_msg_id": "m-1"}});
+        assert_eq!(dedup_key(&body).as_deref(), Some("m-1"));
+        assert_eq!(dedup_key(&json!({"type": "url_verification"})), None);
+    }
+
     #[test]
     fn parse_config_rejects_unknown() {
         let cfg = br#"{"enabled":true,"public_base_url":"https://x","api_base_url":"https://slack.com/api","bot_token":"x","unknown":true}"#;
         let err = parse_config_bytes(cfg).unwrap_err();
-        assert!(err.contains("unknown field"));
+        assert!(err.to_string().contains("unknown field"));
+    }
+
+    #[test]
+    fn slack_status_error_honors_retry_after() {
+        let resp = client::Response {
+            status: 429,
+            headers: vec![("Retry-After".into(), "12".into())],
+            body: Some(br#"{"ok":false,"error":"ratelimited"}"#.to_vec()),
+        };
+        let err = slack_status_error(&resp);
+        let detail = err.detail();
+        assert_eq!(detail.code, provider_common::ProviderErrorCode::RateLimited);
+        assert_eq!(detail.retry_after_secs, Some(12));
+        assert!(detail.retryable);
+    }
+
+    #[test]
+    fn ok_false_bodies_are_classified() {
+        let resp = |body: Value| client::Response {
+            status: 200,
+            headers: vec![("Retry-After".into(), "3".into())],
+            body: Some(serde_json::to_vec(&body).expect("body")),
+        };
+        let code = |body: Value| {
+            slack_api_body(&resp(body))
+                .expect_err("ok:false")
+                .0
+                .detail()
+                .code
+        };
+        use provider_common::ProviderErrorCode as Code;
+        assert_eq!(
+            code(json!({"ok": false, "error": "channel_not_found"})),
+            Code::DestinationNotFound
+        );
+        assert_eq!(
+            code(json!({"ok": false, "error": "not_in_channel"})),
+            Code::DestinationNotFound
+        );
+        assert_eq!(
+            code(json!({"ok": false, "error": "invalid_auth"})),
+            Code::AuthFailed
+        );
+        assert_eq!(
+            code(json!({"ok": false, "error": "token_revoked"})),
+            Code::ReauthRequired
+        );
+        assert_eq!(
+            code(json!({"ok": false, "error": "msg_too_long"})),
+            Code::PayloadTooLarge
+        );
+        let (limited, _) = slack_api_body(&resp(json!({"ok": false, "error": "ratelimited"})))
+            .expect_err("ratelimited");
+        assert_eq!(limited.detail().retry_after_secs, Some(3));
+        let (restricted, _) =
+            slack_api_body(&resp(json!({"ok": false, "error": "restricted_action"})))
+                .expect_err("restricted");
+        assert_eq!(
+            restricted.detail().provider_code.as_deref(),
+            Some("restricted_action")
+        );
+        assert!(slack_api_body(&resp(json!({"ok": true, "ts": "1.2"}))).is_ok());
+    }
+
+    #[test]
+    fn bodies_without_ok_true_are_failures() {
+        let resp = |body: &[u8]| client::Response {
+            status: 200,
+            headers: Vec::new(),
+            body: Some(body.to_vec()),
+        };
+        let (err, _) = slack_api_body(&resp(b"<html>gateway</html>")).expect_err("html");
+        assert_eq!(err.code(), provider_common::ProviderErrorCode::Unknown);
+        assert!(err.to_string().contains("<html>gateway</html>"));
+        assert!(slack_api_body(&resp(br#"{"ts":"1.2"}"#)).is_err());
+        let (long, _) = slack_api_body(&resp("x".repeat(500).as_bytes())).expect_err("long");
+        assert!(long.to_string().len() < 300);
+    }
+
+    #[test]
+    fn diagnostics_collect_warnings_and_messages() {
+        let (_, diagnostics) = slack_api_body(&client::Response {
+            status: 200,
+            headers: Vec::new(),
+            body: Some(
+                serde_json::to_vec(&json!({
+                    "ok": false,
+                    "error": "invalid_blocks",
+                    "warning": "missing_charset",
+                    "response_metadata": {
+                        "warnings": ["missing_charset"],
+                        "messages": ["[ERROR] must be more than 0 characters [json-pointer:/blocks/0/text]"]
+                    }
+                }))
+                .expect("body"),
+            ),
+        })
+        .expect_err("invalid blocks");
+        assert_eq!(
+            diagnostics,
+            vec![
+                "missing_charset".to_string(),
+                "[ERROR] must be more than 0 characters [json-pointer:/blocks/0/text]".to_string(),
+            ]
+        );
     }
 
     #[test]
@@ -1298,7 +1947,7 @@ mod tests {
         let describe = build_describe_payload();
         assert_eq!(
             describe.schema_hash,
-            "0d7cbda46632fd39f7ade4774c1dee9a7deebd7b382b5c785a384b1899faa519"
+            "8e374ebb4d90e56edb9134ed1ba2331baa8a7add39998cb48fcd27282822f9ae"
         );
     }
 
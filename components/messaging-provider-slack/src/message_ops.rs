@@ -0,0 +1,483 @@
+//! Operations beyond sending: updating and deleting sent messages, reacting
+//! to them, and ephemeral and scheduled messages.
+//!
+//! Messages are addressed by the `chat_message_id` returned from `send`
+//! (`slack:<channel>:<ts>`) or its `provider_message_id` (`slack:<ts>`). A ts
+//! is only unique within its conversation, so an id without the channel needs
+//! `channel` in the input; `default_channel` is not assumed.
+
+use super::{
+    DEFAULT_API_BASE, MAX_TEXT_LEN, PROVIDER_TYPE, ProviderConfig, block_kit, chat_message_id,
+    client, error_bytes, error_bytes_after_parts, json_bytes, load_config, parse_blocks,
+    resolve_bot_token, slack_api_body, slack_diagnostics,
+};
+use provider_common::ProviderError;
+use provider_common::formatting::{MarkdownDialect, render as render_markdown};
+use provider_common::http_retry::{RetryPolicy, send_http};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+const PROVIDER_MESSAGE_ID_PREFIX: &str = "slack:";
+
+/// `chat.update`: new text and/or blocks for a sent message, e.g. to replace
+/// a "working on it" placeholder with the answer.
+pub(crate) fn handle_update_message(input_json: &[u8]) -> Vec<u8> {
+    let (parsed, cfg) = match prepare(input_json) {
+        Ok(prepared) => prepared,
+        Err(out) => return out,
+    };
+    let (channel, ts) = match target_message(&parsed) {
+        Ok(target) => target,
+        Err(err) => return error_bytes(err),
+    };
+    let mut payload = match message_content(&parsed) {
+        Ok(content) => content,
+        Err(err) => return error_bytes(err),
+    };
+    payload["channel"] = json!(channel);
+    payload["ts"] = json!(ts);
+    respond(&parsed, &cfg, "chat.update", &payload, "updated", Some(&ts))
+}
+
+/// `chat.delete`; bots can delete their own messages only.
+pub(crate) fn handle_delete_message(input_json: &[u8]) -> Vec<u8> {
+    let (parsed, cfg) = match prepare(input_json) {
+        Ok(prepared) => prepared,
+        Err(out) => return out,
+    };
+    let (channel, ts) = match target_message(&parsed) {
+        Ok(target) => target,
+        Err(err) => return error_bytes(err),
+    };
+    let payload = json!({"channel": channel, "ts": ts});
+    respond(&parsed, &cfg, "chat.delete", &payload, "deleted", Some(&ts))
+}
+
+/// `reactions.add` with the emoji `name` (`eyes`, `:white_check_mark:`).
+/// Reacting twice with the same emoji is not an error.
+pub(crate) fn handle_add_reaction(input_json: &[u8]) -> Vec<u8> {
+    let (parsed, cfg) = match prepare(input_json) {
+        Ok(prepared) => prepared,
+        Err(out) => return out,
+    };
+    let (channel, ts) = match target_message(&parsed) {
+        Ok(target) => target,
+        Err(err) => return error_bytes(err),
+    };
+    let Some(name) = string_field(&parsed, "name").map(|name| emoji_name(&name)) else {
+        return error_bytes(ProviderError::validation("name required"));
+    };
+    if name.is_empty() {
+        return error_bytes(ProviderError::validation("name required"));
+    }
+    let payload = json!({"channel": channel, "timestamp": ts, "name": name});
+    respond(
+        &parsed,
+        &cfg,
+        "reactions.add",
+        &payload,
+        "reacted",
+        Some(&ts),
+    )
+}
+
+/// `chat.postEphemeral`: a message only `user` sees, optionally in a thread.
+/// Ephemeral messages cannot be updated or deleted later.
+pub(crate) fn handle_send_ephemeral(input_json: &[u8]) -> Vec<u8> {
+    let (parsed, cfg) = match prepare(input_json) {
+        Ok(prepared) => prepared,
+        Err(out) => return out,
+    };
+    let channel = match target_channel(&parsed, &cfg) {
+        Ok(channel) => channel,
+        Err(err) => return error_bytes(err),
+    };
+    let Some(user) = string_field(&parsed, "user") else {
+        return error_bytes(ProviderError::validation("user required"));
+    };
+    let mut payload = match message_content(&parsed) {
+        Ok(content) => content,
+        Err(err) => return error_bytes(err),
+    };
+    payload["channel"] = json!(channel);
+    payload["user"] = json!(user);
+    if let Some(thread) = thread_ts(&parsed) {
+        payload["thread_ts"] = json!(thread);
+    }
+    respond(
+        &parsed,
+        &cfg,
+        "chat.postEphemeral",
+        &payload,
+        "sent_ephemeral",
+        None,
+    )
+}
+
+/// `chat.scheduleMessage` at `post_at` (unix seconds). The result carries
+/// the `scheduled_message_id`; the ts only exists once Slack posts it.
+pub(crate) fn handle_schedule_message(input_json: &[u8]) -> Vec<u8> {
+    let (parsed, cfg) = match prepare(input_json) {
+        Ok(prepared) => prepared,
+        Err(out) => return out,
+    };
+    let channel = match target_channel(&parsed, &cfg) {
+        Ok(channel) => channel,
+        Err(err) => return error_bytes(err),
+    };
+    let Some(post_at) = post_at(&parsed) else {
+        return error_bytes(ProviderError::validation("post_at (unix seconds) required"));
+    };
+    let mut payload = match message_content(&parsed) {
+        Ok(content) => content,
+        Err(err) => return error_bytes(err),
+    };
+    payload["channel"] = json!(channel);
+    payload["post_at"] = json!(post_at);
+    if let Some(thread) = thread_ts(&parsed) {
+        payload["thread_ts"] = json!(thread);
+    }
+    respond(
+        &parsed,
+        &cfg,
+        "chat.scheduleMessage",
+        &payload,
+        "scheduled",
+        None,
+    )
+}
+
+fn prepare(input_json: &[u8]) -> Result<(Value, ProviderConfig), Vec<u8>> {
+    let parsed: Value = serde_json::from_slice(input_json)
+        .map_err(|err| error_bytes(ProviderError::validation(format!("invalid json: {err}"))))?;
+    let cfg = load_config(&parsed).map_err(error_bytes)?;
+    if !cfg.enabled {
+        return Err(error_bytes(ProviderError::config_invalid(
+            "provider disabled by config",
+        )));
+    }
+    Ok((parsed, cfg))
+}
+
+fn string_field(parsed: &Value, key: &str) -> Option<String> {
+    parsed
+        .get(key)
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(ToOwned::to_owned)
+}
+
+fn target_channel(parsed: &Value, cfg: &ProviderConfig) -> Result<String, ProviderError> {
+    string_field(parsed, "channel")
+        .or_else(|| cfg.default_channel.clone())
+        .ok_or_else(|| ProviderError::validation("channel required"))
+}
+
+/// Channel and ts of the message an op targets. The channel embedded in the
+/// id wins over `channel`; without either the op is rejected rather than
+/// sent to `default_channel`, where the ts may name another message.
+fn target_message(parsed: &Value) -> Result<(String, String), ProviderError> {
+    let raw = string_field(parsed, "chat_message_id")
+        .or_else(|| string_field(parsed, "provider_message_id"))
+        .or_else(|| string_field(parsed, "message_id"))
+        .ok_or_else(|| ProviderError::validation("provider_message_id required"))?;
+    let (embedded_channel, ts) = parse_provider_message_id(&raw)
+        .ok_or_else(|| ProviderError::validation(format!("invalid provider_message_id: {raw}")))?;
+    let channel = embedded_channel
+        .map(ToOwned::to_owned)
+        .or_else(|| string_field(parsed, "channel"))
+        .ok_or_else(|| {
+            ProviderError::validation("channel required for a provider_message_id without one")
+        })?;
+    Ok((channel, ts.to_string()))
+}
+
+/// The channel, when present, and ts of `slack:<channel>:<ts>` or
+/// `slack:<ts>`; a bare ts is accepted as well.
+fn parse_provider_message_id(raw: &str) -> Option<(Option<&str>, &str)> {
+    let id = raw.strip_prefix(PROVIDER_MESSAGE_ID_PREFIX).unwrap_or(raw);
+    let (channel, ts) = match id.split_once(':') {
+        Some((channel, ts)) if !channel.is_empty() => (Some(channel), ts),
+        Some(_) => return None,
+        None => (None, id),
+    };
+    let (seconds, fraction) = ts.split_once('.')?;
+    let digits = |part: &str| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit());
+    (digits(seconds) && digits(fraction)).then_some((channel, ts))
+}
+
+fn thread_ts(parsed: &Value) -> Option<String> {
+    let raw = string_field(parsed, "thread_ts").or_else(|| string_field(parsed, "thread_id"))?;
+    Some(
+        parse_provider_message_id(&raw)
+            .map(|(_, ts)| ts.to_owned())
+            .unwrap_or(raw),
+    )
+}
+
+fn post_at(parsed: &Value) -> Option<u64> {
+    match parsed.get("post_at")? {
+        Value::Number(number) => number.as_u64(),
+        Value::String(value) => value.trim().parse().ok(),
+        _ => None,
+    }
+    .filter(|post_at| *post_at > 0)
+}
+
+fn emoji_name(name: &str) -> String {
+    name.trim().trim_matches(':').to_string()
+}
+
+/// `text` plus `blocks` from `rich` Block Kit or an `adaptive_card` (in the
+/// input or its `metadata`, as an object or a JSON string). Without text the
+/// card summary is the notification fallback.
+fn message_content(parsed: &Value) -> Result<Value, ProviderError> {
+    let (format, rich_blocks) = parse_blocks(parsed);
+    let card = parsed
+        .get("adaptive_card")
+        .or_else(|| parsed.pointer("/metadata/adaptive_card"))
+        .map(|card| match card {
+            Value::String(json) => json.clone(),
+            other => other.to_string(),
+        });
+    let card_metadata: BTreeMap<String, String> = card
+        .map(|card| BTreeMap::from([("adaptive_card".to_string(), card)]))
+        .unwrap_or_default();
+    let blocks = match format.as_deref() {
+        Some("slack_blocks") => rich_blocks,
+        _ => block_kit::from_metadata(&card_metadata)
+            .filter(|conversion| !conversion.blocks.is_empty())
+            .map(|conversion| Value::Array(conversion.blocks)),
+    };
+    let text = string_field(parsed, "text")
+        .or_else(|| provider_common::extract_ac_text_summary(&card_metadata));
+    let mut content = json!({});
+    match (text, &blocks) {
+        (Some(text), _) => {
+            let text = render_markdown(&text, MarkdownDialect::SlackMrkdwn);
+            if text.chars().count() > MAX_TEXT_LEN {
+                return Err(ProviderError::payload_too_large(format!(
+                    "text exceeds {MAX_TEXT_LEN} characters"
+                )));
+            }
+            content["text"] = json!(text);
+        }
+        (None, Some(_)) => {}
+        (None, None) => return Err(ProviderError::validation("text required")),
+    }
+    if let Some(blocks) = blocks {
+        content["blocks"] = blocks;
+    }
+    Ok(content)
+}
+
+/// Calls `method` and builds the op result.
+fn respond(
+    parsed: &Value,
+    cfg: &ProviderConfig,
+    method: &str,
+    payload: &Value,
+    status: &str,
+    ts: Option<&str>,
+) -> Vec<u8> {
+    let api_base = cfg.api_base_url.as_deref().unwrap_or(DEFAULT_API_BASE);
+    let request = client::Request {
+        method: "POST".into(),
+        url: format!("{api_base}/{method}"),
+        headers: vec![
+            ("Content-Type".into(), "application/json".into()),
+            (
+                "Authorization".into(),
+                format!("Bearer {}", resolve_bot_token(cfg)),
+            ),
+        ],
+        body: Some(json_bytes(payload)),
+    };
+    let outcome = send_http(&request.method, &RetryPolicy::from_input(parsed), || {
+        client::send(&request, None, None)
+    });
+    let attempts = outcome.attempts;
+    match outcome.result {
+        Ok(resp) => {
+            let channel = payload.get("channel").and_then(Value::as_str);
+            op_result(method, status, channel, ts, attempts, &resp)
+        }
+        Err(err) => {
+            error_bytes_after_parts(ProviderError::transport(err.message), attempts, &[], &[])
+        }
+    }
+}
+
+/// The op result for Slack's response to `method`.
+fn op_result(
+    method: &str,
+    status: &str,
+    channel: Option<&str>,
+    ts: Option<&str>,
+    attempts: u32,
+    resp: &client::Response,
+) -> Vec<u8> {
+    let body = match slack_api_body(resp) {
+        Ok(body) => body,
+        Err((err, diagnostics)) => {
+            if method == "reactions.add"
+                && matches!(&err, ProviderError::ProviderRejected { code: Some(code), .. }
+                    if code == "already_reacted")
+            {
+                json!({"ok": true, "already_reacted": true})
+            } else {
+                return error_bytes_after_parts(err, attempts, &[], &diagnostics);
+            }
+        }
+    };
+    let ts = ts.or_else(|| body.get("ts").and_then(Value::as_str));
+    let mut result = json!({
+        "ok": true,
+        "status": status,
+        "provider_type": PROVIDER_TYPE,
+        "message_id": ts,
+        "provider_message_id": ts.map(|ts| format!("{PROVIDER_MESSAGE_ID_PREFIX}{ts}")),
+        "chat_message_id": channel.zip(ts).map(|(channel, ts)| chat_message_id(channel, ts)),
+        "attempts": attempts,
+        "diagnostics": slack_diagnostics(&body),
+        "response": body,
+    });
+    match method {
+        "chat.postEphemeral" => {
+            // Ephemeral messages cannot be addressed later.
+            result["provider_message_id"] = Value::Null;
+            result["chat_message_id"] = Value::Null;
+            result["message_id"] = body.get("message_ts").cloned().unwrap_or(Value::Null);
+        }
+        "chat.scheduleMessage" => {
+            result["message_id"] = Value::Null;
+            result["provider_message_id"] = Value::Null;
+            result["chat_message_id"] = Value::Null;
+            result["scheduled_message_id"] = body
+                .get("scheduled_message_id")
+                .cloned()
+                .unwrap_or(Value::Null);
+            result["post_at"] = body.get("post_at").cloned().unwrap_or(Value::Null);
+        }
+        _ => {}
+    }
+    json_bytes(&result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_message_reads_slack_provider_message_ids() {
+        assert_eq!(
+            target_message(
+                &json!({"provider_message_id": "slack:1700000000.000100", "channel": "C1"})
+            ),
+            Ok(("C1".into(), "1700000000.000100".into()))
+        );
+        assert_eq!(
+            target_message(
+                &json!({"provider_message_id": "slack:C2:1700000000.000100", "channel": "C1"})
+            ),
+            Ok(("C2".into(), "1700000000.000100".into()))
+        );
+        assert_eq!(
+            target_message(&json!({
+                "chat_message_id": "slack:C3:1700000000.000200",
+                "provider_message_id": "slack:1700000000.000100"
+            })),
+            Ok(("C3".into(), "1700000000.000200".into()))
+        );
+        assert_eq!(
+            target_message(&json!({"message_id": "1700000000.000100", "channel": "C1"})),
+            Ok(("C1".into(), "1700000000.000100".into()))
+        );
+        assert!(target_message(&json!({"message_id": "1700000000.000100"})).is_err());
+        assert!(target_message(&json!({"provider_message_id": "tg:1:2"})).is_err());
+        assert!(
+            target_message(&json!({"provider_message_id": "slack::1700000000.000100"})).is_err()
+        );
+        assert!(target_message(&json!({"provider_message_id": "slack:pending-ts"})).is_err());
+        assert!(target_message(&json!({})).is_err());
+    }
+
+    #[test]
+    fn already_reacted_counts_as_success() {
+        let resp = |error: &str| client::Response {
+            status: 200,
+            headers: Vec::new(),
+            body: Some(serde_json::to_vec(&json!({"ok": false, "error": error})).expect("body")),
+        };
+        let ts = Some("1700000000.000100");
+        let out: Value = serde_json::from_slice(&op_result(
+            "reactions.add",
+            "reacted",
+            Some("C1"),
+            ts,
+            1,
+            &resp("already_reacted"),
+        ))
+        .expect("json");
+        assert_eq!(out["ok"], json!(true));
+        assert_eq!(out["status"], "reacted");
+        assert_eq!(out["response"]["already_reacted"], json!(true));
+        assert_eq!(out["provider_message_id"], "slack:1700000000.000100");
+        assert_eq!(out["chat_message_id"], "slack:C1:1700000000.000100");
+
+        let out: Value = serde_json::from_slice(&op_result(
+            "chat.delete",
+            "deleted",
+            Some("C1"),
+            ts,
+            1,
+            &resp("already_reacted"),
+        ))
+        .expect("json");
+        assert_eq!(out["ok"], json!(false));
+        let out: Value = serde_json::from_slice(&op_result(
+            "reactions.add",
+            "reacted",
+            Some("C1"),
+            ts,
+            1,
+            &resp("message_not_found"),
+        ))
+        .expect("json");
+        assert_eq!(out["ok"], json!(false));
+    }
+
+    #[test]
+    fn content_takes_text_blocks_or_a_card() {
+        assert_eq!(
+            message_content(&json!({"text": "**done**"})),
+            Ok(json!({"text": "*done*"}))
+        );
+        let blocks = json!([{"type": "divider"}]);
+        assert_eq!(
+            message_content(&json!({"rich": {"format": "slack_blocks", "blocks": blocks}})),
+            Ok(json!({"blocks": blocks}))
+        );
+        let card = json!({"body": [{"type": "TextBlock", "text": "Build passed"}]});
+        let content = message_content(&json!({"adaptive_card": card.to_string()})).expect("card");
+        assert_eq!(content["text"], "Build passed");
+        assert_eq!(content["blocks"][0]["type"], "section");
+        assert!(message_content(&json!({"text": "  "})).is_err());
+    }
+
+    #[test]
+    fn reaction_names_and_post_at_are_normalized() {
+        assert_eq!(emoji_name(":white_check_mark:"), "white_check_mark");
+        assert_eq!(
+            post_at(&json!({"post_at": 1_800_000_000})),
+            Some(1_800_000_000)
+        );
+        assert_eq!(
+            post_at(&json!({"post_at": "1800000000"})),
+            Some(1_800_000_000)
+        );
+        assert_eq!(post_at(&json!({"post_at": "tomorrow"})), None);
+        assert_eq!(post_at(&json!({})), None);
+    }
+}
@@ -0,0 +1,362 @@
+//! Outbound files.
+//!
+//! `files.upload` is retired; files go through the external upload flow:
+//! `files.getUploadURLExternal` reserves a file id and an upload url per
+//! file, the bytes are posted there, and `files.completeUploadExternal`
+//! shares all of them in the channel (or thread) with the message text as
+//! `initial_comment`. Slack cannot fetch remote urls, so `http(s)` attachments
+//! are downloaded first.
+
+use super::{
+    MAX_TEXT_LEN, PROVIDER_TYPE, chat_message_id, client, error_bytes_after_parts, json_bytes,
+    slack_api_body, slack_diagnostics, slack_status_error,
+};
+use greentic_types::Attachment;
+use provider_common::ProviderError;
+use provider_common::attachments::{AttachmentSource, FetchLimits, MultipartForm};
+use provider_common::form_urlencoded;
+use provider_common::formatting::{MarkdownDialect, render as render_markdown};
+use provider_common::http_retry::{RetryPolicy, send_http};
+use serde_json::{Value, json};
+
+/// Where the files are shared.
+pub(crate) struct UploadTarget<'a> {
+    pub api_base: &'a str,
+    pub token: &'a str,
+    pub channel: &'a str,
+    pub thread_ts: Option<&'a str>,
+}
+
+#[derive(Debug)]
+struct OutboundFile {
+    filename: String,
+    mime_type: String,
+    bytes: Vec<u8>,
+}
+
+/// Uploads the attachments and shares them with `text` as the comment.
+/// Returns the file ids and, when Slack already reports the share, the ts of
+/// the message carrying them.
+pub(crate) fn send_with_attachments(
+    target: &UploadTarget<'_>,
+    attachments: &[Attachment],
+    text: Option<&str>,
+    parsed: &Value,
+    is_reply: bool,
+) -> Vec<u8> {
+    let policy = RetryPolicy::from_input(parsed);
+    let limits = FetchLimits::from_input(parsed);
+    let comment = text.map(|text| render_markdown(text, MarkdownDialect::SlackMrkdwn));
+    if comment
+        .as_ref()
+        .is_some_and(|comment| comment.chars().count() > MAX_TEXT_LEN)
+    {
+        return error_bytes_after_parts(
+            ProviderError::payload_too_large(format!(
+                "text exceeds {MAX_TEXT_LEN} characters of an upload comment"
+            )),
+            0,
+            &[],
+            &[],
+        );
+    }
+
+    let mut attempts = 0;
+    let mut diagnostics = Vec::new();
+    let mut uploaded = Vec::new();
+    for attachment in attachments {
+        let file = match resolve_file(attachment, &limits, &policy, &mut attempts) {
+            Ok(file) => file,
+            Err(err) => return error_bytes_after_parts(err, attempts, &[], &diagnostics),
+        };
+        let reserved = api_call(
+            target,
+            "files.getUploadURLExternal",
+            &[
+                ("filename", file.filename.clone()),
+                ("length", file.bytes.len().to_string()),
+            ],
+            &policy,
+            &mut attempts,
+            &mut diagnostics,
+        );
+        let reserved = match reserved {
+            Ok(body) => body,
+            Err(err) => return error_bytes_after_parts(err, attempts, &[], &diagnostics),
+        };
+        let (Some(upload_url), Some(file_id)) = (
+            reserved.get("upload_url").and_then(Value::as_str),
+            reserved.get("file_id").and_then(Value::as_str),
+        ) else {
+            return error_bytes_after_parts(
+                ProviderError::other("files.getUploadURLExternal returned no upload_url"),
+                attempts,
+                &[],
+                &diagnostics,
+            );
+        };
+        if let Err(err) = post_bytes(upload_url, &file, &policy, &mut attempts) {
+            return error_bytes_after_parts(err, attempts, &[], &diagnostics);
+        }
+        uploaded.push(json!({"id": file_id, "title": file.filename}));
+    }
+
+    let mut params = vec![
+        ("files", Value::Array(uploaded.clone()).to_string()),
+        ("channel_id", target.channel.to_string()),
+    ];
+    if let Some(thread) = target.thread_ts {
+        params.push(("thread_ts", thread.to_string()));
+    }
+    if let Some(comment) = comment.filter(|comment| !comment.trim().is_empty()) {
+        params.push(("initial_comment", comment));
+    }
+    let completed = api_call(
+        target,
+        "files.completeUploadExternal",
+        &params,
+        &policy,
+        &mut attempts,
+        &mut diagnostics,
+    );
+    let completed = match completed {
+        Ok(body) => body,
+        Err(err) => return error_bytes_after_parts(err, attempts, &[], &diagnostics),
+    };
+    let file_ids: Vec<Value> = uploaded.iter().map(|file| file["id"].clone()).collect();
+    let ts = share_ts(&completed, target.channel);
+    json_bytes(&json!({
+        "ok": true,
+        "status": if is_reply { "replied" } else { "sent" },
+        "provider_type": PROVIDER_TYPE,
+        "message_id": ts,
+        "provider_message_id": ts.map(|ts| format!("slack:{ts}")),
+        "chat_message_id": ts.map(|ts| chat_message_id(target.channel, ts)),
+        "file_ids": file_ids,
+        "attempts": attempts,
+        "diagnostics": diagnostics,
+        "response": completed,
+    }))
+}
+
+fn resolve_file(
+    attachment: &Attachment,
+    limits: &FetchLimits,
+    policy: &RetryPolicy,
+    attempts: &mut u32,
+) -> Result<OutboundFile, ProviderError> {
+    let source = AttachmentSource::parse(&attachment.url)?;
+    let declared = attachment.mime_type.trim().to_ascii_lowercase();
+    let (bytes, inline_mime) = match source {
+        AttachmentSource::Inline { bytes, mime_type } => (bytes, mime_type),
+        AttachmentSource::Url(url) => (download(&url, policy, attempts)?, None),
+    };
+    limits.check_size(bytes.len() as u64)?;
+    if bytes.is_empty() {
+        return Err(ProviderError::validation("attachment is empty"));
+    }
+    let mime_type = match inline_mime {
+        Some(inline) if declared.is_empty() => inline,
+        _ if declared.is_empty() => "application/octet-stream".to_string(),
+        _ => declared,
+    };
+    let filename = attachment
+        .name
+        .clone()
+        .filter(|name| !name.trim().is_empty())
+        .or_else(|| url_filename(&attachment.url))
+        .unwrap_or_else(|| "file".to_string());
+    Ok(OutboundFile {
+        filename,
+        mime_type,
+        bytes,
+    })
+}
+
+fn download(url: &str, policy: &RetryPolicy, attempts: &mut u32) -> Result<Vec<u8>, ProviderError> {
+    let request = client::Request {
+        method: "GET".to_string(),
+        url: url.to_string(),
+        headers: Vec::new(),
+        body: None,
+    };
+    let outcome = send_http(&request.method, policy, || {
+        client::send(&request, None, None)
+    });
+    *attempts += outcome.attempts;
+    let resp = outcome
+        .result
+        .map_err(|err| ProviderError::transport(err.message))?;
+    if resp.status < 200 || resp.status >= 300 {
+        return Err(ProviderError::from_http_status(
+            "attachment url",
+            resp.status,
+            None,
+            None,
+        ));
+    }
+    Ok(resp.body.unwrap_or_default())
+}
+
+/// Form-encoded Web API call; the upload methods do not take JSON bodies.
+fn api_call(
+    target: &UploadTarget<'_>,
+    method: &str,
+    params: &[(&str, String)],
+    policy: &RetryPolicy,
+    attempts: &mut u32,
+    diagnostics: &mut Vec<String>,
+) -> Result<Value, ProviderError> {
+    let request = client::Request {
+        method: "POST".into(),
+        url: format!("{}/{method}", target.api_base),
+        headers: vec![
+            (
+                "Content-Type".into(),
+                "application/x-www-form-urlencoded".into(),
+            ),
+            ("Authorization".into(), format!("Bearer {}", target.token)),
+        ],
+        body: Some(form_urlencoded::serialize(params).into_bytes()),
+    };
+    let outcome = send_http(&request.method, policy, || {
+        client::send(&request, None, None)
+    });
+    *attempts += outcome.attempts;
+    let resp = outcome
+        .result
+        .map_err(|err| ProviderError::transport(err.message))?;
+    match slack_api_body(&resp) {
+        Ok(body) => {
+            diagnostics.extend(slack_diagnostics(&body));
+            Ok(body)
+        }
+        Err((err, failure_diagnostics)) => {
+            diagnostics.extend(failure_diagnostics);
+            Err(err)
+        }
+    }
+}
+
+/// The upload url answers `OK - <length>` in plain text.
+fn post_bytes(
+    upload_url: &str,
+    file: &OutboundFile,
+    policy: &RetryPolicy,
+    attempts: &mut u32,
+) -> Result<(), ProviderError> {
+    let (content_type, body) = MultipartForm::new()
+        .file(
+            "file",
+            file.filename.clone(),
+            file.mime_type.clone(),
+            file.bytes.clone(),
+        )
+        .finish();
+    let request = client::Request {
+        method: "POST".into(),
+        url: upload_url.to_string(),
+        headers: vec![("Content-Type".into(), content_type)],
+        body: Some(body),
+    };
+    let outcome = send_http(&request.method, policy, || {
+        client::send(&request, None, None)
+    });
+    *attempts += outcome.attempts;
+    let resp = outcome
+        .result
+        .map_err(|err| ProviderError::transport(err.message))?;
+    if resp.status < 200 || resp.status >= 300 {
+        return Err(slack_status_error(&resp));
+    }
+    Ok(())
+}
+
+/// Slack shares files asynchronously; the ts is only there when the share
+/// already happened.
+fn share_ts<'a>(completed: &'a Value, channel: &str) -> Option<&'a str> {
+    let shares = completed.pointer("/files/0/shares")?;
+    ["public", "private"].into_iter().find_map(|visibility| {
+        shares
+            .get(visibility)?
+            .get(channel)?
+            .get(0)?
+            .get("ts")?
+            .as_str()
+    })
+}
+
+fn url_filename(url: &str) -> Option<String> {
+    if url.starts_with("data:") {
+        return None;
+    }
+    url.split(['?', '#'])
+        .next()?
+        .rsplit('/')
+        .next()
+        .filter(|segment| !segment.is_empty() && !segment.contains(':'))
+        .map(ToOwned::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(url: &str, mime_type: &str, name: Option<&str>) -> Attachment {
+        Attachment {
+            mime_type: mime_type.to_string(),
+            url: url.to_string(),
+            name: name.map(ToOwned::to_owned),
+            size_bytes: None,
+        }
+    }
+
+    #[test]
+    fn inline_attachments_resolve_without_download() {
+        let mut attempts = 0;
+        let file = resolve_file(
+            &attachment("data:text/csv;base64,YSxiCjEsMgo=", "", Some("q3.csv")),
+            &FetchLimits::default(),
+            &RetryPolicy::default(),
+            &mut attempts,
+        )
+        .expect("file");
+        assert_eq!(file.filename, "q3.csv");
+        assert_eq!(file.mime_type, "text/csv");
+        assert_eq!(file.bytes, b"a,b\n1,2\n");
+        assert_eq!(attempts, 0);
+
+        let limits = FetchLimits {
+            max_bytes: 4,
+            ..FetchLimits::default()
+        };
+        let too_big = resolve_file(
+            &attachment("data:text/plain,0123456789", "text/plain", None),
+            &limits,
+            &RetryPolicy::default(),
+            &mut attempts,
+        );
+        assert!(matches!(too_big, Err(ProviderError::PayloadTooLarge(_))));
+    }
+
+    #[test]
+    fn filenames_fall_back_to_the_url() {
+        assert_eq!(
+            url_filename("https://example.com/reports/q3.pdf?sig=1").as_deref(),
+            Some("q3.pdf")
+        );
+        assert_eq!(url_filename("https://example.com/"), None);
+        assert_eq!(url_filename("data:text/plain,abc"), None);
+    }
+
+    #[test]
+    fn share_ts_reads_the_channel_share() {
+        let completed = json!({"ok": true, "files": [{
+            "id": "F1",
+            "shares": {"public": {"C1": [{"ts": "1700000000.000100"}]}}
+        }]});
+        assert_eq!(share_ts(&completed, "C1"), Some("1700000000.000100"));
+        assert_eq!(share_ts(&completed, "C2"), None);
+        assert_eq!(share_ts(&json!({"files": [{"id": "F1"}]}), "C1"), None);
+    }
+}
@@ -13,6 +13,17 @@ use provider_common::component_v0_6::{
     SchemaField, SchemaIr, canonical_cbor_bytes, decode_cbor, default_en_i18n_messages,
     schema_hash,
 };
+use provider_common::formatting::{MarkdownDialect, render as render_markdown};
+use provider_common::http_retry::{
+    RetryPolicy, RetryableResponse, find_header, retry_after, send_http, send_payload_result_bytes,
+};
+use provider_common::ingress_dedup::{DedupWindow, duplicate_ack_body};
+use provider_common::message_split::{SplitOptions, split_markdown};
+use provider_common::outbound_idempotency::send_idempotent;
+use provider_common::state::{HostStateAdapter, key_fragment};
+use provider_common::token_cache::{AccessToken, TokenCache, TokenCacheKey};
+use provider_common::webhook_auth::{self, TEAMS_AUTHORIZATION_HEADER, request_header};
+use provider_common::{ProviderError, ProviderErrorDetail};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use std::collections::BTreeMap;
@@ -29,12 +40,16 @@ mod bindings {
 
 use bindings::greentic::http::http_client as client;
 use bindings::greentic::secrets_store::secrets_store;
+use bindings::greentic::state::state_store;
 
 const PROVIDER_ID: &str = "messaging-provider-teams";
 const PROVIDER_TYPE: &str = "messaging.teams.graph";
+/// Upper bound for a chat message body, leaving room for the HTML wrapper.
+const MAX_TEXT_LEN: usize = 28_000;
 const WORLD_ID: &str = "component-v0-v6-v0";
 const DEFAULT_CLIENT_SECRET_KEY: &str = "MS_GRAPH_CLIENT_SECRET";
 const DEFAULT_REFRESH_TOKEN_KEY: &str = "MS_GRAPH_REFRESH_TOKEN";
+const OUTGOING_WEBHOOK_SECRET_KEY: &str = "TEAMS_OUTGOING_WEBHOOK_SECRET";
 const DEFAULT_TOKEN_SCOPE: &str = "https://graph.microsoft.com/.default";
 const DEFAULT_GRAPH_BASE: &str = "https://graph.microsoft.com/v1.0";
 const DEFAULT_AUTH_BASE: &str = "https://login.microsoftonline.com";
@@ -369,12 +384,12 @@ fn default_enabled() -> bool {
 
 fn dispatch_json_invoke(op: &str, input_json: &[u8]) -> Vec<u8> {
     match op {
-        "send" => handle_send(input_json),
-        "reply" => handle_reply(input_json),
+        "send" => send_idempotent(PROVIDER_ID, &mut host_state(), input_json, handle_send),
+        "reply" => send_idempotent(PROVIDER_ID, &mut host_state(), input_json, handle_reply),
         "ingest_http" => ingest_http(input_json),
         "render_plan" => render_plan(input_json),
         "encode" => encode_op(input_json),
-        "send_payload" => send_payload(input_json),
+        "send_payload" => send_idempotent(PROVIDER_ID, &mut host_state(), input_json, send_payload),
         "subscription_ensure" => subscription_ensure(input_json),
         "subscription_renew" => subscription_renew(input_json),
         "subscription_delete" => subscription_delete(input_json),
@@ -708,16 +723,16 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
     let parsed: Value = match serde_json::from_slice(input_json) {
         Ok(val) => val,
         Err(err) => {
-            return json_bytes(&json!({"ok": false, "error": format!("invalid json: {err}") }));
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
         }
     };
 
     let cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
     if !cfg.enabled {
-        return json_bytes(&json!({"ok": false, "error": "provider disabled by config"}));
+        return error_bytes(ProviderError::config_invalid("provider disabled by config"));
     }
 
     let envelope: ChannelMessageEnvelope = match serde_json::from_slice(input_json) {
@@ -725,15 +740,17 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
         Err(err) => match build_team_envelope_from_input(&parsed, &cfg) {
             Ok(env) => env,
             Err(message) => {
-                return json_bytes(
-                    &json!({"ok": false, "error": format!("invalid envelope: {message}: {err}") }),
-                );
+                return error_bytes(ProviderError::validation(format!(
+                    "invalid envelope: {message}: {err}"
+                )));
             }
         },
     };
 
     if !envelope.attachments.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "attachments not supported"}));
+        return error_bytes(ProviderError::unsupported_content(
+            "attachments not supported",
+        ));
     }
 
     let text = envelope
@@ -744,7 +761,7 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
         .map(ToOwned::to_owned);
     let text = match text {
         Some(value) => value,
-        None => return json_bytes(&json!({"ok": false, "error": "text required"})),
+        None => return error_bytes(ProviderError::validation("text required")),
     };
 
     let destination = envelope
@@ -754,12 +771,12 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
         .or_else(|| default_channel_destination(&cfg));
     let destination = match destination {
         Some(dest) => dest,
-        None => return json_bytes(&json!({"ok": false, "error": "destination required"})),
+        None => return error_bytes(ProviderError::validation("destination required")),
     };
 
     let dest_id = destination.id.trim();
     if dest_id.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "destination id required"}));
+        return error_bytes(ProviderError::validation("destination id required"));
     }
     let dest_id = dest_id.to_string();
     let kind = destination.kind.as_deref().unwrap_or("channel");
@@ -775,108 +792,134 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
                     let team = team.trim();
                     let channel = channel.trim();
                     if team.is_empty() || channel.is_empty() {
-                        return json_bytes(&json!({
-                            "ok": false,
-                            "error": "channel destination must include team_id and channel_id",
-                        }));
+                        return error_bytes(ProviderError::validation(
+                            "channel destination must include team_id and channel_id",
+                        ));
                     }
                     (team.to_string(), channel.to_string())
                 }
                 None => {
-                    return json_bytes(&json!({
-                        "ok": false,
-                        "error": "channel destination must be team_id:channel_id",
-                    }));
+                    return error_bytes(ProviderError::validation(
+                        "channel destination must be team_id:channel_id",
+                    ));
                 }
             };
             format!("{graph_base}/teams/{team_id}/channels/{channel_id}/messages")
         }
         "chat" => {
             if dest_id.is_empty() {
-                return json_bytes(&json!({"ok": false, "error": "destination id required"}));
+                return error_bytes(ProviderError::validation("destination id required"));
             }
             format!("{graph_base}/chats/{dest_id}/messages")
         }
         other => {
-            return json_bytes(&json!({
-                "ok": false,
-                "error": format!("unsupported destination kind: {other}"),
-            }));
+            return error_bytes(ProviderError::validation(format!(
+                "unsupported destination kind: {other}"
+            )));
         }
     };
 
     let token = match acquire_token(&cfg) {
         Ok(tok) => tok,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
 
-    let body = json!({
-        "body": {
-            "content": text,
-            "contentType": "html"
-        }
-    });
+    let dialect = MarkdownDialect::TeamsHtml;
+    let chunks = split_markdown(
+        &text,
+        dialect,
+        &SplitOptions::from_metadata(MAX_TEXT_LEN, &envelope.metadata),
+    );
+    let policy = RetryPolicy::from_input(&parsed);
+    let mut attempts = 0;
+    let mut message_ids: Vec<String> = Vec::new();
+    let mut provider_message_ids = Vec::new();
+    let mut responses = Vec::new();
+    for chunk in &chunks {
+        let body = json!({
+            "body": {
+                "content": render_markdown(chunk, dialect),
+                "contentType": "html"
+            }
+        });
+        // Channel posts start a thread; later parts are replies to the first.
+        let part_url = match (kind, message_ids.first()) {
+            ("channel", Some(first)) => format!("{url}/{first}/replies"),
+            _ => url.clone(),
+        };
 
-    let request = client::Request {
-        method: "POST".into(),
-        url,
-        headers: vec![
-            ("Content-Type".into(), "application/json".into()),
-            ("Authorization".into(), format!("Bearer {token}")),
-        ],
-        body: Some(serde_json::to_vec(&body).unwrap_or_else(|_| b"{}".to_vec())),
-    };
+        let request = client::Request {
+            method: "POST".into(),
+            url: part_url,
+            headers: vec![
+                ("Content-Type".into(), "application/json".into()),
+                ("Authorization".into(), format!("Bearer {token}")),
+            ],
+            body: Some(serde_json::to_vec(&body).unwrap_or_else(|_| b"{}".to_vec())),
+        };
 
-    let resp = match client::send(&request, None, None) {
-        Ok(resp) => resp,
-        Err(err) => {
-            return json_bytes(&json!({
-                "ok": false,
-                "error": format!("transport error: {}", err.message),
-            }));
+        let outcome = send_http(&request.method, &policy, || {
+            client::send(&request, None, None)
+        });
+        attempts += outcome.attempts;
+        let resp = match outcome.result {
+            Ok(resp) => resp,
+            Err(err) => {
+                return error_bytes_after_parts(
+                    ProviderError::transport(err.message),
+                    attempts,
+                    &provider_message_ids,
+                );
+            }
+        };
+
+        forget_rejected_token(&cfg, resp.status);
+        if resp.status < 200 || resp.status >= 300 {
+            return error_bytes_after_parts(
+                graph_status_error(&resp),
+                attempts,
+                &provider_message_ids,
+            );
         }
-    };
 
-    if resp.status < 200 || resp.status >= 300 {
-        return json_bytes(&json!({
-            "ok": false,
-            "error": format!("graph returned status {}", resp.status),
-        }));
+        let body_bytes = resp.body.unwrap_or_default();
+        let body_json: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
+        let message_id = body_json
+            .get("id")
+            .and_then(Value::as_str)
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| "graph-message".to_string());
+        provider_message_ids.push(format!("teams:{message_id}"));
+        message_ids.push(message_id);
+        responses.push(body_json);
     }
 
-    let body_bytes = resp.body.unwrap_or_default();
-    let body_json: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
-    let message_id = body_json
-        .get("id")
-        .and_then(Value::as_str)
-        .map(|s| s.to_string())
-        .unwrap_or_else(|| "graph-message".to_string());
-    let provider_message_id = format!("teams:{message_id}");
-
     json_bytes(&json!({
         "ok": true,
         "status": "sent",
         "provider_type": PROVIDER_TYPE,
         "public_base_url": cfg.public_base_url,
-        "message_id": message_id,
-        "provider_message_id": provider_message_id,
-        "response": body_json,
+        "message_id": message_ids.first(),
+        "provider_message_id": provider_message_ids.first(),
+        "provider_message_ids": provider_message_ids,
+        "attempts": attempts,
+        "response": responses.first(),
     }))
 }
 fn handle_reply(input_json: &[u8]) -> Vec<u8> {
     let parsed: Value = match serde_json::from_slice(input_json) {
         Ok(val) => val,
         Err(err) => {
-            return json_bytes(&json!({"ok": false, "error": format!("invalid json: {err}")}));
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
         }
     };
 
     let cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
     if !cfg.enabled {
-        return json_bytes(&json!({"ok": false, "error": "provider disabled by config"}));
+        return error_bytes(ProviderError::config_invalid("provider disabled by config"));
     }
     let thread_id = parsed
         .get("reply_to_id")
@@ -884,7 +927,9 @@ fn handle_reply(input_json: &[u8]) -> Vec<u8> {
         .and_then(|v| v.as_str())
         .unwrap_or("");
     if thread_id.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "reply_to_id or thread_id required"}));
+        return error_bytes(ProviderError::validation(
+            "reply_to_id or thread_id required",
+        ));
     }
     let text = parsed
         .get("text")
@@ -892,12 +937,12 @@ fn handle_reply(input_json: &[u8]) -> Vec<u8> {
         .unwrap_or("")
         .to_string();
     if text.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "text required"}));
+        return error_bytes(ProviderError::validation("text required"));
     }
 
     let token = match acquire_token(&cfg) {
         Ok(tok) => tok,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
 
     let graph_base = cfg
@@ -915,7 +960,7 @@ fn handle_reply(input_json: &[u8]) -> Vec<u8> {
         .map(|s| s.to_string())
         .or_else(|| cfg.channel_id.clone());
     let (Some(team_id), Some(channel_id)) = (team_id, channel_id) else {
-        return json_bytes(&json!({"ok": false, "error": "team_id and channel_id required"}));
+        return error_bytes(ProviderError::validation("team_id and channel_id required"));
     };
 
     let url = format!(
@@ -924,7 +969,7 @@ fn handle_reply(input_json: &[u8]) -> Vec<u8> {
     );
     let body = json!({
         "body": {
-            "content": text,
+            "content": render_markdown(&text, MarkdownDialect::TeamsHtml),
             "contentType": "html"
         }
     });
@@ -938,20 +983,19 @@ fn handle_reply(input_json: &[u8]) -> Vec<u8> {
         body: Some(serde_json::to_vec(&body).unwrap_or_else(|_| b"{}".to_vec())),
     };
 
-    let resp = match client::send(&request, None, None) {
+    let outcome = send_http(&request.method, &RetryPolicy::from_input(&parsed), || {
+        client::send(&request, None, None)
+    });
+    let attempts = outcome.attempts;
+    let resp = match outcome.result {
         Ok(resp) => resp,
         Err(err) => {
-            return json_bytes(&json!({
-                "ok": false,
-                "error": format!("transport error: {}", err.message),
-            }));
+            return error_bytes_after(ProviderError::transport(err.message), attempts);
         }
     };
+    forget_rejected_token(&cfg, resp.status);
     if resp.status < 200 || resp.status >= 300 {
-        return json_bytes(&json!({
-            "ok": false,
-            "error": format!("graph returned status {}", resp.status),
-        }));
+        return error_bytes_after(graph_status_error(&resp), attempts);
     }
     let body_bytes = resp.body.unwrap_or_default();
     let body_json: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
@@ -969,6 +1013,7 @@ fn handle_reply(input_json: &[u8]) -> Vec<u8> {
         "public_base_url": cfg.public_base_url,
         "message_id": message_id,
         "provider_message_id": provider_message_id,
+        "attempts": attempts,
         "response": body_json,
     }))
 }
@@ -982,12 +1027,28 @@ fn ingest_http(input_json: &[u8]) -> Vec<u8> {
         Ok(bytes) => bytes,
         Err(err) => return http_out_error(400, &format!("invalid body encoding: {err}")),
     };
+    if let Err(out) = verify_webhook(&request, &body_bytes) {
+        return out;
+    }
     let body_val: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
+    // The key is recorded once the delivery normalized, so a redelivery of one
+    // that failed part-way is processed again.
+    let dedup = dedup_key(&body_val);
+    let window = DedupWindow::default();
+    let now = webhook_auth::now_unix_secs();
+    if let Some(key) = &dedup
+        && window.seen(&mut host_state(), PROVIDER_ID, key, now)
+    {
+        return duplicate_ack(key);
+    }
     let text = extract_team_text(&body_val);
     let team_id = extract_team_id(&body_val);
     let channel_id = extract_channel_id(&body_val);
     let user = extract_sender(&body_val);
     let envelope = build_team_envelope(text.clone(), user, team_id.clone(), channel_id.clone());
+    if let Some(key) = &dedup {
+        window.record(&mut host_state(), PROVIDER_ID, key, now);
+    }
     let normalized = json!({
         "ok": true,
         "event": body_val,
@@ -1054,36 +1115,58 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
     let send_in = match serde_json::from_slice::<SendPayloadInV1>(input_json) {
         Ok(value) => value,
         Err(err) => {
-            return send_payload_error(&format!("invalid send_payload input: {err}"), false);
+            return send_payload_error(ProviderError::validation(format!(
+                "invalid send_payload input: {err}"
+            )));
         }
     };
     if send_in.provider_type != PROVIDER_TYPE {
-        return send_payload_error("provider type mismatch", false);
+        return send_payload_error(ProviderError::validation("provider type mismatch"));
     }
     let payload_bytes = match STANDARD.decode(&send_in.payload.body_b64) {
         Ok(bytes) => bytes,
         Err(err) => {
-            return send_payload_error(&format!("payload decode failed: {err}"), false);
+            return send_payload_error(ProviderError::validation(format!(
+                "payload decode failed: {err}"
+            )));
         }
     };
-    let payload: Value = serde_json::from_slice(&payload_bytes).unwrap_or(Value::Null);
+    let mut payload: Value = serde_json::from_slice(&payload_bytes).unwrap_or(Value::Null);
+    if let (Some(map), Some(runtime)) = (payload.as_object_mut(), input_runtime_config(input_json))
+    {
+        map.entry("runtime_config").or_insert(runtime);
+    }
     let payload_bytes = serde_json::to_vec(&payload).unwrap_or_else(|_| b"{}".to_vec());
     let result_bytes = handle_send(&payload_bytes);
     let result_value: Value = serde_json::from_slice(&result_bytes).unwrap_or(Value::Null);
-    let ok = result_value
-        .get("ok")
-        .and_then(Value::as_bool)
-        .unwrap_or(false);
-    if ok {
-        send_payload_success()
-    } else {
-        let message = result_value
-            .get("error")
-            .and_then(Value::as_str)
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| "send_payload failed".to_string());
-        send_payload_error(&message, false)
+    send_payload_from_send_result(&result_value)
+}
+
+/// Maps a `handle_send` result onto `SendPayloadResultV1`, keeping the
+/// classified error so `retryable` follows the error code.
+fn send_payload_from_send_result(result: &Value) -> Vec<u8> {
+    let attempts = result.get("attempts").and_then(Value::as_u64).unwrap_or(0) as u32;
+    if result.get("ok").and_then(Value::as_bool).unwrap_or(false) {
+        let provider_message_id = result.get("provider_message_id").and_then(Value::as_str);
+        return send_payload_success_after(attempts, provider_message_id);
     }
+    let message = result
+        .get("error")
+        .and_then(Value::as_str)
+        .unwrap_or("send_payload failed");
+    let detail = result
+        .get("error_detail")
+        .cloned()
+        .and_then(|value| serde_json::from_value::<ProviderErrorDetail>(value).ok())
+        .unwrap_or_else(|| ProviderError::other(message).detail());
+    send_payload_failure(message, &detail, attempts)
+}
+
+fn input_runtime_config(input_json: &[u8]) -> Option<Value> {
+    serde_json::from_slice::<Value>(input_json)
+        .ok()?
+        .get("runtime_config")
+        .cloned()
 }
 
 fn build_team_envelope(
@@ -1265,6 +1348,64 @@ fn http_out_error(status: u16, message: &str) -> Vec<u8> {
     http_out_v1_bytes(&out)
 }
 
+/// Rejects the request with a 401 when `TEAMS_OUTGOING_WEBHOOK_SECRET` is
+/// configured and the outgoing webhook `Authorization: HMAC` header does not
+/// match the body.
+fn verify_webhook(request: &HttpInV1, body: &[u8]) -> Result<(), Vec<u8>> {
+    let secret = match secrets_store::get(OUTGOING_WEBHOOK_SECRET_KEY) {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return Ok(()),
+        Err(e) => return Err(http_out_error(500, &format!("secret store error: {e:?}"))),
+    };
+    webhook_auth::verify_teams_outgoing(
+        &String::from_utf8_lossy(&secret),
+        request_header(request, TEAMS_AUTHORIZATION_HEADER),
+        body,
+    )
+    .map_err(|err| http_out_error(401, &ProviderError::from(err).to_string()))
+}
+
+fn host_state() -> HostStateAdapter {
+    HostStateAdapter::new(
+        |key| state_store::read(key, None).map_err(|err| err.message),
+        |key, value| {
+            state_store::write(key, value, None)
+                .map(|_| ())
+                .map_err(|err| err.message)
+        },
+        |key| {
+            state_store::delete(key, None)
+                .map(|_| ())
+                .map_err(|err| err.message)
+        },
+    )
+}
+
+/// Acknowledges a redelivered webhook without emitting events.
+fn duplicate_ack(key: &str) -> Vec<u8> {
+    let out = HttpOutV1 {
+        status: 200,
+        headers: Vec::new(),
+        body_b64: STANDARD.encode(duplicate_ack_body(PROVIDER_ID, key)),
+        events: Vec::new(),
+    };
+    http_out_v1_bytes(&out)
+}
+
+/// Bot Framework activities are keyed by conversation and activity id, Graph
+/// change notifications by resource id and change type.
+fn dedup_key(body: &Value) -> Option<String> {
+    if let Some(id) = key_fragment(body.get("id")) {
+        return Some(match key_fragment(body.pointer("/conversation/id")) {
+            Some(conversation) => format!("{conversation}:{id}"),
+            None => id,
+        });
+    }
+    let resource_id = key_fragment(body.pointer("/resourceData/id"))?;
+    let change_type = key_fragment(body.get("changeType")).unwrap_or_default();
+    Some(format!("{resource_id}:{change_type}"))
+}
+
 fn render_plan_error(message: &str) -> Vec<u8> {
     json_bytes(&json!({"ok": false, "error": message}))
 }
@@ -1273,29 +1414,83 @@ fn encode_error(message: &str) -> Vec<u8> {
     json_bytes(&json!({"ok": false, "error": message}))
 }
 
-fn send_payload_error(message: &str, retryable: bool) -> Vec<u8> {
+fn error_bytes(err: ProviderError) -> Vec<u8> {
+    json_bytes(&err.to_result_json())
+}
+
+fn error_bytes_after(err: ProviderError, attempts: u32) -> Vec<u8> {
+    let mut value = err.to_result_json();
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+    }
+    json_bytes(&value)
+}
+
+/// Error result of a split send; ids of the parts already delivered are kept
+/// so callers do not resend them.
+fn error_bytes_after_parts(
+    err: ProviderError,
+    attempts: u32,
+    provider_message_ids: &[String],
+) -> Vec<u8> {
+    let mut value = err.to_result_json();
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+        if !provider_message_ids.is_empty() {
+            map.insert("provider_message_ids".into(), json!(provider_message_ids));
+        }
+    }
+    json_bytes(&value)
+}
+
+fn send_payload_error(err: ProviderError) -> Vec<u8> {
+    send_payload_error_after(err, 0)
+}
+
+fn send_payload_error_after(err: ProviderError, attempts: u32) -> Vec<u8> {
+    send_payload_failure(&err.to_string(), &err.detail(), attempts)
+}
+
+fn send_payload_failure(message: &str, detail: &ProviderErrorDetail, attempts: u32) -> Vec<u8> {
     let result = SendPayloadResultV1 {
         ok: false,
         message: Some(message.to_string()),
-        retryable,
+        retryable: detail.retryable,
     };
-    json_bytes(&result)
+    send_payload_result_bytes(&result, attempts, Some(detail), &[])
 }
 
-fn send_payload_success() -> Vec<u8> {
+fn send_payload_success_after(attempts: u32, provider_message_id: Option<&str>) -> Vec<u8> {
     let result = SendPayloadResultV1 {
         ok: true,
         message: None,
         retryable: false,
     };
-    json_bytes(&result)
+    let mut value = serde_json::to_value(&result).unwrap_or(Value::Null);
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+        if let Some(id) = provider_message_id {
+            map.insert("provider_message_id".into(), json!(id));
+        }
+    }
+    json_bytes(&value)
+}
+
+impl RetryableResponse for client::Response {
+    fn status(&self) -> u16 {
+        self.status
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
 }
 
 fn subscription_ensure(input_json: &[u8]) -> Vec<u8> {
     let parsed: Value = match serde_json::from_slice(input_json) {
         Ok(value) => value,
         Err(err) => {
-            return json_bytes(&json!({"ok": false, "error": format!("invalid json: {err}")}));
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
         }
     };
 
@@ -1324,12 +1519,12 @@ fn subscription_ensure(input_json: &[u8]) -> Vec<u8> {
 
     let cfg = match load_config(&config_value) {
         Ok(cfg) => cfg,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
 
     let token = match acquire_token(&cfg) {
         Ok(token) => token,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
 
     if dto.change_types.is_empty() {
@@ -1417,7 +1612,7 @@ fn subscription_renew(input_json: &[u8]) -> Vec<u8> {
     let parsed: Value = match serde_json::from_slice(input_json) {
         Ok(value) => value,
         Err(err) => {
-            return json_bytes(&json!({"ok": false, "error": format!("invalid json: {err}")}));
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
         }
     };
 
@@ -1436,12 +1631,12 @@ fn subscription_renew(input_json: &[u8]) -> Vec<u8> {
 
     let cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
 
     let token = match acquire_token(&cfg) {
         Ok(token) => token,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
 
     let expiration_target_ms = match dto.expiration_target_unix_ms {
@@ -1476,7 +1671,7 @@ fn subscription_delete(input_json: &[u8]) -> Vec<u8> {
     let parsed: Value = match serde_json::from_slice(input_json) {
         Ok(value) => value,
         Err(err) => {
-            return json_bytes(&json!({"ok": false, "error": format!("invalid json: {err}")}));
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
         }
     };
 
@@ -1495,12 +1690,12 @@ fn subscription_delete(input_json: &[u8]) -> Vec<u8> {
 
     let cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
 
     let token = match acquire_token(&cfg) {
         Ok(token) => token,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
 
     if let Err(err) = delete_subscription(&cfg, &token, &dto.subscription_id) {
@@ -1515,16 +1710,40 @@ fn subscription_delete(input_json: &[u8]) -> Vec<u8> {
     json_bytes(&json!({"ok": true, "subscription": out}))
 }
 
-fn acquire_token(cfg: &ProviderConfig) -> Result<String, String> {
+fn acquire_token(cfg: &ProviderConfig) -> Result<String, ProviderError> {
+    TokenCache::default().get_or_fetch(
+        &mut host_state(),
+        &token_cache_key(cfg),
+        webhook_auth::now_unix_secs(),
+        || fetch_token(cfg),
+    )
+}
+
+fn token_cache_key(cfg: &ProviderConfig) -> TokenCacheKey {
+    TokenCacheKey::new(&cfg.tenant_id, &cfg.client_id, token_scope(cfg))
+}
+
+fn token_scope(cfg: &ProviderConfig) -> String {
+    cfg.token_scope
+        .clone()
+        .unwrap_or_else(|| DEFAULT_TOKEN_SCOPE.to_string())
+}
+
+/// Drops the cached token when Graph rejected it, so the next call exchanges
+/// credentials again.
+fn forget_rejected_token(cfg: &ProviderConfig, status: u16) {
+    if status == 401 {
+        TokenCache::default().invalidate(&mut host_state(), &token_cache_key(cfg));
+    }
+}
+
+fn fetch_token(cfg: &ProviderConfig) -> Result<AccessToken, ProviderError> {
     let auth_base = cfg
         .auth_base_url
         .clone()
         .unwrap_or_else(|| DEFAULT_AUTH_BASE.to_string());
     let token_url = format!("{}/{}/oauth2/v2.0/token", auth_base, cfg.tenant_id);
-    let scope = cfg
-        .token_scope
-        .clone()
-        .unwrap_or_else(|| DEFAULT_TOKEN_SCOPE.to_string());
+    let scope = token_scope(cfg);
 
     let refresh_token = cfg
         .refresh_token
@@ -1551,7 +1770,7 @@ fn acquire_token(cfg: &ProviderConfig) -> Result<String, String> {
         .client_secret
         .clone()
         .or_else(|| get_secret(DEFAULT_CLIENT_SECRET_KEY).ok())
-        .ok_or_else(|| "missing client_secret (config or secret store)".to_string())?;
+        .ok_or_else(|| ProviderError::missing_secret(DEFAULT_CLIENT_SECRET_KEY))?;
     let form = format!(
         "client_id={}&client_secret={}&grant_type=client_credentials&scope={}",
         url_encode(&cfg.client_id),
@@ -1561,7 +1780,7 @@ fn acquire_token(cfg: &ProviderConfig) -> Result<String, String> {
     send_token_request(&token_url, &form)
 }
 
-fn send_token_request(url: &str, form: &str) -> Result<String, String> {
+fn send_token_request(url: &str, form: &str) -> Result<AccessToken, ProviderError> {
     let request = client::Request {
         method: "POST".into(),
         url: url.to_string(),
@@ -1572,19 +1791,66 @@ fn send_token_request(url: &str, form: &str) -> Result<String, String> {
         body: Some(form.as_bytes().to_vec()),
     };
 
-    let resp = client::send(&request, None, None)
-        .map_err(|e| format!("transport error: {}", e.message))?;
+    let resp = send_http(&request.method, &RetryPolicy::default(), || {
+        client::send(&request, None, None)
+    })
+    .result
+    .map_err(|e| ProviderError::transport(e.message))?;
     if resp.status < 200 || resp.status >= 300 {
-        return Err(format!("token endpoint returned status {}", resp.status));
+        return Err(token_status_error(&resp));
     }
     let body = resp.body.unwrap_or_default();
-    let json: Value =
-        serde_json::from_slice(&body).map_err(|e| format!("invalid token response: {e}"))?;
-    let token = json
-        .get("access_token")
+    let json: Value = serde_json::from_slice(&body)
+        .map_err(|e| ProviderError::other(format!("invalid token response: {e}")))?;
+    AccessToken::from_response(&json)
+        .ok_or_else(|| ProviderError::other("token response missing access_token"))
+}
+
+/// Token endpoint failures surface the OAuth `error` code. A rejected grant
+/// (400/401) means the credentials need attention rather than a retry.
+fn token_status_error(resp: &client::Response) -> ProviderError {
+    let body: Value = resp
+        .body
+        .as_deref()
+        .and_then(|body| serde_json::from_slice(body).ok())
+        .unwrap_or(Value::Null);
+    let code = body.get("error").and_then(Value::as_str);
+    let message = match code {
+        Some(code) => format!("token endpoint returned status {}: {code}", resp.status),
+        None => format!("token endpoint returned status {}", resp.status),
+    };
+    match resp.status {
+        400 | 401 => ProviderError::auth_failed(message),
+        status => ProviderError::from_http_response(
+            status,
+            retry_after(resp).map(|delay| delay.as_secs()),
+            code.map(str::to_string),
+            message,
+        ),
+    }
+}
+
+/// Classifies a non-2xx Graph response using the `error.code` from the body.
+fn graph_status_error(resp: &client::Response) -> ProviderError {
+    let body: Value = resp
+        .body
+        .as_deref()
+        .and_then(|body| serde_json::from_slice(body).ok())
+        .unwrap_or(Value::Null);
+    let error = body.get("error");
+    let code = error
+        .and_then(|err| err.get("code"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    let message = match error
+        .and_then(|err| err.get("message"))
         .and_then(Value::as_str)
-        .ok_or_else(|| "token response missing access_token".to_string())?;
-    Ok(token.to_string())
+    {
+        Some(detail) => format!("graph returned status {}: {detail}", resp.status),
+        None => format!("graph returned status {}", resp.status),
+    };
+    let retry_after_secs = retry_after(resp).map(|delay| delay.as_secs());
+    ProviderError::from_http_response(resp.status, retry_after_secs, code, message)
 }
 
 fn get_secret(key: &str) -> Result<String, String> {
@@ -1596,19 +1862,19 @@ fn get_secret(key: &str) -> Result<String, String> {
 }
 
 #[cfg(test)]
-fn parse_config_bytes(bytes: &[u8]) -> Result<ProviderConfig, String> {
+fn parse_config_bytes(bytes: &[u8]) -> Result<ProviderConfig, ProviderError> {
     let cfg = serde_json::from_slice::<ProviderConfig>(bytes)
-        .map_err(|e| format!("invalid config: {e}"))?;
+        .map_err(|e| ProviderError::config_invalid(e.to_string()))?;
     validate_provider_config(cfg)
 }
 
-fn parse_config_value(val: &Value) -> Result<ProviderConfig, String> {
+fn parse_config_value(val: &Value) -> Result<ProviderConfig, ProviderError> {
     let cfg = serde_json::from_value::<ProviderConfig>(val.clone())
-        .map_err(|e| format!("invalid config: {e}"))?;
+        .map_err(|e| ProviderError::config_invalid(e.to_string()))?;
     validate_provider_config(cfg)
 }
 
-fn load_config(input: &Value) -> Result<ProviderConfig, String> {
+fn load_config(input: &Value) -> Result<ProviderConfig, ProviderError> {
     if let Some(cfg) = input.get("config") {
         return parse_config_value(cfg);
     }
@@ -1635,7 +1901,7 @@ fn load_config(input: &Value) -> Result<ProviderConfig, String> {
         return parse_config_value(&Value::Object(partial));
     }
 
-    Err("config required".into())
+    Err(ProviderError::config_invalid("config required"))
 }
 
 fn existing_config_from_answers(answers: &Value) -> Option<ProviderConfigOut> {
@@ -1718,15 +1984,17 @@ fn validate_config_out(config: &ProviderConfigOut) -> Result<(), String> {
     Ok(())
 }
 
-fn validate_provider_config(cfg: ProviderConfig) -> Result<ProviderConfig, String> {
+fn validate_provider_config(cfg: ProviderConfig) -> Result<ProviderConfig, ProviderError> {
     if cfg.tenant_id.trim().is_empty() {
-        return Err("invalid config: tenant_id cannot be empty".to_string());
+        return Err(ProviderError::config_invalid("tenant_id cannot be empty"));
     }
     if cfg.client_id.trim().is_empty() {
-        return Err("invalid config: client_id cannot be empty".to_string());
+        return Err(ProviderError::config_invalid("client_id cannot be empty"));
     }
     if cfg.public_base_url.trim().is_empty() {
-        return Err("invalid config: public_base_url cannot be empty".to_string());
+        return Err(ProviderError::config_invalid(
+            "public_base_url cannot be empty",
+        ));
     }
     Ok(cfg)
 }
@@ -1812,8 +2080,12 @@ fn list_subscriptions(
         headers: vec![("Authorization".into(), format!("Bearer {}", token))],
         body: None,
     };
-    let resp = client::send(&request, None, None)
-        .map_err(|e| GraphRequestError::Transport(format!("transport error: {}", e.message)))?;
+    let resp = send_http(&request.method, &RetryPolicy::default(), || {
+        client::send(&request, None, None)
+    })
+    .result
+    .map_err(|e| GraphRequestError::Transport(format!("transport error: {}", e.message)))?;
+    forget_rejected_token(cfg, resp.status);
     if resp.status < 200 || resp.status >= 300 {
         return Err(GraphRequestError::Status(resp.status));
     }
@@ -1884,8 +2156,12 @@ fn create_subscription(
         ],
         body: Some(serde_json::to_vec(&payload).unwrap_or_else(|_| b"{}".to_vec())),
     };
-    let resp = client::send(&request, None, None)
-        .map_err(|e| GraphRequestError::Transport(format!("transport error: {}", e.message)))?;
+    let resp = send_http(&request.method, &RetryPolicy::default(), || {
+        client::send(&request, None, None)
+    })
+    .result
+    .map_err(|e| GraphRequestError::Transport(format!("transport error: {}", e.message)))?;
+    forget_rejected_token(cfg, resp.status);
     if resp.status < 200 || resp.status >= 300 {
         return Err(GraphRequestError::Status(resp.status));
     }
@@ -1930,8 +2206,12 @@ fn renew_subscription(
         ],
         body: Some(serde_json::to_vec(&payload).unwrap_or_else(|_| b"{}".to_vec())),
     };
-    let resp = client::send(&request, None, None)
-        .map_err(|e| GraphRequestError::Transport(format!("transport error: {}", e.message)))?;
+    let resp = send_http(&request.method, &RetryPolicy::default(), || {
+        client::send(&request, None, None)
+    })
+    .result
+    .map_err(|e| GraphRequestError::Transport(format!("transport error: {}", e.message)))?;
+    forget_rejected_token(cfg, resp.status);
     if resp.status < 200 || resp.status >= 300 {
         return Err(GraphRequestError::Status(resp.status));
     }
@@ -1954,8 +2234,12 @@ fn delete_subscription(
         headers: vec![("Authorization".into(), format!("Bearer {}", token))],
         body: None,
     };
-    let resp = client::send(&request, None, None)
-        .map_err(|e| GraphRequestError::Transport(format!("transport error: {}", e.message)))?;
+    let resp = send_http(&request.method, &RetryPolicy::default(), || {
+        client::send(&request, None, None)
+    })
+    .result
+    .map_err(|e| GraphRequestError::Transport(format!("transport error: {}", e.message)))?;
+    forget_rejected_token(cfg, resp.status);
     if resp.status < 200 || resp.status >= 300 {
         return Err(GraphRequestError::Status(resp.status));
     }
@@ -1969,8 +2253,17 @@ fn json_bytes<T: serde::Serialize>(value: &T) -> Vec<u8> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use provider_common::ProviderErrorCode;
     use std::collections::BTreeSet;
 
+    #[test]
+    fn dedup_key_scopes_activity_id_to_conversation() {
+        let body = json!({"id": "a-1", "conversation": {"id": "c-1"}});
+        assert_eq!(dedup_key(&body).as_deref(), Some("c-1:a-1"));
+        let body = json!({"changeType": "created", "resourceData": {"id": "r-1"}});
+        assert_eq!(dedup_key(&body).as_deref(), Some("r-1:created"));
+    }
+
     #[test]
     fn parse_config_requires_new_fields() {
         let cfg = br#"{"enabled":true,"tenant_id":"t","client_id":"c","public_base_url":"https://example.com","graph_base_url":"https://graph.microsoft.com/v1.0","auth_base_url":"https://login.microsoftonline.com","token_scope":"https://graph.microsoft.com/.default"}"#;
@@ -1996,11 +2289,44 @@ mod tests {
         assert_eq!(cfg.tenant_id, "t");
     }
 
+    #[test]
+    fn graph_status_error_reads_error_code() {
+        let resp = client::Response {
+            status: 429,
+            headers: vec![("Retry-After".into(), "3".into())],
+            body: Some(br#"{"error":{"code":"TooManyRequests","message":"slow down"}}"#.to_vec()),
+        };
+        let err = graph_status_error(&resp);
+        assert_eq!(err.code(), ProviderErrorCode::RateLimited);
+        let detail = err.detail();
+        assert!(detail.retryable);
+        assert_eq!(detail.retry_after_secs, Some(3));
+
+        let resp = client::Response {
+            status: 400,
+            headers: Vec::new(),
+            body: Some(br#"{"error":{"code":"BadRequest","message":"bad body"}}"#.to_vec()),
+        };
+        let detail = graph_status_error(&resp).detail();
+        assert_eq!(detail.code, ProviderErrorCode::ProviderRejected);
+        assert_eq!(detail.provider_code.as_deref(), Some("BadRequest"));
+
+        let resp = client::Response {
+            status: 400,
+            headers: Vec::new(),
+            body: Some(br#"{"error":"invalid_client"}"#.to_vec()),
+        };
+        assert_eq!(
+            token_status_error(&resp).code(),
+            ProviderErrorCode::AuthFailed
+        );
+    }
+
     #[test]
     fn parse_config_rejects_unknown() {
         let cfg = br#"{"enabled":true,"tenant_id":"t","client_id":"c","public_base_url":"https://example.com","graph_base_url":"https://graph.microsoft.com/v1.0","auth_base_url":"https://login.microsoftonline.com","token_scope":"https://graph.microsoft.com/.default","unexpected":true}"#;
         let err = parse_config_bytes(cfg).unwrap_err();
-        assert!(err.contains("unknown field"));
+        assert!(err.to_string().contains("unknown field"));
     }
 
     #[test]
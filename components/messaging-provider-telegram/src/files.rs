@@ -0,0 +1,169 @@
+//! Downloads of inbound files.
+//!
+//! Ingest references files as `telegram-file:<file_id>` attachment urls.
+//! `fetch_attachment` resolves the id with `getFile` and downloads the file
+//! from the Bot API file endpoint, which only serves files up to 20 MB.
+
+use super::{
+    DEFAULT_API_BASE, PROVIDER_TYPE, TELEGRAM_FILE_URL_PREFIX, call_bot_api, client, error_bytes,
+    error_bytes_after, get_bot_token, json_bytes, load_config, telegram_status_error,
+};
+use provider_common::ProviderError;
+use provider_common::attachments::{FetchLimits, FetchedAttachment, response_mime_type};
+use provider_common::http_retry::{RetryPolicy, send_http};
+use serde_json::{Value, json};
+
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Takes the attachment `url` or a bare `file_id`; `mime_type` and `name`
+/// from the envelope attachment fill in what the file endpoint does not say.
+pub(crate) fn handle_fetch_attachment(input_json: &[u8]) -> Vec<u8> {
+    let parsed: Value = match serde_json::from_slice(input_json) {
+        Ok(value) => value,
+        Err(err) => {
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
+        }
+    };
+    let cfg = match load_config(&parsed) {
+        Ok(cfg) => cfg,
+        Err(err) => return error_bytes(err),
+    };
+    if !cfg.enabled {
+        return error_bytes(ProviderError::config_invalid("provider disabled by config"));
+    }
+    let file_id = match file_id_from_input(&parsed) {
+        Ok(file_id) => file_id,
+        Err(err) => return error_bytes(err),
+    };
+    let limits = FetchLimits::from_input(&parsed);
+    let policy = RetryPolicy::from_input(&parsed);
+
+    let payload = json!({ "file_id": file_id });
+    let (body, mut attempts) = match call_bot_api(&cfg, "getFile", &payload, &policy) {
+        Ok(result) => result,
+        Err((err, attempts)) => return error_bytes_after(err, attempts),
+    };
+    let Some(file_path) = body.pointer("/result/file_path").and_then(Value::as_str) else {
+        return error_bytes_after(
+            ProviderError::destination_not_found("file is no longer available for download"),
+            attempts,
+        );
+    };
+    if let Some(size) = body.pointer("/result/file_size").and_then(Value::as_u64)
+        && let Err(err) = limits.check_size(size)
+    {
+        return error_bytes_after(err, attempts);
+    }
+    let hint = parsed
+        .get("mime_type")
+        .and_then(Value::as_str)
+        .filter(|mime| !mime.trim().is_empty());
+    if let Some(hint) = hint
+        && let Err(err) = limits.check_mime(hint)
+    {
+        return error_bytes_after(err, attempts);
+    }
+
+    let token = match get_bot_token(&cfg) {
+        Ok(token) => token,
+        Err(err) => return error_bytes_after(err, attempts),
+    };
+    let api_base = cfg.api_base_url.as_deref().unwrap_or(DEFAULT_API_BASE);
+    let request = client::Request {
+        method: "GET".to_string(),
+        url: format!("{api_base}/file/bot{token}/{file_path}"),
+        headers: Vec::new(),
+        body: None,
+    };
+    let outcome = send_http(&request.method, &policy, || {
+        client::send(&request, None, None)
+    });
+    attempts += outcome.attempts;
+    let resp = match outcome.result {
+        Ok(resp) => resp,
+        Err(err) => return error_bytes_after(ProviderError::transport(err.message), attempts),
+    };
+    if resp.status < 200 || resp.status >= 300 {
+        return error_bytes_after(telegram_status_error(&resp), attempts);
+    }
+    let fetched = FetchedAttachment {
+        mime_type: resolve_mime_type(response_mime_type(&resp.headers), hint),
+        name: parsed
+            .get("name")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned)
+            .or_else(|| file_path.rsplit('/').next().map(ToOwned::to_owned)),
+        bytes: resp.body.unwrap_or_default(),
+    };
+    if let Err(err) = limits
+        .check_size(fetched.bytes.len() as u64)
+        .and_then(|()| limits.check_mime(&fetched.mime_type))
+    {
+        return error_bytes_after(err, attempts);
+    }
+    json_bytes(&fetched.to_result_json(PROVIDER_TYPE, attempts))
+}
+
+fn file_id_from_input(parsed: &Value) -> Result<String, ProviderError> {
+    if let Some(url) = parsed.get("url").and_then(Value::as_str) {
+        return url
+            .trim()
+            .strip_prefix(TELEGRAM_FILE_URL_PREFIX)
+            .filter(|id| !id.is_empty())
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| {
+                ProviderError::validation(format!(
+                    "url must be a {TELEGRAM_FILE_URL_PREFIX}<file_id> reference"
+                ))
+            });
+    }
+    parsed
+        .get("file_id")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| ProviderError::validation("url or file_id required"))
+}
+
+/// The file endpoint mostly answers `application/octet-stream`, so the type
+/// ingest recorded wins over it.
+fn resolve_mime_type(served: Option<String>, hint: Option<&str>) -> String {
+    match (served, hint) {
+        (Some(served), _) if served != OCTET_STREAM => served,
+        (_, Some(hint)) => hint.trim().to_ascii_lowercase(),
+        (served, None) => served.unwrap_or_else(|| OCTET_STREAM.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_id_comes_from_reference_url_or_field() {
+        assert_eq!(
+            file_id_from_input(&json!({"url": "telegram-file:AgAD"})),
+            Ok("AgAD".into())
+        );
+        assert_eq!(
+            file_id_from_input(&json!({"file_id": " AgAD "})),
+            Ok("AgAD".into())
+        );
+        assert!(file_id_from_input(&json!({"url": "https://example.com/a.png"})).is_err());
+        assert!(file_id_from_input(&json!({})).is_err());
+    }
+
+    #[test]
+    fn recorded_mime_type_beats_octet_stream() {
+        assert_eq!(
+            resolve_mime_type(Some(OCTET_STREAM.into()), Some("image/jpeg")),
+            "image/jpeg"
+        );
+        assert_eq!(
+            resolve_mime_type(Some("audio/ogg".into()), Some("image/jpeg")),
+            "audio/ogg"
+        );
+        assert_eq!(resolve_mime_type(None, None), OCTET_STREAM);
+    }
+}
@@ -0,0 +1,204 @@
+//! Inline keyboards for Adaptive Card actions.
+//!
+//! `Action.OpenUrl` becomes a URL button and `Action.Submit` a callback
+//! button whose `callback_data` is the JSON of the submit data. Telegram caps
+//! `callback_data` at 64 bytes, so larger payloads are kept in the state store
+//! and the button carries a short token instead; `resolve_callback_data` turns
+//! either form back into the submit data when the `callback_query` arrives.
+//! Stored payloads expire after `CALLBACK_TTL_SECS`, like the dedup window,
+//! and are purged through an `ExpiryIndex`.
+
+use provider_common::AcAction;
+use provider_common::state::{ExpiryIndex, StateStore};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+/// Keyboard limits advertised in the provider capabilities.
+pub(crate) const MAX_BUTTONS_PER_ROW: usize = 5;
+pub(crate) const MAX_BUTTON_ROWS: usize = 8;
+/// Telegram rejects buttons with longer `callback_data`.
+pub(crate) const CALLBACK_DATA_MAX_BYTES: usize = 64;
+/// Marks tokenized `callback_data`; inline data is JSON and never starts
+/// with it.
+const CALLBACK_TOKEN_PREFIX: &str = "cb:";
+const CALLBACK_STATE_PREFIX: &str = "messaging.telegram.callback";
+/// How long a tokenized button keeps resolving after it was sent.
+pub(crate) const CALLBACK_TTL_SECS: u64 = 30 * 24 * 3600;
+
+/// Stored payload of a tokenized button.
+#[derive(Debug, Serialize, Deserialize)]
+struct CallbackRecord {
+    expires_at: u64,
+    data: Value,
+}
+
+/// Buttons laid out row by row, and how many actions did not fit.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct InlineKeyboard {
+    pub rows: Vec<Vec<Value>>,
+    pub dropped: usize,
+}
+
+impl InlineKeyboard {
+    pub fn reply_markup(&self) -> Value {
+        json!({ "inline_keyboard": self.rows })
+    }
+}
+
+/// Fills rows of `MAX_BUTTONS_PER_ROW` buttons up to `MAX_BUTTON_ROWS`.
+pub(crate) fn build_inline_keyboard(
+    actions: &[AcAction],
+    store: &mut impl StateStore,
+    now: u64,
+) -> Result<InlineKeyboard, String> {
+    let capacity = MAX_BUTTONS_PER_ROW * MAX_BUTTON_ROWS;
+    let mut buttons = Vec::new();
+    for action in actions.iter().take(capacity) {
+        buttons.push(match action {
+            AcAction::OpenUrl { title, url } => json!({ "text": title, "url": url }),
+            AcAction::Submit { title, data } => {
+                json!({ "text": title, "callback_data": encode_callback_data(data, store, now)? })
+            }
+        });
+    }
+    Ok(InlineKeyboard {
+        rows: buttons
+            .chunks(MAX_BUTTONS_PER_ROW)
+            .map(<[Value]>::to_vec)
+            .collect(),
+        dropped: actions.len().saturating_sub(capacity),
+    })
+}
+
+/// `callback_data` for a submit button: the data's JSON when it fits, else a
+/// token for the payload stored in the state store until `now` plus
+/// `CALLBACK_TTL_SECS`.
+pub(crate) fn encode_callback_data(
+    data: &Value,
+    store: &mut impl StateStore,
+    now: u64,
+) -> Result<String, String> {
+    let serialized = data.to_string();
+    if serialized.len() <= CALLBACK_DATA_MAX_BYTES {
+        return Ok(serialized);
+    }
+    let digest = Sha256::digest(serialized.as_bytes());
+    let token: String = digest[..12]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    let record = CallbackRecord {
+        expires_at: now.saturating_add(CALLBACK_TTL_SECS),
+        data: data.clone(),
+    };
+    let bytes = serde_json::to_vec(&record).map_err(|err| err.to_string())?;
+    let key = callback_state_key(&token);
+    store.write(&key, &bytes)?;
+    let index = ExpiryIndex {
+        namespace: CALLBACK_STATE_PREFIX,
+        bucket_secs: CALLBACK_TTL_SECS,
+    };
+    let _ = index.track(store, &key, now);
+    Ok(format!("{CALLBACK_TOKEN_PREFIX}{token}"))
+}
+
+/// Submit data behind a callback's `callback_data`; `None` when a token is
+/// unknown or expired at `now`, and an expired token is deleted. Data that is
+/// not JSON (buttons from other senders) is returned as a string.
+pub(crate) fn resolve_callback_data(
+    raw: &str,
+    store: &mut impl StateStore,
+    now: u64,
+) -> Option<Value> {
+    if let Some(token) = raw.strip_prefix(CALLBACK_TOKEN_PREFIX) {
+        let key = callback_state_key(token);
+        let bytes = store.read(&key).ok().flatten()?;
+        let record = serde_json::from_slice::<CallbackRecord>(&bytes).ok()?;
+        if now >= record.expires_at {
+            let _ = store.delete(&key);
+            return None;
+        }
+        return Some(record.data);
+    }
+    Some(serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string())))
+}
+
+fn callback_state_key(token: &str) -> String {
+    format!("{CALLBACK_STATE_PREFIX}.{token}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use provider_common::testing::MemoryStore;
+
+    fn submit(title: &str, data: Value) -> AcAction {
+        AcAction::Submit {
+            title: title.into(),
+            data,
+        }
+    }
+
+    #[test]
+    fn large_callback_data_round_trips_through_token() {
+        let mut store = MemoryStore::default();
+        let data = json!({"action": "approve", "request": "x".repeat(80)});
+        let encoded = encode_callback_data(&data, &mut store, 100).unwrap();
+        assert!(encoded.starts_with(CALLBACK_TOKEN_PREFIX));
+        assert!(encoded.len() <= CALLBACK_DATA_MAX_BYTES);
+        assert_eq!(resolve_callback_data(&encoded, &mut store, 101), Some(data));
+        assert_eq!(resolve_callback_data("cb:unknown", &mut store, 101), None);
+
+        let small = json!({"id": 1});
+        let encoded = encode_callback_data(&small, &mut store, 100).unwrap();
+        assert_eq!(encoded, r#"{"id":1}"#);
+        assert_eq!(
+            resolve_callback_data(&encoded, &mut store, 101),
+            Some(small)
+        );
+        assert_eq!(
+            resolve_callback_data("legacy", &mut store, 101),
+            Some(json!("legacy"))
+        );
+    }
+
+    #[test]
+    fn callback_tokens_expire() {
+        let mut store = MemoryStore::default();
+        let data = json!({"action": "approve", "request": "x".repeat(80)});
+        let encoded = encode_callback_data(&data, &mut store, 100).unwrap();
+        let last_valid = 100 + CALLBACK_TTL_SECS - 1;
+        assert_eq!(
+            resolve_callback_data(&encoded, &mut store, last_valid),
+            Some(data)
+        );
+        assert_eq!(
+            resolve_callback_data(&encoded, &mut store, last_valid + 1),
+            None
+        );
+        let token = encoded.strip_prefix(CALLBACK_TOKEN_PREFIX).unwrap();
+        assert!(!store.entries.contains_key(&callback_state_key(token)));
+    }
+
+    #[test]
+    fn keyboard_respects_row_limits() {
+        let mut store = MemoryStore::default();
+        let mut actions = vec![AcAction::OpenUrl {
+            title: "Docs".into(),
+            url: "https://example.com".into(),
+        }];
+        actions.extend((0..44).map(|i| submit(&format!("b{i}"), json!(i))));
+        let keyboard = build_inline_keyboard(&actions, &mut store, 100).unwrap();
+        assert_eq!(keyboard.rows.len(), MAX_BUTTON_ROWS);
+        assert!(
+            keyboard
+                .rows
+                .iter()
+                .all(|row| row.len() <= MAX_BUTTONS_PER_ROW)
+        );
+        assert_eq!(keyboard.dropped, 5);
+        assert_eq!(keyboard.rows[0][0]["url"], json!("https://example.com"));
+        assert_eq!(keyboard.rows[0][1]["callback_data"], json!("0"));
+    }
+}
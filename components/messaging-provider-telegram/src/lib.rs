@@ -4,17 +4,35 @@ use greentic_types::messaging::universal_dto::{
     SendPayloadInV1, SendPayloadResultV1,
 };
 use greentic_types::{
-    Actor, ChannelMessageEnvelope, Destination, EnvId, MessageMetadata, TenantCtx, TenantId,
+    Actor, Attachment, ChannelMessageEnvelope, Destination, EnvId, MessageMetadata, ReplyScope,
+    TenantCtx, TenantId,
 };
+use provider_common::attachments::{AttachmentSource, MultipartForm};
 use provider_common::component_v0_6::{
     DescribePayload, I18nText, OperationDescriptor, QaQuestionSpec, QaSpec, RedactionRule,
     SchemaField, SchemaIr, canonical_cbor_bytes, decode_cbor, default_en_i18n_messages,
     schema_hash,
 };
+use provider_common::formatting::{MarkdownDialect, render as render_markdown};
+use provider_common::http_retry::{
+    Idempotency, RetryPolicy, RetryableResponse, find_header, retry_after, send_http,
+    send_payload_result_bytes, send_with_retry,
+};
+use provider_common::ingress_dedup::{DedupWindow, duplicate_ack_body};
+use provider_common::message_split::{SplitOptions, split_markdown};
+use provider_common::outbound_idempotency::send_idempotent;
+use provider_common::state::{HostStateAdapter, StateStore, key_fragment};
+use provider_common::webhook_auth::{self, TELEGRAM_SECRET_TOKEN_HEADER, request_header};
+use provider_common::{ProviderError, ProviderErrorDetail};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use std::collections::BTreeMap;
 
+mod files;
+mod keyboard;
+mod message_ops;
+mod polling;
+
 mod bindings {
     wit_bindgen::generate!({
         path: "wit/messaging-provider-telegram",
@@ -25,12 +43,20 @@ mod bindings {
 
 use bindings::greentic::http::http_client as client;
 use bindings::greentic::secrets_store::secrets_store;
+use bindings::greentic::state::state_store;
 
 const PROVIDER_ID: &str = "messaging-provider-telegram";
 const PROVIDER_TYPE: &str = "messaging.telegram.bot";
 const WORLD_ID: &str = "component-v0-v6-v0";
 const DEFAULT_API_BASE: &str = "https://api.telegram.org";
 const TOKEN_SECRET: &str = "TELEGRAM_BOT_TOKEN";
+/// `sendMessage` text limit after entity parsing.
+const MAX_TEXT_LEN: usize = 4096;
+/// Caption limit of media messages after entity parsing.
+const MAX_CAPTION_LEN: usize = 1024;
+/// `sendMediaGroup` takes between 2 and 10 items.
+const MAX_MEDIA_GROUP_LEN: usize = 10;
+const WEBHOOK_SECRET_KEY: &str = "TELEGRAM_WEBHOOK_SECRET";
 const I18N_KEYS: &[&str] = &[
     "telegram.op.run.title",
     "telegram.op.run.description",
@@ -46,6 +72,18 @@ const I18N_KEYS: &[&str] = &[
     "telegram.op.encode.description",
     "telegram.op.send_payload.title",
     "telegram.op.send_payload.description",
+    "telegram.op.edit_message.title",
+    "telegram.op.edit_message.description",
+    "telegram.op.delete_message.title",
+    "telegram.op.delete_message.description",
+    "telegram.op.answer_callback.title",
+    "telegram.op.answer_callback.description",
+    "telegram.op.chat_action.title",
+    "telegram.op.chat_action.description",
+    "telegram.op.poll_updates.title",
+    "telegram.op.poll_updates.description",
+    "telegram.op.fetch_attachment.title",
+    "telegram.op.fetch_attachment.description",
     "telegram.schema.input.title",
     "telegram.schema.input.description",
     "telegram.schema.input.message.title",
@@ -297,12 +335,18 @@ fn default_enabled() -> bool {
 
 fn dispatch_json_invoke(op: &str, input_json: &[u8]) -> Vec<u8> {
     match op {
-        "send" => handle_send(input_json),
-        "reply" => handle_reply(input_json),
+        "send" => send_idempotent(PROVIDER_ID, &mut host_state(), input_json, handle_send),
+        "reply" => send_idempotent(PROVIDER_ID, &mut host_state(), input_json, handle_reply),
         "ingest_http" => ingest_http(input_json),
         "render_plan" => render_plan(input_json),
         "encode" => encode_op(input_json),
-        "send_payload" => send_payload(input_json),
+        "send_payload" => send_idempotent(PROVIDER_ID, &mut host_state(), input_json, send_payload),
+        "edit_message" => message_ops::handle_edit_message(input_json),
+        "delete_message" => message_ops::handle_delete_message(input_json),
+        "answer_callback" => message_ops::handle_answer_callback(input_json),
+        "chat_action" => message_ops::handle_chat_action(input_json),
+        "poll_updates" => polling::handle_poll_updates(input_json),
+        "fetch_attachment" => files::handle_fetch_attachment(input_json),
         other => json_bytes(&json!({"ok": false, "error": format!("unsupported op: {other}")})),
     }
 }
@@ -350,6 +394,36 @@ fn build_describe_payload() -> DescribePayload {
                 "telegram.op.send_payload.title",
                 "telegram.op.send_payload.description",
             ),
+            op(
+                "edit_message",
+                "telegram.op.edit_message.title",
+                "telegram.op.edit_message.description",
+            ),
+            op(
+                "delete_message",
+                "telegram.op.delete_message.title",
+                "telegram.op.delete_message.description",
+            ),
+            op(
+                "answer_callback",
+                "telegram.op.answer_callback.title",
+                "telegram.op.answer_callback.description",
+            ),
+            op(
+                "chat_action",
+                "telegram.op.chat_action.title",
+                "telegram.op.chat_action.description",
+            ),
+            op(
+                "poll_updates",
+                "telegram.op.poll_updates.title",
+                "telegram.op.poll_updates.description",
+            ),
+            op(
+                "fetch_attachment",
+                "telegram.op.fetch_attachment.title",
+                "telegram.op.fetch_attachment.description",
+            ),
         ],
         input_schema: input_schema.clone(),
         output_schema: output_schema.clone(),
@@ -612,40 +686,47 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
     let parsed: Value = match serde_json::from_slice(input_json) {
         Ok(val) => val,
         Err(err) => {
-            return json_bytes(&json!({"ok": false, "error": format!("invalid json: {err}")}));
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
         }
     };
 
     let cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
     if !cfg.enabled {
-        return json_bytes(&json!({"ok": false, "error": "provider disabled by config"}));
+        return error_bytes(ProviderError::config_invalid("provider disabled by config"));
     }
 
     let envelope: ChannelMessageEnvelope = match serde_json::from_slice(input_json) {
         Ok(env) => env,
         Err(_) => match build_synthetic_envelope(&parsed, &cfg) {
             Ok(env) => env,
-            Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+            Err(err) => return error_bytes(ProviderError::validation(err)),
         },
     };
 
-    if !envelope.attachments.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "attachments not supported"}));
-    }
+    let media = match envelope
+        .attachments
+        .iter()
+        .map(OutboundMedia::from_attachment)
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(media) => media,
+        Err(err) => return error_bytes(err),
+    };
 
     let text = envelope
         .text
         .as_ref()
         .map(|value| value.trim())
         .filter(|value| !value.is_empty())
-        .map(ToOwned::to_owned);
-    let text = match text {
-        Some(value) => value,
-        None => return json_bytes(&json!({"ok": false, "error": "text required"})),
-    };
+        .map(ToOwned::to_owned)
+        .or_else(|| provider_common::extract_ac_text_summary(&envelope.metadata));
+    let actions = provider_common::extract_ac_actions(&envelope.metadata);
+    if text.is_none() && (media.is_empty() || !actions.is_empty()) {
+        return error_bytes(ProviderError::validation("text required"));
+    }
 
     let destination = envelope.to.first().cloned().or_else(|| {
         cfg.default_chat_id.clone().map(|chat| Destination {
@@ -655,69 +736,139 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
     });
     let destination = match destination {
         Some(dest) => dest,
-        None => return json_bytes(&json!({"ok": false, "error": "destination required"})),
+        None => return error_bytes(ProviderError::validation("destination required")),
     };
 
     let dest_id = destination.id.trim();
     if dest_id.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "destination id required"}));
+        return error_bytes(ProviderError::validation("destination id required"));
     }
     let dest_id = dest_id.to_string();
     let kind = destination.kind.as_deref().unwrap_or("chat");
     if kind != "chat" && !kind.is_empty() {
-        return json_bytes(&json!({
-            "ok": false,
-            "error": format!("unsupported destination kind: {kind}")
-        }));
+        return error_bytes(ProviderError::validation(format!(
+            "unsupported destination kind: {kind}"
+        )));
     }
 
     let token = match get_bot_token(&cfg) {
         Ok(s) => s,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
 
     let api_base = cfg
         .api_base_url
         .clone()
         .unwrap_or_else(|| DEFAULT_API_BASE.to_string());
-    let url = format!("{api_base}/bot{token}/sendMessage");
-    let payload = json!({ "chat_id": dest_id.clone(), "text": text });
-    let request = client::Request {
-        method: "POST".to_string(),
-        url,
-        headers: vec![("Content-Type".into(), "application/json".into())],
-        body: Some(serde_json::to_vec(&payload).unwrap_or_else(|_| b"{}".to_vec())),
+    let dialect = MarkdownDialect::TelegramMarkdownV2;
+    let keyboard = if actions.is_empty() {
+        None
+    } else {
+        match keyboard::build_inline_keyboard(
+            &actions,
+            &mut host_state(),
+            webhook_auth::now_unix_secs(),
+        ) {
+            Ok(keyboard) => Some(keyboard),
+            Err(err) => {
+                return error_bytes(ProviderError::other(format!(
+                    "callback token store failed: {err}"
+                )));
+            }
+        }
     };
+    // Buttons go on the last text message, so the text is not used as a
+    // caption when the card has actions.
+    let caption = text
+        .as_deref()
+        .map(|text| render_markdown(text, dialect))
+        .filter(|caption| {
+            !media.is_empty() && keyboard.is_none() && caption.chars().count() <= MAX_CAPTION_LEN
+        });
+    let mut calls = Vec::new();
+    if let Some(text) = text.as_deref().filter(|_| caption.is_none()) {
+        let options = SplitOptions::from_metadata(MAX_TEXT_LEN, &envelope.metadata);
+        let chunks = split_markdown(text, dialect, &options);
+        let last = chunks.len().saturating_sub(1);
+        for (index, chunk) in chunks.iter().enumerate() {
+            let mut payload = json!({
+                "chat_id": dest_id.clone(),
+                "text": render_markdown(chunk, dialect),
+                "parse_mode": dialect.telegram_parse_mode(),
+            });
+            if let Some(keyboard) = keyboard.as_ref().filter(|_| index == last) {
+                payload["reply_markup"] = keyboard.reply_markup();
+            }
+            calls.push(ApiCall::json("sendMessage", &payload));
+        }
+    }
+    calls.extend(media_calls(&dest_id, media, caption));
+    let threading = Threading::from_envelope(&envelope, &dest_id);
+    for (index, call) in calls.iter_mut().enumerate() {
+        threading.apply(call, index == 0);
+    }
 
-    let resp = match client::send(&request, None, None) {
-        Ok(resp) => resp,
-        Err(err) => {
-            return json_bytes(&json!({
-                "ok": false,
-                "error": format!("transport error: {}", err.message),
-            }));
+    let policy = RetryPolicy::from_input(&parsed);
+    let mut attempts = 0;
+    let mut message_ids = Vec::new();
+    let mut provider_message_ids = Vec::new();
+    let mut chat_message_ids = Vec::new();
+    let mut responses = Vec::new();
+    for call in calls {
+        let url = format!("{api_base}/bot{token}/{}", call.method);
+        let (content_type, body) = call.encode();
+        let request = client::Request {
+            method: "POST".to_string(),
+            url,
+            headers: vec![("Content-Type".into(), content_type)],
+            body: Some(body),
+        };
+
+        let outcome = send_http(&request.method, &policy, || {
+            client::send(&request, None, None)
+        });
+        attempts += outcome.attempts;
+        let resp = match outcome.result {
+            Ok(resp) => resp,
+            Err(err) => {
+                return error_bytes_after_parts(
+                    ProviderError::transport(err.message),
+                    attempts,
+                    &provider_message_ids,
+                );
+            }
+        };
+
+        if resp.status < 200 || resp.status >= 300 {
+            return error_bytes_after_parts(
+                telegram_status_error(&resp),
+                attempts,
+                &provider_message_ids,
+            );
         }
-    };
 
-    if resp.status < 200 || resp.status >= 300 {
-        return json_bytes(&json!({
-            "ok": false,
-            "error": format!("telegram returned status {}", resp.status),
-        }));
+        let body = resp.body.unwrap_or_default();
+        let body_json: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        for ids in extract_all_ids(&body_json) {
+            message_ids.push(ids.message_id);
+            provider_message_ids.push(ids.provider_message_id);
+            chat_message_ids.extend(ids.chat_message_id);
+        }
+        responses.push(body_json);
     }
 
-    let body = resp.body.unwrap_or_default();
-    let body_json: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
-    let (message_id, provider_message_id) = extract_ids(&body_json);
-
     json_bytes(&json!({
         "ok": true,
         "status": "sent",
         "provider_type": PROVIDER_TYPE,
         "public_base_url": cfg.public_base_url,
-        "message_id": message_id,
-        "provider_message_id": provider_message_id,
-        "response": body_json
+        "message_id": message_ids.first(),
+        "provider_message_id": provider_message_ids.first(),
+        "provider_message_ids": provider_message_ids,
+        "chat_message_id": chat_message_ids.first(),
+        "chat_message_ids": chat_message_ids,
+        "attempts": attempts,
+        "response": responses.first()
     }))
 }
 
@@ -725,16 +876,16 @@ fn handle_reply(input_json: &[u8]) -> Vec<u8> {
     let parsed: Value = match serde_json::from_slice(input_json) {
         Ok(val) => val,
         Err(err) => {
-            return json_bytes(&json!({"ok": false, "error": format!("invalid json: {err}")}));
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
         }
     };
 
     let cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
     if !cfg.enabled {
-        return json_bytes(&json!({"ok": false, "error": "provider disabled by config"}));
+        return error_bytes(ProviderError::config_invalid("provider disabled by config"));
     }
 
     let text = match parsed
@@ -743,7 +894,7 @@ fn handle_reply(input_json: &[u8]) -> Vec<u8> {
         .map(|s| s.to_string())
     {
         Some(t) if !t.is_empty() => t,
-        _ => return json_bytes(&json!({"ok": false, "error": "text required"})),
+        _ => return error_bytes(ProviderError::validation("text required")),
     };
 
     let chat_id = match parsed
@@ -753,7 +904,7 @@ fn handle_reply(input_json: &[u8]) -> Vec<u8> {
         .or_else(|| cfg.default_chat_id.clone())
     {
         Some(chat) if !chat.is_empty() => chat,
-        _ => return json_bytes(&json!({"ok": false, "error": "chat_id required"})),
+        _ => return error_bytes(ProviderError::validation("chat_id required")),
     };
 
     let reply_to = parsed
@@ -762,12 +913,29 @@ fn handle_reply(input_json: &[u8]) -> Vec<u8> {
         .and_then(|v| v.as_str())
         .unwrap_or("");
     if reply_to.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "reply_to_id or thread_id required"}));
+        return error_bytes(ProviderError::validation(
+            "reply_to_id or thread_id required",
+        ));
+    }
+    let message_thread_id = parsed.get("message_thread_id").and_then(|v| match v {
+        Value::Number(n) => Some(n.to_string()),
+        Value::String(s) => Some(s.clone()),
+        _ => None,
+    });
+    let threading = Threading::new(
+        message_thread_id.as_deref(),
+        Some(reply_to),
+        parsed.get("quote").and_then(Value::as_str),
+    );
+    if threading.reply_parameters.is_none() {
+        return error_bytes(ProviderError::validation(format!(
+            "reply_to_id is not a Telegram message id: {reply_to}"
+        )));
     }
 
     let token = match get_bot_token(&cfg) {
         Ok(s) => s,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
 
     let api_base = cfg
@@ -775,46 +943,50 @@ fn handle_reply(input_json: &[u8]) -> Vec<u8> {
         .clone()
         .unwrap_or_else(|| DEFAULT_API_BASE.to_string());
     let url = format!("{api_base}/bot{token}/sendMessage");
+    let dialect = MarkdownDialect::TelegramMarkdownV2;
     let payload = json!({
         "chat_id": chat_id,
-        "text": text,
-        "reply_to_message_id": reply_to
+        "text": render_markdown(&text, dialect),
+        "parse_mode": dialect.telegram_parse_mode(),
     });
+    let mut call = ApiCall::json("sendMessage", &payload);
+    threading.apply(&mut call, true);
+    let (content_type, body) = call.encode();
     let request = client::Request {
         method: "POST".to_string(),
         url,
-        headers: vec![("Content-Type".to_string(), "application/json".to_string())],
-        body: Some(serde_json::to_vec(&payload).unwrap_or_else(|_| b"{}".to_vec())),
+        headers: vec![("Content-Type".to_string(), content_type)],
+        body: Some(body),
     };
 
-    let resp = match client::send(&request, None, None) {
+    let outcome = send_http(&request.method, &RetryPolicy::from_input(&parsed), || {
+        client::send(&request, None, None)
+    });
+    let attempts = outcome.attempts;
+    let resp = match outcome.result {
         Ok(resp) => resp,
         Err(err) => {
-            return json_bytes(&json!({
-                "ok": false,
-                "error": format!("transport error: {}", err.message),
-            }));
+            return error_bytes_after(ProviderError::transport(err.message), attempts);
         }
     };
 
     if resp.status < 200 || resp.status >= 300 {
-        return json_bytes(&json!({
-            "ok": false,
-            "error": format!("telegram returned status {}", resp.status),
-        }));
+        return error_bytes_after(telegram_status_error(&resp), attempts);
     }
 
     let body_bytes = resp.body.unwrap_or_default();
     let body_json: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
-    let (message_id, provider_message_id) = extract_ids(&body_json);
+    let ids = extract_ids(&body_json);
 
     json_bytes(&json!({
         "ok": true,
         "status": "replied",
         "provider_type": PROVIDER_TYPE,
         "public_base_url": cfg.public_base_url,
-        "message_id": message_id,
-        "provider_message_id": provider_message_id,
+        "message_id": ids.message_id,
+        "provider_message_id": ids.provider_message_id,
+        "chat_message_id": ids.chat_message_id,
+        "attempts": attempts,
         "response": body_json
     }))
 }
@@ -828,19 +1000,43 @@ fn ingest_http(input_json: &[u8]) -> Vec<u8> {
         Ok(bytes) => bytes,
         Err(err) => return http_out_error(400, &format!("invalid body encoding: {err}")),
     };
+    if let Err(out) = verify_webhook(&request) {
+        return out;
+    }
     let body_val: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
-    let message = body_val.get("message").cloned().unwrap_or(Value::Null);
-    let text = extract_message_text(&message);
-    let chat_id = extract_chat_id(&message);
-    let from = extract_from_user(&message);
-    let envelope = build_telegram_envelope(text.clone(), chat_id.clone(), from.clone());
+    // The key is recorded once the delivery normalized, so a redelivery of one
+    // that failed part-way is processed again.
+    let dedup = dedup_key(&body_val, &webhook_bot_id(&request));
+    let window = DedupWindow::default();
+    let now = webhook_auth::now_unix_secs();
+    if let Some(key) = &dedup
+        && window.seen(&mut host_state(), PROVIDER_ID, key, now)
+    {
+        return duplicate_ack(key);
+    }
+    let Some(update) = normalize_update(&body_val, &mut host_state()) else {
+        let ignored = json!({"ok": true, "ignored": true, "event": body_val});
+        let out = HttpOutV1 {
+            status: 200,
+            headers: Vec::new(),
+            body_b64: STANDARD.encode(json_bytes(&ignored)),
+            events: Vec::new(),
+        };
+        return http_out_v1_bytes(&out);
+    };
+    if let Some(key) = &dedup {
+        window.record(&mut host_state(), PROVIDER_ID, key, now);
+    }
     let normalized = json!({
         "ok": true,
         "event": body_val,
-        "message": message,
-        "chat_id": chat_id,
-        "from": from
+        "event_kind": update.kind,
+        "message": update.message,
+        "chat_id": update.chat_id,
+        "from": update.from,
+        "action": update.action,
     });
+    let envelope = build_telegram_envelope(update);
     let normalized_bytes = serde_json::to_vec(&normalized).unwrap_or_else(|_| b"{}".to_vec());
     let out = HttpOutV1 {
         status: 200,
@@ -851,6 +1047,248 @@ fn ingest_http(input_json: &[u8]) -> Vec<u8> {
     http_out_v1_bytes(&out)
 }
 
+/// Update fields carrying a `Message`, with the `event_kind` they map to.
+const MESSAGE_UPDATES: &[(&str, &str)] = &[
+    ("message", "message"),
+    ("edited_message", "message_edited"),
+    ("channel_post", "channel_post"),
+    ("edited_channel_post", "channel_post_edited"),
+];
+/// Prefix of attachment urls that reference a Bot API `file_id`.
+const TELEGRAM_FILE_URL_PREFIX: &str = "telegram-file:";
+
+/// An inbound Update reduced to what the envelope needs.
+#[derive(Debug, Default)]
+struct InboundUpdate {
+    kind: &'static str,
+    update_id: Option<String>,
+    message: Value,
+    chat_id: Option<String>,
+    from: Option<String>,
+    text: Option<String>,
+    attachments: Vec<Attachment>,
+    metadata: BTreeMap<String, String>,
+    action: Option<Value>,
+    /// Forum topic the message belongs to.
+    thread_id: Option<String>,
+}
+
+/// Maps the Update types bots act on; `None` for the ones we ignore.
+fn normalize_update(body: &Value, store: &mut impl StateStore) -> Option<InboundUpdate> {
+    let mut update = MESSAGE_UPDATES
+        .iter()
+        .find_map(|(field, kind)| Some(message_update(kind, body.get(*field)?)))
+        .or_else(|| {
+            body.get("callback_query")
+                .map(|query| callback_update(query, store))
+        })
+        .or_else(|| body.get("my_chat_member").map(membership_update))
+        .or_else(|| body.get("message_reaction").map(reaction_update))??;
+    update.update_id = key_fragment(body.get("update_id"));
+    update.thread_id = topic_thread_id(&update.message);
+    if let Some(thread) = &update.thread_id {
+        update
+            .metadata
+            .insert("message_thread_id".to_string(), thread.clone());
+    }
+    Some(update)
+}
+
+/// `message_thread_id` of a forum topic message. Replies outside topics carry
+/// it too, so only messages marked `is_topic_message` count.
+fn topic_thread_id(message: &Value) -> Option<String> {
+    message
+        .get("is_topic_message")
+        .and_then(Value::as_bool)
+        .filter(|topic| *topic)
+        .and_then(|_| key_fragment(message.get("message_thread_id")))
+}
+
+fn message_update(kind: &'static str, message: &Value) -> Option<InboundUpdate> {
+    let text = message
+        .get("text")
+        .or_else(|| message.get("caption"))
+        .and_then(Value::as_str)
+        .map(ToOwned::to_owned);
+    let attachments = message_attachments(message);
+    if text.is_none() && attachments.is_empty() {
+        return None;
+    }
+    let mut metadata = BTreeMap::new();
+    if let Some(id) = key_fragment(message.get("message_id")) {
+        if kind.ends_with("_edited") {
+            metadata.insert("original_message_id".to_string(), id.clone());
+        }
+        metadata.insert("message_id".to_string(), id);
+    }
+    if let Some(date) = key_fragment(message.get("edit_date")) {
+        metadata.insert("edit_date".to_string(), date);
+    }
+    if let Some(id) = key_fragment(message.pointer("/reply_to_message/message_id")) {
+        metadata.insert("reply_to_message_id".to_string(), id);
+    }
+    Some(InboundUpdate {
+        kind,
+        chat_id: extract_chat_id(message),
+        from: extract_from_user(message).or_else(|| {
+            message
+                .pointer("/sender_chat/id")
+                .and_then(Value::as_i64)
+                .map(|id| id.to_string())
+        }),
+        message: message.clone(),
+        text,
+        attachments,
+        metadata,
+        ..InboundUpdate::default()
+    })
+}
+
+/// A button press; `data` of the action is the submit data the button was
+/// rendered from, resolved through the callback token store when needed.
+fn callback_update(query: &Value, store: &mut impl StateStore) -> Option<InboundUpdate> {
+    let query_id = query.get("id").and_then(Value::as_str)?;
+    let message = query.get("message").cloned().unwrap_or(Value::Null);
+    let raw_data = query.get("data").and_then(Value::as_str);
+    let now = webhook_auth::now_unix_secs();
+    let data = raw_data.map(|raw| keyboard::resolve_callback_data(raw, store, now));
+    let action = json!({
+        "type": "callback",
+        "callback_query_id": query_id,
+        "data": data.clone().flatten().or_else(|| raw_data.map(Value::from)),
+        "message_id": message.get("message_id"),
+        "inline_message_id": query.get("inline_message_id"),
+    });
+    let mut metadata = BTreeMap::new();
+    metadata.insert("callback_query_id".to_string(), query_id.to_string());
+    if let Some(raw) = raw_data {
+        metadata.insert("callback_data".to_string(), raw.to_string());
+    }
+    if data.is_some_and(|data| data.is_none()) {
+        metadata.insert("callback_unresolved".to_string(), "true".to_string());
+    }
+    if let Some(id) = key_fragment(message.get("message_id")) {
+        metadata.insert("message_id".to_string(), id);
+    }
+    metadata.insert("action".to_string(), action.to_string());
+    Some(InboundUpdate {
+        kind: "callback_query",
+        chat_id: extract_chat_id(&message),
+        from: extract_from_user(query),
+        message,
+        action: Some(action),
+        metadata,
+        ..InboundUpdate::default()
+    })
+}
+
+/// `my_chat_member`: the bot's own membership in a chat changed.
+fn membership_update(change: &Value) -> Option<InboundUpdate> {
+    let old_status = change.pointer("/old_chat_member/status")?.as_str()?;
+    let new_status = change.pointer("/new_chat_member/status")?.as_str()?;
+    let is_member = |status: &str| !matches!(status, "left" | "kicked");
+    let kind = match (is_member(old_status), is_member(new_status)) {
+        (false, true) => "bot_added",
+        (true, false) => "bot_removed",
+        _ => "bot_membership_changed",
+    };
+    let mut metadata = BTreeMap::new();
+    metadata.insert("old_status".to_string(), old_status.to_string());
+    metadata.insert("new_status".to_string(), new_status.to_string());
+    Some(InboundUpdate {
+        kind,
+        chat_id: extract_chat_id(change),
+        from: extract_from_user(change),
+        message: change.clone(),
+        metadata,
+        ..InboundUpdate::default()
+    })
+}
+
+fn reaction_update(reaction: &Value) -> Option<InboundUpdate> {
+    let message_id = key_fragment(reaction.get("message_id"))?;
+    let reactions = |field: &str| -> Vec<String> {
+        reaction
+            .get(field)
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|item| {
+                item.get("emoji")
+                    .or_else(|| item.get("custom_emoji_id"))
+                    .and_then(Value::as_str)
+                    .map(ToOwned::to_owned)
+                    .or_else(|| item.get("type").and_then(Value::as_str).map(Into::into))
+            })
+            .collect()
+    };
+    let old = reactions("old_reaction");
+    let new = reactions("new_reaction");
+    let added: Vec<_> = new.iter().filter(|r| !old.contains(r)).cloned().collect();
+    let removed: Vec<_> = old.iter().filter(|r| !new.contains(r)).cloned().collect();
+    let mut metadata = BTreeMap::new();
+    metadata.insert("message_id".to_string(), message_id);
+    metadata.insert("reactions_added".to_string(), added.join(","));
+    metadata.insert("reactions_removed".to_string(), removed.join(","));
+    let from = reaction
+        .pointer("/user/id")
+        .or_else(|| reaction.pointer("/actor_chat/id"))
+        .and_then(Value::as_i64)
+        .map(|id| id.to_string());
+    Some(InboundUpdate {
+        kind: "reaction",
+        chat_id: extract_chat_id(reaction),
+        from,
+        message: reaction.clone(),
+        metadata,
+        ..InboundUpdate::default()
+    })
+}
+
+/// Media of a message as attachments whose url carries the `file_id`; the
+/// bytes are fetched separately through `getFile`.
+fn message_attachments(message: &Value) -> Vec<Attachment> {
+    const MEDIA_FIELDS: &[(&str, &str)] = &[
+        ("document", "application/octet-stream"),
+        ("video", "video/mp4"),
+        ("animation", "video/mp4"),
+        ("video_note", "video/mp4"),
+        ("audio", "audio/mpeg"),
+        ("voice", "audio/ogg"),
+        ("sticker", "image/webp"),
+    ];
+    // Photos arrive as several sizes; the last one is the largest.
+    let photo = message
+        .get("photo")
+        .and_then(Value::as_array)
+        .and_then(|sizes| sizes.last())
+        .map(|size| (size, "image/jpeg"));
+    photo
+        .into_iter()
+        .chain(
+            MEDIA_FIELDS
+                .iter()
+                .filter_map(|(field, mime)| Some((message.get(*field)?, *mime))),
+        )
+        .filter_map(|(file, default_mime)| {
+            let file_id = file.get("file_id")?.as_str()?;
+            Some(Attachment {
+                mime_type: file
+                    .get("mime_type")
+                    .and_then(Value::as_str)
+                    .unwrap_or(default_mime)
+                    .to_string(),
+                url: format!("{TELEGRAM_FILE_URL_PREFIX}{file_id}"),
+                name: file
+                    .get("file_name")
+                    .and_then(Value::as_str)
+                    .map(ToOwned::to_owned),
+                size_bytes: file.get("file_size").and_then(Value::as_u64),
+            })
+        })
+        .collect()
+}
+
 fn render_plan(input_json: &[u8]) -> Vec<u8> {
     match std::panic::catch_unwind(|| render_plan_inner(input_json)) {
         Ok(result) => result,
@@ -877,20 +1315,41 @@ fn render_plan_inner(input_json: &[u8]) -> Vec<u8> {
         })
         .unwrap_or_else(|| "telegram message".to_string());
     let mut warnings: Vec<Value> = Vec::new();
+    let actions = provider_common::extract_ac_actions(&plan_in.message.metadata);
+    let capacity = keyboard::MAX_BUTTONS_PER_ROW * keyboard::MAX_BUTTON_ROWS;
     if plan_in.message.metadata.contains_key("adaptive_card") {
-        warnings
-            .push(json!({"code": "adaptive_cards_not_supported", "message": null, "path": null}));
+        warnings.push(json!({
+            "code": "adaptive_card_downsampled",
+            "message": "card rendered as message text, with its actions as inline keyboard buttons",
+            "path": null,
+        }));
     }
+    if actions.len() > capacity {
+        warnings.push(json!({
+            "code": "actions_dropped",
+            "message": format!("only the first {capacity} actions fit the inline keyboard"),
+            "path": null,
+        }));
+    }
+    let titles: Vec<&str> = actions
+        .iter()
+        .take(capacity)
+        .map(|action| match action {
+            provider_common::AcAction::OpenUrl { title, .. }
+            | provider_common::AcAction::Submit { title, .. } => title.as_str(),
+        })
+        .collect();
+    let tier = if titles.is_empty() { "TierD" } else { "TierC" };
     let plan_obj = json!({
-        "tier": "TierD",
+        "tier": tier,
         "summary_text": summary,
-        "actions": [],
+        "actions": titles,
         "attachments": [],
         "warnings": warnings,
         "debug": {},
     });
     let plan_json =
-        serde_json::to_string(&plan_obj).unwrap_or_else(|_| "{\"tier\":\"TierD\"}".to_string());
+        serde_json::to_string(&plan_obj).unwrap_or_else(|_| format!("{{\"tier\":\"{tier}\"}}"));
     let plan_out = RenderPlanOutV1 { plan_json };
     json_bytes(&json!({"ok": true, "plan": plan_out}))
 }
@@ -923,78 +1382,113 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
     let send_in = match serde_json::from_slice::<SendPayloadInV1>(input_json) {
         Ok(value) => value,
         Err(err) => {
-            return send_payload_error(&format!("invalid send_payload input: {err}"), false);
+            return send_payload_error(ProviderError::validation(format!(
+                "invalid send_payload input: {err}"
+            )));
         }
     };
     if send_in.provider_type != PROVIDER_TYPE {
-        return send_payload_error("provider type mismatch", false);
+        return send_payload_error(ProviderError::validation("provider type mismatch"));
     }
     let payload_bytes = match STANDARD.decode(&send_in.payload.body_b64) {
         Ok(bytes) => bytes,
         Err(err) => {
-            return send_payload_error(&format!("payload decode failed: {err}"), false);
+            return send_payload_error(ProviderError::validation(format!(
+                "payload decode failed: {err}"
+            )));
         }
     };
-    let payload: Value = serde_json::from_slice(&payload_bytes).unwrap_or(Value::Null);
+    let mut payload: Value = serde_json::from_slice(&payload_bytes).unwrap_or(Value::Null);
+    if let (Some(map), Some(runtime)) = (payload.as_object_mut(), input_runtime_config(input_json))
+    {
+        map.entry("runtime_config").or_insert(runtime);
+    }
     match forward_send_payload(&payload) {
-        Ok(_) => send_payload_success(),
-        Err(err) => send_payload_error(&err, false),
+        Ok(result) => send_payload_from_send_result(&result),
+        Err(err) => send_payload_error(err),
     }
 }
 
-fn forward_send_payload(payload: &Value) -> Result<(), String> {
-    let payload_bytes =
-        serde_json::to_vec(payload).map_err(|err| format!("serialize failed: {err}"))?;
+fn input_runtime_config(input_json: &[u8]) -> Option<Value> {
+    serde_json::from_slice::<Value>(input_json)
+        .ok()?
+        .get("runtime_config")
+        .cloned()
+}
+
+fn forward_send_payload(payload: &Value) -> Result<Value, ProviderError> {
+    let payload_bytes = serde_json::to_vec(payload)
+        .map_err(|err| ProviderError::validation(format!("serialize failed: {err}")))?;
     let result = handle_send(&payload_bytes);
-    let result_value: Value =
-        serde_json::from_slice(&result).map_err(|err| format!("parse send result: {err}"))?;
-    let ok = result_value
-        .get("ok")
-        .and_then(Value::as_bool)
-        .unwrap_or(false);
-    if ok {
-        Ok(())
-    } else {
-        let message = result_value
-            .get("error")
-            .and_then(Value::as_str)
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| "send_payload failed".to_string());
-        Err(message)
+    serde_json::from_slice(&result)
+        .map_err(|err| ProviderError::other(format!("parse send result: {err}")))
+}
+
+/// Maps a `handle_send` result onto `SendPayloadResultV1`, keeping the
+/// classified error so `retryable` follows the error code.
+fn send_payload_from_send_result(result: &Value) -> Vec<u8> {
+    let attempts = result.get("attempts").and_then(Value::as_u64).unwrap_or(0) as u32;
+    if result.get("ok").and_then(Value::as_bool).unwrap_or(false) {
+        let provider_message_id = result.get("provider_message_id").and_then(Value::as_str);
+        return send_payload_success_after(attempts, provider_message_id);
     }
+    let message = result
+        .get("error")
+        .and_then(Value::as_str)
+        .unwrap_or("send_payload failed");
+    let detail = result
+        .get("error_detail")
+        .cloned()
+        .and_then(|value| serde_json::from_value::<ProviderErrorDetail>(value).ok())
+        .unwrap_or_else(|| ProviderError::other(message).detail());
+    send_payload_failure(message, &detail, attempts)
 }
 
-fn build_telegram_envelope(
-    text: String,
-    chat_id: Option<String>,
-    from: Option<String>,
-) -> ChannelMessageEnvelope {
+fn build_telegram_envelope(update: InboundUpdate) -> ChannelMessageEnvelope {
     let env = EnvId::try_from("default").expect("env id");
     let tenant = TenantId::try_from("default").expect("tenant id");
     let mut metadata = MessageMetadata::new();
     metadata.insert("universal".to_string(), "true".to_string());
-    if let Some(chat) = &chat_id {
+    metadata.insert("event_kind".to_string(), update.kind.to_string());
+    if let Some(chat) = &update.chat_id {
         metadata.insert("chat_id".to_string(), chat.clone());
     }
-    if let Some(sender) = &from {
+    if let Some(sender) = &update.from {
         metadata.insert("from".to_string(), sender.clone());
     }
+    if let Some(id) = &update.update_id {
+        metadata.insert("update_id".to_string(), id.clone());
+    }
+    let reply_scope = update.chat_id.clone().map(|conversation| ReplyScope {
+        conversation,
+        thread: update.thread_id.clone(),
+        reply_to: update.metadata.get("message_id").cloned(),
+        correlation: None,
+    });
+    metadata.extend(update.metadata);
     let channel = "telegram".to_string();
-    let sender = from.map(|id| Actor {
+    let sender = update.from.map(|id| Actor {
         id,
         kind: Some("user".into()),
     });
     ChannelMessageEnvelope {
-        id: format!("telegram-{channel}"),
+        id: format!(
+            "telegram-{}",
+            update.update_id.as_deref().unwrap_or(&channel)
+        ),
         tenant: TenantCtx::new(env.clone(), tenant.clone()),
         channel: channel.clone(),
-        session_id: chat_id.clone().unwrap_or_else(|| "telegram".to_string()),
-        reply_scope: None,
+        session_id: match (&update.chat_id, &update.thread_id) {
+            (Some(chat), Some(thread)) => format!("{chat}:{thread}"),
+            (Some(chat), None) => chat.clone(),
+            (None, _) => "telegram".to_string(),
+        },
+        reply_scope,
         from: sender,
         to: Vec::new(),
         correlation_id: None,
-        text: Some(text),
-        attachments: Vec::new(),
+        text: update.text,
+        attachments: update.attachments,
         metadata,
     }
 }
@@ -1046,14 +1540,6 @@ fn build_synthetic_envelope(
     })
 }
 
-fn extract_message_text(value: &Value) -> String {
-    value
-        .get("text")
-        .and_then(Value::as_str)
-        .unwrap_or("")
-        .to_string()
-}
-
 fn extract_chat_id(value: &Value) -> Option<String> {
     value
         .get("chat")
@@ -1089,6 +1575,85 @@ fn http_out_error(status: u16, message: &str) -> Vec<u8> {
     http_out_v1_bytes(&out)
 }
 
+/// Rejects the request with a 401 when `TELEGRAM_WEBHOOK_SECRET` is configured
+/// and the `secret_token` header set via `setWebhook` does not match.
+fn verify_webhook(request: &HttpInV1) -> Result<(), Vec<u8>> {
+    let secret = match secrets_store::get(WEBHOOK_SECRET_KEY) {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return Ok(()),
+        Err(e) => return Err(http_out_error(500, &format!("secret store error: {e:?}"))),
+    };
+    webhook_auth::verify_shared_token(
+        &String::from_utf8_lossy(&secret),
+        request_header(request, TELEGRAM_SECRET_TOKEN_HEADER),
+        TELEGRAM_SECRET_TOKEN_HEADER,
+    )
+    .map_err(|err| http_out_error(401, &ProviderError::from(err).to_string()))
+}
+
+fn host_state() -> HostStateAdapter {
+    HostStateAdapter::new(
+        |key| state_store::read(key, None).map_err(|err| err.message),
+        |key, value| {
+            state_store::write(key, value, None)
+                .map(|_| ())
+                .map_err(|err| err.message)
+        },
+        |key| {
+            state_store::delete(key, None)
+                .map(|_| ())
+                .map_err(|err| err.message)
+        },
+    )
+}
+
+/// Acknowledges a redelivered webhook without emitting events.
+fn duplicate_ack(key: &str) -> Vec<u8> {
+    let out = HttpOutV1 {
+        status: 200,
+        headers: Vec::new(),
+        body_b64: STANDARD.encode(duplicate_ack_body(PROVIDER_ID, key)),
+        events: Vec::new(),
+    };
+    http_out_v1_bytes(&out)
+}
+
+/// Telegram redelivers an update with the same `update_id`. Update ids are
+/// only unique per bot, so the key is scoped by the bot id.
+fn dedup_key(body: &Value, bot_id: &str) -> Option<String> {
+    let id = key_fragment(body.get("update_id")).or_else(|| {
+        let message = body.get("message")?;
+        let chat_id = key_fragment(message.pointer("/chat/id"))?;
+        let message_id = key_fragment(message.get("message_id"))?;
+        Some(format!("{chat_id}:{message_id}"))
+    })?;
+    Some(format!("{bot_id}:{id}"))
+}
+
+/// The public part of a bot token, before `:`.
+fn bot_id(token: &str) -> &str {
+    token.split(':').next().unwrap_or_default()
+}
+
+/// Bot id for an inbound webhook; empty when no token is configured.
+fn webhook_bot_id(request: &HttpInV1) -> String {
+    let configured = request
+        .config
+        .as_ref()
+        .and_then(|cfg| cfg.get("bot_token"))
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(str::to_string);
+    configured
+        .or_else(|| {
+            let bytes = secrets_store::get(TOKEN_SECRET).ok().flatten()?;
+            String::from_utf8(bytes).ok()
+        })
+        .map(|token| bot_id(token.trim()).to_string())
+        .unwrap_or_default()
+}
+
 fn render_plan_error(message: &str) -> Vec<u8> {
     json_bytes(&json!({"ok": false, "error": message}))
 }
@@ -1097,25 +1662,165 @@ fn encode_error(message: &str) -> Vec<u8> {
     json_bytes(&json!({"ok": false, "error": message}))
 }
 
-fn send_payload_error(message: &str, retryable: bool) -> Vec<u8> {
+fn error_bytes(err: ProviderError) -> Vec<u8> {
+    json_bytes(&err.to_result_json())
+}
+
+fn error_bytes_after(err: ProviderError, attempts: u32) -> Vec<u8> {
+    let mut value = err.to_result_json();
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+    }
+    json_bytes(&value)
+}
+
+/// Error result of a split send; ids of the parts already delivered are kept
+/// so callers do not resend them.
+fn error_bytes_after_parts(
+    err: ProviderError,
+    attempts: u32,
+    provider_message_ids: &[String],
+) -> Vec<u8> {
+    let mut value = err.to_result_json();
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+        if !provider_message_ids.is_empty() {
+            map.insert("provider_message_ids".into(), json!(provider_message_ids));
+        }
+    }
+    json_bytes(&value)
+}
+
+fn send_payload_error(err: ProviderError) -> Vec<u8> {
+    send_payload_error_after(err, 0)
+}
+
+fn send_payload_error_after(err: ProviderError, attempts: u32) -> Vec<u8> {
+    send_payload_failure(&err.to_string(), &err.detail(), attempts)
+}
+
+fn send_payload_failure(message: &str, detail: &ProviderErrorDetail, attempts: u32) -> Vec<u8> {
     let result = SendPayloadResultV1 {
         ok: false,
         message: Some(message.to_string()),
-        retryable,
+        retryable: detail.retryable,
     };
-    json_bytes(&result)
+    send_payload_result_bytes(&result, attempts, Some(detail), &[])
 }
 
-fn send_payload_success() -> Vec<u8> {
+fn send_payload_success_after(attempts: u32, provider_message_id: Option<&str>) -> Vec<u8> {
     let result = SendPayloadResultV1 {
         ok: true,
         message: None,
         retryable: false,
     };
-    json_bytes(&result)
+    let mut value = serde_json::to_value(&result).unwrap_or(Value::Null);
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+        if let Some(id) = provider_message_id {
+            map.insert("provider_message_id".into(), json!(id));
+        }
+    }
+    json_bytes(&value)
+}
+
+impl RetryableResponse for client::Response {
+    fn status(&self) -> u16 {
+        self.status
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
 }
 
-fn extract_ids(body: &Value) -> (String, String) {
+/// Classifies a non-2xx Bot API response, using the `description` and
+/// `parameters.retry_after` fields Telegram puts in error bodies.
+fn telegram_status_error(resp: &client::Response) -> ProviderError {
+    let body: Value = resp
+        .body
+        .as_deref()
+        .and_then(|bytes| serde_json::from_slice(bytes).ok())
+        .unwrap_or(Value::Null);
+    let description = body
+        .get("description")
+        .and_then(Value::as_str)
+        .map(ToOwned::to_owned);
+    let retry_after_secs = body
+        .pointer("/parameters/retry_after")
+        .and_then(Value::as_u64)
+        .or_else(|| retry_after(resp).map(|delay| delay.as_secs()));
+    let lowered = description
+        .as_deref()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if lowered.contains("chat not found") || lowered.contains("user not found") {
+        return ProviderError::destination_not_found(
+            description.unwrap_or_else(|| "chat not found".into()),
+        );
+    }
+    if lowered.contains("message is too long") {
+        return ProviderError::payload_too_large(
+            description.unwrap_or_else(|| "message is too long".into()),
+        );
+    }
+    ProviderError::from_http_status("telegram", resp.status, retry_after_secs, description)
+}
+
+/// Calls a Bot API method that has the same effect when repeated, so
+/// transport failures are retried. Returns the response body and the number
+/// of attempts.
+fn call_bot_api(
+    cfg: &ProviderConfig,
+    method: &str,
+    payload: &Value,
+    policy: &RetryPolicy,
+) -> Result<(Value, u32), (ProviderError, u32)> {
+    let token = get_bot_token(cfg).map_err(|err| (err, 0))?;
+    let api_base = cfg.api_base_url.as_deref().unwrap_or(DEFAULT_API_BASE);
+    let request = client::Request {
+        method: "POST".to_string(),
+        url: format!("{api_base}/bot{token}/{method}"),
+        headers: vec![("Content-Type".into(), "application/json".into())],
+        body: Some(json_bytes(payload)),
+    };
+    let outcome = send_with_retry(policy, Idempotency::Idempotent, || {
+        client::send(&request, None, None)
+    });
+    let attempts = outcome.attempts;
+    let resp = outcome
+        .result
+        .map_err(|err| (ProviderError::transport(err.message), attempts))?;
+    if resp.status < 200 || resp.status >= 300 {
+        return Err((telegram_status_error(&resp), attempts));
+    }
+    let body = resp
+        .body
+        .as_deref()
+        .and_then(|bytes| serde_json::from_slice(bytes).ok())
+        .unwrap_or(Value::Null);
+    Ok((body, attempts))
+}
+
+/// Ids of a sent message. `provider_message_id` is `tg:<message_id>`;
+/// `chat_message_id` adds the chat (`tg:<chat_id>:<message_id>`) so later ops
+/// can address the message without a separate `chat_id`.
+#[derive(Debug, Clone, PartialEq)]
+struct SentIds {
+    message_id: String,
+    provider_message_id: String,
+    chat_message_id: Option<String>,
+}
+
+fn provider_message_id(message_id: &str) -> String {
+    format!("tg:{message_id}")
+}
+
+fn chat_message_id(chat_id: &str, message_id: &str) -> String {
+    format!("tg:{chat_id}:{message_id}")
+}
+
+fn extract_ids(body: &Value) -> SentIds {
     let message_id = body
         .get("result")
         .and_then(|v| v.get("message_id"))
@@ -1125,24 +1830,333 @@ fn extract_ids(body: &Value) -> (String, String) {
             other => other.to_string(),
         })
         .unwrap_or_else(|| "dummy-message-id".into());
-    let provider_message_id = format!("tg:{message_id}");
-    (message_id, provider_message_id)
+    SentIds {
+        provider_message_id: provider_message_id(&message_id),
+        chat_message_id: key_fragment(body.pointer("/result/chat/id"))
+            .map(|chat_id| chat_message_id(&chat_id, &message_id)),
+        message_id,
+    }
+}
+
+/// Splits a `provider_message_id` or `chat_message_id` (or a bare message id)
+/// into the chat id, when it carries one, and the message id.
+fn parse_provider_message_id(id: &str) -> Option<(Option<String>, String)> {
+    let id = id.trim();
+    let id = id.strip_prefix("tg:").unwrap_or(id);
+    let (chat_id, message_id) = match id.rsplit_once(':') {
+        Some((chat_id, message_id)) => (Some(chat_id.to_string()), message_id),
+        None => (None, id),
+    };
+    (!message_id.is_empty() && message_id.chars().all(|c| c.is_ascii_digit()))
+        .then(|| (chat_id, message_id.to_string()))
+}
+
+/// Ids of every message in a Bot API result; `sendMediaGroup` returns an
+/// array of messages, the other send methods a single one.
+fn extract_all_ids(body: &Value) -> Vec<SentIds> {
+    match body.get("result") {
+        Some(Value::Array(messages)) => messages
+            .iter()
+            .map(|message| extract_ids(&json!({ "result": message })))
+            .collect(),
+        _ => vec![extract_ids(body)],
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MediaKind {
+    Photo,
+    Video,
+    Audio,
+    Document,
+}
+
+impl MediaKind {
+    /// Formats Telegram renders natively; anything else goes out as a document.
+    fn from_mime(mime_type: &str) -> Self {
+        match mime_type {
+            "image/jpeg" | "image/jpg" | "image/png" | "image/webp" => Self::Photo,
+            "video/mp4" => Self::Video,
+            "audio/mpeg" | "audio/mp3" | "audio/mp4" | "audio/m4a" | "audio/x-m4a" => Self::Audio,
+            _ => Self::Document,
+        }
+    }
+
+    /// Field name of the file in single sends, and the `InputMedia` type.
+    fn field(self) -> &'static str {
+        match self {
+            Self::Photo => "photo",
+            Self::Video => "video",
+            Self::Audio => "audio",
+            Self::Document => "document",
+        }
+    }
+
+    fn method(self) -> &'static str {
+        match self {
+            Self::Photo => "sendPhoto",
+            Self::Video => "sendVideo",
+            Self::Audio => "sendAudio",
+            Self::Document => "sendDocument",
+        }
+    }
+
+    /// Albums may mix photos and videos; audio and documents only group with
+    /// their own kind.
+    fn groups_with(self, other: Self) -> bool {
+        match (self, other) {
+            (Self::Photo | Self::Video, Self::Photo | Self::Video) => true,
+            _ => self == other,
+        }
+    }
 }
 
-fn parse_config_value(val: &Value) -> Result<ProviderConfig, String> {
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct OutboundMedia {
+    kind: MediaKind,
+    source: AttachmentSource,
+    filename: String,
+    mime_type: String,
+}
+
+impl OutboundMedia {
+    fn from_attachment(attachment: &Attachment) -> Result<Self, ProviderError> {
+        let source = AttachmentSource::parse(&attachment.url)?;
+        let declared = attachment.mime_type.trim().to_ascii_lowercase();
+        let mime_type = match &source {
+            AttachmentSource::Inline {
+                mime_type: Some(inline),
+                ..
+            } if declared.is_empty() => inline.clone(),
+            _ if declared.is_empty() => "application/octet-stream".to_string(),
+            _ => declared,
+        };
+        let kind = MediaKind::from_mime(&mime_type);
+        let filename = attachment
+            .name
+            .clone()
+            .filter(|name| !name.trim().is_empty())
+            .unwrap_or_else(|| kind.field().to_string());
+        Ok(Self {
+            kind,
+            source,
+            filename,
+            mime_type,
+        })
+    }
+}
+
+#[derive(Debug)]
+enum ApiBody {
+    Json(Value),
+    Multipart(MultipartForm),
+}
+
+/// A prepared Bot API request; parameters can still be added until it is
+/// encoded.
+#[derive(Debug)]
+struct ApiCall {
+    method: &'static str,
+    body: ApiBody,
+}
+
+impl ApiCall {
+    fn json(method: &'static str, payload: &Value) -> Self {
+        Self {
+            method,
+            body: ApiBody::Json(payload.clone()),
+        }
+    }
+
+    fn multipart(method: &'static str, form: MultipartForm) -> Self {
+        Self {
+            method,
+            body: ApiBody::Multipart(form),
+        }
+    }
+
+    /// Sets a top-level parameter; multipart bodies carry non-string values
+    /// as JSON text.
+    fn set_param(&mut self, name: &str, value: &Value) {
+        match &mut self.body {
+            ApiBody::Json(payload) => payload[name] = value.clone(),
+            ApiBody::Multipart(form) => {
+                let text = match value {
+                    Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                };
+                *form = std::mem::take(form).text(name, text);
+            }
+        }
+    }
+
+    /// `Content-Type` and bytes of the request body.
+    fn encode(self) -> (String, Vec<u8>) {
+        match self.body {
+            ApiBody::Json(payload) => ("application/json".into(), json_bytes(&payload)),
+            ApiBody::Multipart(form) => form.finish(),
+        }
+    }
+}
+
+/// Forum topic and reply target of an outbound message.
+#[derive(Debug, Default, PartialEq)]
+struct Threading {
+    message_thread_id: Option<i64>,
+    reply_parameters: Option<Value>,
+}
+
+impl Threading {
+    /// `reply_to` may be a message id or a `provider_message_id`; `quote` is
+    /// the part of the replied-to message to quote.
+    fn new(thread: Option<&str>, reply_to: Option<&str>, quote: Option<&str>) -> Self {
+        let reply_parameters = reply_to
+            .and_then(parse_provider_message_id)
+            .and_then(|(_, id)| id.parse::<i64>().ok())
+            .map(|message_id| {
+                let mut params = json!({ "message_id": message_id });
+                if let Some(quote) = quote.filter(|quote| !quote.trim().is_empty()) {
+                    params["quote"] = json!(quote);
+                }
+                params
+            });
+        Self {
+            message_thread_id: thread.and_then(|id| id.trim().parse().ok()),
+            reply_parameters,
+        }
+    }
+
+    /// Reads `message_thread_id`, `reply_to_message_id` and `quote` from the
+    /// metadata, falling back to the reply scope of an inbound message in
+    /// the same chat.
+    fn from_envelope(envelope: &ChannelMessageEnvelope, chat_id: &str) -> Self {
+        let scope = envelope
+            .reply_scope
+            .as_ref()
+            .filter(|scope| scope.conversation.is_empty() || scope.conversation == chat_id);
+        let metadata = |key: &str| envelope.metadata.get(key).map(String::as_str);
+        Self::new(
+            metadata("message_thread_id").or_else(|| scope?.thread.as_deref()),
+            metadata("reply_to_message_id").or_else(|| scope?.reply_to.as_deref()),
+            metadata("quote"),
+        )
+    }
+
+    /// Every part goes to the topic; only the first one is the reply.
+    fn apply(&self, call: &mut ApiCall, first: bool) {
+        if let Some(thread) = self.message_thread_id {
+            call.set_param("message_thread_id", &json!(thread));
+        }
+        if let Some(params) = self.reply_parameters.as_ref().filter(|_| first) {
+            call.set_param("reply_parameters", params);
+        }
+    }
+}
+
+/// Requests sending `media` in order: consecutive attachments that may share
+/// an album go out through `sendMediaGroup`, the rest one by one. `caption`
+/// (already rendered MarkdownV2) goes on the first item. Inline attachments
+/// are uploaded as `multipart/form-data`, URLs are passed for Telegram to
+/// fetch.
+fn media_calls(chat_id: &str, media: Vec<OutboundMedia>, caption: Option<String>) -> Vec<ApiCall> {
+    let mut groups: Vec<Vec<OutboundMedia>> = Vec::new();
+    for item in media {
+        match groups.last_mut() {
+            Some(group)
+                if group.len() < MAX_MEDIA_GROUP_LEN && group[0].kind.groups_with(item.kind) =>
+            {
+                group.push(item)
+            }
+            _ => groups.push(vec![item]),
+        }
+    }
+
+    let mut caption = caption;
+    let parse_mode = MarkdownDialect::TelegramMarkdownV2.telegram_parse_mode();
+    groups
+        .into_iter()
+        .map(|mut group| {
+            let caption = caption.take();
+            if group.len() == 1 {
+                let item = group.remove(0);
+                let field = item.kind.field();
+                return match item.source {
+                    AttachmentSource::Url(url) => {
+                        let mut payload = json!({ "chat_id": chat_id, field: url });
+                        if let Some(caption) = caption {
+                            payload["caption"] = json!(caption);
+                            payload["parse_mode"] = json!(parse_mode);
+                        }
+                        ApiCall::json(item.kind.method(), &payload)
+                    }
+                    AttachmentSource::Inline { bytes, .. } => {
+                        let mut form = MultipartForm::new().text("chat_id", chat_id);
+                        if let Some(caption) = caption {
+                            form = form.text("caption", caption);
+                            if let Some(mode) = parse_mode {
+                                form = form.text("parse_mode", mode);
+                            }
+                        }
+                        ApiCall::multipart(
+                            item.kind.method(),
+                            form.file(field, item.filename, item.mime_type, bytes),
+                        )
+                    }
+                };
+            }
+
+            let mut files = Vec::new();
+            let mut entries = Vec::new();
+            for (index, item) in group.into_iter().enumerate() {
+                let reference = match item.source {
+                    AttachmentSource::Url(url) => url,
+                    AttachmentSource::Inline { bytes, .. } => {
+                        let name = format!("file{index}");
+                        let reference = format!("attach://{name}");
+                        files.push((name, item.filename, item.mime_type, bytes));
+                        reference
+                    }
+                };
+                let mut entry = json!({ "type": item.kind.field(), "media": reference });
+                if index == 0
+                    && let Some(caption) = &caption
+                {
+                    entry["caption"] = json!(caption);
+                    entry["parse_mode"] = json!(parse_mode);
+                }
+                entries.push(entry);
+            }
+            if files.is_empty() {
+                return ApiCall::json(
+                    "sendMediaGroup",
+                    &json!({ "chat_id": chat_id, "media": entries }),
+                );
+            }
+            let mut form = MultipartForm::new()
+                .text("chat_id", chat_id)
+                .text("media", Value::Array(entries).to_string());
+            for (name, filename, mime_type, bytes) in files {
+                form = form.file(name, filename, mime_type, bytes);
+            }
+            ApiCall::multipart("sendMediaGroup", form)
+        })
+        .collect()
+}
+
+fn parse_config_value(val: &Value) -> Result<ProviderConfig, ProviderError> {
     let cfg = serde_json::from_value::<ProviderConfig>(val.clone())
-        .map_err(|e| format!("invalid config: {e}"))?;
+        .map_err(|e| ProviderError::config_invalid(e.to_string()))?;
     validate_provider_config(cfg)
 }
 
 #[cfg(test)]
-fn parse_config_bytes(bytes: &[u8]) -> Result<ProviderConfig, String> {
+fn parse_config_bytes(bytes: &[u8]) -> Result<ProviderConfig, ProviderError> {
     let cfg = serde_json::from_slice::<ProviderConfig>(bytes)
-        .map_err(|e| format!("invalid config: {e}"))?;
+        .map_err(|e| ProviderError::config_invalid(e.to_string()))?;
     validate_provider_config(cfg)
 }
 
-fn load_config(input: &Value) -> Result<ProviderConfig, String> {
+fn load_config(input: &Value) -> Result<ProviderConfig, ProviderError> {
     if let Some(cfg) = input.get("config") {
         return parse_config_value(cfg);
     }
@@ -1175,14 +2189,16 @@ fn load_config(input: &Value) -> Result<ProviderConfig, String> {
     })
 }
 
-fn validate_provider_config(cfg: ProviderConfig) -> Result<ProviderConfig, String> {
+fn validate_provider_config(cfg: ProviderConfig) -> Result<ProviderConfig, ProviderError> {
     if cfg.public_base_url.trim().is_empty() {
-        return Err("invalid config: public_base_url cannot be empty".to_string());
+        return Err(ProviderError::config_invalid(
+            "public_base_url cannot be empty",
+        ));
     }
     Ok(cfg)
 }
 
-fn get_bot_token(cfg: &ProviderConfig) -> Result<String, String> {
+fn get_bot_token(cfg: &ProviderConfig) -> Result<String, ProviderError> {
     if let Some(token) = cfg.bot_token.clone() {
         let token = token.trim().to_string();
         if !token.is_empty() {
@@ -1190,11 +2206,10 @@ fn get_bot_token(cfg: &ProviderConfig) -> Result<String, String> {
         }
     }
     match secrets_store::get(TOKEN_SECRET) {
-        Ok(Some(bytes)) => String::from_utf8(bytes).map_err(|_| "bot token not utf-8".to_string()),
-        Ok(None) => Err(format!(
-            "missing bot_token (config or secret: {TOKEN_SECRET})"
-        )),
-        Err(e) => Err(format!("secret store error: {e:?}")),
+        Ok(Some(bytes)) => String::from_utf8(bytes)
+            .map_err(|_| ProviderError::config_invalid("bot token not utf-8")),
+        Ok(None) => Err(ProviderError::missing_secret(TOKEN_SECRET)),
+        Err(e) => Err(ProviderError::other(format!("secret store error: {e:?}"))),
     }
 }
 
@@ -1205,8 +2220,150 @@ fn json_bytes<T: serde::Serialize>(value: &T) -> Vec<u8> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use provider_common::testing::MemoryStore;
     use std::collections::BTreeSet;
 
+    #[test]
+    fn dedup_key_prefers_update_id() {
+        let body = json!({"update_id": 77, "message": {"message_id": 1, "chat": {"id": 9}}});
+        assert_eq!(dedup_key(&body, "123").as_deref(), Some("123:77"));
+        assert_eq!(dedup_key(&body, "456").as_deref(), Some("456:77"));
+        let body = json!({"message": {"message_id": 1, "chat": {"id": 9}}});
+        assert_eq!(dedup_key(&body, "123").as_deref(), Some("123:9:1"));
+    }
+
+    fn normalize(body: Value) -> Option<InboundUpdate> {
+        normalize_update(&body, &mut MemoryStore::default())
+    }
+
+    #[test]
+    fn forum_topic_messages_carry_thread_in_session_and_reply_scope() {
+        let update = normalize(json!({"update_id": 3, "message": {
+            "message_id": 9, "chat": {"id": -100}, "text": "hi",
+            "message_thread_id": 77, "is_topic_message": true
+        }}))
+        .expect("update");
+        let envelope = build_telegram_envelope(update);
+        assert_eq!(envelope.session_id, "-100:77");
+        let scope = envelope.reply_scope.expect("reply scope");
+        assert_eq!(scope.conversation, "-100");
+        assert_eq!(scope.thread.as_deref(), Some("77"));
+        assert_eq!(scope.reply_to.as_deref(), Some("9"));
+        assert_eq!(envelope.metadata["message_thread_id"], "77");
+
+        let plain_reply = normalize(json!({"update_id": 4, "message": {
+            "message_id": 10, "chat": {"id": -100}, "text": "hi",
+            "message_thread_id": 9
+        }}))
+        .expect("update");
+        assert_eq!(build_telegram_envelope(plain_reply).session_id, "-100");
+    }
+
+    #[test]
+    fn threading_targets_topic_and_replies_with_first_part_only() {
+        let threading = Threading::new(Some("77"), Some("tg:-100:9"), Some("quoted"));
+        let mut first = ApiCall::json("sendMessage", &json!({"chat_id": "-100"}));
+        let mut second = ApiCall::json("sendPhoto", &json!({"chat_id": "-100"}));
+        threading.apply(&mut first, true);
+        threading.apply(&mut second, false);
+        let first: Value = serde_json::from_slice(&first.encode().1).unwrap();
+        let second: Value = serde_json::from_slice(&second.encode().1).unwrap();
+        assert_eq!(first["message_thread_id"], json!(77));
+        assert_eq!(
+            first["reply_parameters"],
+            json!({"message_id": 9, "quote": "quoted"})
+        );
+        assert_eq!(second["message_thread_id"], json!(77));
+        assert!(second.get("reply_parameters").is_none());
+
+        assert_eq!(
+            Threading::new(None, Some("abc"), None),
+            Threading::default()
+        );
+    }
+
+    #[test]
+    fn callback_query_resolves_tokenized_submit_data() {
+        let mut store = MemoryStore::default();
+        let data = json!({"action": "approve", "comment": "y".repeat(100)});
+        let token =
+            keyboard::encode_callback_data(&data, &mut store, webhook_auth::now_unix_secs())
+                .expect("token");
+        let update = normalize_update(
+            &json!({"callback_query": {"id": "cb-2", "from": {"id": 7}, "data": token}}),
+            &mut store,
+        )
+        .expect("callback");
+        assert_eq!(update.action.expect("action")["data"], data);
+
+        let expired = normalize(json!({"callback_query": {"id": "cb-3", "data": "cb:gone"}}))
+            .expect("callback");
+        assert_eq!(expired.metadata["callback_unresolved"], "true");
+    }
+
+    #[test]
+    fn normalize_update_maps_callbacks_and_edits() {
+        let callback = normalize(json!({
+            "update_id": 10,
+            "callback_query": {
+                "id": "cb-1",
+                "from": {"id": 7},
+                "data": "approve:42",
+                "message": {"message_id": 3, "chat": {"id": -100}}
+            }
+        }))
+        .expect("callback");
+        let envelope = build_telegram_envelope(callback);
+        assert_eq!(envelope.id, "telegram-10");
+        assert_eq!(envelope.text, None);
+        assert_eq!(envelope.metadata["event_kind"], "callback_query");
+        assert_eq!(envelope.metadata["callback_data"], "approve:42");
+        let action: Value = serde_json::from_str(&envelope.metadata["action"]).expect("action");
+        assert_eq!(action["callback_query_id"], json!("cb-1"));
+        assert_eq!(action["message_id"], json!(3));
+
+        let edit = normalize(json!({
+            "edited_message": {"message_id": 5, "chat": {"id": 9}, "text": "fixed", "edit_date": 1}
+        }))
+        .expect("edit");
+        assert_eq!(edit.kind, "message_edited");
+        assert_eq!(edit.metadata["original_message_id"], "5");
+    }
+
+    #[test]
+    fn normalize_update_maps_membership_reactions_and_media() {
+        let added = normalize(json!({"my_chat_member": {
+            "chat": {"id": -5}, "from": {"id": 1},
+            "old_chat_member": {"status": "left"},
+            "new_chat_member": {"status": "member"}
+        }}))
+        .expect("membership");
+        assert_eq!(added.kind, "bot_added");
+
+        let reaction = normalize(json!({"message_reaction": {
+            "chat": {"id": 9}, "message_id": 4, "user": {"id": 2},
+            "old_reaction": [{"type": "emoji", "emoji": "👍"}],
+            "new_reaction": [{"type": "emoji", "emoji": "🔥"}]
+        }}))
+        .expect("reaction");
+        assert_eq!(reaction.metadata["reactions_added"], "🔥");
+        assert_eq!(reaction.metadata["reactions_removed"], "👍");
+        assert_eq!(reaction.from.as_deref(), Some("2"));
+
+        let photo = normalize(json!({"channel_post": {
+            "message_id": 1, "chat": {"id": -7}, "caption": "look",
+            "photo": [{"file_id": "small"}, {"file_id": "large", "file_size": 900}]
+        }}))
+        .expect("photo");
+        assert_eq!(photo.kind, "channel_post");
+        assert_eq!(photo.text.as_deref(), Some("look"));
+        assert_eq!(photo.attachments.len(), 1);
+        assert_eq!(photo.attachments[0].url, "telegram-file:large");
+        assert_eq!(photo.attachments[0].size_bytes, Some(900));
+
+        assert!(normalize(json!({"update_id": 1, "poll": {"id": "p"}})).is_none());
+    }
+
     #[test]
     fn load_config_prefers_nested_config() {
         let input = json!({
@@ -1232,15 +2389,127 @@ mod tests {
     fn parse_config_bytes_rejects_unknown_fields() {
         let cfg = br#"{ "enabled": true, "public_base_url": "https://example.com", "api_base_url": "https://api.telegram.org", "default_chat_id": "abc", "unknown": "field" }"#;
         let err = parse_config_bytes(cfg).expect_err("should fail");
-        assert!(err.contains("unknown field"));
+        assert!(err.to_string().contains("unknown field"));
+    }
+
+    #[test]
+    fn send_payload_result_reports_attempts() {
+        let bytes = send_payload_error_after(
+            ProviderError::rate_limited("telegram returned status 429", Some(3)),
+            3,
+        );
+        let value: Value = serde_json::from_slice(&bytes).expect("json");
+        assert_eq!(value["attempts"], json!(3));
+        assert_eq!(value["retryable"], json!(true));
+        assert_eq!(value["error_detail"]["code"], json!("rate_limited"));
+        let result: SendPayloadResultV1 = serde_json::from_slice(&bytes).expect("result");
+        assert!(!result.ok);
+    }
+
+    #[test]
+    fn telegram_status_error_reads_error_body() {
+        let response = |status: u16, body: Value| client::Response {
+            status,
+            headers: Vec::new(),
+            body: Some(serde_json::to_vec(&body).expect("body")),
+        };
+        let missing = telegram_status_error(&response(
+            400,
+            json!({"ok": false, "error_code": 400, "description": "Bad Request: chat not found"}),
+        ));
+        assert!(matches!(missing, ProviderError::DestinationNotFound(_)));
+
+        let limited = telegram_status_error(&response(
+            429,
+            json!({"ok": false, "error_code": 429, "parameters": {"retry_after": 7}}),
+        ));
+        assert_eq!(limited.detail().retry_after_secs, Some(7));
+        assert!(limited.retryable());
+    }
+
+    #[test]
+    fn chat_message_id_carries_chat() {
+        let body = json!({"result": {"message_id": 7, "chat": {"id": -100}}});
+        let ids = extract_ids(&body);
+        assert_eq!(ids.provider_message_id, "tg:7");
+        let chat = ids.chat_message_id.expect("chat id");
+        assert_eq!(chat, "tg:-100:7");
+        assert_eq!(
+            parse_provider_message_id(&chat),
+            Some((Some("-100".into()), "7".into()))
+        );
+        assert_eq!(parse_provider_message_id("tg:7"), Some((None, "7".into())));
+        assert_eq!(parse_provider_message_id("tg:"), None);
     }
 
     #[test]
     fn extract_ids_handles_strings() {
         let body = json!({"result": {"message_id": "42"}});
-        let (id, provider) = extract_ids(&body);
-        assert_eq!(id, "42");
-        assert_eq!(provider, "tg:42");
+        let ids = extract_ids(&body);
+        assert_eq!(ids.message_id, "42");
+        assert_eq!(ids.provider_message_id, "tg:42");
+        assert_eq!(ids.chat_message_id, None);
+    }
+
+    fn attachment(mime_type: &str, url: &str) -> Attachment {
+        Attachment {
+            mime_type: mime_type.into(),
+            url: url.into(),
+            name: Some("chart.png".into()),
+            size_bytes: None,
+        }
+    }
+
+    #[test]
+    fn media_calls_group_albums_and_caption_first_item() {
+        let media = [
+            attachment("image/png", "https://example.com/a.png"),
+            attachment("video/mp4", "https://example.com/b.mp4"),
+            attachment("application/pdf", "https://example.com/c.pdf"),
+        ]
+        .iter()
+        .map(OutboundMedia::from_attachment)
+        .collect::<Result<Vec<_>, _>>()
+        .expect("media");
+        let calls = media_calls("42", media, Some("hi".into()));
+        assert_eq!(
+            calls.iter().map(|call| call.method).collect::<Vec<_>>(),
+            vec!["sendMediaGroup", "sendDocument"]
+        );
+        let mut calls = calls.into_iter().map(|call| call.encode().1);
+        let album: Value = serde_json::from_slice(&calls.next().unwrap()).expect("album");
+        assert_eq!(album["media"][0]["type"], json!("photo"));
+        assert_eq!(album["media"][0]["caption"], json!("hi"));
+        assert_eq!(album["media"][1]["type"], json!("video"));
+        assert_eq!(album["media"][1].get("caption"), None);
+        let document: Value = serde_json::from_slice(&calls.next().unwrap()).expect("document");
+        assert_eq!(document["document"], json!("https://example.com/c.pdf"));
+        assert_eq!(document.get("caption"), None);
+    }
+
+    #[test]
+    fn inline_attachments_are_uploaded_as_multipart() {
+        let media = OutboundMedia::from_attachment(&attachment("", "data:image/png;base64,iVBO"))
+            .expect("media");
+        assert_eq!(media.kind, MediaKind::Photo);
+        let mut call = media_calls("42", vec![media], None).remove(0);
+        assert_eq!(call.method, "sendPhoto");
+        call.set_param("message_thread_id", &json!(7));
+        let (content_type, body) = call.encode();
+        assert!(content_type.starts_with("multipart/form-data; boundary="));
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("name=\"message_thread_id\"\r\n\r\n7\r\n"));
+        assert!(body.contains("name=\"photo\"; filename=\"chart.png\"\r\nContent-Type: image/png"));
+    }
+
+    #[test]
+    fn extract_all_ids_reads_media_groups() {
+        let body = json!({"result": [{"message_id": 5}, {"message_id": 6}]});
+        let ids = extract_all_ids(&body)
+            .into_iter()
+            .map(|ids| ids.provider_message_id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["tg:5", "tg:6"]);
     }
 
     #[test]
@@ -0,0 +1,308 @@
+//! Operations on conversations beyond sending: editing and deleting sent
+//! messages, answering button presses and showing chat actions.
+//!
+//! Messages are addressed by the `chat_message_id` returned from `send`
+//! (`tg:<chat_id>:<message_id>`), or by its `provider_message_id`
+//! (`tg:<message_id>`) together with `chat_id`.
+
+use super::{
+    MAX_TEXT_LEN, PROVIDER_TYPE, ProviderConfig, call_bot_api, chat_message_id, error_bytes,
+    error_bytes_after, host_state, json_bytes, keyboard, load_config, parse_provider_message_id,
+    provider_message_id,
+};
+use provider_common::ProviderError;
+use provider_common::formatting::{MarkdownDialect, render as render_markdown};
+use provider_common::http_retry::RetryPolicy;
+use provider_common::webhook_auth;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+
+/// Actions `sendChatAction` accepts.
+const CHAT_ACTIONS: &[&str] = &[
+    "typing",
+    "upload_photo",
+    "record_video",
+    "upload_video",
+    "record_voice",
+    "upload_voice",
+    "upload_document",
+    "choose_sticker",
+    "find_location",
+    "record_video_note",
+    "upload_video_note",
+];
+/// Length limit of the notification shown by `answerCallbackQuery`.
+const MAX_CALLBACK_ANSWER_LEN: usize = 200;
+
+/// `editMessageText` when the input has `text`, else `editMessageReplyMarkup`.
+/// Buttons come from an `adaptive_card`; editing without one removes the
+/// message's inline keyboard.
+pub(crate) fn handle_edit_message(input_json: &[u8]) -> Vec<u8> {
+    let (parsed, cfg) = match prepare(input_json) {
+        Ok(prepared) => prepared,
+        Err(out) => return out,
+    };
+    let (chat_id, message_id) = match target_message(&parsed, &cfg) {
+        Ok(target) => target,
+        Err(err) => return error_bytes(err),
+    };
+    let text = parsed
+        .get("text")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|text| !text.is_empty());
+    let (method, mut payload) = match text {
+        Some(text) => {
+            let dialect = MarkdownDialect::TelegramMarkdownV2;
+            let rendered = render_markdown(text, dialect);
+            if rendered.chars().count() > MAX_TEXT_LEN {
+                return error_bytes(ProviderError::payload_too_large(format!(
+                    "edited text exceeds {MAX_TEXT_LEN} characters"
+                )));
+            }
+            (
+                "editMessageText",
+                json!({
+                    "chat_id": chat_id,
+                    "message_id": message_id,
+                    "text": rendered,
+                    "parse_mode": dialect.telegram_parse_mode(),
+                }),
+            )
+        }
+        None => (
+            "editMessageReplyMarkup",
+            json!({"chat_id": chat_id, "message_id": message_id}),
+        ),
+    };
+    match card_reply_markup(&parsed) {
+        Ok(Some(markup)) => payload["reply_markup"] = markup,
+        Ok(None) => {}
+        Err(err) => return error_bytes(err),
+    }
+    respond(
+        &parsed,
+        &cfg,
+        method,
+        &payload,
+        "edited",
+        Some((&chat_id, &message_id)),
+    )
+}
+
+pub(crate) fn handle_delete_message(input_json: &[u8]) -> Vec<u8> {
+    let (parsed, cfg) = match prepare(input_json) {
+        Ok(prepared) => prepared,
+        Err(out) => return out,
+    };
+    let (chat_id, message_id) = match target_message(&parsed, &cfg) {
+        Ok(target) => target,
+        Err(err) => return error_bytes(err),
+    };
+    let payload = json!({"chat_id": chat_id, "message_id": message_id});
+    respond(
+        &parsed,
+        &cfg,
+        "deleteMessage",
+        &payload,
+        "deleted",
+        Some((&chat_id, &message_id)),
+    )
+}
+
+/// Acknowledges a button press; `text` shows as a toast, or as an alert
+/// with `show_alert`.
+pub(crate) fn handle_answer_callback(input_json: &[u8]) -> Vec<u8> {
+    let (parsed, cfg) = match prepare(input_json) {
+        Ok(prepared) => prepared,
+        Err(out) => return out,
+    };
+    let Some(query_id) = string_field(&parsed, "callback_query_id") else {
+        return error_bytes(ProviderError::validation("callback_query_id required"));
+    };
+    let mut payload = json!({"callback_query_id": query_id});
+    if let Some(text) = string_field(&parsed, "text") {
+        if text.chars().count() > MAX_CALLBACK_ANSWER_LEN {
+            return error_bytes(ProviderError::payload_too_large(format!(
+                "callback answer exceeds {MAX_CALLBACK_ANSWER_LEN} characters"
+            )));
+        }
+        payload["text"] = json!(text);
+    }
+    if let Some(show_alert) = parsed.get("show_alert").and_then(Value::as_bool) {
+        payload["show_alert"] = json!(show_alert);
+    }
+    if let Some(url) = string_field(&parsed, "url") {
+        payload["url"] = json!(url);
+    }
+    if let Some(cache_time) = parsed.get("cache_time").and_then(Value::as_u64) {
+        payload["cache_time"] = json!(cache_time);
+    }
+    respond(
+        &parsed,
+        &cfg,
+        "answerCallbackQuery",
+        &payload,
+        "answered",
+        None,
+    )
+}
+
+/// Shows a chat action such as the typing indicator, in a forum topic when
+/// `message_thread_id` is given; Telegram clears it after five seconds or
+/// when the bot sends a message.
+pub(crate) fn handle_chat_action(input_json: &[u8]) -> Vec<u8> {
+    let (parsed, cfg) = match prepare(input_json) {
+        Ok(prepared) => prepared,
+        Err(out) => return out,
+    };
+    let Some(chat_id) = string_field(&parsed, "chat_id").or_else(|| cfg.default_chat_id.clone())
+    else {
+        return error_bytes(ProviderError::validation("chat_id required"));
+    };
+    let action = string_field(&parsed, "action").unwrap_or_else(|| "typing".to_string());
+    if !CHAT_ACTIONS.contains(&action.as_str()) {
+        return error_bytes(ProviderError::validation(format!(
+            "unsupported chat action: {action}"
+        )));
+    }
+    let mut payload = json!({"chat_id": chat_id, "action": action});
+    if let Some(thread) = string_field(&parsed, "message_thread_id") {
+        payload["message_thread_id"] = json!(thread);
+    }
+    respond(
+        &parsed,
+        &cfg,
+        "sendChatAction",
+        &payload,
+        "action_sent",
+        None,
+    )
+}
+
+fn prepare(input_json: &[u8]) -> Result<(Value, ProviderConfig), Vec<u8>> {
+    let parsed: Value = serde_json::from_slice(input_json)
+        .map_err(|err| error_bytes(ProviderError::validation(format!("invalid json: {err}"))))?;
+    let cfg = load_config(&parsed).map_err(error_bytes)?;
+    if !cfg.enabled {
+        return Err(error_bytes(ProviderError::config_invalid(
+            "provider disabled by config",
+        )));
+    }
+    Ok((parsed, cfg))
+}
+
+fn string_field(parsed: &Value, key: &str) -> Option<String> {
+    match parsed.get(key)? {
+        Value::String(value) if !value.trim().is_empty() => Some(value.trim().to_string()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Chat id and message id of the message an op targets.
+fn target_message(parsed: &Value, cfg: &ProviderConfig) -> Result<(String, String), ProviderError> {
+    let raw = string_field(parsed, "chat_message_id")
+        .or_else(|| string_field(parsed, "provider_message_id"))
+        .or_else(|| string_field(parsed, "message_id"))
+        .ok_or_else(|| ProviderError::validation("provider_message_id required"))?;
+    let (embedded_chat, message_id) = parse_provider_message_id(&raw)
+        .ok_or_else(|| ProviderError::validation(format!("invalid provider_message_id: {raw}")))?;
+    let chat_id = embedded_chat
+        .or_else(|| string_field(parsed, "chat_id"))
+        .or_else(|| cfg.default_chat_id.clone())
+        .ok_or_else(|| ProviderError::validation("chat_id required"))?;
+    Ok((chat_id, message_id))
+}
+
+/// Inline keyboard for the actions of an `adaptive_card` in the input or its
+/// `metadata`, given as an object or a JSON string.
+fn card_reply_markup(parsed: &Value) -> Result<Option<Value>, ProviderError> {
+    let Some(card) = parsed
+        .get("adaptive_card")
+        .or_else(|| parsed.pointer("/metadata/adaptive_card"))
+    else {
+        return Ok(None);
+    };
+    let card = match card {
+        Value::String(json) => json.clone(),
+        other => other.to_string(),
+    };
+    let metadata = BTreeMap::from([("adaptive_card".to_string(), card)]);
+    let actions = provider_common::extract_ac_actions(&metadata);
+    if actions.is_empty() {
+        return Ok(None);
+    }
+    keyboard::build_inline_keyboard(&actions, &mut host_state(), webhook_auth::now_unix_secs())
+        .map(|keyboard| Some(keyboard.reply_markup()))
+        .map_err(|err| ProviderError::other(format!("callback token store failed: {err}")))
+}
+
+/// Calls `method` and builds the op result; `target` is the chat and message
+/// the op acted on.
+fn respond(
+    parsed: &Value,
+    cfg: &ProviderConfig,
+    method: &str,
+    payload: &Value,
+    status: &str,
+    target: Option<(&str, &str)>,
+) -> Vec<u8> {
+    let (body, attempts) =
+        match call_bot_api(cfg, method, payload, &RetryPolicy::from_input(parsed)) {
+            Ok(result) => result,
+            Err((err, attempts)) => return error_bytes_after(err, attempts),
+        };
+    json_bytes(&json!({
+        "ok": true,
+        "status": status,
+        "provider_type": PROVIDER_TYPE,
+        "provider_message_id": target.map(|(_, message_id)| provider_message_id(message_id)),
+        "chat_message_id": target.map(|(chat_id, message_id)| chat_message_id(chat_id, message_id)),
+        "attempts": attempts,
+        "response": body,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ProviderConfig {
+        ProviderConfig {
+            enabled: true,
+            public_base_url: "https://example.com".into(),
+            default_chat_id: Some("555".into()),
+            api_base_url: None,
+            bot_token: None,
+        }
+    }
+
+    #[test]
+    fn target_message_prefers_chat_from_chat_message_id() {
+        let cfg = config();
+        let target = target_message(&json!({"chat_message_id": "tg:-100:42"}), &cfg);
+        assert_eq!(target, Ok(("-100".into(), "42".into())));
+        let target = target_message(
+            &json!({"provider_message_id": "tg:42", "chat_id": "-200"}),
+            &cfg,
+        );
+        assert_eq!(target, Ok(("-200".into(), "42".into())));
+        let target = target_message(&json!({"provider_message_id": "tg:42"}), &cfg);
+        assert_eq!(target, Ok(("555".into(), "42".into())));
+        assert!(target_message(&json!({"provider_message_id": "tg:abc"}), &cfg).is_err());
+        assert!(target_message(&json!({}), &cfg).is_err());
+    }
+
+    #[test]
+    fn card_reply_markup_reads_card_actions() {
+        let card = json!({"type": "AdaptiveCard", "actions": [
+            {"type": "Action.OpenUrl", "title": "Open", "url": "https://example.com"}
+        ]});
+        let markup = card_reply_markup(&json!({"adaptive_card": card.to_string()}))
+            .expect("markup")
+            .expect("keyboard");
+        assert_eq!(markup["inline_keyboard"][0][0]["text"], json!("Open"));
+        assert_eq!(card_reply_markup(&json!({"text": "x"})), Ok(None));
+    }
+}
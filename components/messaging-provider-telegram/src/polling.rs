@@ -0,0 +1,243 @@
+//! Long-polling ingress through `getUpdates`, for deployments that cannot
+//! expose a public HTTPS endpoint for the webhook.
+//!
+//! The operator runs `poll_updates` on a schedule. The offset of the next
+//! unconfirmed update is kept in the state store per bot, and updates are
+//! normalized exactly like webhook deliveries. Telegram refuses `getUpdates`
+//! while a webhook is set, so the op checks first and either fails or, with
+//! `delete_webhook`, removes the webhook.
+
+use super::{
+    PROVIDER_ID, PROVIDER_TYPE, bot_id, build_telegram_envelope, call_bot_api, dedup_key,
+    error_bytes, error_bytes_after, get_bot_token, host_state, json_bytes, load_config,
+    normalize_update,
+};
+use greentic_types::ChannelMessageEnvelope;
+use provider_common::ProviderError;
+use provider_common::http_retry::RetryPolicy;
+use provider_common::ingress_dedup::DedupWindow;
+use provider_common::lifecycle_keys::messaging_state_key;
+use provider_common::state::StateStore;
+use provider_common::webhook_auth;
+use serde_json::{Value, json};
+
+/// `getUpdates` returns at most this many updates per call.
+const MAX_POLL_LIMIT: u64 = 100;
+/// Upper bound for the long-poll `timeout`, below common host HTTP timeouts.
+const MAX_POLL_TIMEOUT_SECS: u64 = 50;
+const DEFAULT_TENANT: &str = "default";
+
+pub(crate) fn handle_poll_updates(input_json: &[u8]) -> Vec<u8> {
+    let parsed: Value = match serde_json::from_slice(input_json) {
+        Ok(value) => value,
+        Err(err) => {
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
+        }
+    };
+    let cfg = match load_config(&parsed) {
+        Ok(cfg) => cfg,
+        Err(err) => return error_bytes(err),
+    };
+    if !cfg.enabled {
+        return error_bytes(ProviderError::config_invalid("provider disabled by config"));
+    }
+    let token = match get_bot_token(&cfg) {
+        Ok(token) => token,
+        Err(err) => return error_bytes(err),
+    };
+    let policy = RetryPolicy::from_input(&parsed);
+    let delete_webhook = parsed
+        .get("delete_webhook")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+
+    let (info, mut attempts) = match call_bot_api(&cfg, "getWebhookInfo", &json!({}), &policy) {
+        Ok(result) => result,
+        Err((err, attempts)) => return error_bytes_after(err, attempts),
+    };
+    let webhook_url = info
+        .pointer("/result/url")
+        .and_then(Value::as_str)
+        .filter(|url| !url.is_empty());
+    if let Some(url) = webhook_url {
+        if !delete_webhook {
+            return error_bytes_after(
+                ProviderError::config_invalid(format!(
+                    "webhook is set to {url}; remove it or pass delete_webhook to poll"
+                )),
+                attempts,
+            );
+        }
+        let payload = json!({"drop_pending_updates": false});
+        match call_bot_api(&cfg, "deleteWebhook", &payload, &policy) {
+            Ok((_, n)) => attempts += n,
+            Err((err, n)) => return error_bytes_after(err, attempts + n),
+        }
+    }
+
+    let state_key = offset_state_key(&parsed, &token);
+    let mut store = host_state();
+    let offset = read_offset(&mut store, &state_key);
+    let mut payload = json!({
+        "timeout": parsed
+            .get("timeout_secs")
+            .and_then(Value::as_u64)
+            .unwrap_or(0)
+            .min(MAX_POLL_TIMEOUT_SECS),
+        "limit": parsed
+            .get("limit")
+            .and_then(Value::as_u64)
+            .unwrap_or(MAX_POLL_LIMIT)
+            .clamp(1, MAX_POLL_LIMIT),
+    });
+    if let Some(offset) = offset {
+        payload["offset"] = json!(offset);
+    }
+    if let Some(allowed) = parsed.get("allowed_updates").filter(|v| v.is_array()) {
+        payload["allowed_updates"] = allowed.clone();
+    }
+    let (body, n) = match call_bot_api(&cfg, "getUpdates", &payload, &policy) {
+        Ok(result) => result,
+        Err((err, n)) => return error_bytes_after(err, attempts + n),
+    };
+    attempts += n;
+
+    let updates = body
+        .get("result")
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default();
+    let batch = collect_updates(
+        &updates,
+        bot_id(&token),
+        &mut store,
+        webhook_auth::now_unix_secs(),
+    );
+    let next_offset = batch.next_offset.or(offset);
+    let offset_persisted = match batch.next_offset {
+        Some(next) => store.write(&state_key, next.to_string().as_bytes()).is_ok(),
+        None => true,
+    };
+    json_bytes(&json!({
+        "ok": true,
+        "status": "polled",
+        "provider_type": PROVIDER_TYPE,
+        "events": batch.events,
+        "updates": updates.len(),
+        "ignored": batch.ignored,
+        "offset": next_offset,
+        "offset_persisted": offset_persisted,
+        "webhook_deleted": webhook_url.is_some(),
+        "attempts": attempts,
+    }))
+}
+
+/// Envelopes of a `getUpdates` batch and the offset that confirms it.
+#[derive(Debug, Default)]
+struct PolledBatch {
+    events: Vec<ChannelMessageEnvelope>,
+    /// Updates that were duplicates or have no envelope mapping.
+    ignored: usize,
+    next_offset: Option<i64>,
+}
+
+fn collect_updates(
+    updates: &[Value],
+    bot_id: &str,
+    store: &mut impl StateStore,
+    now: u64,
+) -> PolledBatch {
+    let window = DedupWindow::default();
+    let mut batch = PolledBatch::default();
+    for update in updates {
+        if let Some(id) = update.get("update_id").and_then(Value::as_i64) {
+            batch.next_offset = Some(batch.next_offset.unwrap_or(i64::MIN).max(id + 1));
+        }
+        // Shares the webhook's dedup records, so switching modes does not
+        // deliver an update twice.
+        let key = dedup_key(update, bot_id);
+        if key
+            .as_deref()
+            .is_some_and(|key| window.seen(store, PROVIDER_ID, key, now))
+        {
+            batch.ignored += 1;
+            continue;
+        }
+        match normalize_update(update, store) {
+            Some(normalized) => {
+                if let Some(key) = &key {
+                    window.record(store, PROVIDER_ID, key, now);
+                }
+                batch.events.push(build_telegram_envelope(normalized));
+            }
+            None => batch.ignored += 1,
+        }
+    }
+    batch
+}
+
+/// Offsets belong to a bot, so the key carries the bot id (the public part
+/// of the token before `:`).
+fn offset_state_key(parsed: &Value, token: &str) -> String {
+    let tenant = parsed.get("tenant");
+    let tenant_id = tenant
+        .and_then(|t| t.get("tenant_id"))
+        .or_else(|| parsed.get("tenant_id"))
+        .and_then(Value::as_str)
+        .filter(|id| !id.trim().is_empty())
+        .unwrap_or(DEFAULT_TENANT);
+    let team_id = tenant
+        .and_then(|t| t.get("team_id"))
+        .or_else(|| parsed.get("team_id"))
+        .and_then(Value::as_str);
+    messaging_state_key(
+        PROVIDER_ID,
+        tenant_id,
+        team_id,
+        &format!("updates_offset:{}", bot_id(token)),
+    )
+}
+
+fn read_offset(store: &mut impl StateStore, key: &str) -> Option<i64> {
+    let bytes = store.read(key).ok().flatten()?;
+    std::str::from_utf8(&bytes).ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use provider_common::testing::MemoryStore;
+
+    #[test]
+    fn batch_advances_offset_and_skips_duplicates() {
+        let mut store = MemoryStore::default();
+        let updates = vec![
+            json!({"update_id": 10, "message": {"message_id": 1, "chat": {"id": 5}, "text": "hi"}}),
+            json!({"update_id": 11, "poll": {"id": "p"}}),
+        ];
+        let batch = collect_updates(&updates, "123", &mut store, 100);
+        assert_eq!(batch.events.len(), 1);
+        assert_eq!(batch.ignored, 1);
+        assert_eq!(batch.next_offset, Some(12));
+
+        let again = collect_updates(&updates[..1], "123", &mut store, 101);
+        assert!(again.events.is_empty());
+        assert_eq!(again.next_offset, Some(11));
+
+        let other_bot = collect_updates(&updates[..1], "456", &mut store, 101);
+        assert_eq!(other_bot.events.len(), 1);
+    }
+
+    #[test]
+    fn offset_key_is_per_bot_and_tenant() {
+        let key = offset_state_key(&json!({"tenant_id": "acme"}), "123:secret");
+        assert_eq!(
+            key,
+            "providers:messaging:messaging-provider-telegram:tenants:acme:state:updates_offset:123"
+        );
+        let mut store = MemoryStore::default();
+        assert_eq!(read_offset(&mut store, &key), None);
+        store.write(&key, b"42").unwrap();
+        assert_eq!(read_offset(&mut store, &key), Some(42));
+    }
+}
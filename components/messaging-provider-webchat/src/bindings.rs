@@ -2568,9 +2568,7 @@ macro_rules! __export_component_v0_v6_v0_impl {
 #[doc(inline)]
 pub(crate) use __export_component_v0_v6_v0_impl as export;
 #[cfg(target_arch = "wasm32")]
-#[unsafe(
-    link_section = "component-type:wit-bindgen:0.41.0:greentic:component@0.6.1:component-v0-v6-v0:encoded world"
-)]
+#[unsafe(link_section = "component-type:wit-bindgen:0.41.0:greentic:component@0.6.1:component-v0-v6-v0:encoded world")]
 #[doc(hidden)]
 #[allow(clippy::octal_escapes)]
 pub static __WIT_BINDGEN_COMPONENT_TYPE: [u8; 2247] = *b"\
@@ -634,6 +634,11 @@ mod tests {
             self.data.insert(key.to_string(), value.to_vec());
             Ok(())
         }
+
+        fn delete(&mut self, key: &str) -> Result<(), String> {
+            self.data.remove(key);
+            Ok(())
+        }
     }
 
     struct TestSecretStore {
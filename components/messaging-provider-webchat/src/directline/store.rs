@@ -1,14 +1,9 @@
+pub use provider_common::state::StateStore;
 use serde::{Deserialize, Serialize};
 
 use crate::bindings::greentic::secrets_store::secrets_store;
 use crate::bindings::greentic::state::state_store;
 
-/// Driver for namespaced state operations used by the Direct Line contract.
-pub trait StateStore {
-    fn read(&mut self, key: &str) -> Result<Option<Vec<u8>>, String>;
-    fn write(&mut self, key: &str, value: &[u8]) -> Result<(), String>;
-}
-
 /// Driver for reading secrets required by the Direct Line contract.
 pub trait SecretStore {
     fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String>;
@@ -37,6 +32,12 @@ impl StateStore for HostStateStore {
             .map(|_| ())
             .map_err(|err| format!("state write error: {} - {}", err.code, err.message))
     }
+
+    fn delete(&mut self, key: &str) -> Result<(), String> {
+        state_store::delete(key, None)
+            .map(|_| ())
+            .map_err(|err| format!("state delete error: {} - {}", err.code, err.message))
+    }
 }
 
 /// Host-backed secrets drive implementation.
@@ -23,11 +23,20 @@ mod bindings {
 mod directline;
 
 use bindings::greentic::state::state_store;
+use directline::store::SecretStore;
 use directline::{HostSecretStore, HostStateStore, handle_directline_request};
+use provider_common::ProviderError;
+use provider_common::http_retry::send_payload_result_bytes;
+use provider_common::ingress_dedup::{DedupWindow, duplicate_ack_body};
+use provider_common::outbound_idempotency::send_idempotent;
+use provider_common::state::key_fragment;
+use provider_common::webhook_auth::{self, request_header};
 
 const PROVIDER_ID: &str = "messaging-provider-webchat";
 const PROVIDER_TYPE: &str = "messaging.webchat";
 const WORLD_ID: &str = "component-v0-v6-v0";
+const WEBHOOK_SECRET_KEY: &str = "WEBCHAT_WEBHOOK_SECRET";
+const WEBHOOK_SECRET_HEADER: &str = "x-webchat-secret";
 const I18N_KEYS: &[&str] = &[
     "webchat.op.run.title",
     "webchat.op.run.description",
@@ -298,12 +307,14 @@ bindings::export!(Component with_types_in bindings);
 
 fn dispatch_json_invoke(op: &str, input_json: &[u8]) -> Vec<u8> {
     match op {
-        "send" => handle_send(input_json),
+        "send" => send_idempotent(PROVIDER_ID, &mut HostStateStore, input_json, handle_send),
         "ingest" => handle_ingest(input_json),
         "ingest_http" => ingest_http(input_json),
         "render_plan" => render_plan(input_json),
         "encode" => encode_op(input_json),
-        "send_payload" => send_payload(input_json),
+        "send_payload" => {
+            send_idempotent(PROVIDER_ID, &mut HostStateStore, input_json, send_payload)
+        }
         other => json_bytes(&json!({"ok": false, "error": format!("unsupported op: {other}")})),
     }
 }
@@ -671,7 +682,20 @@ fn ingest_http(input_json: &[u8]) -> Vec<u8> {
         Ok(bytes) => bytes,
         Err(err) => return http_out_error(400, &format!("invalid body encoding: {err}")),
     };
+    if let Err(out) = verify_webhook(&request) {
+        return out;
+    }
     let body_val: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
+    // The key is recorded once the delivery normalized, so a redelivery of one
+    // that failed part-way is processed again.
+    let dedup = dedup_key(&body_val);
+    let window = DedupWindow::default();
+    let now = webhook_auth::now_unix_secs();
+    if let Some(key) = &dedup
+        && window.seen(&mut HostStateStore, PROVIDER_ID, key, now)
+    {
+        return duplicate_ack(key);
+    }
     let text = extract_text(&body_val);
     let user = user_from_value(&body_val);
     let route =
@@ -683,6 +707,9 @@ fn ingest_http(input_json: &[u8]) -> Vec<u8> {
         route.clone(),
         tenant_channel_id.clone(),
     );
+    if let Some(key) = &dedup {
+        window.record(&mut HostStateStore, PROVIDER_ID, key, now);
+    }
     let normalized = json!({
         "ok": true,
         "event": body_val,
@@ -763,35 +790,39 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
     let send_in = match serde_json::from_slice::<SendPayloadInV1>(input_json) {
         Ok(value) => value,
         Err(err) => {
-            return send_payload_error(&format!("invalid send_payload input: {err}"), false);
+            return send_payload_error(ProviderError::validation(format!(
+                "invalid send_payload input: {err}"
+            )));
         }
     };
     if send_in.provider_type != PROVIDER_TYPE {
-        return send_payload_error("provider type mismatch", false);
+        return send_payload_error(ProviderError::validation("provider type mismatch"));
     }
     let payload_bytes = match general_purpose::STANDARD.decode(&send_in.payload.body_b64) {
         Ok(bytes) => bytes,
         Err(err) => {
-            return send_payload_error(&format!("payload decode failed: {err}"), false);
+            return send_payload_error(ProviderError::validation(format!(
+                "payload decode failed: {err}"
+            )));
         }
     };
     let payload: Value = serde_json::from_slice(&payload_bytes).unwrap_or(Value::Null);
     match persist_send_payload(&payload) {
         Ok(_) => send_payload_success(),
-        Err(err) => send_payload_error(&err, false),
+        Err(err) => send_payload_error(err),
     }
 }
 
-fn persist_send_payload(payload: &Value) -> Result<(), String> {
+fn persist_send_payload(payload: &Value) -> Result<(), ProviderError> {
     let route = route_from_value(payload);
     let tenant_channel_id = tenant_channel_from_value(payload);
     let key = route
         .clone()
         .or(tenant_channel_id.clone())
-        .ok_or_else(|| "route or tenant_channel_id required".to_string())?;
+        .ok_or_else(|| ProviderError::validation("route or tenant_channel_id required"))?;
     let text = extract_text(payload);
     if text.is_empty() {
-        return Err("text required".into());
+        return Err(ProviderError::validation("text required"));
     }
     let public_base_url = public_base_url_from_value(payload);
     let stored = json!({
@@ -803,7 +834,7 @@ fn persist_send_payload(payload: &Value) -> Result<(), String> {
         "text": text,
     });
     state_store::write(&key, &json_bytes(&stored), None)
-        .map_err(|err| format!("state write error: {}", err.message))?;
+        .map_err(|err| ProviderError::other(format!("state write error: {}", err.message)))?;
     Ok(())
 }
 
@@ -908,6 +939,38 @@ fn http_out_error(status: u16, message: &str) -> Vec<u8> {
     http_out_v1_bytes(&out)
 }
 
+/// Rejects the request with a 401 when `WEBCHAT_WEBHOOK_SECRET` is configured
+/// and the `X-Webchat-Secret` header does not match it.
+fn verify_webhook(request: &HttpInV1) -> Result<(), Vec<u8>> {
+    let secret = match HostSecretStore.get(WEBHOOK_SECRET_KEY) {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return Ok(()),
+        Err(e) => return Err(http_out_error(500, &format!("secret store error: {e}"))),
+    };
+    webhook_auth::verify_shared_token(
+        &String::from_utf8_lossy(&secret),
+        request_header(request, WEBHOOK_SECRET_HEADER),
+        WEBHOOK_SECRET_HEADER,
+    )
+    .map_err(|err| http_out_error(401, &ProviderError::from(err).to_string()))
+}
+
+/// Acknowledges a redelivered webhook without emitting events.
+fn duplicate_ack(key: &str) -> Vec<u8> {
+    let out = HttpOutV1 {
+        status: 200,
+        headers: Vec::new(),
+        body_b64: general_purpose::STANDARD.encode(duplicate_ack_body(PROVIDER_ID, key)),
+        events: Vec::new(),
+    };
+    http_out_v1_bytes(&out)
+}
+
+/// Webchat clients that resend a message reuse its `id` or `message_id`.
+fn dedup_key(body: &Value) -> Option<String> {
+    key_fragment(body.get("id")).or_else(|| key_fragment(body.get("message_id")))
+}
+
 fn render_plan_error(message: &str) -> Vec<u8> {
     json_bytes(&json!({"ok": false, "error": message}))
 }
@@ -916,13 +979,14 @@ fn encode_error(message: &str) -> Vec<u8> {
     json_bytes(&json!({"ok": false, "error": message}))
 }
 
-fn send_payload_error(message: &str, retryable: bool) -> Vec<u8> {
+fn send_payload_error(err: ProviderError) -> Vec<u8> {
+    let detail = err.detail();
     let result = SendPayloadResultV1 {
         ok: false,
-        message: Some(message.to_string()),
-        retryable,
+        message: Some(err.to_string()),
+        retryable: detail.retryable,
     };
-    json_bytes(&result)
+    send_payload_result_bytes(&result, 0, Some(&detail), &[])
 }
 
 fn send_payload_success() -> Vec<u8> {
@@ -931,7 +995,7 @@ fn send_payload_success() -> Vec<u8> {
         message: None,
         retryable: false,
     };
-    json_bytes(&result)
+    send_payload_result_bytes(&result, 0, None, &[])
 }
 
 #[cfg(test)]
@@ -1082,6 +1146,16 @@ mod tests {
     use super::*;
     use std::collections::BTreeSet;
 
+    #[test]
+    fn send_payload_errors_are_classified() {
+        let bytes = send_payload_error(ProviderError::validation("text required"));
+        let value: Value = serde_json::from_slice(&bytes).expect("json");
+        assert_eq!(value["retryable"], json!(false));
+        assert_eq!(value["error_detail"]["code"], json!("invalid_request"));
+        let missing = persist_send_payload(&json!({"text": "hi"})).expect_err("no route");
+        assert!(matches!(missing, ProviderError::Validation(_)));
+    }
+
     #[test]
     fn parse_config_requires_new_fields() {
         let cfg = br#"{"enabled":true,"public_base_url":"https://example.com","mode":"local_queue","route":"r"}"#;
@@ -12,6 +12,18 @@ use provider_common::component_v0_6::{
     SchemaField, SchemaIr, canonical_cbor_bytes, decode_cbor, default_en_i18n_messages,
     schema_hash,
 };
+use provider_common::formatting::{MarkdownDialect, render as render_markdown};
+use provider_common::http_retry::{
+    RetryPolicy, RetryableResponse, find_header, retry_after, send_http, send_payload_result_bytes,
+};
+use provider_common::ingress_dedup::{DedupWindow, duplicate_ack_body};
+use provider_common::message_split::{SplitOptions, split_markdown};
+use provider_common::outbound_idempotency::send_idempotent;
+use provider_common::state::{HostStateAdapter, key_fragment};
+use provider_common::webhook_auth::{
+    self, WEBEX_SIGNATURE_HEADER, WEBEX_SIGNATURE_HEADER_ALT, request_header,
+};
+use provider_common::{ProviderError, ProviderErrorDetail};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use std::collections::BTreeMap;
@@ -26,12 +38,16 @@ mod bindings {
 
 use bindings::greentic::http::http_client as client;
 use bindings::greentic::secrets_store::secrets_store;
+use bindings::greentic::state::state_store;
 
 const PROVIDER_ID: &str = "messaging-provider-webex";
 const PROVIDER_TYPE: &str = "messaging.webex.bot";
+/// Webex rejects message text beyond this length.
+const MAX_TEXT_LEN: usize = 7439;
 const WORLD_ID: &str = "component-v0-v6-v0";
 const DEFAULT_API_BASE: &str = "https://webexapis.com/v1";
 const DEFAULT_TOKEN_KEY: &str = "WEBEX_BOT_TOKEN";
+const WEBHOOK_SECRET_KEY: &str = "WEBEX_WEBHOOK_SECRET";
 const I18N_KEYS: &[&str] = &[
     "webex.op.run.title",
     "webex.op.run.description",
@@ -311,12 +327,12 @@ fn default_enabled() -> bool {
 
 fn dispatch_json_invoke(op: &str, input_json: &[u8]) -> Vec<u8> {
     match op {
-        "send" => handle_send(input_json),
-        "reply" => handle_reply(input_json),
+        "send" => send_idempotent(PROVIDER_ID, &mut host_state(), input_json, handle_send),
+        "reply" => send_idempotent(PROVIDER_ID, &mut host_state(), input_json, handle_reply),
         "ingest_http" => ingest_http(input_json),
         "render_plan" => render_plan(input_json),
         "encode" => encode_op(input_json),
-        "send_payload" => send_payload(input_json),
+        "send_payload" => send_idempotent(PROVIDER_ID, &mut host_state(), input_json, send_payload),
         other => json_bytes(&json!({"ok": false, "error": format!("unsupported op: {other}")})),
     }
 }
@@ -627,16 +643,16 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
     let parsed: Value = match serde_json::from_slice(input_json) {
         Ok(val) => val,
         Err(err) => {
-            return json_bytes(&json!({"ok": false, "error": format!("invalid json: {err}")}));
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
         }
     };
 
     let mut cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
     if !cfg.enabled {
-        return json_bytes(&json!({"ok": false, "error": "provider disabled by config"}));
+        return error_bytes(ProviderError::config_invalid("provider disabled by config"));
     }
 
     let envelope: ChannelMessageEnvelope = match serde_json::from_slice(input_json) {
@@ -644,9 +660,9 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
         Err(err) => match build_send_envelope_from_input(&parsed, &cfg) {
             Ok(env) => env,
             Err(message) => {
-                return json_bytes(
-                    &json!({"ok": false, "error": format!("invalid envelope: {message}: {err}")}),
-                );
+                return error_bytes(ProviderError::validation(format!(
+                    "invalid envelope: {message}: {err}"
+                )));
             }
         },
     };
@@ -658,7 +674,9 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
         serde_json::to_string(&envelope).unwrap_or_default()
     );
     if !envelope.attachments.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "attachments not supported"}));
+        return error_bytes(ProviderError::unsupported_content(
+            "attachments not supported",
+        ));
     }
 
     let text = envelope
@@ -669,7 +687,7 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
         .map(ToOwned::to_owned);
     let text = match text {
         Some(value) => value,
-        None => return json_bytes(&json!({"ok": false, "error": "text required"})),
+        None => return error_bytes(ProviderError::validation("text required")),
     };
 
     let destination = envelope.to.first().cloned().or_else(|| {
@@ -683,12 +701,12 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
     println!("webex envelope to={:?}", envelope.to);
     let destination = match destination {
         Some(dest) => dest,
-        None => return json_bytes(&json!({"ok": false, "error": "destination required"})),
+        None => return error_bytes(ProviderError::validation("destination required")),
     };
 
     let dest_id = destination.id.trim();
     if dest_id.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "destination id required"}));
+        return error_bytes(ProviderError::validation("destination id required"));
     }
     let dest_id = dest_id.to_string();
     let kind = destination
@@ -701,78 +719,101 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
         .clone()
         .unwrap_or_else(|| DEFAULT_API_BASE.to_string());
     let url = format!("{}/messages", api_base);
-    let mut body = json!({ "text": text });
-    let body_obj = body.as_object_mut().expect("body object");
-    match kind {
-        "room" => {
-            body_obj.insert("roomId".into(), Value::String(dest_id));
-        }
-        "person" | "user" => {
-            body_obj.insert("toPersonId".into(), Value::String(dest_id));
-        }
-        "email" | "" => {
-            body_obj.insert("toPersonEmail".into(), Value::String(dest_id));
-        }
+    let dest_field = match kind {
+        "room" => "roomId",
+        "person" | "user" => "toPersonId",
+        "email" | "" => "toPersonEmail",
         other => {
-            return json_bytes(&json!({
-                "ok": false,
-                "error": format!("unsupported destination kind: {other}")
-            }));
+            return error_bytes(ProviderError::validation(format!(
+                "unsupported destination kind: {other}"
+            )));
         }
-    }
+    };
 
     let token = match get_token(&cfg) {
         Ok(token) => token,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
 
-    println!(
-        "webex send url={} body={}",
-        url,
-        serde_json::to_string(&body).unwrap_or_default()
+    let chunks = split_markdown(
+        &text,
+        MarkdownDialect::WebexMarkdown,
+        &SplitOptions::from_metadata(MAX_TEXT_LEN, &envelope.metadata),
     );
-    let request = client::Request {
-        method: "POST".into(),
-        url,
-        headers: vec![
-            ("Content-Type".into(), "application/json".into()),
-            ("Authorization".into(), format!("Bearer {token}")),
-        ],
-        body: Some(serde_json::to_vec(&body).unwrap_or_else(|_| b"{}".to_vec())),
-    };
+    let policy = RetryPolicy::from_input(&parsed);
+    let mut attempts = 0;
+    let mut message_ids = Vec::new();
+    let mut provider_message_ids = Vec::new();
+    let mut responses = Vec::new();
+    for chunk in &chunks {
+        let mut body = json!({
+            "text": render_markdown(chunk, MarkdownDialect::PlainText),
+            "markdown": render_markdown(chunk, MarkdownDialect::WebexMarkdown),
+        });
+        body.as_object_mut()
+            .expect("body object")
+            .insert(dest_field.into(), Value::String(dest_id.clone()));
+
+        println!(
+            "webex send url={} body={}",
+            url,
+            serde_json::to_string(&body).unwrap_or_default()
+        );
+        let request = client::Request {
+            method: "POST".into(),
+            url: url.clone(),
+            headers: vec![
+                ("Content-Type".into(), "application/json".into()),
+                ("Authorization".into(), format!("Bearer {token}")),
+            ],
+            body: Some(serde_json::to_vec(&body).unwrap_or_else(|_| b"{}".to_vec())),
+        };
 
-    let resp = match client::send(&request, None, None) {
-        Ok(resp) => resp,
-        Err(err) => {
-            return json_bytes(
-                &json!({"ok": false, "error": format!("transport error: {}", err.message)}),
+        let outcome = send_http(&request.method, &policy, || {
+            client::send(&request, None, None)
+        });
+        attempts += outcome.attempts;
+        let resp = match outcome.result {
+            Ok(resp) => resp,
+            Err(err) => {
+                return error_bytes_after_parts(
+                    ProviderError::transport(err.message),
+                    attempts,
+                    &provider_message_ids,
+                );
+            }
+        };
+
+        if resp.status < 200 || resp.status >= 300 {
+            return error_bytes_after_parts(
+                webex_status_error(&resp),
+                attempts,
+                &provider_message_ids,
             );
         }
-    };
 
-    if resp.status < 200 || resp.status >= 300 {
-        return json_bytes(
-            &json!({"ok": false, "error": format!("webex returned status {}", resp.status)}),
-        );
+        let body_bytes = resp.body.unwrap_or_default();
+        let body_json: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
+        let msg_id = body_json
+            .get("id")
+            .and_then(|v| v.as_str())
+            .unwrap_or("webex-message")
+            .to_string();
+        provider_message_ids.push(format!("webex:{msg_id}"));
+        message_ids.push(msg_id);
+        responses.push(body_json);
     }
 
-    let body_bytes = resp.body.unwrap_or_default();
-    let body_json: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
-    let msg_id = body_json
-        .get("id")
-        .and_then(|v| v.as_str())
-        .unwrap_or("webex-message")
-        .to_string();
-    let provider_message_id = format!("webex:{msg_id}");
-
     json_bytes(&json!({
         "ok": true,
         "status": "sent",
         "provider_type": PROVIDER_TYPE,
         "public_base_url": cfg.public_base_url,
-        "message_id": msg_id,
-        "provider_message_id": provider_message_id,
-        "response": body_json
+        "message_id": message_ids.first(),
+        "provider_message_id": provider_message_ids.first(),
+        "provider_message_ids": provider_message_ids,
+        "attempts": attempts,
+        "response": responses.first()
     }))
 }
 
@@ -780,15 +821,15 @@ fn handle_reply(_input_json: &[u8]) -> Vec<u8> {
     let parsed: Value = match serde_json::from_slice(_input_json) {
         Ok(val) => val,
         Err(err) => {
-            return json_bytes(&json!({"ok": false, "error": format!("invalid json: {err}")}));
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
         }
     };
     let cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
     if !cfg.enabled {
-        return json_bytes(&json!({"ok": false, "error": "provider disabled by config"}));
+        return error_bytes(ProviderError::config_invalid("provider disabled by config"));
     }
 
     let text = parsed
@@ -797,7 +838,7 @@ fn handle_reply(_input_json: &[u8]) -> Vec<u8> {
         .unwrap_or("")
         .to_string();
     if text.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "text required"}));
+        return error_bytes(ProviderError::validation("text required"));
     }
     let thread_id = parsed
         .get("reply_to_id")
@@ -806,12 +847,14 @@ fn handle_reply(_input_json: &[u8]) -> Vec<u8> {
         .unwrap_or("")
         .to_string();
     if thread_id.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "reply_to_id or thread_id required"}));
+        return error_bytes(ProviderError::validation(
+            "reply_to_id or thread_id required",
+        ));
     }
 
     let token = match get_token(&cfg) {
         Ok(token) => token,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
     let api_base = cfg
         .api_base_url
@@ -820,7 +863,7 @@ fn handle_reply(_input_json: &[u8]) -> Vec<u8> {
     let url = format!("{}/messages", api_base);
     let payload = json!({
         "parentId": thread_id,
-        "markdown": text,
+        "markdown": render_markdown(&text, MarkdownDialect::WebexMarkdown),
     });
     let request = client::Request {
         method: "POST".into(),
@@ -832,20 +875,18 @@ fn handle_reply(_input_json: &[u8]) -> Vec<u8> {
         body: Some(serde_json::to_vec(&payload).unwrap_or_else(|_| b"{}".to_vec())),
     };
 
-    let resp = match client::send(&request, None, None) {
+    let outcome = send_http(&request.method, &RetryPolicy::from_input(&parsed), || {
+        client::send(&request, None, None)
+    });
+    let attempts = outcome.attempts;
+    let resp = match outcome.result {
         Ok(resp) => resp,
         Err(err) => {
-            return json_bytes(&json!({
-                "ok": false,
-                "error": format!("transport error: {}", err.message),
-            }));
+            return error_bytes_after(ProviderError::transport(err.message), attempts);
         }
     };
     if resp.status < 200 || resp.status >= 300 {
-        return json_bytes(&json!({
-            "ok": false,
-            "error": format!("webex returned status {}", resp.status),
-        }));
+        return error_bytes_after(webex_status_error(&resp), attempts);
     }
     let body_bytes = resp.body.unwrap_or_default();
     let body_json: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
@@ -863,24 +904,25 @@ fn handle_reply(_input_json: &[u8]) -> Vec<u8> {
         "public_base_url": cfg.public_base_url,
         "message_id": msg_id,
         "provider_message_id": provider_message_id,
+        "attempts": attempts,
         "response": body_json
     }))
 }
 
 #[cfg(test)]
-fn parse_config_bytes(bytes: &[u8]) -> Result<ProviderConfig, String> {
+fn parse_config_bytes(bytes: &[u8]) -> Result<ProviderConfig, ProviderError> {
     let cfg = serde_json::from_slice::<ProviderConfig>(bytes)
-        .map_err(|e| format!("invalid config: {e}"))?;
+        .map_err(|e| ProviderError::config_invalid(e.to_string()))?;
     validate_provider_config(cfg)
 }
 
-fn parse_config_value(val: &Value) -> Result<ProviderConfig, String> {
+fn parse_config_value(val: &Value) -> Result<ProviderConfig, ProviderError> {
     let cfg = serde_json::from_value::<ProviderConfig>(val.clone())
-        .map_err(|e| format!("invalid config: {e}"))?;
+        .map_err(|e| ProviderError::config_invalid(e.to_string()))?;
     validate_provider_config(cfg)
 }
 
-fn load_config(input: &Value) -> Result<ProviderConfig, String> {
+fn load_config(input: &Value) -> Result<ProviderConfig, ProviderError> {
     if let Some(cfg) = input.get("config") {
         return parse_config_value(cfg);
     }
@@ -923,9 +965,11 @@ fn override_config_from_metadata(cfg: &mut ProviderConfig, metadata: &MessageMet
     }
 }
 
-fn validate_provider_config(cfg: ProviderConfig) -> Result<ProviderConfig, String> {
+fn validate_provider_config(cfg: ProviderConfig) -> Result<ProviderConfig, ProviderError> {
     if cfg.public_base_url.trim().is_empty() {
-        return Err("invalid config: public_base_url cannot be empty".to_string());
+        return Err(ProviderError::config_invalid(
+            "public_base_url cannot be empty",
+        ));
     }
     Ok(cfg)
 }
@@ -945,7 +989,7 @@ fn detect_destination_kind(dest_id: &str) -> &'static str {
     }
 }
 
-fn get_token(cfg: &ProviderConfig) -> Result<String, String> {
+fn get_token(cfg: &ProviderConfig) -> Result<String, ProviderError> {
     if let Some(token) = cfg.bot_token.clone() {
         let token = token.trim().to_string();
         if !token.is_empty() {
@@ -1090,6 +1134,19 @@ fn build_webex_body(
     map
 }
 
+/// Classifies a non-2xx Webex API response, keeping the body text in the
+/// message since Webex reports details there rather than in an error code.
+fn webex_status_error(resp: &client::Response) -> ProviderError {
+    let body = resp.body.as_deref().unwrap_or_default();
+    let retry_after_secs = retry_after(resp).map(|delay| delay.as_secs());
+    ProviderError::from_http_response(
+        resp.status,
+        retry_after_secs,
+        None,
+        format_webex_error(resp.status, body),
+    )
+}
+
 fn format_webex_error(status: u16, body: &[u8]) -> String {
     let trimmed = String::from_utf8_lossy(body).trim().to_string();
     if trimmed.is_empty() {
@@ -1244,6 +1301,7 @@ fn handle_webhook_event(body: &Value, cfg: &ProviderConfig) -> IngestOutcome {
                 }
             },
             Err(err) => {
+                let err = err.to_string();
                 let session_id = webhook_room.clone().unwrap_or_else(|| message_id.clone());
                 let sender = pick_sender(&webhook_person_email, &webhook_person_id);
                 let metadata = build_webhook_metadata(
@@ -1323,8 +1381,11 @@ fn fetch_message_details(
         headers: vec![("Authorization".into(), format!("Bearer {token}"))],
         body: None,
     };
-    let resp = client::send(&request, None, None)
-        .map_err(|err| format!("transport error: {}", err.message))?;
+    let resp = send_http(&request.method, &RetryPolicy::default(), || {
+        client::send(&request, None, None)
+    })
+    .result
+    .map_err(|err| format!("transport error: {}", err.message))?;
     println!("webex ingest fetch {message_id} status={}", resp.status);
     if resp.status < 200 || resp.status >= 300 {
         let body = resp.body.unwrap_or_default();
@@ -1505,9 +1566,27 @@ fn ingest_http(input_json: &[u8]) -> Vec<u8> {
         Ok(bytes) => bytes,
         Err(err) => return http_out_error(400, &format!("invalid body encoding: {err}")),
     };
+    if let Err(out) = verify_webhook(&request, &body_bytes) {
+        return out;
+    }
     let body_val: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
+    // The key is recorded once the delivery normalized, so a redelivery of one
+    // that failed part-way is processed again.
+    let dedup = dedup_key(&body_val);
+    let window = DedupWindow::default();
+    let now = webhook_auth::now_unix_secs();
+    if let Some(key) = &dedup
+        && window.seen(&mut host_state(), PROVIDER_ID, key, now)
+    {
+        return duplicate_ack(key);
+    }
     let cfg = load_config(&json!({})).unwrap_or_default();
     let outcome = handle_webhook_event(&body_val, &cfg);
+    if outcome.error.is_none()
+        && let Some(key) = &dedup
+    {
+        window.record(&mut host_state(), PROVIDER_ID, key, now);
+    }
 
     let mut normalized = json!({
         "ok": outcome.error.is_none(),
@@ -1575,11 +1654,13 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
     let send_in = match serde_json::from_slice::<SendPayloadInV1>(input_json) {
         Ok(value) => value,
         Err(err) => {
-            return send_payload_error(&format!("invalid send_payload input: {err}"), false);
+            return send_payload_error(ProviderError::validation(format!(
+                "invalid send_payload input: {err}"
+            )));
         }
     };
     if send_in.provider_type != PROVIDER_TYPE {
-        return send_payload_error("provider type mismatch", false);
+        return send_payload_error(ProviderError::validation("provider type mismatch"));
     }
     let ProviderPayloadV1 {
         content_type,
@@ -1599,13 +1680,19 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
         .unwrap_or_else(|| "POST".to_string());
     let body_bytes = match STANDARD.decode(&body_b64) {
         Ok(bytes) => bytes,
-        Err(err) => return send_payload_error(&format!("payload decode failed: {err}"), false),
+        Err(err) => {
+            return send_payload_error(ProviderError::validation(format!(
+                "payload decode failed: {err}"
+            )));
+        }
     };
     let envelope = match serde_json::from_slice::<ChannelMessageEnvelope>(&body_bytes) {
         Ok(env) => env,
         Err(err) => {
             eprintln!("webex send_payload invalid envelope: {err}");
-            return send_payload_error(&format!("invalid envelope: {err}"), false);
+            return send_payload_error(ProviderError::validation(format!(
+                "invalid envelope: {err}"
+            )));
         }
     };
     if !envelope.attachments.is_empty() {
@@ -1613,7 +1700,9 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
             "webex send_payload rejected attachments {:?}",
             envelope.attachments
         );
-        return send_payload_error("attachments not supported", false);
+        return send_payload_error(ProviderError::unsupported_content(
+            "attachments not supported",
+        ));
     }
     let text = envelope
         .text
@@ -1631,7 +1720,7 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
             "webex send_payload missing text envelope metadata={:?}",
             envelope.metadata
         );
-        return send_payload_error("text required", false);
+        return send_payload_error(ProviderError::validation("text required"));
     }
     let destination = envelope.to.first().cloned().or_else(|| {
         metadata
@@ -1645,15 +1734,15 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
     let destination = match destination {
         Some(dest) => dest,
         None => {
-            return send_payload_error(
-                &format!("destination required (envelope to={:?})", envelope.to),
-                false,
-            );
+            return send_payload_error(ProviderError::validation(format!(
+                "destination required (envelope to={:?})",
+                envelope.to
+            )));
         }
     };
     let dest_id = destination.id.trim();
     if dest_id.is_empty() {
-        return send_payload_error("destination id required", false);
+        return send_payload_error(ProviderError::validation("destination id required"));
     }
     let summary_text = text.clone().or(card_summary.clone());
     let markdown_value = summary_text.clone().unwrap_or_else(|| " ".to_string());
@@ -1673,7 +1762,9 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
             body_map.insert("toPersonEmail".into(), Value::String(dest_id.to_string()));
         }
         other => {
-            return send_payload_error(&format!("unsupported destination kind: {other}"), false);
+            return send_payload_error(ProviderError::validation(format!(
+                "unsupported destination kind: {other}"
+            )));
         }
     }
     let body_req = Value::Object(body_map);
@@ -1684,7 +1775,7 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
     );
     let token = match get_secret_string(DEFAULT_TOKEN_KEY) {
         Ok(value) => value,
-        Err(err) => return send_payload_error(&err, false),
+        Err(err) => return send_payload_error(err),
     };
     let request = client::Request {
         method,
@@ -1695,18 +1786,29 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
         ],
         body: Some(serde_json::to_vec(&body_req).unwrap_or_else(|_| b"{}".to_vec())),
     };
-    let resp = match client::send(&request, None, None) {
+    let policy = serde_json::from_slice::<Value>(input_json)
+        .map(|input| RetryPolicy::from_input(&input))
+        .unwrap_or_default();
+    let outcome = send_http(&request.method, &policy, || {
+        client::send(&request, None, None)
+    });
+    let attempts = outcome.attempts;
+    let resp = match outcome.result {
         Ok(value) => value,
         Err(err) => {
-            return send_payload_error(&format!("transport error: {}", err.message), true);
+            return send_payload_error_after(ProviderError::transport(err.message), attempts);
         }
     };
     if resp.status < 200 || resp.status >= 300 {
-        let body = resp.body.unwrap_or_default();
-        let detail = format_webex_error(resp.status, &body);
-        return send_payload_error(&detail, resp.status >= 500);
+        return send_payload_error_after(webex_status_error(&resp), attempts);
     }
-    send_payload_success()
+    let body_json: Value =
+        serde_json::from_slice(resp.body.as_deref().unwrap_or_default()).unwrap_or(Value::Null);
+    let provider_message_id = body_json
+        .get("id")
+        .and_then(Value::as_str)
+        .map(|id| format!("webex:{id}"));
+    send_payload_success_after(attempts, provider_message_id.as_deref())
 }
 
 /// Serialize HttpOutV1 with "v":1 for operator v0.4.x compatibility.
@@ -1728,6 +1830,57 @@ fn http_out_error(status: u16, message: &str) -> Vec<u8> {
     http_out_v1_bytes(&out)
 }
 
+/// Rejects the request with a 401 when `WEBEX_WEBHOOK_SECRET` is configured and
+/// the webhook signature does not match the body.
+fn verify_webhook(request: &HttpInV1, body: &[u8]) -> Result<(), Vec<u8>> {
+    let secret = match secrets_store::get(WEBHOOK_SECRET_KEY) {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return Ok(()),
+        Err(e) => return Err(http_out_error(500, &format!("secret store error: {e:?}"))),
+    };
+    let signature = request_header(request, WEBEX_SIGNATURE_HEADER)
+        .or_else(|| request_header(request, WEBEX_SIGNATURE_HEADER_ALT));
+    webhook_auth::verify_webex(&secret, signature, body)
+        .map_err(|err| http_out_error(401, &ProviderError::from(err).to_string()))
+}
+
+fn host_state() -> HostStateAdapter {
+    HostStateAdapter::new(
+        |key| state_store::read(key, None).map_err(|err| err.message),
+        |key, value| {
+            state_store::write(key, value, None)
+                .map(|_| ())
+                .map_err(|err| err.message)
+        },
+        |key| {
+            state_store::delete(key, None)
+                .map(|_| ())
+                .map_err(|err| err.message)
+        },
+    )
+}
+
+/// Acknowledges a redelivered webhook without emitting events.
+fn duplicate_ack(key: &str) -> Vec<u8> {
+    let out = HttpOutV1 {
+        status: 200,
+        headers: Vec::new(),
+        body_b64: STANDARD.encode(duplicate_ack_body(PROVIDER_ID, key)),
+        events: Vec::new(),
+    };
+    http_out_v1_bytes(&out)
+}
+
+/// Webex notifications are keyed by the changed resource id and event; the
+/// top-level `id` names the webhook itself when `data` is present.
+fn dedup_key(body: &Value) -> Option<String> {
+    if let Some(data_id) = key_fragment(body.pointer("/data/id")) {
+        let event = key_fragment(body.get("event")).unwrap_or_default();
+        return Some(format!("{data_id}:{event}"));
+    }
+    key_fragment(body.get("id"))
+}
+
 fn render_plan_error(message: &str) -> Vec<u8> {
     json_bytes(&json!({"ok": false, "error": message}))
 }
@@ -1736,29 +1889,84 @@ fn encode_error(message: &str) -> Vec<u8> {
     json_bytes(&json!({"ok": false, "error": message}))
 }
 
-fn send_payload_error(message: &str, retryable: bool) -> Vec<u8> {
+fn error_bytes(err: ProviderError) -> Vec<u8> {
+    json_bytes(&err.to_result_json())
+}
+
+fn error_bytes_after(err: ProviderError, attempts: u32) -> Vec<u8> {
+    let mut value = err.to_result_json();
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+    }
+    json_bytes(&value)
+}
+
+/// Error result of a split send; ids of the parts already delivered are kept
+/// so callers do not resend them.
+fn error_bytes_after_parts(
+    err: ProviderError,
+    attempts: u32,
+    provider_message_ids: &[String],
+) -> Vec<u8> {
+    let mut value = err.to_result_json();
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+        if !provider_message_ids.is_empty() {
+            map.insert("provider_message_ids".into(), json!(provider_message_ids));
+        }
+    }
+    json_bytes(&value)
+}
+
+fn send_payload_error(err: ProviderError) -> Vec<u8> {
+    send_payload_error_after(err, 0)
+}
+
+fn send_payload_error_after(err: ProviderError, attempts: u32) -> Vec<u8> {
+    send_payload_failure(&err.to_string(), &err.detail(), attempts)
+}
+
+fn send_payload_failure(message: &str, detail: &ProviderErrorDetail, attempts: u32) -> Vec<u8> {
     let result = SendPayloadResultV1 {
         ok: false,
         message: Some(message.to_string()),
-        retryable,
+        retryable: detail.retryable,
     };
-    json_bytes(&result)
+    send_payload_result_bytes(&result, attempts, Some(detail), &[])
 }
 
-fn send_payload_success() -> Vec<u8> {
+fn send_payload_success_after(attempts: u32, provider_message_id: Option<&str>) -> Vec<u8> {
     let result = SendPayloadResultV1 {
         ok: true,
         message: None,
         retryable: false,
     };
-    json_bytes(&result)
+    let mut value = serde_json::to_value(&result).unwrap_or(Value::Null);
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+        if let Some(id) = provider_message_id {
+            map.insert("provider_message_id".into(), json!(id));
+        }
+    }
+    json_bytes(&value)
+}
+
+impl RetryableResponse for client::Response {
+    fn status(&self) -> u16 {
+        self.status
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
 }
 
-fn get_secret_string(key: &str) -> Result<String, String> {
+fn get_secret_string(key: &str) -> Result<String, ProviderError> {
     match secrets_store::get(key) {
-        Ok(Some(bytes)) => String::from_utf8(bytes).map_err(|_| "secret not valid utf-8".into()),
-        Ok(None) => Err(format!("missing secret: {key}")),
-        Err(e) => Err(format!("secret store error: {e:?}")),
+        Ok(Some(bytes)) => String::from_utf8(bytes)
+            .map_err(|_| ProviderError::config_invalid(format!("secret {key} not valid utf-8"))),
+        Ok(None) => Err(ProviderError::missing_secret(key)),
+        Err(e) => Err(ProviderError::other(format!("secret store error: {e:?}"))),
     }
 }
 
@@ -1767,6 +1975,13 @@ mod tests {
     use super::*;
     use std::collections::BTreeSet;
 
+    #[test]
+    fn dedup_key_uses_resource_id_over_webhook_id() {
+        let body = json!({"id": "hook-1", "event": "created", "data": {"id": "msg-1"}});
+        assert_eq!(dedup_key(&body).as_deref(), Some("msg-1:created"));
+        assert_eq!(dedup_key(&json!({"id": "evt"})).as_deref(), Some("evt"));
+    }
+
     #[test]
     fn build_webex_body_includes_markdown_and_attachment() {
         let card = json!({
@@ -1805,6 +2020,18 @@ mod tests {
         assert_eq!(empty, "webex returned status 500");
     }
 
+    #[test]
+    fn webex_status_error_classifies_status() {
+        let resp = client::Response {
+            status: 404,
+            headers: Vec::new(),
+            body: Some(br#"{"message":"Room not found"}"#.to_vec()),
+        };
+        let err = webex_status_error(&resp);
+        assert!(matches!(err, ProviderError::DestinationNotFound(_)));
+        assert!(err.to_string().contains("Room not found"));
+    }
+
     #[test]
     fn parse_config_requires_new_fields() {
         let cfg = br#"{"enabled":true,"public_base_url":"https://example.com","api_base_url":"https://webexapis.com/v1"}"#;
@@ -1823,7 +2050,7 @@ mod tests {
     fn parse_config_rejects_unknown() {
         let cfg = br#"{"enabled":true,"public_base_url":"https://example.com","api_base_url":"https://webexapis.com/v1","default_room_id":"k","unexpected":true}"#;
         let err = parse_config_bytes(cfg).unwrap_err();
-        assert!(err.contains("unknown field"));
+        assert!(err.to_string().contains("unknown field"));
     }
 
     #[test]
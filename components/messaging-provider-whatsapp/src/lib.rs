@@ -4,17 +4,30 @@ use greentic_types::messaging::universal_dto::{
     SendPayloadInV1, SendPayloadResultV1,
 };
 use greentic_types::{
-    Actor, ChannelMessageEnvelope, Destination, EnvId, MessageMetadata, TenantCtx, TenantId,
+    Actor, Attachment, ChannelMessageEnvelope, Destination, EnvId, MessageMetadata, TenantCtx,
+    TenantId,
 };
 use provider_common::component_v0_6::{
     DescribePayload, I18nText, OperationDescriptor, QaQuestionSpec, QaSpec, RedactionRule,
     SchemaField, SchemaIr, canonical_cbor_bytes, decode_cbor, default_en_i18n_messages,
     schema_hash,
 };
+use provider_common::formatting::{MarkdownDialect, render as render_markdown};
+use provider_common::http_retry::{
+    RetryPolicy, RetryableResponse, find_header, retry_after, send_http, send_payload_result_bytes,
+};
+use provider_common::ingress_dedup::{DedupWindow, duplicate_ack_body};
+use provider_common::message_split::{SplitOptions, split_markdown};
+use provider_common::outbound_idempotency::send_idempotent;
+use provider_common::state::{HostStateAdapter, key_fragment};
+use provider_common::webhook_auth::{self, META_SIGNATURE_HEADER, request_header};
+use provider_common::{ProviderError, ProviderErrorDetail};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use std::collections::{BTreeMap, HashMap};
 
+mod media;
+
 mod bindings {
     wit_bindgen::generate!({
         path: "wit/messaging-provider-whatsapp",
@@ -25,13 +38,17 @@ mod bindings {
 
 use bindings::greentic::http::http_client as client;
 use bindings::greentic::secrets_store::secrets_store;
+use bindings::greentic::state::state_store;
 
 const PROVIDER_ID: &str = "messaging-provider-whatsapp";
 const PROVIDER_TYPE: &str = "messaging.whatsapp.cloud";
+/// Cloud API text body limit.
+const MAX_TEXT_LEN: usize = 4096;
 const WORLD_ID: &str = "component-v0-v6-v0";
 const DEFAULT_API_BASE: &str = "https://graph.facebook.com";
 const DEFAULT_API_VERSION: &str = "v19.0";
 const DEFAULT_TOKEN_KEY: &str = "WHATSAPP_TOKEN";
+const APP_SECRET_KEY: &str = "WHATSAPP_APP_SECRET";
 const I18N_KEYS: &[&str] = &[
     "whatsapp.op.run.title",
     "whatsapp.op.run.description",
@@ -47,6 +64,8 @@ const I18N_KEYS: &[&str] = &[
     "whatsapp.op.encode.description",
     "whatsapp.op.send_payload.title",
     "whatsapp.op.send_payload.description",
+    "whatsapp.op.fetch_attachment.title",
+    "whatsapp.op.fetch_attachment.description",
     "whatsapp.schema.input.title",
     "whatsapp.schema.input.description",
     "whatsapp.schema.input.message.title",
@@ -326,12 +345,13 @@ fn default_enabled() -> bool {
 
 fn dispatch_json_invoke(op: &str, input_json: &[u8]) -> Vec<u8> {
     match op {
-        "send" => handle_send(input_json),
-        "reply" => handle_reply(input_json),
+        "send" => send_idempotent(PROVIDER_ID, &mut host_state(), input_json, handle_send),
+        "reply" => send_idempotent(PROVIDER_ID, &mut host_state(), input_json, handle_reply),
         "ingest_http" => ingest_http(input_json),
         "render_plan" => render_plan(input_json),
         "encode" => encode_op(input_json),
-        "send_payload" => send_payload(input_json),
+        "send_payload" => send_idempotent(PROVIDER_ID, &mut host_state(), input_json, send_payload),
+        "fetch_attachment" => media::handle_fetch_attachment(input_json),
         other => json_bytes(&json!({"ok": false, "error": format!("unsupported op: {other}")})),
     }
 }
@@ -379,6 +399,11 @@ fn build_describe_payload() -> DescribePayload {
                 "whatsapp.op.send_payload.title",
                 "whatsapp.op.send_payload.description",
             ),
+            op(
+                "fetch_attachment",
+                "whatsapp.op.fetch_attachment.title",
+                "whatsapp.op.fetch_attachment.description",
+            ),
         ],
         input_schema: input_schema.clone(),
         output_schema: output_schema.clone(),
@@ -613,22 +638,24 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
     let parsed: Value = match serde_json::from_slice(input_json) {
         Ok(val) => val,
         Err(err) => {
-            return json_bytes(&json!({"ok": false, "error": format!("invalid json: {err}")}));
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
         }
     };
 
     if let Some(rich) = parsed.get("rich")
         && rich.get("format").and_then(Value::as_str) == Some("whatsapp_template")
     {
-        return json_bytes(&json!({"ok": false, "error": "template messages not supported yet"}));
+        return error_bytes(ProviderError::unsupported_content(
+            "template messages not supported yet",
+        ));
     }
 
     let cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
     if !cfg.enabled {
-        return json_bytes(&json!({"ok": false, "error": "provider disabled by config"}));
+        return error_bytes(ProviderError::config_invalid("provider disabled by config"));
     }
 
     let envelope: ChannelMessageEnvelope = match serde_json::from_slice(input_json) {
@@ -636,15 +663,17 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
         Err(err) => match build_send_envelope_from_input(&parsed) {
             Ok(env) => env,
             Err(message) => {
-                return json_bytes(
-                    &json!({"ok": false, "error": format!("invalid envelope: {message}: {err}")}),
-                );
+                return error_bytes(ProviderError::validation(format!(
+                    "invalid envelope: {message}: {err}"
+                )));
             }
         },
     };
 
     if !envelope.attachments.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "attachments not supported"}));
+        return error_bytes(ProviderError::unsupported_content(
+            "attachments not supported",
+        ));
     }
 
     let text = envelope
@@ -655,30 +684,29 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
         .map(ToOwned::to_owned);
     let text = match text {
         Some(value) => value,
-        None => return json_bytes(&json!({"ok": false, "error": "text required"})),
+        None => return error_bytes(ProviderError::validation("text required")),
     };
 
     let destination = envelope.to.first().cloned();
     let destination = match destination {
         Some(dest) => dest,
-        None => return json_bytes(&json!({"ok": false, "error": "destination required"})),
+        None => return error_bytes(ProviderError::validation("destination required")),
     };
 
     let dest_id = destination.id.trim();
     if dest_id.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "destination id required"}));
+        return error_bytes(ProviderError::validation("destination id required"));
     }
     let kind = destination.kind.as_deref().unwrap_or("phone");
     if kind != "phone" {
-        return json_bytes(&json!({
-            "ok": false,
-            "error": format!("unsupported destination kind: {kind}"),
-        }));
+        return error_bytes(ProviderError::validation(format!(
+            "unsupported destination kind: {kind}"
+        )));
     }
 
     let token = match get_token(&cfg) {
         Ok(token) => token,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
 
     let api_base = cfg
@@ -694,58 +722,83 @@ fn handle_send(input_json: &[u8]) -> Vec<u8> {
         api_base, api_version, cfg.phone_number_id
     );
 
-    let payload = json!({
-        "messaging_product": "whatsapp",
-        "to": dest_id,
-        "type": "text",
-        "text": {"body": text},
-    });
+    let dialect = MarkdownDialect::WhatsApp;
+    let chunks = split_markdown(
+        &text,
+        dialect,
+        &SplitOptions::from_metadata(MAX_TEXT_LEN, &envelope.metadata),
+    );
+    let policy = RetryPolicy::from_input(&parsed);
+    let mut attempts = 0;
+    let mut message_ids = Vec::new();
+    let mut provider_message_ids = Vec::new();
+    let mut responses = Vec::new();
+    for chunk in &chunks {
+        let payload = json!({
+            "messaging_product": "whatsapp",
+            "to": dest_id,
+            "type": "text",
+            "text": {"body": render_markdown(chunk, dialect)},
+        });
 
-    let request = client::Request {
-        method: "POST".into(),
-        url,
-        headers: vec![
-            ("Content-Type".into(), "application/json".into()),
-            ("Authorization".into(), format!("Bearer {token}")),
-        ],
-        body: Some(serde_json::to_vec(&payload).unwrap_or_else(|_| b"{}".to_vec())),
-    };
+        let request = client::Request {
+            method: "POST".into(),
+            url: url.clone(),
+            headers: vec![
+                ("Content-Type".into(), "application/json".into()),
+                ("Authorization".into(), format!("Bearer {token}")),
+            ],
+            body: Some(serde_json::to_vec(&payload).unwrap_or_else(|_| b"{}".to_vec())),
+        };
 
-    let resp = match client::send(&request, None, None) {
-        Ok(resp) => resp,
-        Err(err) => {
-            return json_bytes(
-                &json!({"ok": false, "error": format!("transport error: {}", err.message)}),
+        let outcome = send_http(&request.method, &policy, || {
+            client::send(&request, None, None)
+        });
+        attempts += outcome.attempts;
+        let resp = match outcome.result {
+            Ok(resp) => resp,
+            Err(err) => {
+                return error_bytes_after_parts(
+                    ProviderError::transport(err.message),
+                    attempts,
+                    &provider_message_ids,
+                );
+            }
+        };
+
+        if resp.status < 200 || resp.status >= 300 {
+            return error_bytes_after_parts(
+                whatsapp_status_error(&resp),
+                attempts,
+                &provider_message_ids,
             );
         }
-    };
 
-    if resp.status < 200 || resp.status >= 300 {
-        return json_bytes(
-            &json!({"ok": false, "error": format!("whatsapp returned status {}", resp.status)}),
-        );
+        let body = resp.body.unwrap_or_default();
+        let body_json: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        let msg_id = body_json
+            .get("messages")
+            .and_then(|v| v.as_array())
+            .and_then(|arr| arr.first())
+            .and_then(|v| v.get("id"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("wa-message")
+            .to_string();
+        provider_message_ids.push(format!("whatsapp:{msg_id}"));
+        message_ids.push(msg_id);
+        responses.push(body_json);
     }
 
-    let body = resp.body.unwrap_or_default();
-    let body_json: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
-    let msg_id = body_json
-        .get("messages")
-        .and_then(|v| v.as_array())
-        .and_then(|arr| arr.first())
-        .and_then(|v| v.get("id"))
-        .and_then(|v| v.as_str())
-        .unwrap_or("wa-message")
-        .to_string();
-    let provider_message_id = format!("whatsapp:{msg_id}");
-
     json_bytes(&json!({
         "ok": true,
         "status": "sent",
         "provider_type": PROVIDER_TYPE,
         "public_base_url": cfg.public_base_url,
-        "message_id": msg_id,
-        "provider_message_id": provider_message_id,
-        "response": body_json
+        "message_id": message_ids.first(),
+        "provider_message_id": provider_message_ids.first(),
+        "provider_message_ids": provider_message_ids,
+        "attempts": attempts,
+        "response": responses.first()
     }))
 }
 
@@ -817,15 +870,15 @@ fn handle_reply(_input_json: &[u8]) -> Vec<u8> {
     let parsed: Value = match serde_json::from_slice(_input_json) {
         Ok(val) => val,
         Err(err) => {
-            return json_bytes(&json!({"ok": false, "error": format!("invalid json: {err}")}));
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
         }
     };
     let cfg = match load_config(&parsed) {
         Ok(cfg) => cfg,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
     if !cfg.enabled {
-        return json_bytes(&json!({"ok": false, "error": "provider disabled by config"}));
+        return error_bytes(ProviderError::config_invalid("provider disabled by config"));
     }
     let to_kind = parsed
         .get("to")
@@ -838,7 +891,9 @@ fn handle_reply(_input_json: &[u8]) -> Vec<u8> {
         .and_then(|v| v.as_str())
         .unwrap_or("");
     if to_kind != "user" || to_id.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "to.kind=user with to.id required"}));
+        return error_bytes(ProviderError::validation(
+            "to.kind=user with to.id required",
+        ));
     }
     let text = parsed
         .get("text")
@@ -846,7 +901,7 @@ fn handle_reply(_input_json: &[u8]) -> Vec<u8> {
         .unwrap_or("")
         .to_string();
     if text.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "text required"}));
+        return error_bytes(ProviderError::validation("text required"));
     }
     let reply_to = parsed
         .get("reply_to_id")
@@ -854,12 +909,14 @@ fn handle_reply(_input_json: &[u8]) -> Vec<u8> {
         .and_then(|v| v.as_str())
         .unwrap_or("");
     if reply_to.is_empty() {
-        return json_bytes(&json!({"ok": false, "error": "reply_to_id or thread_id required"}));
+        return error_bytes(ProviderError::validation(
+            "reply_to_id or thread_id required",
+        ));
     }
 
     let token = match get_token(&cfg) {
         Ok(token) => token,
-        Err(err) => return json_bytes(&json!({"ok": false, "error": err})),
+        Err(err) => return error_bytes(err),
     };
     let api_base = cfg
         .api_base_url
@@ -878,7 +935,7 @@ fn handle_reply(_input_json: &[u8]) -> Vec<u8> {
         "to": to_id,
         "type": "text",
         "context": {"message_id": reply_to},
-        "text": { "body": text }
+        "text": { "body": render_markdown(&text, MarkdownDialect::WhatsApp) }
     });
     let request = client::Request {
         method: "POST".into(),
@@ -889,20 +946,18 @@ fn handle_reply(_input_json: &[u8]) -> Vec<u8> {
         ],
         body: Some(serde_json::to_vec(&payload).unwrap_or_else(|_| b"{}".to_vec())),
     };
-    let resp = match client::send(&request, None, None) {
+    let outcome = send_http(&request.method, &RetryPolicy::from_input(&parsed), || {
+        client::send(&request, None, None)
+    });
+    let attempts = outcome.attempts;
+    let resp = match outcome.result {
         Ok(resp) => resp,
         Err(err) => {
-            return json_bytes(&json!({
-                "ok": false,
-                "error": format!("transport error: {}", err.message),
-            }));
+            return error_bytes_after(ProviderError::transport(err.message), attempts);
         }
     };
     if resp.status < 200 || resp.status >= 300 {
-        return json_bytes(&json!({
-            "ok": false,
-            "error": format!("whatsapp returned status {}", resp.status),
-        }));
+        return error_bytes_after(whatsapp_status_error(&resp), attempts);
     }
     let body_bytes = resp.body.unwrap_or_default();
     let body_json: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
@@ -923,6 +978,7 @@ fn handle_reply(_input_json: &[u8]) -> Vec<u8> {
         "public_base_url": cfg.public_base_url,
         "message_id": msg_id,
         "provider_message_id": provider_message_id,
+        "attempts": attempts,
         "response": body_json
     }))
 }
@@ -948,18 +1004,44 @@ fn ingest_http(input_json: &[u8]) -> Vec<u8> {
         Ok(bytes) => bytes,
         Err(err) => return http_out_error(400, &format!("invalid body encoding: {err}")),
     };
+    if let Err(out) = verify_webhook(&request, &body_bytes) {
+        return out;
+    }
     let body_val: Value = serde_json::from_slice(&body_bytes).unwrap_or(Value::Null);
-    let text = body_val
-        .get("text")
-        .and_then(|t| t.get("body"))
+    // The key is recorded once the delivery normalized, so a redelivery of one
+    // that failed part-way is processed again.
+    let dedup = dedup_key(&body_val);
+    let window = DedupWindow::default();
+    let now = webhook_auth::now_unix_secs();
+    if let Some(key) = &dedup
+        && window.seen(&mut host_state(), PROVIDER_ID, key, now)
+    {
+        return duplicate_ack(key);
+    }
+    // Cloud API webhooks nest the message; bare message objects are accepted
+    // as well.
+    let message = body_val
+        .pointer("/entry/0/changes/0/value/messages/0")
+        .unwrap_or(&body_val);
+    let attachments = media::message_attachments(message);
+    let text = message
+        .pointer("/text/body")
+        .or_else(|| {
+            attachments
+                .first()
+                .and_then(|_| message.get(message.get("type")?.as_str()?)?.get("caption"))
+        })
         .and_then(Value::as_str)
         .unwrap_or("")
         .to_string();
-    let from = body_val
+    let from = message
         .get("from")
         .and_then(Value::as_str)
         .map(str::to_string);
-    let envelope = build_whatsapp_envelope(text.clone(), from.clone());
+    let envelope = build_whatsapp_envelope(text.clone(), from.clone(), attachments);
+    if let Some(key) = &dedup {
+        window.record(&mut host_state(), PROVIDER_ID, key, now);
+    }
     let normalized = json!({
         "ok": true,
         "event": body_val,
@@ -1060,48 +1142,73 @@ fn send_payload(input_json: &[u8]) -> Vec<u8> {
     let send_in = match serde_json::from_slice::<SendPayloadInV1>(input_json) {
         Ok(value) => value,
         Err(err) => {
-            return send_payload_error(&format!("invalid send_payload input: {err}"), false);
+            return send_payload_error(ProviderError::validation(format!(
+                "invalid send_payload input: {err}"
+            )));
         }
     };
     if send_in.provider_type != PROVIDER_TYPE {
-        return send_payload_error("provider type mismatch", false);
+        return send_payload_error(ProviderError::validation("provider type mismatch"));
     }
     let payload_bytes = match general_purpose::STANDARD.decode(&send_in.payload.body_b64) {
         Ok(bytes) => bytes,
         Err(err) => {
-            return send_payload_error(&format!("payload decode failed: {err}"), false);
+            return send_payload_error(ProviderError::validation(format!(
+                "payload decode failed: {err}"
+            )));
         }
     };
-    let payload: Value = serde_json::from_slice(&payload_bytes).unwrap_or(Value::Null);
+    let mut payload: Value = serde_json::from_slice(&payload_bytes).unwrap_or(Value::Null);
+    if let (Some(map), Some(runtime)) = (payload.as_object_mut(), input_runtime_config(input_json))
+    {
+        map.entry("runtime_config").or_insert(runtime);
+    }
     match forward_send_payload(&payload) {
-        Ok(_) => send_payload_success(),
-        Err(err) => send_payload_error(&err, false),
+        Ok(result) => send_payload_from_send_result(&result),
+        Err(err) => send_payload_error(err),
     }
 }
 
-fn forward_send_payload(payload: &Value) -> Result<(), String> {
-    let payload_bytes =
-        serde_json::to_vec(payload).map_err(|err| format!("serialize failed: {err}"))?;
+fn input_runtime_config(input_json: &[u8]) -> Option<Value> {
+    serde_json::from_slice::<Value>(input_json)
+        .ok()?
+        .get("runtime_config")
+        .cloned()
+}
+
+fn forward_send_payload(payload: &Value) -> Result<Value, ProviderError> {
+    let payload_bytes = serde_json::to_vec(payload)
+        .map_err(|err| ProviderError::validation(format!("serialize failed: {err}")))?;
     let result = handle_send(&payload_bytes);
-    let result_value: Value =
-        serde_json::from_slice(&result).map_err(|err| format!("parse send result: {err}"))?;
-    let ok = result_value
-        .get("ok")
-        .and_then(Value::as_bool)
-        .unwrap_or(false);
-    if ok {
-        Ok(())
-    } else {
-        let message = result_value
-            .get("error")
-            .and_then(Value::as_str)
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| "send_payload failed".to_string());
-        Err(message)
+    serde_json::from_slice(&result)
+        .map_err(|err| ProviderError::other(format!("parse send result: {err}")))
+}
+
+/// Maps a `handle_send` result onto `SendPayloadResultV1`, keeping the
+/// classified error so `retryable` follows the error code.
+fn send_payload_from_send_result(result: &Value) -> Vec<u8> {
+    let attempts = result.get("attempts").and_then(Value::as_u64).unwrap_or(0) as u32;
+    if result.get("ok").and_then(Value::as_bool).unwrap_or(false) {
+        let provider_message_id = result.get("provider_message_id").and_then(Value::as_str);
+        return send_payload_success_after(attempts, provider_message_id);
     }
+    let message = result
+        .get("error")
+        .and_then(Value::as_str)
+        .unwrap_or("send_payload failed");
+    let detail = result
+        .get("error_detail")
+        .cloned()
+        .and_then(|value| serde_json::from_value::<ProviderErrorDetail>(value).ok())
+        .unwrap_or_else(|| ProviderError::other(message).detail());
+    send_payload_failure(message, &detail, attempts)
 }
 
-fn build_whatsapp_envelope(text: String, from: Option<String>) -> ChannelMessageEnvelope {
+fn build_whatsapp_envelope(
+    text: String,
+    from: Option<String>,
+    attachments: Vec<Attachment>,
+) -> ChannelMessageEnvelope {
     let env = EnvId::try_from("default").expect("env id");
     let tenant = TenantId::try_from("default").expect("tenant id");
     let mut metadata = MessageMetadata::new();
@@ -1124,7 +1231,7 @@ fn build_whatsapp_envelope(text: String, from: Option<String>) -> ChannelMessage
         to: Vec::new(),
         correlation_id: None,
         text: Some(text),
-        attachments: Vec::new(),
+        attachments,
         metadata,
     }
 }
@@ -1160,6 +1267,63 @@ fn http_out_error(status: u16, message: &str) -> Vec<u8> {
     http_out_v1_bytes(&out)
 }
 
+/// Rejects the request with a 401 when `WHATSAPP_APP_SECRET` is configured and
+/// the `X-Hub-Signature-256` header does not match the body.
+fn verify_webhook(request: &HttpInV1, body: &[u8]) -> Result<(), Vec<u8>> {
+    let secret = match secrets_store::get(APP_SECRET_KEY) {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return Ok(()),
+        Err(e) => return Err(http_out_error(500, &format!("secret store error: {e:?}"))),
+    };
+    webhook_auth::verify_meta(
+        &secret,
+        request_header(request, META_SIGNATURE_HEADER),
+        body,
+    )
+    .map_err(|err| http_out_error(401, &ProviderError::from(err).to_string()))
+}
+
+fn host_state() -> HostStateAdapter {
+    HostStateAdapter::new(
+        |key| state_store::read(key, None).map_err(|err| err.message),
+        |key, value| {
+            state_store::write(key, value, None)
+                .map(|_| ())
+                .map_err(|err| err.message)
+        },
+        |key| {
+            state_store::delete(key, None)
+                .map(|_| ())
+                .map_err(|err| err.message)
+        },
+    )
+}
+
+/// Acknowledges a redelivered webhook without emitting events.
+fn duplicate_ack(key: &str) -> Vec<u8> {
+    let out = HttpOutV1 {
+        status: 200,
+        headers: Vec::new(),
+        body_b64: general_purpose::STANDARD.encode(duplicate_ack_body(PROVIDER_ID, key)),
+        events: Vec::new(),
+    };
+    http_out_v1_bytes(&out)
+}
+
+/// Meta resends the same `wamid` for a message; status callbacks are keyed by
+/// message id and status so each transition is delivered once.
+fn dedup_key(body: &Value) -> Option<String> {
+    let value = body.pointer("/entry/0/changes/0/value");
+    key_fragment(value.and_then(|v| v.pointer("/messages/0/id")))
+        .or_else(|| {
+            let status = value?.pointer("/statuses/0")?;
+            let id = key_fragment(status.get("id"))?;
+            let state = key_fragment(status.get("status")).unwrap_or_default();
+            Some(format!("{id}:{state}"))
+        })
+        .or_else(|| key_fragment(body.get("id")))
+}
+
 fn render_plan_error(message: &str) -> Vec<u8> {
     json_bytes(&json!({"ok": false, "error": message}))
 }
@@ -1168,38 +1332,124 @@ fn encode_error(message: &str) -> Vec<u8> {
     json_bytes(&json!({"ok": false, "error": message}))
 }
 
-fn send_payload_error(message: &str, retryable: bool) -> Vec<u8> {
+fn error_bytes(err: ProviderError) -> Vec<u8> {
+    json_bytes(&err.to_result_json())
+}
+
+fn error_bytes_after(err: ProviderError, attempts: u32) -> Vec<u8> {
+    let mut value = err.to_result_json();
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+    }
+    json_bytes(&value)
+}
+
+/// Error result of a split send; ids of the parts already delivered are kept
+/// so callers do not resend them.
+fn error_bytes_after_parts(
+    err: ProviderError,
+    attempts: u32,
+    provider_message_ids: &[String],
+) -> Vec<u8> {
+    let mut value = err.to_result_json();
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+        if !provider_message_ids.is_empty() {
+            map.insert("provider_message_ids".into(), json!(provider_message_ids));
+        }
+    }
+    json_bytes(&value)
+}
+
+fn send_payload_error(err: ProviderError) -> Vec<u8> {
+    send_payload_error_after(err, 0)
+}
+
+fn send_payload_error_after(err: ProviderError, attempts: u32) -> Vec<u8> {
+    send_payload_failure(&err.to_string(), &err.detail(), attempts)
+}
+
+fn send_payload_failure(message: &str, detail: &ProviderErrorDetail, attempts: u32) -> Vec<u8> {
     let result = SendPayloadResultV1 {
         ok: false,
         message: Some(message.to_string()),
-        retryable,
+        retryable: detail.retryable,
     };
-    json_bytes(&result)
+    send_payload_result_bytes(&result, attempts, Some(detail), &[])
 }
 
-fn send_payload_success() -> Vec<u8> {
+fn send_payload_success_after(attempts: u32, provider_message_id: Option<&str>) -> Vec<u8> {
     let result = SendPayloadResultV1 {
         ok: true,
         message: None,
         retryable: false,
     };
-    json_bytes(&result)
+    let mut value = serde_json::to_value(&result).unwrap_or(Value::Null);
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+        if let Some(id) = provider_message_id {
+            map.insert("provider_message_id".into(), json!(id));
+        }
+    }
+    json_bytes(&value)
+}
+
+impl RetryableResponse for client::Response {
+    fn status(&self) -> u16 {
+        self.status
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+}
+
+/// Classifies a non-2xx Cloud API response from the Graph `error` object.
+/// Throttling and undeliverable-recipient codes are mapped explicitly; other
+/// codes fall back to the HTTP status with the numeric code preserved.
+fn whatsapp_status_error(resp: &client::Response) -> ProviderError {
+    let error = resp
+        .body
+        .as_deref()
+        .and_then(|bytes| serde_json::from_slice::<Value>(bytes).ok())
+        .and_then(|body| body.get("error").cloned())
+        .unwrap_or(Value::Null);
+    let code = error.get("code").and_then(Value::as_i64);
+    let message = error
+        .get("message")
+        .and_then(Value::as_str)
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(|| format!("whatsapp returned status {}", resp.status));
+    let retry_after_secs = retry_after(resp).map(|delay| delay.as_secs());
+    match code {
+        Some(4 | 80007 | 130429 | 131048 | 131056) => {
+            ProviderError::rate_limited(message, retry_after_secs)
+        }
+        Some(190) => ProviderError::auth_failed(message),
+        Some(131026 | 131030) => ProviderError::destination_not_found(message),
+        _ => ProviderError::from_http_status(
+            "whatsapp",
+            resp.status,
+            retry_after_secs,
+            code.map(|code| code.to_string()),
+        ),
+    }
 }
 
 #[cfg(test)]
-fn parse_config_bytes(bytes: &[u8]) -> Result<ProviderConfig, String> {
+fn parse_config_bytes(bytes: &[u8]) -> Result<ProviderConfig, ProviderError> {
     let cfg = serde_json::from_slice::<ProviderConfig>(bytes)
-        .map_err(|e| format!("invalid config: {e}"))?;
+        .map_err(|e| ProviderError::config_invalid(e.to_string()))?;
     validate_provider_config(cfg)
 }
 
-fn parse_config_value(val: &Value) -> Result<ProviderConfig, String> {
+fn parse_config_value(val: &Value) -> Result<ProviderConfig, ProviderError> {
     let cfg = serde_json::from_value::<ProviderConfig>(val.clone())
-        .map_err(|e| format!("invalid config: {e}"))?;
+        .map_err(|e| ProviderError::config_invalid(e.to_string()))?;
     validate_provider_config(cfg)
 }
 
-fn load_config(input: &Value) -> Result<ProviderConfig, String> {
+fn load_config(input: &Value) -> Result<ProviderConfig, ProviderError> {
     if let Some(cfg) = input.get("config") {
         return parse_config_value(cfg);
     }
@@ -1221,7 +1471,7 @@ fn load_config(input: &Value) -> Result<ProviderConfig, String> {
         return parse_config_value(&Value::Object(partial));
     }
 
-    Err("config required".into())
+    Err(ProviderError::config_invalid("config required"))
 }
 
 fn existing_config_from_answers(answers: &Value) -> Option<ProviderConfigOut> {
@@ -1295,12 +1545,16 @@ fn json_bytes<T: serde::Serialize>(value: &T) -> Vec<u8> {
     serde_json::to_vec(value).unwrap_or_else(|_| b"{}".to_vec())
 }
 
-fn validate_provider_config(cfg: ProviderConfig) -> Result<ProviderConfig, String> {
+fn validate_provider_config(cfg: ProviderConfig) -> Result<ProviderConfig, ProviderError> {
     if cfg.phone_number_id.trim().is_empty() {
-        return Err("invalid config: phone_number_id cannot be empty".to_string());
+        return Err(ProviderError::config_invalid(
+            "phone_number_id cannot be empty",
+        ));
     }
     if cfg.public_base_url.trim().is_empty() {
-        return Err("invalid config: public_base_url cannot be empty".to_string());
+        return Err(ProviderError::config_invalid(
+            "public_base_url cannot be empty",
+        ));
     }
     if let Some(business_account_id) = cfg.business_account_id.as_deref() {
         let _ = business_account_id.trim();
@@ -1308,7 +1562,7 @@ fn validate_provider_config(cfg: ProviderConfig) -> Result<ProviderConfig, Strin
     Ok(cfg)
 }
 
-fn get_token(cfg: &ProviderConfig) -> Result<String, String> {
+fn get_token(cfg: &ProviderConfig) -> Result<String, ProviderError> {
     if let Some(token) = cfg.token.clone() {
         let token = token.trim().to_string();
         if !token.is_empty() {
@@ -1316,14 +1570,10 @@ fn get_token(cfg: &ProviderConfig) -> Result<String, String> {
         }
     }
     match secrets_store::get(DEFAULT_TOKEN_KEY) {
-        Ok(Some(bytes)) => {
-            String::from_utf8(bytes).map_err(|_| "access_token not utf-8".to_string())
-        }
-        Ok(None) => Err(format!(
-            "missing token (config or secret: {})",
-            DEFAULT_TOKEN_KEY
-        )),
-        Err(e) => Err(format!("secret store error: {e:?}")),
+        Ok(Some(bytes)) => String::from_utf8(bytes)
+            .map_err(|_| ProviderError::config_invalid("access_token not utf-8")),
+        Ok(None) => Err(ProviderError::missing_secret(DEFAULT_TOKEN_KEY)),
+        Err(e) => Err(ProviderError::other(format!("secret store error: {e:?}"))),
     }
 }
 
@@ -1332,6 +1582,15 @@ mod tests {
     use super::*;
     use std::collections::BTreeSet;
 
+    #[test]
+    fn dedup_key_reads_message_and_status_ids() {
+        let message =
+            json!({"entry": [{"changes": [{"value": {"messages": [{"id": "wamid.1"}]}}]}]});
+        assert_eq!(dedup_key(&message).as_deref(), Some("wamid.1"));
+        let status = json!({"entry": [{"changes": [{"value": {"statuses": [{"id": "wamid.1", "status": "read"}]}}]}]});
+        assert_eq!(dedup_key(&status).as_deref(), Some("wamid.1:read"));
+    }
+
     #[test]
     fn parse_config_requires_new_fields() {
         let cfg = br#"{"enabled":true,"phone_number_id":"pn","public_base_url":"https://example.com","api_base_url":"https://graph.facebook.com","api_version":"v19.0"}"#;
@@ -1344,7 +1603,30 @@ mod tests {
     fn parse_config_rejects_unknown() {
         let cfg = br#"{"enabled":true,"phone_number_id":"p","public_base_url":"https://example.com","api_base_url":"https://graph.facebook.com","api_version":"v19.0","unexpected":true}"#;
         let err = parse_config_bytes(cfg).unwrap_err();
-        assert!(err.contains("unknown field"));
+        assert!(err.to_string().contains("unknown field"));
+    }
+
+    #[test]
+    fn whatsapp_status_error_maps_graph_codes() {
+        let response = |status: u16, code: i64| client::Response {
+            status,
+            headers: Vec::new(),
+            body: Some(
+                serde_json::to_vec(&json!({"error": {"message": "graph error", "code": code}}))
+                    .expect("body"),
+            ),
+        };
+        assert!(matches!(
+            whatsapp_status_error(&response(400, 131056)),
+            ProviderError::RateLimited { .. }
+        ));
+        assert!(matches!(
+            whatsapp_status_error(&response(401, 190)),
+            ProviderError::AuthFailed(_)
+        ));
+        let rejected = whatsapp_status_error(&response(400, 100)).detail();
+        assert_eq!(rejected.provider_code.as_deref(), Some("100"));
+        assert!(!rejected.retryable);
     }
 
     #[test]
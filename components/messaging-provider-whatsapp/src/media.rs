@@ -0,0 +1,213 @@
+//! Inbound media.
+//!
+//! Webhook messages carry a media id instead of the file. Ingest turns it into
+//! a `whatsapp-media:<id>` attachment url; `fetch_attachment` looks the id up
+//! with `GET /{media-id}` and downloads the short-lived url it returns, which
+//! needs the same bearer token.
+
+use super::{
+    DEFAULT_API_BASE, DEFAULT_API_VERSION, PROVIDER_TYPE, client, error_bytes, error_bytes_after,
+    get_token, json_bytes, load_config, whatsapp_status_error,
+};
+use greentic_types::Attachment;
+use provider_common::ProviderError;
+use provider_common::attachments::{FetchLimits, FetchedAttachment, response_mime_type};
+use provider_common::http_retry::{RetryPolicy, send_http};
+use serde_json::Value;
+
+/// Prefix of attachment urls that reference a Cloud API media id.
+pub(crate) const WHATSAPP_MEDIA_URL_PREFIX: &str = "whatsapp-media:";
+/// Message types whose object carries a media `id`.
+const MEDIA_TYPES: &[&str] = &["image", "video", "audio", "document", "sticker"];
+
+/// Attachment references for the media of an inbound message.
+pub(crate) fn message_attachments(message: &Value) -> Vec<Attachment> {
+    MEDIA_TYPES
+        .iter()
+        .filter_map(|kind| {
+            let media = message.get(*kind)?;
+            let id = media.get("id")?.as_str()?;
+            Some(Attachment {
+                mime_type: media
+                    .get("mime_type")
+                    .and_then(Value::as_str)
+                    .map(|mime| mime.split(';').next().unwrap_or(mime).trim().to_string())
+                    .unwrap_or_else(|| "application/octet-stream".to_string()),
+                url: format!("{WHATSAPP_MEDIA_URL_PREFIX}{id}"),
+                name: media
+                    .get("filename")
+                    .and_then(Value::as_str)
+                    .map(ToOwned::to_owned),
+                size_bytes: None,
+            })
+        })
+        .collect()
+}
+
+/// Takes the attachment `url` or a bare `media_id`.
+pub(crate) fn handle_fetch_attachment(input_json: &[u8]) -> Vec<u8> {
+    let parsed: Value = match serde_json::from_slice(input_json) {
+        Ok(value) => value,
+        Err(err) => {
+            return error_bytes(ProviderError::validation(format!("invalid json: {err}")));
+        }
+    };
+    let cfg = match load_config(&parsed) {
+        Ok(cfg) => cfg,
+        Err(err) => return error_bytes(err),
+    };
+    if !cfg.enabled {
+        return error_bytes(ProviderError::config_invalid("provider disabled by config"));
+    }
+    let media_id = match media_id_from_input(&parsed) {
+        Ok(media_id) => media_id,
+        Err(err) => return error_bytes(err),
+    };
+    let token = match get_token(&cfg) {
+        Ok(token) => token,
+        Err(err) => return error_bytes(err),
+    };
+    let limits = FetchLimits::from_input(&parsed);
+    let policy = RetryPolicy::from_input(&parsed);
+    let api_base = cfg.api_base_url.as_deref().unwrap_or(DEFAULT_API_BASE);
+    let api_version = cfg.api_version.as_deref().unwrap_or(DEFAULT_API_VERSION);
+
+    let (resp, mut attempts) = match get(
+        &format!("{api_base}/{api_version}/{media_id}"),
+        &token,
+        &policy,
+    ) {
+        Ok(result) => result,
+        Err((err, attempts)) => return error_bytes_after(err, attempts),
+    };
+    let info: Value = resp
+        .body
+        .as_deref()
+        .and_then(|bytes| serde_json::from_slice(bytes).ok())
+        .unwrap_or(Value::Null);
+    let Some(download_url) = info.get("url").and_then(Value::as_str) else {
+        return error_bytes_after(
+            ProviderError::other("media lookup returned no url"),
+            attempts,
+        );
+    };
+    let declared_mime = info.get("mime_type").and_then(Value::as_str);
+    let checked = info
+        .get("file_size")
+        .and_then(Value::as_u64)
+        .map_or(Ok(()), |size| limits.check_size(size))
+        .and_then(|()| declared_mime.map_or(Ok(()), |mime| limits.check_mime(mime)));
+    if let Err(err) = checked {
+        return error_bytes_after(err, attempts);
+    }
+
+    let (resp, n) = match get(download_url, &token, &policy) {
+        Ok(result) => result,
+        Err((err, n)) => return error_bytes_after(err, attempts + n),
+    };
+    attempts += n;
+    let fetched = FetchedAttachment {
+        mime_type: declared_mime
+            .map(|mime| {
+                mime.split(';')
+                    .next()
+                    .unwrap_or(mime)
+                    .trim()
+                    .to_ascii_lowercase()
+            })
+            .or_else(|| response_mime_type(&resp.headers))
+            .unwrap_or_else(|| "application/octet-stream".to_string()),
+        name: parsed
+            .get("name")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned),
+        bytes: resp.body.unwrap_or_default(),
+    };
+    if let Err(err) = limits
+        .check_size(fetched.bytes.len() as u64)
+        .and_then(|()| limits.check_mime(&fetched.mime_type))
+    {
+        return error_bytes_after(err, attempts);
+    }
+    json_bytes(&fetched.to_result_json(PROVIDER_TYPE, attempts))
+}
+
+fn media_id_from_input(parsed: &Value) -> Result<String, ProviderError> {
+    if let Some(url) = parsed.get("url").and_then(Value::as_str) {
+        return url
+            .trim()
+            .strip_prefix(WHATSAPP_MEDIA_URL_PREFIX)
+            .filter(|id| !id.is_empty() && !id.contains('/'))
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| {
+                ProviderError::validation(format!(
+                    "url must be a {WHATSAPP_MEDIA_URL_PREFIX}<media_id> reference"
+                ))
+            });
+    }
+    parsed
+        .get("media_id")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && !id.contains('/'))
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| ProviderError::validation("url or media_id required"))
+}
+
+fn get(
+    url: &str,
+    token: &str,
+    policy: &RetryPolicy,
+) -> Result<(client::Response, u32), (ProviderError, u32)> {
+    let request = client::Request {
+        method: "GET".to_string(),
+        url: url.to_string(),
+        headers: vec![("Authorization".into(), format!("Bearer {token}"))],
+        body: None,
+    };
+    let outcome = send_http(&request.method, policy, || {
+        client::send(&request, None, None)
+    });
+    let attempts = outcome.attempts;
+    let resp = outcome
+        .result
+        .map_err(|err| (ProviderError::transport(err.message), attempts))?;
+    if resp.status < 200 || resp.status >= 300 {
+        return Err((whatsapp_status_error(&resp), attempts));
+    }
+    Ok((resp, attempts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn media_messages_become_attachment_references() {
+        let message = json!({
+            "type": "document",
+            "document": {"id": "123", "mime_type": "application/pdf", "filename": "a.pdf"}
+        });
+        let attachments = message_attachments(&message);
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].url, "whatsapp-media:123");
+        assert_eq!(attachments[0].mime_type, "application/pdf");
+        assert_eq!(attachments[0].name.as_deref(), Some("a.pdf"));
+        assert!(message_attachments(&json!({"text": {"body": "hi"}})).is_empty());
+    }
+
+    #[test]
+    fn media_id_comes_from_reference_url_or_field() {
+        assert_eq!(
+            media_id_from_input(&json!({"url": "whatsapp-media:123"})),
+            Ok("123".into())
+        );
+        assert_eq!(
+            media_id_from_input(&json!({"media_id": "123"})),
+            Ok("123".into())
+        );
+        assert!(media_id_from_input(&json!({"url": "whatsapp-media:../me"})).is_err());
+        assert!(media_id_from_input(&json!({})).is_err());
+    }
+}
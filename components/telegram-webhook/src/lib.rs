@@ -20,8 +20,14 @@ const DEFAULT_API_BASE: &str = "https://api.telegram.org";
 const DEFAULT_WEBHOOK_PATH: &str = "";
 const TOKEN_SECRET: &str = "TELEGRAM_BOT_TOKEN";
 
+/// Largest `max_connections` Telegram accepts.
+const MAX_CONNECTIONS_LIMIT: u32 = 100;
+
 #[derive(Deserialize)]
 struct ReconcileInput {
+    #[serde(default)]
+    mode: ReconcileMode,
+    #[serde(default)]
     public_base_url: String,
     #[serde(default)]
     webhook_path: Option<String>,
@@ -30,22 +36,143 @@ struct ReconcileInput {
     #[serde(default)]
     secret_token: Option<String>,
     #[serde(default)]
+    allowed_updates: Option<Vec<String>>,
+    #[serde(default)]
+    max_connections: Option<u32>,
+    #[serde(default)]
+    drop_pending_updates: Option<bool>,
+    #[serde(default)]
+    ip_address: Option<String>,
+    #[serde(default)]
     dry_run: Option<bool>,
 }
 
+/// `remove` tears the webhook down for the pack's Remove QA mode; the other
+/// QA modes register it.
+#[derive(Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+enum ReconcileMode {
+    #[default]
+    #[serde(alias = "default", alias = "setup", alias = "upgrade")]
+    Apply,
+    Remove,
+}
+
 #[derive(Serialize)]
 struct ReconcileOutput {
     ok: bool,
-    expected_url: String,
+    mode: ReconcileMode,
+    expected_url: Option<String>,
     current_url: Option<String>,
     final_url: Option<String>,
+    /// Declared settings that differed from `getWebhookInfo` before
+    /// reconciling.
+    drift: Vec<String>,
     webhook_reconciled: bool,
     set_attempted: bool,
     set_skipped_dry_run: bool,
     set_response: Option<Value>,
+    delete_attempted: bool,
+    delete_skipped_dry_run: bool,
+    delete_response: Option<Value>,
+    pending_update_count: Option<u64>,
+    last_error_message: Option<String>,
+    last_error_date: Option<i64>,
+    diagnostics: Vec<String>,
     webhook_info: Value,
 }
 
+/// Webhook settings the reconcile input declares; `None` leaves a setting
+/// unchecked.
+#[derive(Debug, Default)]
+struct DesiredWebhook {
+    url: String,
+    allowed_updates: Option<Vec<String>>,
+    max_connections: Option<u32>,
+    ip_address: Option<String>,
+}
+
+impl DesiredWebhook {
+    fn from_input(input: &ReconcileInput) -> Result<Self, String> {
+        let base = input.public_base_url.trim();
+        if base.is_empty() {
+            return Err("public_base_url is required".to_string());
+        }
+        let webhook_path = input
+            .webhook_path
+            .as_deref()
+            .unwrap_or(DEFAULT_WEBHOOK_PATH)
+            .trim();
+        if let Some(max) = input.max_connections
+            && !(1..=MAX_CONNECTIONS_LIMIT).contains(&max)
+        {
+            return Err(format!(
+                "max_connections must be between 1 and {MAX_CONNECTIONS_LIMIT}"
+            ));
+        }
+        let allowed_updates = input.allowed_updates.as_ref().map(|updates| {
+            let mut updates: Vec<String> = updates
+                .iter()
+                .map(|update| update.trim().to_string())
+                .filter(|update| !update.is_empty())
+                .collect();
+            updates.sort();
+            updates.dedup();
+            updates
+        });
+        Ok(Self {
+            url: join_url(base, webhook_path),
+            allowed_updates,
+            max_connections: input.max_connections,
+            ip_address: input
+                .ip_address
+                .as_deref()
+                .map(str::trim)
+                .filter(|ip| !ip.is_empty())
+                .map(ToOwned::to_owned),
+        })
+    }
+
+    /// Names of the settings `getWebhookInfo` reports differently.
+    fn drift(&self, info: &Value) -> Vec<String> {
+        let result = info.get("result").unwrap_or(&Value::Null);
+        let mut drift = Vec::new();
+        if extract_url(info).as_deref() != Some(self.url.as_str()) {
+            drift.push("url".to_string());
+        }
+        if let Some(desired) = &self.allowed_updates {
+            // Telegram omits the field when every default update type is
+            // delivered, which an explicit empty list also requests.
+            let mut current: Vec<String> = result
+                .get("allowed_updates")
+                .and_then(Value::as_array)
+                .map(|updates| {
+                    updates
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(ToOwned::to_owned)
+                        .collect()
+                })
+                .unwrap_or_default();
+            current.sort();
+            if current != *desired {
+                drift.push("allowed_updates".to_string());
+            }
+        }
+        if let Some(desired) = self.max_connections
+            && result.get("max_connections").and_then(Value::as_u64) != Some(u64::from(desired))
+        {
+            drift.push("max_connections".to_string());
+        }
+        if let Some(desired) = &self.ip_address
+            && result.get("ip_address").and_then(Value::as_str) != Some(desired.as_str())
+        {
+            drift.push("ip_address".to_string());
+        }
+        drift
+    }
+}
+
 component_entrypoint!({
     manifest: describe_manifest,
     invoke: handle_message,
@@ -85,58 +212,141 @@ fn invoke(result: Result<String, String>) -> InvokeResult {
 fn reconcile_webhook(input: &str) -> Result<String, String> {
     let parsed: ReconcileInput =
         serde_json::from_str(input).map_err(|err| format!("invalid input: {err}"))?;
-    let base = parsed.public_base_url.trim();
-    if base.is_empty() {
-        return Err("public_base_url is required".to_string());
-    }
-    let webhook_path = parsed
-        .webhook_path
-        .as_deref()
-        .unwrap_or(DEFAULT_WEBHOOK_PATH)
-        .trim();
-    let expected_url = join_url(base, webhook_path);
+    let desired = match parsed.mode {
+        ReconcileMode::Apply => Some(DesiredWebhook::from_input(&parsed)?),
+        ReconcileMode::Remove => None,
+    };
     let api_base = parsed
         .api_base_url
         .as_deref()
         .filter(|s| !s.trim().is_empty())
         .unwrap_or(DEFAULT_API_BASE);
+    let dry_run = parsed.dry_run.unwrap_or(false);
     let token = load_token()?;
     let current_info = get_webhook_info(api_base, &token)?;
     let current_url = extract_url(&current_info);
-    let mut set_response = None;
-    let mut set_attempted = false;
-    let mut set_skipped = false;
-    if current_url.as_deref() != Some(&expected_url) {
-        if parsed.dry_run.unwrap_or(false) {
-            set_skipped = true;
-        } else {
-            let response = set_webhook(
-                api_base,
-                &token,
-                &expected_url,
-                parsed.secret_token.as_deref(),
-            )?;
-            set_response = Some(response);
-            set_attempted = true;
+    let mut output = ReconcileOutput {
+        ok: true,
+        mode: parsed.mode,
+        expected_url: desired.as_ref().map(|desired| desired.url.clone()),
+        current_url: current_url.clone(),
+        final_url: None,
+        drift: Vec::new(),
+        webhook_reconciled: false,
+        set_attempted: false,
+        set_skipped_dry_run: false,
+        set_response: None,
+        delete_attempted: false,
+        delete_skipped_dry_run: false,
+        delete_response: None,
+        pending_update_count: None,
+        last_error_message: None,
+        last_error_date: None,
+        diagnostics: Vec::new(),
+        webhook_info: Value::Null,
+    };
+    match &desired {
+        Some(desired) => {
+            output.drift = desired.drift(&current_info);
+            if !output.drift.is_empty() {
+                if dry_run {
+                    output.set_skipped_dry_run = true;
+                } else {
+                    let payload = set_webhook_payload(desired, &parsed);
+                    output.set_response =
+                        Some(call_method(api_base, &token, "setWebhook", &payload)?);
+                    output.set_attempted = true;
+                }
+            }
+        }
+        None => {
+            if current_url.as_deref().is_some_and(|url| !url.is_empty()) {
+                output.drift.push("url".to_string());
+                if dry_run {
+                    output.delete_skipped_dry_run = true;
+                } else {
+                    let payload = json!({
+                        "drop_pending_updates": parsed.drop_pending_updates.unwrap_or(false),
+                    });
+                    output.delete_response =
+                        Some(call_method(api_base, &token, "deleteWebhook", &payload)?);
+                    output.delete_attempted = true;
+                }
+            }
         }
     }
     let final_info = get_webhook_info(api_base, &token)?;
-    let final_url = extract_url(&final_info);
-    let webhook_reconciled = final_url.as_deref() == Some(&expected_url);
-    let output = ReconcileOutput {
-        ok: true,
-        expected_url,
-        current_url,
-        final_url,
-        webhook_reconciled,
-        set_attempted,
-        set_skipped_dry_run: set_skipped,
-        set_response,
-        webhook_info: final_info,
+    output.final_url = extract_url(&final_info);
+    output.webhook_reconciled = match &desired {
+        Some(desired) => desired.drift(&final_info).is_empty(),
+        None => output.final_url.as_deref().is_none_or(str::is_empty),
     };
+    let result = final_info.get("result").unwrap_or(&Value::Null);
+    output.pending_update_count = result.get("pending_update_count").and_then(Value::as_u64);
+    output.last_error_message = result
+        .get("last_error_message")
+        .and_then(Value::as_str)
+        .map(ToOwned::to_owned);
+    output.last_error_date = result.get("last_error_date").and_then(Value::as_i64);
+    output.diagnostics = webhook_diagnostics(&final_info);
+    output.webhook_info = final_info;
     serde_json::to_string(&output).map_err(|err| format!("serialization failed: {err}"))
 }
 
+/// `drop_pending_updates` and `secret_token` are not reported back by
+/// `getWebhookInfo`, so they only apply when the webhook is registered again.
+fn set_webhook_payload(desired: &DesiredWebhook, input: &ReconcileInput) -> Value {
+    let mut payload = json!({ "url": desired.url });
+    if let Some(secret) = input
+        .secret_token
+        .as_deref()
+        .filter(|s| !s.trim().is_empty())
+    {
+        payload["secret_token"] = json!(secret);
+    }
+    if let Some(updates) = &desired.allowed_updates {
+        payload["allowed_updates"] = json!(updates);
+    }
+    if let Some(max) = desired.max_connections {
+        payload["max_connections"] = json!(max);
+    }
+    if let Some(ip) = &desired.ip_address {
+        payload["ip_address"] = json!(ip);
+    }
+    if let Some(drop) = input.drop_pending_updates {
+        payload["drop_pending_updates"] = json!(drop);
+    }
+    payload
+}
+
+/// Human-readable findings for stuck or failing deliveries.
+fn webhook_diagnostics(info: &Value) -> Vec<String> {
+    let result = info.get("result").unwrap_or(&Value::Null);
+    let mut diagnostics = Vec::new();
+    if let Some(message) = result.get("last_error_message").and_then(Value::as_str) {
+        let date = result
+            .get("last_error_date")
+            .and_then(Value::as_i64)
+            .map(|date| format!(" at {date}"))
+            .unwrap_or_default();
+        diagnostics.push(format!("last delivery failed{date}: {message}"));
+    }
+    if let Some(pending) = result
+        .get("pending_update_count")
+        .and_then(Value::as_u64)
+        .filter(|pending| *pending > 0)
+    {
+        diagnostics.push(format!("{pending} updates are waiting for delivery"));
+    }
+    if let Some(date) = result
+        .get("last_synchronization_error_date")
+        .and_then(Value::as_i64)
+    {
+        diagnostics.push(format!("telegram failed to synchronize updates at {date}"));
+    }
+    diagnostics
+}
+
 fn join_url(base: &str, path: &str) -> String {
     let mut base = base.trim_end_matches('/').to_string();
     let trimmed = path.trim();
@@ -172,30 +382,23 @@ fn get_webhook_info(api_base: &str, token: &str) -> Result<Value, String> {
     Ok(response)
 }
 
-fn set_webhook(
+fn call_method(
     api_base: &str,
     token: &str,
-    expected_url: &str,
-    secret_token: Option<&str>,
+    method: &str,
+    payload: &Value,
 ) -> Result<Value, String> {
-    let mut payload = json!({ "url": expected_url });
-    if let Some(secret) = secret_token.filter(|s| !s.trim().is_empty()) {
-        payload.as_object_mut().expect("payload object").insert(
-            "secret_token".to_string(),
-            Value::String(secret.to_string()),
-        );
-    }
     let request = client::Request {
         method: "POST".into(),
-        url: format!("{api_base}/bot{token}/setWebhook"),
+        url: format!("{api_base}/bot{token}/{method}"),
         headers: vec![("Content-Type".into(), "application/json".into())],
         body: Some(
-            serde_json::to_vec(&payload)
+            serde_json::to_vec(payload)
                 .map_err(|err| format!("payload serialization failed: {err}"))?,
         ),
     };
     let response = send_request(&request)?;
-    ensure_ok(&response, "setWebhook")?;
+    ensure_ok(&response, method)?;
     Ok(response)
 }
 
@@ -225,3 +428,68 @@ fn extract_url(body: &Value) -> Option<String> {
         .and_then(|url| url.as_str())
         .map(|s| s.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(value: Value) -> ReconcileInput {
+        serde_json::from_value(value).expect("input")
+    }
+
+    #[test]
+    fn drift_compares_declared_settings_only() {
+        let desired = DesiredWebhook::from_input(&input(json!({
+            "public_base_url": "https://example.com/",
+            "webhook_path": "tg",
+            "allowed_updates": ["message", "callback_query"],
+            "max_connections": 10,
+        })))
+        .expect("desired");
+        let info = json!({"ok": true, "result": {
+            "url": "https://example.com/tg",
+            "allowed_updates": ["callback_query", "message"],
+            "max_connections": 40,
+            "ip_address": "1.2.3.4",
+        }});
+        assert_eq!(desired.drift(&info), vec!["max_connections"]);
+
+        let unset = json!({"ok": true, "result": {"url": ""}});
+        assert_eq!(
+            desired.drift(&unset),
+            vec!["url", "allowed_updates", "max_connections"]
+        );
+    }
+
+    #[test]
+    fn remove_mode_accepts_qa_mode_names() {
+        let parsed = input(json!({"mode": "remove"}));
+        assert_eq!(parsed.mode, ReconcileMode::Remove);
+        assert_eq!(input(json!({"mode": "setup"})).mode, ReconcileMode::Apply);
+        assert!(DesiredWebhook::from_input(&parsed).is_err());
+        assert!(
+            DesiredWebhook::from_input(&input(
+                json!({"public_base_url": "https://x", "max_connections": 101})
+            ))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn diagnostics_surface_delivery_errors_and_backlog() {
+        let info = json!({"result": {
+            "url": "https://example.com",
+            "pending_update_count": 7,
+            "last_error_date": 1700000000,
+            "last_error_message": "Connection refused",
+        }});
+        assert_eq!(
+            webhook_diagnostics(&info),
+            vec![
+                "last delivery failed at 1700000000: Connection refused",
+                "7 updates are waiting for delivery",
+            ]
+        );
+        assert!(webhook_diagnostics(&json!({"result": {"pending_update_count": 0}})).is_empty());
+    }
+}
@@ -32,6 +32,7 @@ use greentic_types::{
     ChannelMessageEnvelope, Destination, EnvId, MessageMetadata, TenantCtx, TenantId,
 };
 use http::Request;
+use provider_common::webhook_auth::{self, WEBEX_SIGNATURE_HEADER, WEBEX_SIGNATURE_HEADER_ALT};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use tokio::net::TcpListener;
@@ -42,8 +43,6 @@ use crate::http_mock::{HttpHistory, HttpMode, new_history};
 use crate::requirements::ValidationReport;
 use crate::values::Values;
 use crate::wasm_harness::{ComponentHarness, WasmHarness, find_component_wasm_path};
-use hmac::{Hmac, Mac};
-use sha2::Sha256;
 
 #[derive(Parser)]
 #[command(name = "greentic-messaging-tester")]
@@ -1043,30 +1042,9 @@ fn load_webhook_signature_secret(values: &Values, provider: &str) -> Option<Vec<
 }
 
 fn verify_webex_signature(secret: &[u8], headers: &[(String, String)], body: &str) -> bool {
-    let header_value = find_header_value(headers, "x-webex-signature")
-        .or_else(|| find_header_value(headers, "x-spark-signature"));
-    let header_value = match header_value {
-        Some(value) => value,
-        None => return false,
-    };
-    let sha256_part = header_value
-        .split(',')
-        .find_map(|segment| segment.trim().strip_prefix("SHA-256=").map(|v| v.trim()));
-    let hex = match sha256_part {
-        Some(value) => value.trim_matches('"'),
-        None => return false,
-    };
-    let sig_bytes = match decode_hex(hex) {
-        Some(bytes) => bytes,
-        None => return false,
-    };
-    type HmacSha256 = Hmac<Sha256>;
-    let mut mac = match HmacSha256::new_from_slice(secret) {
-        Ok(mac) => mac,
-        Err(_) => return false,
-    };
-    mac.update(body.as_bytes());
-    mac.verify_slice(&sig_bytes).is_ok()
+    let signature = find_header_value(headers, WEBEX_SIGNATURE_HEADER_ALT)
+        .or_else(|| find_header_value(headers, WEBEX_SIGNATURE_HEADER));
+    webhook_auth::verify_webex(secret, signature.as_deref(), body.as_bytes()).is_ok()
 }
 
 fn find_header_value(headers: &[(String, String)], key: &str) -> Option<String> {
@@ -1076,19 +1054,6 @@ fn find_header_value(headers: &[(String, String)], key: &str) -> Option<String>
         .map(|(_, value)| value.clone())
 }
 
-fn decode_hex(input: &str) -> Option<Vec<u8>> {
-    if !input.len().is_multiple_of(2) {
-        return None;
-    }
-    let mut bytes = Vec::with_capacity(input.len() / 2);
-    let normalized = input.trim();
-    for chunk in normalized.as_bytes().chunks(2) {
-        let hex_str = std::str::from_utf8(chunk).ok()?;
-        bytes.push(u8::from_str_radix(hex_str, 16).ok()?);
-    }
-    Some(bytes)
-}
-
 #[derive(Serialize, Deserialize)]
 struct HttpInFile {
     method: String,
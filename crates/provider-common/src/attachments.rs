@@ -0,0 +1,402 @@
+//! Attachment sources, `multipart/form-data` bodies and inbound downloads.
+//!
+//! An envelope attachment either references a URL the provider API can fetch
+//! itself, or carries its bytes inline as a `data:` URL. Inline attachments
+//! are uploaded with a multipart body built by [`MultipartForm`].
+//!
+//! Inbound attachments are provider references that the `fetch_attachment`
+//! op downloads within the [`FetchLimits`] of the request.
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+use crate::ProviderError;
+
+/// Where the payload of an outbound attachment comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttachmentSource {
+    /// Remote URL the provider API downloads.
+    Url(String),
+    /// Bytes decoded from a `data:` URL, with the media type it declared.
+    Inline {
+        bytes: Vec<u8>,
+        mime_type: Option<String>,
+    },
+}
+
+impl AttachmentSource {
+    /// Parses an attachment `url`: `data:` URLs (base64 or percent-encoded)
+    /// become [`AttachmentSource::Inline`], `http(s)` URLs are kept as is.
+    pub fn parse(url: &str) -> Result<Self, ProviderError> {
+        let url = url.trim();
+        if let Some(rest) = url.strip_prefix("data:") {
+            return parse_data_url(rest);
+        }
+        if url.starts_with("https://") || url.starts_with("http://") {
+            return Ok(Self::Url(url.to_string()));
+        }
+        Err(ProviderError::validation(
+            "attachment url must be http(s) or a data: url",
+        ))
+    }
+}
+
+fn parse_data_url(rest: &str) -> Result<AttachmentSource, ProviderError> {
+    let (header, data) = rest
+        .split_once(',')
+        .ok_or_else(|| ProviderError::validation("data url missing ','"))?;
+    let mut params = header.split(';');
+    let mime_type = params
+        .next()
+        .map(str::trim)
+        .filter(|mime| !mime.is_empty())
+        .map(str::to_ascii_lowercase);
+    let bytes = if params.any(|param| param.eq_ignore_ascii_case("base64")) {
+        let compact: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+        STANDARD
+            .decode(percent_decode(&compact))
+            .map_err(|err| ProviderError::validation(format!("invalid base64 data url: {err}")))?
+    } else {
+        percent_decode(data)
+    };
+    Ok(AttachmentSource::Inline { bytes, mime_type })
+}
+
+pub(crate) fn percent_decode(input: &str) -> Vec<u8> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(hex) = input.get(i + 1..i + 3)
+            && let Ok(byte) = u8::from_str_radix(hex, 16)
+        {
+            out.push(byte);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    out
+}
+
+#[derive(Debug)]
+enum Part {
+    Text {
+        name: String,
+        value: String,
+    },
+    File {
+        name: String,
+        filename: String,
+        content_type: String,
+        bytes: Vec<u8>,
+    },
+}
+
+/// Builder for a `multipart/form-data` request body.
+#[derive(Debug, Default)]
+pub struct MultipartForm {
+    parts: Vec<Part>,
+}
+
+impl MultipartForm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.parts.push(Part::Text {
+            name: name.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    pub fn file(
+        mut self,
+        name: impl Into<String>,
+        filename: impl Into<String>,
+        content_type: impl Into<String>,
+        bytes: Vec<u8>,
+    ) -> Self {
+        self.parts.push(Part::File {
+            name: name.into(),
+            filename: filename.into(),
+            content_type: content_type.into(),
+            bytes,
+        });
+        self
+    }
+
+    /// Returns the `Content-Type` header value and the encoded body. The
+    /// boundary is derived from the part contents so bodies are reproducible.
+    pub fn finish(self) -> (String, Vec<u8>) {
+        let boundary = self.boundary();
+        let mut body = Vec::new();
+        for part in &self.parts {
+            body.extend_from_slice(format!("--{boundary}\r\n").as_bytes());
+            match part {
+                Part::Text { name, value } => {
+                    body.extend_from_slice(
+                        format!(
+                            "Content-Disposition: form-data; name=\"{}\"\r\n\r\n",
+                            quote(name)
+                        )
+                        .as_bytes(),
+                    );
+                    body.extend_from_slice(value.as_bytes());
+                }
+                Part::File {
+                    name,
+                    filename,
+                    content_type,
+                    bytes,
+                } => {
+                    body.extend_from_slice(
+                        format!(
+                            "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {content_type}\r\n\r\n",
+                            quote(name),
+                            quote(filename)
+                        )
+                        .as_bytes(),
+                    );
+                    body.extend_from_slice(bytes);
+                }
+            }
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+        (format!("multipart/form-data; boundary={boundary}"), body)
+    }
+
+    fn boundary(&self) -> String {
+        let mut hasher = Sha256::new();
+        for part in &self.parts {
+            match part {
+                Part::Text { name, value } => {
+                    hasher.update(name.as_bytes());
+                    hasher.update(value.as_bytes());
+                }
+                Part::File { name, bytes, .. } => {
+                    hasher.update(name.as_bytes());
+                    hasher.update(bytes);
+                }
+            }
+        }
+        let hex: String = hasher.finalize()[..12]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        format!("----greentic-{hex}")
+    }
+}
+
+/// Download cap of `fetch_attachment` when the input sets no `max_bytes`;
+/// also the largest file the Telegram Bot API serves.
+pub const DEFAULT_FETCH_MAX_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Limits for downloading an inbound attachment, from the `max_bytes` and
+/// `allowed_mime_types` fields of the op input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchLimits {
+    pub max_bytes: u64,
+    /// Exact types or `type/*` patterns; empty allows any type.
+    pub allowed_mime_types: Vec<String>,
+}
+
+impl Default for FetchLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_FETCH_MAX_BYTES,
+            allowed_mime_types: Vec::new(),
+        }
+    }
+}
+
+impl FetchLimits {
+    pub fn from_input(input: &Value) -> Self {
+        let defaults = Self::default();
+        Self {
+            max_bytes: input
+                .get("max_bytes")
+                .and_then(Value::as_u64)
+                .filter(|max| *max > 0)
+                .unwrap_or(defaults.max_bytes),
+            allowed_mime_types: input
+                .get("allowed_mime_types")
+                .and_then(Value::as_array)
+                .map(|types| {
+                    types
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(|mime| mime.trim().to_ascii_lowercase())
+                        .filter(|mime| !mime.is_empty())
+                        .collect()
+                })
+                .unwrap_or(defaults.allowed_mime_types),
+        }
+    }
+
+    /// Checked against the size the provider declares before downloading and
+    /// against the downloaded bytes.
+    pub fn check_size(&self, size: u64) -> Result<(), ProviderError> {
+        if size > self.max_bytes {
+            return Err(ProviderError::payload_too_large(format!(
+                "attachment is {size} bytes, limit is {}",
+                self.max_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn check_mime(&self, mime_type: &str) -> Result<(), ProviderError> {
+        let mime_type = essence(mime_type);
+        let allowed = self.allowed_mime_types.is_empty()
+            || self
+                .allowed_mime_types
+                .iter()
+                .any(|pattern| match pattern.strip_suffix("/*") {
+                    Some(top) => mime_type
+                        .split_once('/')
+                        .is_some_and(|(mime_top, _)| mime_top == top),
+                    None => *pattern == mime_type,
+                });
+        if !allowed {
+            return Err(ProviderError::unsupported_content(format!(
+                "attachment type {mime_type} is not allowed"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Media type of a response without parameters, from its `Content-Type`
+/// header.
+pub fn response_mime_type(headers: &[(String, String)]) -> Option<String> {
+    headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        .map(|(_, value)| essence(value))
+        .filter(|mime| !mime.is_empty())
+}
+
+fn essence(mime_type: &str) -> String {
+    mime_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// A downloaded inbound attachment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchedAttachment {
+    pub mime_type: String,
+    pub name: Option<String>,
+    pub bytes: Vec<u8>,
+}
+
+impl FetchedAttachment {
+    /// Result of the `fetch_attachment` op; the bytes are base64 in
+    /// `data_b64`.
+    pub fn to_result_json(&self, provider_type: &str, attempts: u32) -> Value {
+        json!({
+            "ok": true,
+            "status": "fetched",
+            "provider_type": provider_type,
+            "content_type": self.mime_type,
+            "name": self.name,
+            "size_bytes": self.bytes.len(),
+            "data_b64": STANDARD.encode(&self.bytes),
+            "attempts": attempts,
+        })
+    }
+}
+
+fn quote(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_data_and_remote_urls() {
+        assert_eq!(
+            AttachmentSource::parse("data:image/PNG;base64,aGVs bG8=").unwrap(),
+            AttachmentSource::Inline {
+                bytes: b"hello".to_vec(),
+                mime_type: Some("image/png".into()),
+            }
+        );
+        assert_eq!(
+            AttachmentSource::parse("data:,a%20b").unwrap(),
+            AttachmentSource::Inline {
+                bytes: b"a b".to_vec(),
+                mime_type: None,
+            }
+        );
+        assert_eq!(
+            AttachmentSource::parse(" https://example.com/a.pdf ").unwrap(),
+            AttachmentSource::Url("https://example.com/a.pdf".into())
+        );
+        assert!(AttachmentSource::parse("file:///etc/passwd").is_err());
+        assert!(AttachmentSource::parse("data:;base64,***").is_err());
+    }
+
+    #[test]
+    fn multipart_body_has_parts_and_closing_boundary() {
+        let (content_type, body) = MultipartForm::new()
+            .text("chat_id", "42")
+            .file(
+                "document",
+                "re\"port.pdf",
+                "application/pdf",
+                b"%PDF".to_vec(),
+            )
+            .finish();
+        let boundary = content_type
+            .strip_prefix("multipart/form-data; boundary=")
+            .unwrap();
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.starts_with(&format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"chat_id\"\r\n\r\n42\r\n"
+        )));
+        assert!(body.contains(
+            "name=\"document\"; filename=\"re\\\"port.pdf\"\r\nContent-Type: application/pdf\r\n\r\n%PDF\r\n"
+        ));
+        assert!(body.ends_with(&format!("--{boundary}--\r\n")));
+    }
+
+    #[test]
+    fn fetch_limits_check_size_and_mime_patterns() {
+        let limits = FetchLimits::from_input(&json!({
+            "max_bytes": 10,
+            "allowed_mime_types": ["image/*", "Application/PDF"],
+        }));
+        assert!(limits.check_size(10).is_ok());
+        assert!(matches!(
+            limits.check_size(11),
+            Err(ProviderError::PayloadTooLarge(_))
+        ));
+        assert!(limits.check_mime("image/png").is_ok());
+        assert!(limits.check_mime("application/pdf; charset=binary").is_ok());
+        assert!(matches!(
+            limits.check_mime("video/mp4"),
+            Err(ProviderError::UnsupportedContent(_))
+        ));
+        assert_eq!(FetchLimits::from_input(&json!({})), FetchLimits::default());
+        assert!(FetchLimits::default().check_mime("video/mp4").is_ok());
+
+        let headers = vec![("Content-Type".into(), "Image/JPEG; q=1".into())];
+        assert_eq!(response_mime_type(&headers).as_deref(), Some("image/jpeg"));
+    }
+}
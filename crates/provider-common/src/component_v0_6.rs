@@ -46,6 +46,76 @@ pub enum SchemaIr {
         fields: BTreeMap<String, SchemaField>,
         additional_properties: bool,
     },
+    Number {
+        title: I18nText,
+        description: I18nText,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        minimum: Option<serde_json::Number>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        maximum: Option<serde_json::Number>,
+    },
+    Integer {
+        title: I18nText,
+        description: I18nText,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        minimum: Option<i64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        maximum: Option<i64>,
+    },
+    Array {
+        title: I18nText,
+        description: I18nText,
+        items: Box<SchemaIr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min_items: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_items: Option<u64>,
+    },
+    /// A closed set of string values, each with its own display label.
+    Enum {
+        title: I18nText,
+        description: I18nText,
+        values: Vec<EnumValue>,
+    },
+    OneOf {
+        title: I18nText,
+        description: I18nText,
+        variants: Vec<SchemaIr>,
+    },
+}
+
+impl SchemaIr {
+    pub fn title(&self) -> &I18nText {
+        match self {
+            SchemaIr::Bool { title, .. }
+            | SchemaIr::String { title, .. }
+            | SchemaIr::Object { title, .. }
+            | SchemaIr::Number { title, .. }
+            | SchemaIr::Integer { title, .. }
+            | SchemaIr::Array { title, .. }
+            | SchemaIr::Enum { title, .. }
+            | SchemaIr::OneOf { title, .. } => title,
+        }
+    }
+
+    pub fn description(&self) -> &I18nText {
+        match self {
+            SchemaIr::Bool { description, .. }
+            | SchemaIr::String { description, .. }
+            | SchemaIr::Object { description, .. }
+            | SchemaIr::Number { description, .. }
+            | SchemaIr::Integer { description, .. }
+            | SchemaIr::Array { description, .. }
+            | SchemaIr::Enum { description, .. }
+            | SchemaIr::OneOf { description, .. } => description,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EnumValue {
+    pub value: String,
+    pub label: I18nText,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -192,6 +262,65 @@ fn canonicalize_json(value: serde_json::Value) -> serde_json::Value {
 mod tests {
     use super::*;
 
+    fn text(key: &str) -> I18nText {
+        I18nText {
+            key: key.to_string(),
+        }
+    }
+
+    #[test]
+    fn schema_hash_unchanged_for_existing_kinds() {
+        let mut fields = BTreeMap::new();
+        fields.insert(
+            "enabled".to_string(),
+            SchemaField {
+                required: true,
+                schema: SchemaIr::Bool {
+                    title: text("t"),
+                    description: text("d"),
+                },
+            },
+        );
+        let schema = SchemaIr::Object {
+            title: text("t"),
+            description: text("d"),
+            fields,
+            additional_properties: false,
+        };
+        let value = serde_json::to_value(&schema).expect("serialize");
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "kind": "object",
+                "title": {"key": "t"},
+                "description": {"key": "d"},
+                "fields": {
+                    "enabled": {
+                        "required": true,
+                        "schema": {"kind": "bool", "title": {"key": "t"}, "description": {"key": "d"}}
+                    }
+                },
+                "additional_properties": false
+            })
+        );
+    }
+
+    #[test]
+    fn new_kinds_omit_unset_bounds() {
+        let schema = SchemaIr::Integer {
+            title: text("t"),
+            description: text("d"),
+            minimum: Some(1),
+            maximum: None,
+        };
+        let value = serde_json::to_value(&schema).expect("serialize");
+        assert_eq!(value["kind"], "integer");
+        assert_eq!(value["minimum"], 1);
+        assert!(value.get("maximum").is_none());
+        let back: SchemaIr = serde_json::from_value(value).expect("deserialize");
+        assert_eq!(back, schema);
+    }
+
     #[test]
     fn builds_human_readable_default_i18n_message() {
         assert_eq!(
@@ -0,0 +1,148 @@
+//! `application/x-www-form-urlencoded` request bodies.
+//!
+//! Some webhooks (Slack interactivity and slash commands) post forms instead
+//! of JSON, and some API methods only take form parameters. Field order is
+//! preserved and repeated names are kept.
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::attachments::percent_decode;
+
+/// Decodes `name=value` pairs; `+` stands for a space. Invalid UTF-8 is
+/// replaced rather than rejected.
+pub fn parse(body: &str) -> Vec<(String, String)> {
+    body.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode_component(name), decode_component(value))
+        })
+        .collect()
+}
+
+/// First value of `name`, if the body has it.
+pub fn field(body: &str, name: &str) -> Option<String> {
+    parse(body)
+        .into_iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value)
+}
+
+/// The JSON object in the `payload` field of an interactivity form (button
+/// clicks, menus, modal submissions); `None` for anything else, including
+/// JSON bodies.
+pub fn json_payload(body: &str) -> Option<Value> {
+    let payload = field(body.trim(), "payload")?;
+    serde_json::from_str::<Value>(&payload)
+        .ok()
+        .filter(Value::is_object)
+}
+
+/// Fields of a slash command form, without the legacy verification `token`;
+/// `None` unless `command` names a `/command`. Later duplicates win.
+pub fn slash_command_fields(body: &str) -> Option<BTreeMap<String, String>> {
+    let fields: BTreeMap<String, String> = parse(body.trim())
+        .into_iter()
+        .filter(|(name, _)| name != "token")
+        .collect();
+    fields
+        .get("command")
+        .filter(|command| command.starts_with('/'))?;
+    Some(fields)
+}
+
+/// Encodes `name=value` pairs; everything but unreserved characters is
+/// percent-encoded and spaces become `+`.
+pub fn serialize<N: AsRef<str>, V: AsRef<str>>(pairs: &[(N, V)]) -> String {
+    pairs
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "{}={}",
+                encode_component(name.as_ref()),
+                encode_component(value.as_ref())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn encode_component(component: &str) -> String {
+    let mut out = String::with_capacity(component.len());
+    for byte in component.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(byte as char)
+            }
+            b' ' => out.push('+'),
+            _ => out.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    out
+}
+
+fn decode_component(component: &str) -> String {
+    String::from_utf8_lossy(&percent_decode(&component.replace('+', " "))).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_pairs_in_order() {
+        let pairs = parse("command=%2Fdeploy&text=prod+now&flag&text=again");
+        assert_eq!(
+            pairs,
+            vec![
+                ("command".to_string(), "/deploy".to_string()),
+                ("text".to_string(), "prod now".to_string()),
+                ("flag".to_string(), String::new()),
+                ("text".to_string(), "again".to_string()),
+            ]
+        );
+        assert_eq!(
+            field("payload=%7B%22a%22%3A1%7D", "payload").as_deref(),
+            Some("{\"a\":1}")
+        );
+        assert_eq!(field("a=1", "payload"), None);
+    }
+
+    #[test]
+    fn reads_interaction_payloads_and_slash_commands() {
+        let payload = json_payload("payload=%7B%22type%22%3A%22block_actions%22%7D\n").unwrap();
+        assert_eq!(payload["type"], "block_actions");
+        assert_eq!(json_payload("payload=%5B1%5D"), None);
+        assert_eq!(json_payload("{\"type\":\"event_callback\"}"), None);
+
+        let fields = slash_command_fields("token=t&command=%2Fdeploy&text=").unwrap();
+        assert_eq!(fields.get("command").map(String::as_str), Some("/deploy"));
+        assert_eq!(fields.get("text").map(String::as_str), Some(""));
+        assert!(!fields.contains_key("token"));
+        assert_eq!(slash_command_fields("command=deploy"), None);
+    }
+
+    #[test]
+    fn serialize_roundtrips() {
+        let pairs = [
+            ("filename", "q3 report.pdf"),
+            ("files", "[{\"id\":\"F1\"}]"),
+        ];
+        let body = serialize(&pairs);
+        assert_eq!(
+            body,
+            "filename=q3+report.pdf&files=%5B%7B%22id%22%3A%22F1%22%7D%5D"
+        );
+        let decoded = parse(&body);
+        assert_eq!(decoded[0].1, "q3 report.pdf");
+        assert_eq!(decoded[1].1, "[{\"id\":\"F1\"}]");
+    }
+
+    #[test]
+    fn literal_plus_is_percent_encoded() {
+        assert_eq!(field("text=1%2B1", "text").as_deref(), Some("1+1"));
+        assert_eq!(field("text=caf%C3%A9", "text").as_deref(), Some("café"));
+    }
+}
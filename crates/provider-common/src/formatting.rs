@@ -0,0 +1,582 @@
+//! Markdown dialect conversion for outbound text.
+//!
+//! Flows author message text as CommonMark. [`render`] parses it once and
+//! emits the markup a channel understands, escaping text so that characters
+//! the channel reserves for formatting are shown literally. Slack and WhatsApp
+//! have no escape syntax for `*`, `_` and `~`; only what they can escape is.
+
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+/// Target markup of a channel's text field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkdownDialect {
+    /// Slack `mrkdwn` (`*bold*`, `<url|label>`).
+    SlackMrkdwn,
+    /// Telegram `parse_mode: MarkdownV2`.
+    TelegramMarkdownV2,
+    /// Telegram `parse_mode: HTML`.
+    TelegramHtml,
+    /// WhatsApp text formatting (`*bold*`, `_italic_`, `~strike~`).
+    WhatsApp,
+    /// Teams message body with `contentType: html`.
+    TeamsHtml,
+    /// Webex `markdown` message field.
+    WebexMarkdown,
+    /// Formatting stripped; links shown as `label (url)`.
+    PlainText,
+}
+
+impl MarkdownDialect {
+    /// `parse_mode` to send with text rendered in a Telegram dialect.
+    pub fn telegram_parse_mode(self) -> Option<&'static str> {
+        match self {
+            Self::TelegramMarkdownV2 => Some("MarkdownV2"),
+            Self::TelegramHtml => Some("HTML"),
+            _ => None,
+        }
+    }
+}
+
+/// Characters Telegram MarkdownV2 requires to be escaped outside entities.
+pub const TELEGRAM_MARKDOWN_V2_RESERVED: &str = "_*[]()~`>#+-=|{}.!\\";
+const WEBEX_RESERVED: &str = "\\`*_[]~";
+const RULE: &str = "———";
+
+/// Renders CommonMark `markdown` in `dialect`.
+pub fn render(markdown: &str, dialect: MarkdownDialect) -> String {
+    let mut renderer = Renderer::new(dialect);
+    for event in Parser::new_ext(markdown, Options::ENABLE_STRIKETHROUGH) {
+        renderer.event(event);
+    }
+    renderer.finish()
+}
+
+/// Escapes every MarkdownV2 reserved character with a backslash.
+pub fn escape_telegram_markdown_v2(text: &str) -> String {
+    escape_with_backslash(text, TELEGRAM_MARKDOWN_V2_RESERVED)
+}
+
+/// Escapes `&`, `<`, `>` and `"` for HTML text and attribute values.
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Escapes the control characters Slack reserves for links and mentions.
+pub fn escape_slack(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escape_with_backslash(text: &str, reserved: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if reserved.contains(ch) {
+            out.push('\\');
+        }
+        out.push(ch);
+    }
+    out
+}
+
+#[derive(Clone, Copy)]
+enum Inline {
+    Strong,
+    Emphasis,
+    Strikethrough,
+}
+
+struct Renderer {
+    dialect: MarkdownDialect,
+    out: String,
+    at_line_start: bool,
+    /// A block ended; the next block is separated from it.
+    block_gap: bool,
+    /// Next item number of each open list; `None` for bullet lists.
+    lists: Vec<Option<u64>>,
+    /// Destination and output offset of each open link or image.
+    links: Vec<(String, usize)>,
+    quote_depth: usize,
+    /// Language and raw text of the code block being read.
+    code_block: Option<(String, String)>,
+}
+
+impl Renderer {
+    fn new(dialect: MarkdownDialect) -> Self {
+        Self {
+            dialect,
+            out: String::new(),
+            at_line_start: true,
+            block_gap: false,
+            lists: Vec::new(),
+            links: Vec::new(),
+            quote_depth: 0,
+            code_block: None,
+        }
+    }
+
+    fn finish(self) -> String {
+        self.out.trim_end().to_string()
+    }
+
+    fn event(&mut self, event: Event<'_>) {
+        if let Some((_, code)) = self.code_block.as_mut() {
+            match event {
+                Event::Text(text) => code.push_str(&text),
+                Event::End(TagEnd::CodeBlock) => self.end_code_block(),
+                _ => {}
+            }
+            return;
+        }
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                let escaped = self.escape_text(&text);
+                self.write(&escaped);
+            }
+            Event::Code(code) => self.code_span(&code),
+            Event::SoftBreak | Event::HardBreak => self.line_break(),
+            Event::Rule => self.rule(),
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag<'_>) {
+        match tag {
+            Tag::Paragraph => {
+                self.start_block();
+                if self.dialect == MarkdownDialect::TeamsHtml {
+                    self.write("<p>");
+                }
+            }
+            Tag::Heading { level, .. } => {
+                self.start_block();
+                match self.dialect {
+                    MarkdownDialect::TeamsHtml => self.write(&format!("<{}>", heading_tag(level))),
+                    MarkdownDialect::WebexMarkdown => {
+                        self.write(&format!("{} ", "#".repeat(level as usize)))
+                    }
+                    _ => self.inline_open(Inline::Strong),
+                }
+            }
+            Tag::BlockQuote(_) => {
+                self.start_block();
+                self.quote_depth += 1;
+                match self.dialect {
+                    MarkdownDialect::TeamsHtml | MarkdownDialect::TelegramHtml => {
+                        self.write("<blockquote>")
+                    }
+                    _ => {
+                        let marker = self.quote_marker();
+                        self.write(marker);
+                    }
+                }
+            }
+            Tag::CodeBlock(kind) => {
+                self.start_block();
+                let lang = match kind {
+                    CodeBlockKind::Fenced(info) => {
+                        info.split_whitespace().next().unwrap_or("").to_string()
+                    }
+                    CodeBlockKind::Indented => String::new(),
+                };
+                self.code_block = Some((lang, String::new()));
+            }
+            Tag::List(start) => {
+                self.start_block();
+                if self.dialect == MarkdownDialect::TeamsHtml {
+                    match start {
+                        Some(1) => self.write("<ol>"),
+                        Some(n) => self.write(&format!("<ol start=\"{n}\">")),
+                        None => self.write("<ul>"),
+                    }
+                }
+                self.lists.push(start);
+            }
+            Tag::Item => self.start_item(),
+            Tag::Emphasis => self.inline_open(Inline::Emphasis),
+            Tag::Strong => self.inline_open(Inline::Strong),
+            Tag::Strikethrough => self.inline_open(Inline::Strikethrough),
+            Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => {
+                self.link_open(&dest_url);
+                self.links.push((dest_url.to_string(), self.out.len()));
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => {
+                if self.dialect == MarkdownDialect::TeamsHtml {
+                    self.write("</p>");
+                }
+                self.block_gap = true;
+            }
+            TagEnd::Heading(level) => {
+                match self.dialect {
+                    MarkdownDialect::TeamsHtml => self.write(&format!("</{}>", heading_tag(level))),
+                    MarkdownDialect::WebexMarkdown => {}
+                    _ => self.inline_close(Inline::Strong),
+                }
+                self.block_gap = true;
+            }
+            TagEnd::BlockQuote(_) => {
+                if matches!(
+                    self.dialect,
+                    MarkdownDialect::TeamsHtml | MarkdownDialect::TelegramHtml
+                ) {
+                    self.write("</blockquote>");
+                }
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+                self.block_gap = true;
+            }
+            TagEnd::List(ordered) => {
+                self.lists.pop();
+                if self.dialect == MarkdownDialect::TeamsHtml {
+                    self.write(if ordered { "</ol>" } else { "</ul>" });
+                }
+                self.block_gap = true;
+            }
+            TagEnd::Item => {
+                if self.dialect == MarkdownDialect::TeamsHtml {
+                    self.write("</li>");
+                }
+            }
+            TagEnd::Emphasis => self.inline_close(Inline::Emphasis),
+            TagEnd::Strong => self.inline_close(Inline::Strong),
+            TagEnd::Strikethrough => self.inline_close(Inline::Strikethrough),
+            TagEnd::Link | TagEnd::Image => {
+                if let Some((url, start)) = self.links.pop() {
+                    self.link_close(&url, start);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.out.push_str(text);
+        self.at_line_start = false;
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        let prefix = self.quote_prefix();
+        self.out.push_str(&prefix);
+        self.at_line_start = true;
+    }
+
+    /// Line marker of one quote level; empty where quotes are tags.
+    fn quote_marker(&self) -> &'static str {
+        match self.dialect {
+            MarkdownDialect::TeamsHtml | MarkdownDialect::TelegramHtml => "",
+            MarkdownDialect::TelegramMarkdownV2 => ">",
+            _ => "> ",
+        }
+    }
+
+    fn quote_prefix(&self) -> String {
+        self.quote_marker().repeat(self.quote_depth)
+    }
+
+    fn start_block(&mut self) {
+        if self.block_gap && !self.out.is_empty() && self.dialect != MarkdownDialect::TeamsHtml {
+            if !self.at_line_start {
+                self.newline();
+            }
+            if self.lists.is_empty() {
+                self.newline();
+            }
+        }
+        self.block_gap = false;
+    }
+
+    fn start_item(&mut self) {
+        self.block_gap = false;
+        if self.dialect == MarkdownDialect::TeamsHtml {
+            self.write("<li>");
+            return;
+        }
+        if !self.out.is_empty() && !self.at_line_start {
+            self.newline();
+        }
+        let depth = self.lists.len().saturating_sub(1);
+        let marker = match self.lists.last_mut() {
+            Some(Some(n)) => {
+                let number = *n;
+                *n += 1;
+                if self.dialect == MarkdownDialect::TelegramMarkdownV2 {
+                    format!("{number}\\. ")
+                } else {
+                    format!("{number}. ")
+                }
+            }
+            _ if matches!(
+                self.dialect,
+                MarkdownDialect::WebexMarkdown | MarkdownDialect::PlainText
+            ) =>
+            {
+                "- ".to_string()
+            }
+            _ => "• ".to_string(),
+        };
+        let indent = " ".repeat(depth * 2);
+        self.write(&format!("{indent}{marker}"));
+    }
+
+    fn inline_markers(&self, inline: Inline) -> (&'static str, &'static str) {
+        use MarkdownDialect::*;
+        match (self.dialect, inline) {
+            (PlainText, _) => ("", ""),
+            (TeamsHtml, Inline::Strong) => ("<strong>", "</strong>"),
+            (TeamsHtml, Inline::Emphasis) => ("<em>", "</em>"),
+            (TelegramHtml, Inline::Strong) => ("<b>", "</b>"),
+            (TelegramHtml, Inline::Emphasis) => ("<i>", "</i>"),
+            (TeamsHtml | TelegramHtml, Inline::Strikethrough) => ("<s>", "</s>"),
+            (WebexMarkdown, Inline::Strong) => ("**", "**"),
+            (WebexMarkdown, Inline::Emphasis) => ("*", "*"),
+            (WebexMarkdown, Inline::Strikethrough) => ("~~", "~~"),
+            (_, Inline::Strong) => ("*", "*"),
+            (_, Inline::Emphasis) => ("_", "_"),
+            (_, Inline::Strikethrough) => ("~", "~"),
+        }
+    }
+
+    fn inline_open(&mut self, inline: Inline) {
+        let (open, _) = self.inline_markers(inline);
+        self.write(open);
+    }
+
+    fn inline_close(&mut self, inline: Inline) {
+        let (_, close) = self.inline_markers(inline);
+        self.write(close);
+    }
+
+    fn escape_text(&self, text: &str) -> String {
+        match self.dialect {
+            MarkdownDialect::SlackMrkdwn => escape_slack(text),
+            MarkdownDialect::TelegramMarkdownV2 => escape_telegram_markdown_v2(text),
+            MarkdownDialect::TelegramHtml | MarkdownDialect::TeamsHtml => escape_html(text),
+            MarkdownDialect::WebexMarkdown => escape_with_backslash(text, WEBEX_RESERVED),
+            MarkdownDialect::WhatsApp | MarkdownDialect::PlainText => text.to_string(),
+        }
+    }
+
+    fn escape_code(&self, code: &str) -> String {
+        match self.dialect {
+            MarkdownDialect::SlackMrkdwn => escape_slack(code),
+            MarkdownDialect::TelegramMarkdownV2 => escape_with_backslash(code, "`\\"),
+            MarkdownDialect::TelegramHtml | MarkdownDialect::TeamsHtml => escape_html(code),
+            _ => code.to_string(),
+        }
+    }
+
+    fn code_span(&mut self, code: &str) {
+        let escaped = self.escape_code(code);
+        let rendered = match self.dialect {
+            MarkdownDialect::PlainText => escaped,
+            MarkdownDialect::TelegramHtml | MarkdownDialect::TeamsHtml => {
+                format!("<code>{escaped}</code>")
+            }
+            MarkdownDialect::WebexMarkdown if code.contains('`') => format!("`` {escaped} ``"),
+            _ => format!("`{escaped}`"),
+        };
+        self.write(&rendered);
+    }
+
+    fn end_code_block(&mut self) {
+        let Some((lang, code)) = self.code_block.take() else {
+            return;
+        };
+        let code = code.strip_suffix('\n').unwrap_or(&code);
+        let escaped = self.escape_code(code);
+        let rendered = match self.dialect {
+            MarkdownDialect::PlainText => escaped,
+            MarkdownDialect::TeamsHtml => format!("<pre><code>{escaped}</code></pre>"),
+            MarkdownDialect::TelegramHtml if !lang.is_empty() => format!(
+                "<pre><code class=\"language-{}\">{escaped}</code></pre>",
+                escape_html(&lang)
+            ),
+            MarkdownDialect::TelegramHtml => format!("<pre>{escaped}</pre>"),
+            MarkdownDialect::TelegramMarkdownV2 | MarkdownDialect::WebexMarkdown => {
+                format!("```{lang}\n{escaped}\n```")
+            }
+            MarkdownDialect::SlackMrkdwn | MarkdownDialect::WhatsApp => {
+                format!("```\n{escaped}\n```")
+            }
+        };
+        self.write(&rendered);
+        self.block_gap = true;
+    }
+
+    fn line_break(&mut self) {
+        if self.dialect == MarkdownDialect::TeamsHtml {
+            self.write("<br>");
+        } else {
+            self.newline();
+        }
+    }
+
+    fn rule(&mut self) {
+        self.start_block();
+        match self.dialect {
+            MarkdownDialect::TeamsHtml => self.write("<hr>"),
+            MarkdownDialect::WebexMarkdown => self.write("---"),
+            _ => self.write(RULE),
+        }
+        self.block_gap = true;
+    }
+
+    fn link_open(&mut self, url: &str) {
+        let open = match self.dialect {
+            MarkdownDialect::SlackMrkdwn => format!("<{}|", escape_slack(url)),
+            MarkdownDialect::TelegramMarkdownV2 | MarkdownDialect::WebexMarkdown => "[".into(),
+            MarkdownDialect::TelegramHtml | MarkdownDialect::TeamsHtml => {
+                format!("<a href=\"{}\">", escape_html(url))
+            }
+            MarkdownDialect::WhatsApp | MarkdownDialect::PlainText => String::new(),
+        };
+        self.write(&open);
+    }
+
+    fn link_close(&mut self, url: &str, label_start: usize) {
+        let close = match self.dialect {
+            MarkdownDialect::SlackMrkdwn => ">".to_string(),
+            MarkdownDialect::TelegramMarkdownV2 => {
+                format!("]({})", escape_with_backslash(url, ")\\"))
+            }
+            MarkdownDialect::WebexMarkdown => format!("]({url})"),
+            MarkdownDialect::TelegramHtml | MarkdownDialect::TeamsHtml => "</a>".into(),
+            MarkdownDialect::WhatsApp | MarkdownDialect::PlainText => {
+                let label = &self.out[label_start..];
+                if label.is_empty() {
+                    url.to_string()
+                } else if label == url || label.strip_prefix("mailto:") == Some(url) {
+                    String::new()
+                } else {
+                    format!(" ({url})")
+                }
+            }
+        };
+        self.write(&close);
+    }
+}
+
+fn heading_tag(level: HeadingLevel) -> &'static str {
+    match level {
+        HeadingLevel::H1 => "h1",
+        HeadingLevel::H2 => "h2",
+        HeadingLevel::H3 => "h3",
+        HeadingLevel::H4 => "h4",
+        HeadingLevel::H5 => "h5",
+        HeadingLevel::H6 => "h6",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str =
+        "Hi **Ann**, see [the docs](https://example.com/a_b) and `x_y`.\n\n- one\n- two";
+
+    #[test]
+    fn renders_common_elements_per_dialect() {
+        assert_eq!(
+            render(SAMPLE, MarkdownDialect::SlackMrkdwn),
+            "Hi *Ann*, see <https://example.com/a_b|the docs> and `x_y`.\n\n• one\n• two"
+        );
+        assert_eq!(
+            render(SAMPLE, MarkdownDialect::TelegramMarkdownV2),
+            "Hi *Ann*, see [the docs](https://example.com/a_b) and `x_y`\\.\n\n• one\n• two"
+        );
+        assert_eq!(
+            render(SAMPLE, MarkdownDialect::TelegramHtml),
+            "Hi <b>Ann</b>, see <a href=\"https://example.com/a_b\">the docs</a> and <code>x_y</code>.\n\n• one\n• two"
+        );
+        assert_eq!(
+            render(SAMPLE, MarkdownDialect::WhatsApp),
+            "Hi *Ann*, see the docs (https://example.com/a_b) and `x_y`.\n\n• one\n• two"
+        );
+        assert_eq!(
+            render(SAMPLE, MarkdownDialect::TeamsHtml),
+            "<p>Hi <strong>Ann</strong>, see <a href=\"https://example.com/a_b\">the docs</a> and <code>x_y</code>.</p><ul><li>one</li><li>two</li></ul>"
+        );
+        assert_eq!(
+            render(SAMPLE, MarkdownDialect::WebexMarkdown),
+            "Hi **Ann**, see [the docs](https://example.com/a_b) and `x_y`.\n\n- one\n- two"
+        );
+        assert_eq!(
+            render(SAMPLE, MarkdownDialect::PlainText),
+            "Hi Ann, see the docs (https://example.com/a_b) and x_y.\n\n- one\n- two"
+        );
+    }
+
+    #[test]
+    fn escapes_telegram_reserved_characters() {
+        assert_eq!(
+            render(
+                "a_b * [c] (d) ~e 1+1=2 #tag {x} | v1.0!",
+                MarkdownDialect::TelegramMarkdownV2
+            ),
+            "a\\_b \\* \\[c\\] \\(d\\) \\~e 1\\+1\\=2 \\#tag \\{x\\} \\| v1\\.0\\!"
+        );
+        assert_eq!(
+            render(
+                "[q](https://x.io/a_(b))",
+                MarkdownDialect::TelegramMarkdownV2
+            ),
+            "[q](https://x.io/a_(b\\))"
+        );
+        assert_eq!(
+            render("1 < 2 & <b>", MarkdownDialect::TelegramHtml),
+            "1 &lt; 2 &amp; &lt;b&gt;"
+        );
+    }
+
+    #[test]
+    fn renders_ordered_lists_code_blocks_and_quotes() {
+        let markdown =
+            "1. first\n2. second\n   - nested\n\n```rust\nlet a = `b`;\n```\n\n> quoted\n> twice";
+        assert_eq!(
+            render(markdown, MarkdownDialect::TelegramMarkdownV2),
+            "1\\. first\n2\\. second\n  • nested\n\n```rust\nlet a = \\`b\\`;\n```\n\n>quoted\n>twice"
+        );
+        assert_eq!(
+            render(markdown, MarkdownDialect::SlackMrkdwn),
+            "1. first\n2. second\n  • nested\n\n```\nlet a = `b`;\n```\n\n> quoted\n> twice"
+        );
+        assert_eq!(
+            render(markdown, MarkdownDialect::TeamsHtml),
+            "<ol><li>first</li><li>second<ul><li>nested</li></ul></li></ol><pre><code>let a = `b`;</code></pre><blockquote><p>quoted<br>twice</p></blockquote>"
+        );
+    }
+
+    #[test]
+    fn plain_links_collapse_when_label_is_url() {
+        assert_eq!(
+            render("<https://example.com>", MarkdownDialect::WhatsApp),
+            "https://example.com"
+        );
+        assert_eq!(
+            MarkdownDialect::TelegramMarkdownV2.telegram_parse_mode(),
+            Some("MarkdownV2")
+        );
+        assert_eq!(MarkdownDialect::SlackMrkdwn.telegram_parse_mode(), None);
+    }
+}
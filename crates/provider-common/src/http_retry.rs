@@ -0,0 +1,474 @@
+//! Retrying executor for outbound provider HTTP calls.
+//!
+//! Components own their generated `http-client` bindings, so the executor is
+//! generic over the response type and the send closure. The attempt budget
+//! comes from `ProviderRuntimeConfig.network.max_attempts`.
+
+use crate::ProviderErrorDetail;
+use provider_runtime_config::{NetworkConfig, ProviderRuntimeConfig};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Input key carrying a `ProviderRuntimeConfig` next to the operation payload.
+pub const RUNTIME_CONFIG_KEY: &str = "runtime_config";
+pub const DEFAULT_BASE_DELAY_MS: u64 = 250;
+pub const DEFAULT_MAX_DELAY_MS: u64 = 8_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::from_network(&NetworkConfig::default())
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_network(network: &NetworkConfig) -> Self {
+        Self {
+            max_attempts: network.max_attempts.max(1),
+            base_delay_ms: DEFAULT_BASE_DELAY_MS,
+            max_delay_ms: DEFAULT_MAX_DELAY_MS,
+        }
+    }
+
+    /// Reads the runtime config from an invocation input, either as an object
+    /// or as a JSON string. Missing or invalid config yields the defaults.
+    pub fn from_input(input: &Value) -> Self {
+        let Some(raw) = input.get(RUNTIME_CONFIG_KEY) else {
+            return Self::default();
+        };
+        let parsed = match raw {
+            Value::String(text) => serde_json::from_str::<ProviderRuntimeConfig>(text).ok(),
+            other => serde_json::from_value::<ProviderRuntimeConfig>(other.clone()).ok(),
+        };
+        parsed
+            .filter(|cfg| cfg.validate().is_ok())
+            .map(|cfg| Self::from_network(&cfg.network))
+            .unwrap_or_default()
+    }
+
+    /// Exponential backoff for the given retry (1-based) with equal jitter.
+    pub fn backoff(&self, retry: u32, entropy: u64) -> Duration {
+        let exp = retry.saturating_sub(1).min(16);
+        let ceiling = self
+            .base_delay_ms
+            .saturating_mul(1u64 << exp)
+            .min(self.max_delay_ms);
+        let half = ceiling / 2;
+        let jitter = if half == 0 { 0 } else { entropy % (half + 1) };
+        Duration::from_millis(half + jitter)
+    }
+}
+
+/// Whether a request may safely be replayed after an ambiguous failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Idempotency {
+    Idempotent,
+    NonIdempotent,
+}
+
+impl Idempotency {
+    pub fn from_method(method: &str) -> Self {
+        match method.trim().to_ascii_uppercase().as_str() {
+            "GET" | "HEAD" | "OPTIONS" | "PUT" | "DELETE" => Idempotency::Idempotent,
+            _ => Idempotency::NonIdempotent,
+        }
+    }
+}
+
+/// Minimal view of an HTTP response needed to drive retries.
+pub trait RetryableResponse {
+    fn status(&self) -> u16;
+    fn header(&self, name: &str) -> Option<&str>;
+}
+
+#[derive(Debug)]
+pub struct RetryOutcome<R, E> {
+    pub result: Result<R, E>,
+    pub attempts: u32,
+}
+
+/// Sends an HTTP request with retries; its `method` decides whether failures
+/// that may have reached the server are replayed. `send` performs one attempt
+/// through the component's own `http-client` binding.
+pub fn send_http<R, E>(
+    method: &str,
+    policy: &RetryPolicy,
+    send: impl FnMut() -> Result<R, E>,
+) -> RetryOutcome<R, E>
+where
+    R: RetryableResponse,
+{
+    send_with_retry(policy, Idempotency::from_method(method), send)
+}
+
+/// Serializes a `send_payload` result with the number of attempts, the
+/// `error_detail` of a failure and, when there are any, `diagnostics`.
+pub fn send_payload_result_bytes<T: Serialize>(
+    result: &T,
+    attempts: u32,
+    detail: Option<&ProviderErrorDetail>,
+    diagnostics: &[String],
+) -> Vec<u8> {
+    let mut value = serde_json::to_value(result).unwrap_or(Value::Null);
+    if let Some(map) = value.as_object_mut() {
+        map.insert("attempts".into(), json!(attempts));
+        if let Some(detail) = detail {
+            map.insert("error_detail".into(), json!(detail));
+        }
+        if !diagnostics.is_empty() {
+            map.insert("diagnostics".into(), json!(diagnostics));
+        }
+    }
+    serde_json::to_vec(&value).unwrap_or_else(|_| b"{}".to_vec())
+}
+
+/// Sends with retries, sleeping on the current thread between attempts.
+pub fn send_with_retry<R, E>(
+    policy: &RetryPolicy,
+    idempotency: Idempotency,
+    send: impl FnMut() -> Result<R, E>,
+) -> RetryOutcome<R, E>
+where
+    R: RetryableResponse,
+{
+    send_with_retry_using(policy, idempotency, send, std::thread::sleep)
+}
+
+/// Same as [`send_with_retry`] with an injectable sleep, for tests.
+pub fn send_with_retry_using<R, E>(
+    policy: &RetryPolicy,
+    idempotency: Idempotency,
+    mut send: impl FnMut() -> Result<R, E>,
+    mut sleep: impl FnMut(Duration),
+) -> RetryOutcome<R, E>
+where
+    R: RetryableResponse,
+{
+    let max_attempts = policy.max_attempts.max(1);
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let result = send();
+        if attempts >= max_attempts {
+            return RetryOutcome { result, attempts };
+        }
+        let delay = match &result {
+            Ok(resp) if should_retry_status(resp.status(), idempotency) => {
+                match retry_after(resp) {
+                    Some(wait) if wait > Duration::from_millis(policy.max_delay_ms) => {
+                        return RetryOutcome { result, attempts };
+                    }
+                    Some(wait) => wait,
+                    None => policy.backoff(attempts, entropy(attempts)),
+                }
+            }
+            Err(_) if idempotency == Idempotency::Idempotent => {
+                policy.backoff(attempts, entropy(attempts))
+            }
+            _ => return RetryOutcome { result, attempts },
+        };
+        sleep(delay);
+    }
+}
+
+/// 429 and 503 mean the request was not processed, so they are safe to replay
+/// for any method. Other gateway failures are only retried when idempotent.
+pub fn should_retry_status(status: u16, idempotency: Idempotency) -> bool {
+    match status {
+        429 | 503 => true,
+        408 | 500 | 502 | 504 => idempotency == Idempotency::Idempotent,
+        _ => false,
+    }
+}
+
+/// Parses the `Retry-After` header of 429/503 responses, given either as
+/// delta-seconds or as an HTTP date.
+pub fn retry_after<R: RetryableResponse>(resp: &R) -> Option<Duration> {
+    if !matches!(resp.status(), 429 | 503) {
+        return None;
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    parse_retry_after(resp.header("retry-after")?, now)
+}
+
+/// Delay named by a `Retry-After` value at `now` (Unix seconds). A date in
+/// the past means no wait.
+pub fn parse_retry_after(value: &str, now: u64) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = parse_http_date(value)?;
+    Some(Duration::from_secs(at.saturating_sub(now)))
+}
+
+/// Unix seconds of an IMF-fixdate (`Sun, 06 Nov 1994 08:49:37 GMT`), the
+/// only date form senders may generate.
+fn parse_http_date(value: &str) -> Option<u64> {
+    let (_weekday, rest) = value.split_once(", ")?;
+    let parts: Vec<&str> = rest.split(' ').collect();
+    let [day, month, year, time, "GMT"] = parts.as_slice() else {
+        return None;
+    };
+    let day: u64 = day.parse().ok()?;
+    let month = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ]
+    .iter()
+    .position(|name| name == month)? as u64
+        + 1;
+    let year: u64 = year.parse().ok()?;
+    let mut clock = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next()??);
+    if clock.next().is_some() || !(1..=31).contains(&day) || year < 1970 {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date (Howard Hinnant's
+/// `days_from_civil`), for years from 1970.
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let shifted_month = (month + 9) % 12;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Case-insensitive lookup over `(name, value)` header pairs.
+pub fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+fn entropy(attempt: u32) -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+    let mut x = nanos ^ (u64::from(attempt) << 32) ^ 0x9E37_79B9_7F4A_7C15;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::cell::RefCell;
+
+    struct Resp {
+        status: u16,
+        headers: Vec<(String, String)>,
+    }
+
+    impl RetryableResponse for Resp {
+        fn status(&self) -> u16 {
+            self.status
+        }
+
+        fn header(&self, name: &str) -> Option<&str> {
+            find_header(&self.headers, name)
+        }
+    }
+
+    fn resp(status: u16) -> Resp {
+        Resp {
+            status,
+            headers: Vec::new(),
+        }
+    }
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            ..RetryPolicy::default()
+        }
+    }
+
+    fn run(
+        policy: &RetryPolicy,
+        idempotency: Idempotency,
+        mut responses: Vec<Result<Resp, String>>,
+    ) -> (RetryOutcome<Resp, String>, Vec<Duration>) {
+        responses.reverse();
+        let sleeps = RefCell::new(Vec::new());
+        let outcome = send_with_retry_using(
+            policy,
+            idempotency,
+            || responses.pop().expect("unexpected extra attempt"),
+            |delay| sleeps.borrow_mut().push(delay),
+        );
+        (outcome, sleeps.into_inner())
+    }
+
+    #[test]
+    fn reads_max_attempts_from_runtime_config() {
+        let input =
+            json!({"runtime_config": {"schema_version": 1, "network": {"max_attempts": 3}}});
+        assert_eq!(RetryPolicy::from_input(&input).max_attempts, 3);
+        let as_string = json!({"runtime_config": r#"{"network":{"max_attempts":2}}"#});
+        assert_eq!(RetryPolicy::from_input(&as_string).max_attempts, 2);
+        assert_eq!(RetryPolicy::from_input(&json!({})).max_attempts, 1);
+        let zero = json!({"runtime_config": {"network": {"max_attempts": 0}}});
+        assert_eq!(RetryPolicy::from_input(&zero).max_attempts, 1);
+    }
+
+    #[test]
+    fn retries_rate_limit_and_honors_retry_after() {
+        let limited = Resp {
+            status: 429,
+            headers: vec![("Retry-After".into(), "2".into())],
+        };
+        let (outcome, sleeps) = run(
+            &policy(3),
+            Idempotency::NonIdempotent,
+            vec![Ok(limited), Ok(resp(200))],
+        );
+        assert_eq!(outcome.attempts, 2);
+        assert_eq!(outcome.result.expect("ok").status, 200);
+        assert_eq!(sleeps, vec![Duration::from_secs(2)]);
+    }
+
+    #[test]
+    fn retry_after_accepts_http_dates() {
+        let date = "Sun, 06 Nov 1994 08:49:37 GMT";
+        assert_eq!(
+            parse_retry_after(date, 784_111_770),
+            Some(Duration::from_secs(7))
+        );
+        assert_eq!(parse_retry_after(date, 784_111_800), Some(Duration::ZERO));
+        assert_eq!(parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+        assert_eq!(
+            parse_http_date("Tue, 29 Feb 2028 23:59:59 GMT"),
+            Some(1_835_481_599)
+        );
+        assert_eq!(parse_retry_after(" 5 ", 0), Some(Duration::from_secs(5)));
+        assert_eq!(parse_retry_after("Sun, 06 Nov 1994 08:49:37 UTC", 0), None);
+        assert_eq!(parse_retry_after("soon", 0), None);
+    }
+
+    #[test]
+    fn gives_up_when_retry_after_exceeds_cap() {
+        let limited = Resp {
+            status: 429,
+            headers: vec![("retry-after".into(), "120".into())],
+        };
+        let (outcome, sleeps) = run(&policy(3), Idempotency::Idempotent, vec![Ok(limited)]);
+        assert_eq!(outcome.attempts, 1);
+        assert_eq!(outcome.result.expect("response").status, 429);
+        assert!(sleeps.is_empty());
+    }
+
+    #[test]
+    fn bad_gateway_only_retried_when_idempotent() {
+        let (outcome, _) = run(&policy(3), Idempotency::NonIdempotent, vec![Ok(resp(502))]);
+        assert_eq!(outcome.attempts, 1);
+
+        let (outcome, sleeps) = run(
+            &policy(3),
+            Idempotency::Idempotent,
+            vec![Ok(resp(502)), Ok(resp(502)), Ok(resp(200))],
+        );
+        assert_eq!(outcome.attempts, 3);
+        assert_eq!(sleeps.len(), 2);
+    }
+
+    #[test]
+    fn transport_errors_only_retried_when_idempotent() {
+        let (outcome, _) = run(
+            &policy(2),
+            Idempotency::NonIdempotent,
+            vec![Err("reset".into())],
+        );
+        assert_eq!(outcome.attempts, 1);
+        assert!(outcome.result.is_err());
+
+        let (outcome, _) = run(
+            &policy(2),
+            Idempotency::Idempotent,
+            vec![Err("reset".into()), Ok(resp(204))],
+        );
+        assert_eq!(outcome.attempts, 2);
+        assert!(outcome.result.is_ok());
+    }
+
+    #[test]
+    fn stops_at_max_attempts() {
+        let (outcome, sleeps) = run(
+            &policy(2),
+            Idempotency::Idempotent,
+            vec![Ok(resp(503)), Ok(resp(503))],
+        );
+        assert_eq!(outcome.attempts, 2);
+        assert_eq!(outcome.result.expect("response").status, 503);
+        assert_eq!(sleeps.len(), 1);
+    }
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let policy = policy(5);
+        for entropy in [0, 7, u64::MAX] {
+            let first = policy.backoff(1, entropy);
+            assert!(first >= Duration::from_millis(125) && first <= Duration::from_millis(250));
+            let third = policy.backoff(3, entropy);
+            assert!(third >= Duration::from_millis(500) && third <= Duration::from_millis(1000));
+            let late = policy.backoff(30, entropy);
+            assert!(late <= Duration::from_millis(DEFAULT_MAX_DELAY_MS));
+        }
+    }
+
+    #[test]
+    fn send_payload_results_carry_attempts_and_detail() {
+        let detail = crate::ProviderError::rate_limited("slow down", Some(3)).detail();
+        let bytes = send_payload_result_bytes(
+            &json!({"ok": false, "retryable": true}),
+            2,
+            Some(&detail),
+            &["missing_charset".to_string()],
+        );
+        let value: Value = serde_json::from_slice(&bytes).expect("json");
+        assert_eq!(value["attempts"], json!(2));
+        assert_eq!(value["error_detail"]["retry_after_secs"], json!(3));
+        assert_eq!(value["diagnostics"], json!(["missing_charset"]));
+        let ok: Value = serde_json::from_slice(&send_payload_result_bytes(
+            &json!({"ok": true}),
+            1,
+            None,
+            &[],
+        ))
+        .expect("json");
+        assert_eq!(ok, json!({"ok": true, "attempts": 1}));
+    }
+
+    #[test]
+    fn idempotency_follows_method() {
+        assert_eq!(Idempotency::from_method("get"), Idempotency::Idempotent);
+        assert_eq!(Idempotency::from_method("DELETE"), Idempotency::Idempotent);
+        assert_eq!(Idempotency::from_method("POST"), Idempotency::NonIdempotent);
+        assert_eq!(
+            Idempotency::from_method("PATCH"),
+            Idempotency::NonIdempotent
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Duplicate-delivery suppression for webhook ingress.
+//!
+//! Providers redeliver webhooks on timeouts and non-2xx responses. Each
+//! provider extracts an idempotency key from the delivery (Slack `event_id`,
+//! Telegram `update_id`, ...) and consults a state-backed window before turning
+//! the delivery into envelopes. State store failures never drop a delivery: the
+//! check fails open and the delivery is treated as fresh.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::state::{ExpiryIndex, StateStore};
+
+pub const DEFAULT_DEDUP_TTL_SECS: u64 = 3600;
+pub const DEDUP_STATE_PREFIX: &str = "messaging.ingress.dedup";
+/// Flag set in the normalized ingest body when a duplicate was suppressed.
+pub const DUPLICATE_SUPPRESSED_FLAG: &str = "duplicate_suppressed";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SeenRecord {
+    seen_at: u64,
+}
+
+/// How long a delivery key is remembered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DedupWindow {
+    pub ttl_secs: u64,
+}
+
+impl Default for DedupWindow {
+    fn default() -> Self {
+        Self {
+            ttl_secs: DEFAULT_DEDUP_TTL_SECS,
+        }
+    }
+}
+
+impl DedupWindow {
+    /// Whether `idempotency_key` was recorded less than `ttl_secs` before `now`.
+    /// An expired record is deleted.
+    pub fn seen(
+        &self,
+        store: &mut impl StateStore,
+        provider: &str,
+        idempotency_key: &str,
+        now: u64,
+    ) -> bool {
+        let key = dedup_state_key(provider, idempotency_key);
+        let Some(record) = store
+            .read(&key)
+            .ok()
+            .flatten()
+            .and_then(|bytes| serde_json::from_slice::<SeenRecord>(&bytes).ok())
+        else {
+            return false;
+        };
+        if now.saturating_sub(record.seen_at) < self.ttl_secs {
+            return true;
+        }
+        let _ = store.delete(&key);
+        false
+    }
+
+    /// Records `idempotency_key` as seen at `now`. Use with [`Self::seen`] when
+    /// a delivery should only count once it was processed successfully.
+    /// Records of deliveries that are never repeated are purged through an
+    /// [`ExpiryIndex`] with one bucket per window.
+    pub fn record(
+        &self,
+        store: &mut impl StateStore,
+        provider: &str,
+        idempotency_key: &str,
+        now: u64,
+    ) {
+        let key = dedup_state_key(provider, idempotency_key);
+        let record = serde_json::to_vec(&SeenRecord { seen_at: now }).unwrap_or_default();
+        if store.write(&key, &record).is_ok() {
+            let namespace = format!("{DEDUP_STATE_PREFIX}.{provider}");
+            let index = ExpiryIndex {
+                namespace: &namespace,
+                bucket_secs: self.ttl_secs,
+            };
+            let _ = index.track(store, &key, now);
+        }
+    }
+}
+
+/// State key for a delivery; the idempotency key is hashed so arbitrary
+/// provider ids stay within the key charset.
+pub fn dedup_state_key(provider: &str, idempotency_key: &str) -> String {
+    let digest = Sha256::digest(idempotency_key.as_bytes());
+    let hex: String = digest.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("{DEDUP_STATE_PREFIX}.{provider}.{hex}")
+}
+
+/// Normalized body returned with the 200 acknowledgement of a suppressed
+/// duplicate.
+pub fn duplicate_ack_body(provider: &str, idempotency_key: &str) -> Vec<u8> {
+    let body = json!({
+        "ok": true,
+        DUPLICATE_SUPPRESSED_FLAG: true,
+        "provider": provider,
+        "idempotency_key": idempotency_key,
+    });
+    serde_json::to_vec(&body).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryStore;
+    use serde_json::Value;
+
+    #[test]
+    fn repeats_inside_window_are_duplicates() {
+        let mut store = MemoryStore::default();
+        let window = DedupWindow { ttl_secs: 60 };
+        assert!(!window.seen(&mut store, "slack", "Ev1", 1_000));
+        window.record(&mut store, "slack", "Ev1", 1_000);
+        assert!(window.seen(&mut store, "slack", "Ev1", 1_059));
+        assert!(!window.seen(&mut store, "telegram", "Ev1", 1_010));
+        assert!(!window.seen(&mut store, "slack", "Ev1", 1_060));
+    }
+
+    #[test]
+    fn expired_records_are_deleted() {
+        let mut store = MemoryStore::default();
+        let window = DedupWindow { ttl_secs: 60 };
+        window.record(&mut store, "slack", "Ev1", 1_000);
+        assert!(!window.seen(&mut store, "slack", "Ev1", 1_060));
+        assert!(!store.entries.contains_key(&dedup_state_key("slack", "Ev1")));
+
+        window.record(&mut store, "slack", "Ev2", 1_000);
+        window.record(&mut store, "slack", "Ev3", 1_200);
+        assert!(!store.entries.contains_key(&dedup_state_key("slack", "Ev2")));
+        assert!(window.seen(&mut store, "slack", "Ev3", 1_210));
+    }
+
+    #[test]
+    fn store_failures_fail_open() {
+        let mut store = MemoryStore {
+            fail: true,
+            ..Default::default()
+        };
+        let window = DedupWindow::default();
+        for _ in 0..2 {
+            assert!(!window.seen(&mut store, "slack", "Ev1", 1_000));
+            window.record(&mut store, "slack", "Ev1", 1_000);
+        }
+    }
+
+    #[test]
+    fn ack_body_carries_flag() {
+        let body: Value = serde_json::from_slice(&duplicate_ack_body("slack", "Ev1")).unwrap();
+        assert_eq!(body[DUPLICATE_SUPPRESSED_FLAG], json!(true));
+        assert!(dedup_state_key("slack", "a/b c").starts_with("messaging.ingress.dedup.slack."));
+    }
+}
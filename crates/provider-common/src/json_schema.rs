@@ -0,0 +1,463 @@
+//! Conversion between `SchemaIr` and JSON Schema draft 2020-12.
+//!
+//! I18n keys travel in `x-i18n-*` keywords so the conversion is lossless;
+//! resolved `title`/`description` text is only added when a message catalog
+//! is supplied and is ignored when reading a schema back.
+
+use crate::component_v0_6::{EnumValue, I18nText, SchemaField, SchemaIr};
+use serde_json::{Map, Number, Value, json};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const JSON_SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+const X_TITLE: &str = "x-i18n-title";
+const X_DESCRIPTION: &str = "x-i18n-description";
+const X_ENUM_LABELS: &str = "x-i18n-enum";
+const X_REDACT: &str = "x-redact";
+
+/// Converts a schema to JSON Schema, keeping i18n keys but no resolved text.
+pub fn to_json_schema(schema: &SchemaIr) -> Value {
+    convert(schema, None)
+}
+
+/// Like [`to_json_schema`], also filling `title`/`description` from `messages`.
+pub fn to_json_schema_localized(schema: &SchemaIr, messages: &Map<String, Value>) -> Value {
+    convert(schema, Some(messages))
+}
+
+/// A standalone document with `$schema`, suitable for `config.schema.json`.
+pub fn json_schema_document(schema: &SchemaIr, messages: &Map<String, Value>) -> Value {
+    let mut doc = to_json_schema_localized(schema, messages);
+    if let Some(map) = doc.as_object_mut() {
+        map.insert("$schema".into(), json!(JSON_SCHEMA_DIALECT));
+    }
+    doc
+}
+
+/// Reads a schema produced by [`to_json_schema`]. Missing i18n keys become
+/// empty keys so hand-written schemas still load.
+pub fn from_json_schema(value: &Value) -> Result<SchemaIr, String> {
+    let obj = value
+        .as_object()
+        .ok_or_else(|| "schema must be an object".to_string())?;
+    let title = i18n_keyword(obj, X_TITLE);
+    let description = i18n_keyword(obj, X_DESCRIPTION);
+
+    if let Some(variants) = obj.get("oneOf") {
+        let variants = variants
+            .as_array()
+            .ok_or_else(|| "oneOf must be an array".to_string())?
+            .iter()
+            .map(from_json_schema)
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(SchemaIr::OneOf {
+            title,
+            description,
+            variants,
+        });
+    }
+
+    if let Some(values) = obj.get("enum") {
+        let values = values
+            .as_array()
+            .ok_or_else(|| "enum must be an array".to_string())?;
+        let labels = obj
+            .get(X_ENUM_LABELS)
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default();
+        let values = values
+            .iter()
+            .enumerate()
+            .map(|(idx, value)| {
+                let value = value
+                    .as_str()
+                    .ok_or_else(|| "enum values must be strings".to_string())?;
+                let label = labels.get(idx).and_then(Value::as_str).unwrap_or_default();
+                Ok(EnumValue {
+                    value: value.to_string(),
+                    label: key(label),
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        return Ok(SchemaIr::Enum {
+            title,
+            description,
+            values,
+        });
+    }
+
+    let kind = obj
+        .get("type")
+        .and_then(Value::as_str)
+        .ok_or_else(|| "schema type required".to_string())?;
+    match kind {
+        "boolean" => Ok(SchemaIr::Bool { title, description }),
+        "string" => Ok(SchemaIr::String {
+            title,
+            description,
+            format: obj.get("format").and_then(Value::as_str).map(String::from),
+            secret: obj.get(X_REDACT).and_then(Value::as_bool).unwrap_or(false),
+        }),
+        "number" => Ok(SchemaIr::Number {
+            title,
+            description,
+            minimum: number_keyword(obj, "minimum")?,
+            maximum: number_keyword(obj, "maximum")?,
+        }),
+        "integer" => Ok(SchemaIr::Integer {
+            title,
+            description,
+            minimum: integer_keyword(obj, "minimum")?,
+            maximum: integer_keyword(obj, "maximum")?,
+        }),
+        "array" => {
+            let items = obj
+                .get("items")
+                .ok_or_else(|| "array schema requires items".to_string())?;
+            Ok(SchemaIr::Array {
+                title,
+                description,
+                items: Box::new(from_json_schema(items)?),
+                min_items: count_keyword(obj, "minItems")?,
+                max_items: count_keyword(obj, "maxItems")?,
+            })
+        }
+        "object" => {
+            let required = obj
+                .get("required")
+                .and_then(Value::as_array)
+                .map(|names| {
+                    names
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .collect::<BTreeSet<_>>()
+                })
+                .unwrap_or_default();
+            let mut fields = BTreeMap::new();
+            if let Some(properties) = obj.get("properties").and_then(Value::as_object) {
+                for (name, property) in properties {
+                    let schema =
+                        from_json_schema(property).map_err(|err| format!("{name}: {err}"))?;
+                    fields.insert(
+                        name.clone(),
+                        SchemaField {
+                            required: required.contains(name.as_str()),
+                            schema,
+                        },
+                    );
+                }
+            }
+            Ok(SchemaIr::Object {
+                title,
+                description,
+                fields,
+                additional_properties: obj
+                    .get("additionalProperties")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(true),
+            })
+        }
+        other => Err(format!("unsupported schema type: {other}")),
+    }
+}
+
+fn convert(schema: &SchemaIr, messages: Option<&Map<String, Value>>) -> Value {
+    let mut out = Map::new();
+    match schema {
+        SchemaIr::Bool { .. } => {
+            out.insert("type".into(), json!("boolean"));
+        }
+        SchemaIr::String { format, secret, .. } => {
+            out.insert("type".into(), json!("string"));
+            if let Some(format) = format {
+                out.insert("format".into(), json!(format));
+            }
+            if *secret {
+                out.insert(X_REDACT.into(), json!(true));
+            }
+        }
+        SchemaIr::Number {
+            minimum, maximum, ..
+        } => {
+            out.insert("type".into(), json!("number"));
+            insert_opt(&mut out, "minimum", minimum.clone());
+            insert_opt(&mut out, "maximum", maximum.clone());
+        }
+        SchemaIr::Integer {
+            minimum, maximum, ..
+        } => {
+            out.insert("type".into(), json!("integer"));
+            insert_opt(&mut out, "minimum", *minimum);
+            insert_opt(&mut out, "maximum", *maximum);
+        }
+        SchemaIr::Array {
+            items,
+            min_items,
+            max_items,
+            ..
+        } => {
+            out.insert("type".into(), json!("array"));
+            out.insert("items".into(), convert(items, messages));
+            insert_opt(&mut out, "minItems", *min_items);
+            insert_opt(&mut out, "maxItems", *max_items);
+        }
+        SchemaIr::Enum { values, .. } => {
+            out.insert("type".into(), json!("string"));
+            out.insert(
+                "enum".into(),
+                values.iter().map(|v| json!(v.value)).collect(),
+            );
+            out.insert(
+                X_ENUM_LABELS.into(),
+                values.iter().map(|v| json!(v.label.key)).collect(),
+            );
+        }
+        SchemaIr::OneOf { variants, .. } => {
+            out.insert(
+                "oneOf".into(),
+                variants.iter().map(|v| convert(v, messages)).collect(),
+            );
+        }
+        SchemaIr::Object {
+            fields,
+            additional_properties,
+            ..
+        } => {
+            out.insert("type".into(), json!("object"));
+            let properties = fields
+                .iter()
+                .map(|(name, field)| (name.clone(), convert(&field.schema, messages)))
+                .collect::<Map<_, _>>();
+            let required = fields
+                .iter()
+                .filter(|(_, field)| field.required)
+                .map(|(name, _)| json!(name))
+                .collect::<Vec<_>>();
+            out.insert("properties".into(), Value::Object(properties));
+            if !required.is_empty() {
+                out.insert("required".into(), Value::Array(required));
+            }
+            out.insert("additionalProperties".into(), json!(*additional_properties));
+        }
+    }
+
+    let title = schema.title();
+    let description = schema.description();
+    out.insert(X_TITLE.into(), json!(title.key));
+    out.insert(X_DESCRIPTION.into(), json!(description.key));
+    if let Some(messages) = messages {
+        if let Some(text) = messages.get(&title.key) {
+            out.insert("title".into(), text.clone());
+        }
+        if let Some(text) = messages.get(&description.key) {
+            out.insert("description".into(), text.clone());
+        }
+    }
+    Value::Object(out)
+}
+
+fn insert_opt<T: Into<Value>>(out: &mut Map<String, Value>, name: &str, value: Option<T>) {
+    if let Some(value) = value {
+        out.insert(name.into(), value.into());
+    }
+}
+
+fn key(value: &str) -> I18nText {
+    I18nText {
+        key: value.to_string(),
+    }
+}
+
+fn i18n_keyword(obj: &Map<String, Value>, name: &str) -> I18nText {
+    key(obj.get(name).and_then(Value::as_str).unwrap_or_default())
+}
+
+fn number_keyword(obj: &Map<String, Value>, name: &str) -> Result<Option<Number>, String> {
+    match obj.get(name) {
+        None => Ok(None),
+        Some(Value::Number(n)) => Ok(Some(n.clone())),
+        Some(_) => Err(format!("{name} must be a number")),
+    }
+}
+
+fn integer_keyword(obj: &Map<String, Value>, name: &str) -> Result<Option<i64>, String> {
+    match obj.get(name) {
+        None => Ok(None),
+        Some(value) => value
+            .as_i64()
+            .map(Some)
+            .ok_or_else(|| format!("{name} must be an integer")),
+    }
+}
+
+fn count_keyword(obj: &Map<String, Value>, name: &str) -> Result<Option<u64>, String> {
+    match obj.get(name) {
+        None => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .map(Some)
+            .ok_or_else(|| format!("{name} must be a non-negative integer")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(required: bool, schema: SchemaIr) -> SchemaField {
+        SchemaField { required, schema }
+    }
+
+    fn sample() -> SchemaIr {
+        let mut fields = BTreeMap::new();
+        fields.insert(
+            "enabled".to_string(),
+            field(
+                true,
+                SchemaIr::Bool {
+                    title: key("p.enabled.title"),
+                    description: key("p.enabled.description"),
+                },
+            ),
+        );
+        fields.insert(
+            "token".to_string(),
+            field(
+                false,
+                SchemaIr::String {
+                    title: key("p.token.title"),
+                    description: key("p.token.description"),
+                    format: None,
+                    secret: true,
+                },
+            ),
+        );
+        fields.insert(
+            "port".to_string(),
+            field(
+                true,
+                SchemaIr::Integer {
+                    title: key("p.port.title"),
+                    description: key("p.port.description"),
+                    minimum: Some(1),
+                    maximum: Some(65_535),
+                },
+            ),
+        );
+        fields.insert(
+            "ratio".to_string(),
+            field(
+                false,
+                SchemaIr::Number {
+                    title: key("p.ratio.title"),
+                    description: key("p.ratio.description"),
+                    minimum: Number::from_f64(0.5),
+                    maximum: None,
+                },
+            ),
+        );
+        fields.insert(
+            "hosts".to_string(),
+            field(
+                false,
+                SchemaIr::Array {
+                    title: key("p.hosts.title"),
+                    description: key("p.hosts.description"),
+                    items: Box::new(SchemaIr::String {
+                        title: key("p.hosts.item.title"),
+                        description: key("p.hosts.item.description"),
+                        format: Some("uri".into()),
+                        secret: false,
+                    }),
+                    min_items: Some(1),
+                    max_items: None,
+                },
+            ),
+        );
+        fields.insert(
+            "mode".to_string(),
+            field(
+                true,
+                SchemaIr::Enum {
+                    title: key("p.mode.title"),
+                    description: key("p.mode.description"),
+                    values: vec![
+                        EnumValue {
+                            value: "starttls".into(),
+                            label: key("p.mode.starttls"),
+                        },
+                        EnumValue {
+                            value: "none".into(),
+                            label: key("p.mode.none"),
+                        },
+                    ],
+                },
+            ),
+        );
+        fields.insert(
+            "target".to_string(),
+            field(
+                false,
+                SchemaIr::OneOf {
+                    title: key("p.target.title"),
+                    description: key("p.target.description"),
+                    variants: vec![
+                        SchemaIr::String {
+                            title: key("p.target.id.title"),
+                            description: key("p.target.id.description"),
+                            format: None,
+                            secret: false,
+                        },
+                        SchemaIr::Integer {
+                            title: key("p.target.num.title"),
+                            description: key("p.target.num.description"),
+                            minimum: None,
+                            maximum: None,
+                        },
+                    ],
+                },
+            ),
+        );
+        SchemaIr::Object {
+            title: key("p.title"),
+            description: key("p.description"),
+            fields,
+            additional_properties: false,
+        }
+    }
+
+    #[test]
+    fn round_trips_all_kinds() {
+        let schema = sample();
+        let json = to_json_schema(&schema);
+        assert_eq!(from_json_schema(&json).expect("parse"), schema);
+    }
+
+    #[test]
+    fn emits_draft_2020_12_keywords() {
+        let mut messages = Map::new();
+        messages.insert("p.port.title".into(), json!("Port"));
+        let doc = json_schema_document(&sample(), &messages);
+        assert_eq!(doc["$schema"], JSON_SCHEMA_DIALECT);
+        assert_eq!(doc["additionalProperties"], false);
+        assert_eq!(doc["required"], json!(["enabled", "mode", "port"]));
+        let port = &doc["properties"]["port"];
+        assert_eq!(port["type"], "integer");
+        assert_eq!(port["maximum"], 65_535);
+        assert_eq!(port["title"], "Port");
+        assert_eq!(
+            doc["properties"]["mode"]["enum"],
+            json!(["starttls", "none"])
+        );
+        assert_eq!(doc["properties"]["token"][X_REDACT], true);
+        assert_eq!(doc["properties"]["hosts"]["minItems"], 1);
+        assert_eq!(from_json_schema(&doc).expect("parse"), sample());
+    }
+
+    #[test]
+    fn rejects_unknown_types() {
+        let err = from_json_schema(&json!({"type": "null"})).unwrap_err();
+        assert!(err.contains("unsupported schema type"));
+    }
+}
@@ -1,5 +1,18 @@
+pub mod attachments;
 pub mod component_v0_6;
+pub mod form_urlencoded;
+pub mod formatting;
+pub mod http_retry;
+pub mod ingress_dedup;
+pub mod json_schema;
 pub mod lifecycle_keys;
+pub mod message_split;
+pub mod outbound_idempotency;
+pub mod state;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+pub mod token_cache;
+pub mod webhook_auth;
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -18,10 +31,64 @@ pub enum ProviderError {
         scope: String,
         remediation: String,
     },
+    #[error("auth failed: {0}")]
+    AuthFailed(String),
+    /// The user's grant was revoked or expired; only re-running consent fixes it.
+    #[error("reauth required: {0}")]
+    ReauthRequired(String),
+    #[error("rate limited: {message}")]
+    RateLimited {
+        message: String,
+        retry_after_secs: Option<u64>,
+    },
+    #[error("destination not found: {0}")]
+    DestinationNotFound(String),
+    #[error("payload too large: {0}")]
+    PayloadTooLarge(String),
+    #[error("unsupported content: {0}")]
+    UnsupportedContent(String),
+    #[error("provider rejected request: {message}")]
+    ProviderRejected {
+        code: Option<String>,
+        message: String,
+    },
+    #[error("invalid config: {0}")]
+    ConfigInvalid(String),
     #[error("unknown provider error: {0}")]
     Other(String),
 }
 
+/// Machine-readable classification of a [`ProviderError`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderErrorCode {
+    InvalidRequest,
+    TransientTransport,
+    MissingSecret,
+    AuthFailed,
+    ReauthRequired,
+    RateLimited,
+    DestinationNotFound,
+    PayloadTooLarge,
+    UnsupportedContent,
+    ProviderRejected,
+    ConfigInvalid,
+    Unknown,
+}
+
+/// Wire form of a [`ProviderError`], emitted as `error_detail` next to the
+/// human-readable `error` string in operation results.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProviderErrorDetail {
+    pub code: ProviderErrorCode,
+    pub message: String,
+    pub retryable: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_code: Option<String>,
+}
+
 impl ProviderError {
     pub fn validation(msg: impl Into<String>) -> Self {
         ProviderError::Validation(msg.into())
@@ -35,6 +102,44 @@ impl ProviderError {
         ProviderError::Other(msg.into())
     }
 
+    pub fn auth_failed(msg: impl Into<String>) -> Self {
+        ProviderError::AuthFailed(msg.into())
+    }
+
+    pub fn reauth_required(msg: impl Into<String>) -> Self {
+        ProviderError::ReauthRequired(msg.into())
+    }
+
+    pub fn rate_limited(msg: impl Into<String>, retry_after_secs: Option<u64>) -> Self {
+        ProviderError::RateLimited {
+            message: msg.into(),
+            retry_after_secs,
+        }
+    }
+
+    pub fn destination_not_found(msg: impl Into<String>) -> Self {
+        ProviderError::DestinationNotFound(msg.into())
+    }
+
+    pub fn payload_too_large(msg: impl Into<String>) -> Self {
+        ProviderError::PayloadTooLarge(msg.into())
+    }
+
+    pub fn unsupported_content(msg: impl Into<String>) -> Self {
+        ProviderError::UnsupportedContent(msg.into())
+    }
+
+    pub fn provider_rejected(code: Option<String>, msg: impl Into<String>) -> Self {
+        ProviderError::ProviderRejected {
+            code,
+            message: msg.into(),
+        }
+    }
+
+    pub fn config_invalid(msg: impl Into<String>) -> Self {
+        ProviderError::ConfigInvalid(msg.into())
+    }
+
     pub fn missing_secret(name: impl Into<String>) -> Self {
         let name = name.into();
         ProviderError::MissingSecret {
@@ -45,6 +150,118 @@ impl ProviderError {
             ),
         }
     }
+
+    /// Classifies a non-success HTTP response from a provider API.
+    ///
+    /// `provider_code` is the provider's own error identifier when the body
+    /// carried one. It is kept on `ProviderRejected` and folded into the
+    /// message for the other variants.
+    pub fn from_http_status(
+        provider: &str,
+        status: u16,
+        retry_after_secs: Option<u64>,
+        provider_code: Option<String>,
+    ) -> Self {
+        let message = match &provider_code {
+            Some(code) => format!("{provider} returned status {status} ({code})"),
+            None => format!("{provider} returned status {status}"),
+        };
+        Self::from_http_response(status, retry_after_secs, provider_code, message)
+    }
+
+    /// Same as [`ProviderError::from_http_status`] with a caller-built message,
+    /// for providers whose error bodies carry more useful text.
+    pub fn from_http_response(
+        status: u16,
+        retry_after_secs: Option<u64>,
+        provider_code: Option<String>,
+        message: String,
+    ) -> Self {
+        match status {
+            401 | 403 => ProviderError::AuthFailed(message),
+            404 | 410 => ProviderError::DestinationNotFound(message),
+            413 => ProviderError::PayloadTooLarge(message),
+            415 => ProviderError::UnsupportedContent(message),
+            429 => ProviderError::RateLimited {
+                message,
+                retry_after_secs,
+            },
+            408 | 500..=599 => ProviderError::Transport(message),
+            _ => ProviderError::ProviderRejected {
+                code: provider_code,
+                message,
+            },
+        }
+    }
+
+    pub fn code(&self) -> ProviderErrorCode {
+        match self {
+            ProviderError::Validation(_) => ProviderErrorCode::InvalidRequest,
+            ProviderError::Transport(_) => ProviderErrorCode::TransientTransport,
+            ProviderError::MissingSecret { .. } => ProviderErrorCode::MissingSecret,
+            ProviderError::AuthFailed(_) => ProviderErrorCode::AuthFailed,
+            ProviderError::ReauthRequired(_) => ProviderErrorCode::ReauthRequired,
+            ProviderError::RateLimited { .. } => ProviderErrorCode::RateLimited,
+            ProviderError::DestinationNotFound(_) => ProviderErrorCode::DestinationNotFound,
+            ProviderError::PayloadTooLarge(_) => ProviderErrorCode::PayloadTooLarge,
+            ProviderError::UnsupportedContent(_) => ProviderErrorCode::UnsupportedContent,
+            ProviderError::ProviderRejected { .. } => ProviderErrorCode::ProviderRejected,
+            ProviderError::ConfigInvalid(_) => ProviderErrorCode::ConfigInvalid,
+            ProviderError::Other(_) => ProviderErrorCode::Unknown,
+        }
+    }
+
+    /// Whether replaying the same request later may succeed.
+    pub fn retryable(&self) -> bool {
+        matches!(
+            self,
+            ProviderError::Transport(_) | ProviderError::RateLimited { .. }
+        )
+    }
+
+    pub fn detail(&self) -> ProviderErrorDetail {
+        let message = match self {
+            ProviderError::Validation(msg)
+            | ProviderError::Transport(msg)
+            | ProviderError::AuthFailed(msg)
+            | ProviderError::ReauthRequired(msg)
+            | ProviderError::DestinationNotFound(msg)
+            | ProviderError::PayloadTooLarge(msg)
+            | ProviderError::UnsupportedContent(msg)
+            | ProviderError::ConfigInvalid(msg)
+            | ProviderError::Other(msg) => msg.clone(),
+            ProviderError::MissingSecret { remediation, .. } => remediation.clone(),
+            ProviderError::RateLimited { message, .. }
+            | ProviderError::ProviderRejected { message, .. } => message.clone(),
+        };
+        let retry_after_secs = match self {
+            ProviderError::RateLimited {
+                retry_after_secs, ..
+            } => *retry_after_secs,
+            _ => None,
+        };
+        let provider_code = match self {
+            ProviderError::ProviderRejected { code, .. } => code.clone(),
+            _ => None,
+        };
+        ProviderErrorDetail {
+            code: self.code(),
+            message,
+            retryable: self.retryable(),
+            retry_after_secs,
+            provider_code,
+        }
+    }
+
+    /// Failure body for JSON operations: `ok`, the display string under
+    /// `error`, and the classified `error_detail`.
+    pub fn to_result_json(&self) -> Value {
+        serde_json::json!({
+            "ok": false,
+            "error": self.to_string(),
+            "error_detail": self.detail(),
+        })
+    }
 }
 
 pub const PROVIDER_CAPABILITIES_VERSION: &str = "v1";
@@ -175,6 +392,96 @@ fn collect_text_blocks(elements: &[Value], parts: &mut Vec<String>) {
     }
 }
 
+/// Button-like action of an Adaptive Card.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AcAction {
+    /// `Action.OpenUrl`.
+    OpenUrl { title: String, url: String },
+    /// `Action.Submit` or `Action.Execute`; `data` is what the card would post
+    /// back (the `verb` of an execute action is added to object data).
+    Submit { title: String, data: Value },
+}
+
+/// Extract the actions of an Adaptive Card stored in message metadata, in
+/// display order: `ActionSet` elements of the body first, then the card's
+/// own `actions`. Actions without a counterpart outside Adaptive Cards
+/// (`Action.ShowCard`, `Action.ToggleVisibility`) are skipped.
+pub fn extract_ac_actions(metadata: &std::collections::BTreeMap<String, String>) -> Vec<AcAction> {
+    let Some(ac) = metadata
+        .get("adaptive_card")
+        .and_then(|json| serde_json::from_str::<Value>(json).ok())
+    else {
+        return Vec::new();
+    };
+    let mut actions = Vec::new();
+    if let Some(body) = ac.get("body").and_then(Value::as_array) {
+        collect_action_sets(body, &mut actions);
+    }
+    if let Some(card_actions) = ac.get("actions").and_then(Value::as_array) {
+        actions.extend(card_actions.iter().filter_map(parse_ac_action));
+    }
+    actions
+}
+
+fn collect_action_sets(elements: &[Value], actions: &mut Vec<AcAction>) {
+    for element in elements {
+        match element.get("type").and_then(Value::as_str) {
+            Some("ActionSet") => {
+                if let Some(items) = element.get("actions").and_then(Value::as_array) {
+                    actions.extend(items.iter().filter_map(parse_ac_action));
+                }
+            }
+            Some("Container") => {
+                if let Some(items) = element.get("items").and_then(Value::as_array) {
+                    collect_action_sets(items, actions);
+                }
+            }
+            Some("ColumnSet") => {
+                for column in element
+                    .get("columns")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                {
+                    if let Some(items) = column.get("items").and_then(Value::as_array) {
+                        collect_action_sets(items, actions);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_ac_action(action: &Value) -> Option<AcAction> {
+    let title = action
+        .get("title")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|title| !title.is_empty())?
+        .to_string();
+    match action.get("type").and_then(Value::as_str)? {
+        "Action.OpenUrl" => {
+            let url = action.get("url").and_then(Value::as_str)?.to_string();
+            Some(AcAction::OpenUrl { title, url })
+        }
+        kind @ ("Action.Submit" | "Action.Execute") => {
+            let mut data = action
+                .get("data")
+                .cloned()
+                .or_else(|| action.get("id").cloned())
+                .unwrap_or_else(|| Value::String(title.clone()));
+            if kind == "Action.Execute"
+                && let (Some(map), Some(verb)) = (data.as_object_mut(), action.get("verb"))
+            {
+                map.insert("verb".into(), verb.clone());
+            }
+            Some(AcAction::Submit { title, data })
+        }
+        _ => None,
+    }
+}
+
 /// Backwards-friendly aliases for V1.
 pub type ProviderCapabilities = ProviderCapabilitiesV1;
 pub type ProviderLimits = ProviderLimitsV1;
@@ -246,6 +553,90 @@ mod tests {
         assert_eq!(err.to_string(), "missing secret: API_KEY (scope: tenant)");
     }
 
+    #[test]
+    fn classifies_http_status() {
+        let limited = ProviderError::from_http_status("slack", 429, Some(30), None);
+        assert_eq!(limited.code(), ProviderErrorCode::RateLimited);
+        assert!(limited.retryable());
+        assert_eq!(limited.detail().retry_after_secs, Some(30));
+
+        let auth = ProviderError::from_http_status("slack", 401, None, None);
+        assert_eq!(auth.code(), ProviderErrorCode::AuthFailed);
+        assert!(!auth.retryable());
+
+        let gateway = ProviderError::from_http_status("slack", 502, None, None);
+        assert_eq!(gateway.code(), ProviderErrorCode::TransientTransport);
+        assert!(gateway.retryable());
+
+        let rejected =
+            ProviderError::from_http_status("telegram", 400, None, Some("bad_request".into()));
+        let detail = rejected.detail();
+        assert_eq!(detail.code, ProviderErrorCode::ProviderRejected);
+        assert_eq!(detail.provider_code.as_deref(), Some("bad_request"));
+        assert_eq!(detail.message, "telegram returned status 400 (bad_request)");
+    }
+
+    #[test]
+    fn result_json_carries_code_and_retryable() {
+        let err = ProviderError::rate_limited("slow down", Some(5));
+        let value = err.to_result_json();
+        assert_eq!(value["ok"], false);
+        assert_eq!(value["error"], "rate limited: slow down");
+        assert_eq!(value["error_detail"]["code"], "rate_limited");
+        assert_eq!(value["error_detail"]["retryable"], true);
+        assert_eq!(value["error_detail"]["retry_after_secs"], 5);
+
+        let detail: ProviderErrorDetail =
+            serde_json::from_value(value["error_detail"].clone()).expect("detail");
+        assert_eq!(detail, err.detail());
+
+        let reauth = ProviderError::reauth_required("invalid_grant").to_result_json();
+        assert_eq!(reauth["error_detail"]["code"], "reauth_required");
+        assert_eq!(reauth["error_detail"]["retryable"], false);
+    }
+
+    #[test]
+    fn extracts_card_actions_in_display_order() {
+        let card = serde_json::json!({
+            "type": "AdaptiveCard",
+            "body": [
+                {"type": "Container", "items": [{"type": "ActionSet", "actions": [
+                    {"type": "Action.OpenUrl", "title": "Docs", "url": "https://example.com"}
+                ]}]}
+            ],
+            "actions": [
+                {"type": "Action.Submit", "title": "Approve", "data": {"id": 1}},
+                {"type": "Action.ShowCard", "title": "More", "card": {}},
+                {"type": "Action.Execute", "title": "Run", "verb": "run", "data": {}},
+                {"type": "Action.Submit", "title": "Plain"}
+            ]
+        });
+        let mut metadata = std::collections::BTreeMap::new();
+        metadata.insert("adaptive_card".to_string(), card.to_string());
+        let actions = extract_ac_actions(&metadata);
+        assert_eq!(
+            actions,
+            vec![
+                AcAction::OpenUrl {
+                    title: "Docs".into(),
+                    url: "https://example.com".into()
+                },
+                AcAction::Submit {
+                    title: "Approve".into(),
+                    data: serde_json::json!({"id": 1})
+                },
+                AcAction::Submit {
+                    title: "Run".into(),
+                    data: serde_json::json!({"verb": "run"})
+                },
+                AcAction::Submit {
+                    title: "Plain".into(),
+                    data: serde_json::json!("Plain")
+                },
+            ]
+        );
+    }
+
     #[test]
     fn capabilities_round_trip() {
         let caps = CapabilitiesResponseV1::new(
@@ -0,0 +1,519 @@
+//! Length-aware splitting of outbound text into several sends.
+//!
+//! Text is split as CommonMark source so each chunk still renders on its own:
+//! chunks end at block boundaries where possible, then at line, sentence and
+//! word boundaries. Inline entities (bold, links, code spans) are never cut;
+//! one that does not fit a chunk on its own is sent as escaped plain text
+//! instead. An oversized code block is split into separately fenced parts. Limits
+//! are checked against the rendered chunk in the channel's dialect, counted in
+//! characters.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+use crate::formatting::{MarkdownDialect, render};
+
+/// Envelope metadata key that turns on `(1/3)` part markers when set to `true`.
+pub const PART_MARKERS_METADATA_KEY: &str = "split_part_markers";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitOptions {
+    /// Maximum rendered length of one chunk, in characters.
+    pub max_len: usize,
+    /// Append a `(i/n)` line to every chunk when the text is split.
+    pub part_markers: bool,
+}
+
+impl SplitOptions {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            max_len,
+            part_markers: false,
+        }
+    }
+
+    /// Reads [`PART_MARKERS_METADATA_KEY`] from envelope metadata.
+    pub fn from_metadata(max_len: usize, metadata: &BTreeMap<String, String>) -> Self {
+        let part_markers = metadata
+            .get(PART_MARKERS_METADATA_KEY)
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("true"));
+        Self {
+            max_len,
+            part_markers,
+        }
+    }
+}
+
+/// Splits `markdown` into CommonMark chunks that each render in `dialect`
+/// within `options.max_len`. Text that already fits is returned as one chunk.
+pub fn split_markdown(
+    markdown: &str,
+    dialect: MarkdownDialect,
+    options: &SplitOptions,
+) -> Vec<String> {
+    let splitter = Splitter {
+        dialect,
+        budget: options.max_len,
+    };
+    if options.max_len == 0 || splitter.fits(markdown) {
+        return vec![markdown.to_string()];
+    }
+    if !options.part_markers {
+        return splitter.split(markdown);
+    }
+
+    let mut reserve = 0;
+    loop {
+        let splitter = Splitter {
+            dialect,
+            budget: options.max_len.saturating_sub(reserve),
+        };
+        if splitter.budget == 0 {
+            return Splitter {
+                dialect,
+                budget: options.max_len,
+            }
+            .split(markdown);
+        }
+        let chunks = splitter.split(markdown);
+        let total = chunks.len();
+        let needed = marker_len(dialect, total);
+        if needed <= reserve {
+            return chunks
+                .into_iter()
+                .enumerate()
+                .map(|(idx, chunk)| format!("{chunk}\n\n{}", part_marker(idx + 1, total)))
+                .collect();
+        }
+        reserve = needed;
+    }
+}
+
+fn part_marker(part: usize, total: usize) -> String {
+    format!("({part}/{total})")
+}
+
+/// Rendered length a marker adds to a chunk when there are `total` parts.
+fn marker_len(dialect: MarkdownDialect, total: usize) -> usize {
+    let base = render("x", dialect).chars().count();
+    let marked = render(&format!("x\n\n{}", part_marker(total, total)), dialect);
+    marked.chars().count().saturating_sub(base)
+}
+
+struct Splitter {
+    dialect: MarkdownDialect,
+    budget: usize,
+}
+
+impl Splitter {
+    fn fits(&self, source: &str) -> bool {
+        render(source, self.dialect).chars().count() <= self.budget
+    }
+
+    /// Packs whole top-level blocks into chunks, splitting blocks that do not
+    /// fit on their own.
+    fn split(&self, markdown: &str) -> Vec<String> {
+        let mut chunks = Vec::new();
+        let mut current = String::new();
+        let mut current_end: Option<usize> = None;
+        for block in top_level_blocks(markdown) {
+            if let Some(end) = current_end {
+                let candidate = format!("{current}{}", &markdown[end..block.end]);
+                if self.fits(&candidate) {
+                    current = candidate;
+                    current_end = Some(block.end);
+                    continue;
+                }
+                chunks.push(std::mem::take(&mut current));
+            }
+            let source = &markdown[block.clone()];
+            current_end = Some(block.end);
+            if self.fits(source) {
+                current = source.to_string();
+                continue;
+            }
+            let mut pieces = self.split_block(source);
+            current = pieces.pop().unwrap_or_default();
+            chunks.extend(pieces);
+        }
+        chunks.push(current);
+        chunks
+            .into_iter()
+            .map(|chunk| chunk.trim().to_string())
+            .filter(|chunk| !chunk.is_empty())
+            .collect()
+    }
+
+    fn split_block(&self, block: &str) -> Vec<String> {
+        if let Some((lang, code)) = fenced_code(block) {
+            return self.split_code(&lang, &code);
+        }
+        let mut pieces = Vec::new();
+        let mut rest = block.trim().to_string();
+        while !rest.is_empty() && !self.fits(&rest) {
+            let cut = match self.best_cut(&rest) {
+                Cut::At(cut) => cut,
+                Cut::Degrade(entity) => {
+                    rest = degrade_entity(&rest, entity);
+                    continue;
+                }
+            };
+            let piece = rest[..cut].trim_end();
+            if !piece.is_empty() {
+                pieces.push(piece.to_string());
+            }
+            rest = rest[cut..].trim_start().to_string();
+        }
+        if !rest.is_empty() {
+            pieces.push(rest);
+        }
+        pieces
+    }
+
+    /// Where to end the next piece of `text`, preferring line, then sentence,
+    /// then word boundaries outside inline entities.
+    fn best_cut(&self, text: &str) -> Cut {
+        let protected = protected_ranges(text);
+        let allowed = |pos: usize| !protected.iter().any(|r| r.start < pos && pos < r.end);
+        let mut best: Option<usize> = None;
+        for boundary in [Boundary::Line, Boundary::Sentence, Boundary::Word] {
+            let candidates: Vec<usize> = boundary_positions(text, boundary)
+                .into_iter()
+                .filter(|&pos| allowed(pos) && !text[..pos].trim().is_empty())
+                .collect();
+            let Some(pos) = self.last_fitting(text, &candidates) else {
+                continue;
+            };
+            if render(&text[..pos], self.dialect).chars().count() * 2 >= self.budget {
+                return Cut::At(pos);
+            }
+            best = best.max(Some(pos));
+        }
+        best.map_or_else(|| self.hard_cut(text, &protected), Cut::At)
+    }
+
+    fn last_fitting(&self, text: &str, candidates: &[usize]) -> Option<usize> {
+        let fitting = candidates.partition_point(|&pos| self.fits(&text[..pos]));
+        fitting.checked_sub(1).map(|idx| candidates[idx])
+    }
+
+    /// Longest prefix that fits, ignoring boundaries but not entities or
+    /// backslash escapes. When the text opens with an entity that is longer
+    /// than the budget, that entity is degraded instead.
+    fn hard_cut(&self, text: &str, protected: &[Range<usize>]) -> Cut {
+        let positions: Vec<usize> = text
+            .char_indices()
+            .map(|(idx, _)| idx)
+            .skip(1)
+            .chain(std::iter::once(text.len()))
+            .filter(|&pos| {
+                !protected.iter().any(|r| r.start < pos && pos < r.end)
+                    && !text[..pos].ends_with('\\')
+                    && !text[..pos].trim().is_empty()
+            })
+            .collect();
+        if let Some(pos) = self.last_fitting(text, &positions) {
+            return Cut::At(pos);
+        }
+        let opening = protected
+            .iter()
+            .filter(|r| r.start == 0)
+            .max_by_key(|r| r.end);
+        match opening {
+            Some(entity) => Cut::Degrade(entity.clone()),
+            None => Cut::At(positions.first().copied().unwrap_or(text.len())),
+        }
+    }
+
+    fn split_code(&self, lang: &str, code: &str) -> Vec<String> {
+        let fence = |body: &str| format!("```{lang}\n{body}\n```");
+        let mut pieces = Vec::new();
+        let mut current = String::new();
+        for line in code.lines() {
+            let candidate = if current.is_empty() {
+                line.to_string()
+            } else {
+                format!("{current}\n{line}")
+            };
+            if self.fits(&fence(&candidate)) {
+                current = candidate;
+                continue;
+            }
+            if !current.is_empty() {
+                pieces.push(fence(&current));
+            }
+            current = line.to_string();
+            while !current.is_empty() && !self.fits(&fence(&current)) {
+                let overhead = self.rendered_len(&fence(""));
+                let room = self.budget.saturating_sub(overhead).max(1);
+                let split_at = current
+                    .char_indices()
+                    .nth(room)
+                    .map_or(current.len(), |(idx, _)| idx);
+                let tail = current.split_off(split_at);
+                pieces.push(fence(&current));
+                current = tail;
+            }
+        }
+        if !current.is_empty() {
+            pieces.push(fence(&current));
+        }
+        pieces
+    }
+
+    fn rendered_len(&self, source: &str) -> usize {
+        render(source, self.dialect).chars().count()
+    }
+}
+
+enum Cut {
+    /// End the piece at this byte offset.
+    At(usize),
+    /// The entity at this source range cannot fit any chunk.
+    Degrade(Range<usize>),
+}
+
+#[derive(Clone, Copy)]
+enum Boundary {
+    Line,
+    Sentence,
+    Word,
+}
+
+fn boundary_positions(text: &str, boundary: Boundary) -> Vec<usize> {
+    let mut positions = Vec::new();
+    let mut prev: Option<char> = None;
+    for (idx, ch) in text.char_indices() {
+        let is_boundary = match boundary {
+            Boundary::Line => ch == '\n',
+            Boundary::Sentence => {
+                ch.is_whitespace() && matches!(prev, Some('.' | '!' | '?' | ':' | ';'))
+            }
+            Boundary::Word => ch.is_whitespace(),
+        };
+        if is_boundary {
+            positions.push(idx);
+        }
+        prev = Some(ch);
+    }
+    positions
+}
+
+fn top_level_blocks(markdown: &str) -> Vec<Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut depth = 0usize;
+    for (event, range) in
+        Parser::new_ext(markdown, Options::ENABLE_STRIKETHROUGH).into_offset_iter()
+    {
+        match event {
+            Event::Start(_) => {
+                if depth == 0 {
+                    blocks.push(range);
+                }
+                depth += 1;
+            }
+            Event::End(_) => depth = depth.saturating_sub(1),
+            _ if depth == 0 => blocks.push(range),
+            _ => {}
+        }
+    }
+    blocks
+}
+
+/// Source ranges that a split must not fall inside.
+fn protected_ranges(text: &str) -> Vec<Range<usize>> {
+    Parser::new_ext(text, Options::ENABLE_STRIKETHROUGH)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(
+                Tag::Emphasis
+                | Tag::Strong
+                | Tag::Strikethrough
+                | Tag::Link { .. }
+                | Tag::Image { .. }
+                | Tag::CodeBlock(_),
+            )
+            | Event::Code(_) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Replaces the entity at `range` with its text, escaped so it renders
+/// literally. Links and images keep their destination in parentheses.
+fn degrade_entity(text: &str, range: Range<usize>) -> String {
+    let mut plain = String::new();
+    let mut destinations = Vec::new();
+    for event in Parser::new_ext(&text[range.clone()], Options::ENABLE_STRIKETHROUGH) {
+        match event {
+            Event::Text(value) | Event::Code(value) => plain.push_str(&value),
+            Event::SoftBreak | Event::HardBreak => plain.push(' '),
+            Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. }) => {
+                destinations.push(dest_url)
+            }
+            Event::End(TagEnd::Link | TagEnd::Image) => {
+                if let Some(url) = destinations.pop() {
+                    plain.push_str(&format!(" ({url})"));
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut escaped = String::with_capacity(plain.len());
+    for ch in plain.chars() {
+        if ch.is_ascii_punctuation() {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    format!("{}{escaped}{}", &text[..range.start], &text[range.end..])
+}
+
+/// Language and body of a block that consists of one code block.
+fn fenced_code(block: &str) -> Option<(String, String)> {
+    let mut events = Parser::new_ext(block, Options::ENABLE_STRIKETHROUGH);
+    let lang = match events.next()? {
+        Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+            info.split_whitespace().next().unwrap_or("").to_string()
+        }
+        Event::Start(Tag::CodeBlock(CodeBlockKind::Indented)) => String::new(),
+        _ => return None,
+    };
+    let mut code = String::new();
+    for event in events {
+        if let Event::Text(text) = event {
+            code.push_str(&text);
+        }
+    }
+    Some((lang, code.trim_end_matches('\n').to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rendered_lengths(chunks: &[String], dialect: MarkdownDialect) -> Vec<usize> {
+        chunks
+            .iter()
+            .map(|chunk| render(chunk, dialect).chars().count())
+            .collect()
+    }
+
+    #[test]
+    fn short_text_is_one_chunk() {
+        let chunks = split_markdown(
+            "hello",
+            MarkdownDialect::SlackMrkdwn,
+            &SplitOptions::new(40_000),
+        );
+        assert_eq!(chunks, vec!["hello".to_string()]);
+    }
+
+    #[test]
+    fn splits_at_paragraphs_then_sentences() {
+        let text = "First paragraph here.\n\nSecond one is a bit longer. It has two sentences.";
+        let chunks = split_markdown(text, MarkdownDialect::PlainText, &SplitOptions::new(30));
+        assert_eq!(
+            chunks,
+            vec![
+                "First paragraph here.",
+                "Second one is a bit longer.",
+                "It has two sentences."
+            ]
+        );
+    }
+
+    #[test]
+    fn never_cuts_inside_entities() {
+        let text = "aa **bold words here** and [a link label](https://example.com) end";
+        let chunks = split_markdown(
+            text,
+            MarkdownDialect::TelegramMarkdownV2,
+            &SplitOptions::new(24),
+        );
+        for chunk in &chunks {
+            assert_eq!(chunk.matches("**").count() % 2, 0, "{chunk}");
+            assert_eq!(chunk.contains('['), chunk.contains("]("), "{chunk}");
+        }
+        assert!(
+            rendered_lengths(&chunks, MarkdownDialect::TelegramMarkdownV2)
+                .iter()
+                .all(|&len| len <= 24)
+        );
+    }
+
+    #[test]
+    fn oversized_entities_become_plain_text() {
+        let text = "**one two three four five six seven eight nine ten**";
+        let chunks = split_markdown(
+            text,
+            MarkdownDialect::TelegramMarkdownV2,
+            &SplitOptions::new(20),
+        );
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(!chunk.contains("**"), "{chunk}");
+        }
+        assert!(
+            rendered_lengths(&chunks, MarkdownDialect::TelegramMarkdownV2)
+                .iter()
+                .all(|&len| len <= 20)
+        );
+        let words: Vec<String> = chunks
+            .iter()
+            .flat_map(|chunk| {
+                render(chunk, MarkdownDialect::PlainText)
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        assert_eq!(
+            words.join(" "),
+            "one two three four five six seven eight nine ten"
+        );
+    }
+
+    #[test]
+    fn code_blocks_are_refenced() {
+        let code: Vec<String> = (0..20).map(|n| format!("let v{n} = {n};")).collect();
+        let text = format!("Intro.\n\n```rust\n{}\n```", code.join("\n"));
+        let chunks = split_markdown(&text, MarkdownDialect::SlackMrkdwn, &SplitOptions::new(120));
+        assert!(chunks.len() > 2);
+        for chunk in &chunks[1..] {
+            assert!(
+                chunk.starts_with("```rust\n") && chunk.ends_with("```"),
+                "{chunk}"
+            );
+        }
+        assert!(
+            rendered_lengths(&chunks, MarkdownDialect::SlackMrkdwn)
+                .iter()
+                .all(|&len| len <= 120)
+        );
+    }
+
+    #[test]
+    fn part_markers_fit_within_limit() {
+        let text = "one two three four five six seven eight nine ten eleven twelve";
+        let options = SplitOptions {
+            max_len: 25,
+            part_markers: true,
+        };
+        let chunks = split_markdown(text, MarkdownDialect::PlainText, &options);
+        let total = chunks.len();
+        assert!(total > 1);
+        for (idx, chunk) in chunks.iter().enumerate() {
+            assert!(chunk.ends_with(&format!("({}/{total})", idx + 1)));
+        }
+        assert!(
+            rendered_lengths(&chunks, MarkdownDialect::PlainText)
+                .iter()
+                .all(|&len| len <= 25)
+        );
+        let metadata = BTreeMap::from([(PART_MARKERS_METADATA_KEY.to_string(), "true".into())]);
+        assert!(SplitOptions::from_metadata(10, &metadata).part_markers);
+    }
+}
@@ -0,0 +1,242 @@
+//! Idempotent outbound sends.
+//!
+//! Callers that retry `send` or `send_payload` after a timeout pass an
+//! `idempotency_key` in the envelope metadata or in the `SendPayloadInV1`
+//! payload metadata. The first successful result is recorded in the state
+//! store under the tenant's messaging namespace; later calls with the same key
+//! get that result back without the provider API being called again.
+//!
+//! A split send that fails after delivering some parts is recorded too. Its
+//! replay reports the failure as not retryable, together with the ids of the
+//! parts that went out, instead of posting those parts a second time.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::lifecycle_keys::messaging_state_key;
+use crate::state::StateStore;
+
+pub const IDEMPOTENCY_KEY: &str = "idempotency_key";
+/// Flag set on a result returned from the state store instead of a new send.
+pub const REPLAY_FLAG: &str = "idempotent_replay";
+/// Flag set on a replayed failure whose earlier attempt delivered some parts.
+pub const PARTIAL_DELIVERY_FLAG: &str = "partial_delivery";
+const DEFAULT_TENANT: &str = "default";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SentRecord {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_message_id: Option<String>,
+    pub result: Value,
+    /// The send failed after delivering the parts in `provider_message_ids`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+}
+
+/// Idempotency key of a send request and the tenant scope it is recorded in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendIdempotency {
+    pub key: String,
+    pub tenant_id: String,
+    pub team_id: Option<String>,
+}
+
+impl SendIdempotency {
+    /// Reads the key from envelope `metadata` or `payload.metadata`, and the
+    /// scope from the envelope tenant or `tenant_id`.
+    pub fn from_input(input: &Value) -> Option<Self> {
+        let key = input
+            .get("metadata")
+            .or_else(|| input.pointer("/payload/metadata"))
+            .and_then(|metadata| metadata.get(IDEMPOTENCY_KEY))
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|key| !key.is_empty())?;
+        let tenant = input.get("tenant");
+        let tenant_id = tenant
+            .and_then(|t| t.get("tenant_id").or_else(|| t.get("tenant")))
+            .or_else(|| input.get("tenant_id"))
+            .and_then(Value::as_str)
+            .filter(|id| !id.trim().is_empty())
+            .unwrap_or(DEFAULT_TENANT);
+        let team_id = tenant
+            .and_then(|t| t.get("team_id").or_else(|| t.get("team")))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        Some(Self {
+            key: key.to_string(),
+            tenant_id: tenant_id.to_string(),
+            team_id,
+        })
+    }
+
+    pub fn state_key(&self, provider_id: &str) -> String {
+        let digest = Sha256::digest(self.key.as_bytes());
+        let hex: String = digest.iter().map(|byte| format!("{byte:02x}")).collect();
+        messaging_state_key(
+            provider_id,
+            &self.tenant_id,
+            self.team_id.as_deref(),
+            &format!("sent:{hex}"),
+        )
+    }
+}
+
+/// Runs `send` unless a result for the input's idempotency key was recorded,
+/// and records the output when it reports `ok: true` or a partial delivery.
+/// Inputs without a key are passed straight through. State store failures
+/// never block a send.
+pub fn send_idempotent(
+    provider_id: &str,
+    store: &mut impl StateStore,
+    input_json: &[u8],
+    send: impl FnOnce(&[u8]) -> Vec<u8>,
+) -> Vec<u8> {
+    let idempotency = serde_json::from_slice::<Value>(input_json)
+        .ok()
+        .and_then(|input| SendIdempotency::from_input(&input));
+    let Some(idempotency) = idempotency else {
+        return send(input_json);
+    };
+    let state_key = idempotency.state_key(provider_id);
+    if let Some(record) = store
+        .read(&state_key)
+        .ok()
+        .flatten()
+        .and_then(|bytes| serde_json::from_slice::<SentRecord>(&bytes).ok())
+    {
+        return serde_json::to_vec(&replay_result(record)).unwrap_or_default();
+    }
+
+    let output = send(input_json);
+    let Ok(result) = serde_json::from_slice::<Value>(&output) else {
+        return output;
+    };
+    let ok = result.get("ok").and_then(Value::as_bool) == Some(true);
+    let partial = !ok
+        && result
+            .get("provider_message_ids")
+            .and_then(Value::as_array)
+            .is_some_and(|ids| !ids.is_empty());
+    if ok || partial {
+        let record = SentRecord {
+            provider_message_id: result
+                .get("provider_message_id")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            result,
+            partial,
+        };
+        if let Ok(bytes) = serde_json::to_vec(&record) {
+            let _ = store.write(&state_key, &bytes);
+        }
+    }
+    output
+}
+
+fn replay_result(record: SentRecord) -> Value {
+    let mut result = record.result;
+    if let Some(map) = result.as_object_mut() {
+        map.insert(REPLAY_FLAG.into(), Value::Bool(true));
+        if let Some(id) = record.provider_message_id {
+            map.entry("provider_message_id")
+                .or_insert(Value::String(id));
+        }
+        if record.partial {
+            map.insert(PARTIAL_DELIVERY_FLAG.into(), Value::Bool(true));
+            if let Some(detail) = map.get_mut("error_detail").and_then(Value::as_object_mut) {
+                detail.insert("retryable".into(), Value::Bool(false));
+                detail.remove("retry_after_secs");
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryStore;
+    use serde_json::json;
+    use std::cell::Cell;
+
+    fn envelope_input(key: &str) -> Vec<u8> {
+        serde_json::to_vec(&json!({
+            "tenant": {"tenant_id": "acme", "team_id": "ops"},
+            "metadata": {"idempotency_key": key},
+            "text": "hi",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn replays_recorded_success() {
+        let mut store = MemoryStore::default();
+        let calls = Cell::new(0);
+        let send = |_: &[u8]| {
+            calls.set(calls.get() + 1);
+            serde_json::to_vec(&json!({"ok": true, "provider_message_id": "slack:1"})).unwrap()
+        };
+        let first = send_idempotent("slack", &mut store, &envelope_input("k1"), send);
+        let second = send_idempotent("slack", &mut store, &envelope_input("k1"), send);
+        assert_eq!(calls.get(), 1);
+        let first: Value = serde_json::from_slice(&first).unwrap();
+        let second: Value = serde_json::from_slice(&second).unwrap();
+        assert_eq!(first.get(REPLAY_FLAG), None);
+        assert_eq!(second[REPLAY_FLAG], json!(true));
+        assert_eq!(second["provider_message_id"], json!("slack:1"));
+        assert!(store.entries.keys().all(|key| {
+            key.starts_with("providers:messaging:slack:tenants:acme:teams:ops:state:sent:")
+        }));
+    }
+
+    #[test]
+    fn failures_and_keyless_inputs_are_not_recorded() {
+        let mut store = MemoryStore::default();
+        let failed = |_: &[u8]| serde_json::to_vec(&json!({"ok": false})).unwrap();
+        send_idempotent("slack", &mut store, &envelope_input("k1"), failed);
+        assert!(store.entries.is_empty());
+        let ok = |_: &[u8]| serde_json::to_vec(&json!({"ok": true})).unwrap();
+        send_idempotent("slack", &mut store, br#"{"text":"hi"}"#, ok);
+        assert!(store.entries.is_empty());
+    }
+
+    #[test]
+    fn partial_failures_replay_without_resending() {
+        let mut store = MemoryStore::default();
+        let calls = Cell::new(0);
+        let send = |_: &[u8]| {
+            calls.set(calls.get() + 1);
+            serde_json::to_vec(&json!({
+                "ok": false,
+                "provider_message_ids": ["slack:1"],
+                "error_detail": {"code": "transient_transport", "retryable": true},
+            }))
+            .unwrap()
+        };
+        let first = send_idempotent("slack", &mut store, &envelope_input("k1"), send);
+        let second = send_idempotent("slack", &mut store, &envelope_input("k1"), send);
+        assert_eq!(calls.get(), 1);
+        let first: Value = serde_json::from_slice(&first).unwrap();
+        let second: Value = serde_json::from_slice(&second).unwrap();
+        assert_eq!(first["error_detail"]["retryable"], json!(true));
+        assert_eq!(second["ok"], json!(false));
+        assert_eq!(second[PARTIAL_DELIVERY_FLAG], json!(true));
+        assert_eq!(second["error_detail"]["retryable"], json!(false));
+        assert_eq!(second["provider_message_ids"], json!(["slack:1"]));
+    }
+
+    #[test]
+    fn reads_send_payload_metadata() {
+        let input = json!({
+            "provider_type": "messaging.slack.api",
+            "tenant_id": "acme",
+            "payload": {"content_type": "application/json", "metadata": {"idempotency_key": "p-1"}},
+        });
+        let idempotency = SendIdempotency::from_input(&input).unwrap();
+        assert_eq!(idempotency.key, "p-1");
+        assert_eq!(idempotency.tenant_id, "acme");
+        assert_eq!(idempotency.team_id, None);
+    }
+}
@@ -0,0 +1,192 @@
+//! Namespaced key/value state shared by the stateful helpers.
+//!
+//! Ingress dedup, outbound idempotency, token caches and provider-specific
+//! records all persist through the host `state-store` import. They take a
+//! [`StateStore`] so the logic stays testable outside a component.
+
+use serde_json::Value;
+
+/// Namespaced state access.
+pub trait StateStore {
+    fn read(&mut self, key: &str) -> Result<Option<Vec<u8>>, String>;
+    fn write(&mut self, key: &str, value: &[u8]) -> Result<(), String>;
+    fn delete(&mut self, key: &str) -> Result<(), String>;
+}
+
+/// [`StateStore`] over a component's host `state-store` import. Bindings are
+/// generated per component, so the adapter takes the host calls with their
+/// error already reduced to its message.
+#[derive(Clone, Copy)]
+pub struct HostStateAdapter {
+    read: fn(&str) -> Result<Vec<u8>, String>,
+    write: fn(&str, &[u8]) -> Result<(), String>,
+    delete: fn(&str) -> Result<(), String>,
+}
+
+impl HostStateAdapter {
+    pub fn new(
+        read: fn(&str) -> Result<Vec<u8>, String>,
+        write: fn(&str, &[u8]) -> Result<(), String>,
+        delete: fn(&str) -> Result<(), String>,
+    ) -> Self {
+        Self {
+            read,
+            write,
+            delete,
+        }
+    }
+}
+
+impl StateStore for HostStateAdapter {
+    fn read(&mut self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        (self.read)(key)
+            .map(Some)
+            .map_err(|err| format!("state read error: {err}"))
+    }
+
+    fn write(&mut self, key: &str, value: &[u8]) -> Result<(), String> {
+        (self.write)(key, value).map_err(|err| format!("state write error: {err}"))
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), String> {
+        (self.delete)(key).map_err(|err| format!("state delete error: {err}"))
+    }
+}
+
+/// Bucketed index of the keys written under a namespace, so records that
+/// outlived their TTL can be deleted without listing the store.
+///
+/// A key is tracked in the bucket of the time it was written. Buckets older
+/// than the previous one are purged by the next [`Self::track`] call, so a
+/// record lives between one and two `bucket_secs` after its last write. Keys
+/// written again since are tracked in a live bucket and survive the purge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpiryIndex<'a> {
+    pub namespace: &'a str,
+    pub bucket_secs: u64,
+}
+
+impl ExpiryIndex<'_> {
+    /// Tracks `key` as written at `now` and purges expired buckets.
+    pub fn track(&self, store: &mut impl StateStore, key: &str, now: u64) -> Result<(), String> {
+        let current = now / self.bucket_secs.max(1);
+        let buckets = read_json::<Vec<u64>>(store, &self.buckets_key());
+        let (expired, mut live): (Vec<u64>, Vec<u64>) = buckets
+            .into_iter()
+            .partition(|bucket| bucket.saturating_add(1) < current);
+        if !live.contains(&current) {
+            live.push(current);
+        }
+        let mut keys = read_json::<Vec<String>>(store, &self.bucket_key(current));
+        if !keys.iter().any(|tracked| tracked == key) {
+            keys.push(key.to_string());
+            write_json(store, &self.bucket_key(current), &keys)?;
+        }
+        if !expired.is_empty() {
+            let mut keep = Vec::new();
+            for bucket in &live {
+                keep.extend(read_json::<Vec<String>>(store, &self.bucket_key(*bucket)));
+            }
+            for bucket in expired {
+                if self.purge(store, bucket, &keep).is_err() {
+                    live.push(bucket);
+                }
+            }
+        }
+        live.sort_unstable();
+        write_json(store, &self.buckets_key(), &live)
+    }
+
+    fn purge(
+        &self,
+        store: &mut impl StateStore,
+        bucket: u64,
+        keep: &[String],
+    ) -> Result<(), String> {
+        let bucket_key = self.bucket_key(bucket);
+        for key in read_json::<Vec<String>>(store, &bucket_key) {
+            if !keep.contains(&key) {
+                store.delete(&key)?;
+            }
+        }
+        store.delete(&bucket_key)
+    }
+
+    fn buckets_key(&self) -> String {
+        format!("{}.expiry", self.namespace)
+    }
+
+    fn bucket_key(&self, bucket: u64) -> String {
+        format!("{}.expiry.{bucket}", self.namespace)
+    }
+}
+
+/// Index records that cannot be read count as empty: hosts report missing
+/// keys as read errors.
+fn read_json<T: serde::de::DeserializeOwned + Default>(
+    store: &mut impl StateStore,
+    key: &str,
+) -> T {
+    store
+        .read(key)
+        .ok()
+        .flatten()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn write_json<T: serde::Serialize>(
+    store: &mut impl StateStore,
+    key: &str,
+    value: &T,
+) -> Result<(), String> {
+    let bytes = serde_json::to_vec(value).map_err(|err| err.to_string())?;
+    store.write(key, &bytes)
+}
+
+/// Reads a string or integer field as a key fragment.
+pub fn key_fragment(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryStore;
+    use serde_json::json;
+
+    #[test]
+    fn expiry_index_purges_buckets_older_than_the_previous_one() {
+        let mut store = MemoryStore::default();
+        let index = ExpiryIndex {
+            namespace: "ns",
+            bucket_secs: 100,
+        };
+        let put = |store: &mut MemoryStore, key: &str, now: u64| {
+            store.write(key, b"1").unwrap();
+            index.track(store, key, now).unwrap();
+        };
+        put(&mut store, "ns.a", 0);
+        put(&mut store, "ns.b", 150);
+        put(&mut store, "ns.a", 160);
+        put(&mut store, "ns.c", 250);
+        assert!(store.entries.contains_key("ns.a"), "rewritten key survives");
+        assert!(!store.entries.contains_key("ns.expiry.0"));
+
+        put(&mut store, "ns.d", 450);
+        let keys: Vec<&str> = store.entries.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["ns.d", "ns.expiry", "ns.expiry.4"]);
+    }
+
+    #[test]
+    fn key_fragment_accepts_strings_and_numbers() {
+        assert_eq!(key_fragment(Some(&json!(42))), Some("42".to_string()));
+        assert_eq!(key_fragment(Some(&json!(" Ev1 "))), Some("Ev1".to_string()));
+        assert_eq!(key_fragment(Some(&json!(""))), None);
+        assert_eq!(key_fragment(Some(&json!(true))), None);
+    }
+}
@@ -0,0 +1,42 @@
+//! Test doubles for the state-backed helpers, shared with provider crates
+//! through the `test-util` feature.
+
+use std::collections::BTreeMap;
+
+use crate::state::StateStore;
+
+/// In-memory [`StateStore`]. With `fail` set every call errors, like an
+/// unavailable host store.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    pub entries: BTreeMap<String, Vec<u8>>,
+    pub fail: bool,
+}
+
+impl MemoryStore {
+    fn check(&self) -> Result<(), String> {
+        if self.fail {
+            return Err("unavailable".into());
+        }
+        Ok(())
+    }
+}
+
+impl StateStore for MemoryStore {
+    fn read(&mut self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        self.check()?;
+        Ok(self.entries.get(key).cloned())
+    }
+
+    fn write(&mut self, key: &str, value: &[u8]) -> Result<(), String> {
+        self.check()?;
+        self.entries.insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), String> {
+        self.check()?;
+        self.entries.remove(key);
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+//! State-backed cache for OAuth access tokens.
+//!
+//! Token exchanges are slow and throttled by the identity provider, so an
+//! access token is reused until shortly before it expires. Entries are keyed
+//! by tenant, client id and scope, plus the user for delegated tokens. A
+//! provider that gets a 401 with a cached token invalidates the entry so the
+//! next call performs a fresh exchange. State store failures only cost a
+//! token exchange.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::state::StateStore;
+
+/// Tokens are treated as expired this long before `expires_in` runs out.
+pub const DEFAULT_EXPIRY_MARGIN_SECS: u64 = 300;
+pub const TOKEN_CACHE_PREFIX: &str = "messaging.oauth.token";
+
+/// Identity a cached token was issued for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenCacheKey {
+    pub tenant: String,
+    pub client_id: String,
+    pub scope: String,
+    /// User or refresh-token reference for delegated tokens.
+    pub subject: Option<String>,
+}
+
+impl TokenCacheKey {
+    pub fn new(
+        tenant: impl Into<String>,
+        client_id: impl Into<String>,
+        scope: impl Into<String>,
+    ) -> Self {
+        Self {
+            tenant: tenant.into(),
+            client_id: client_id.into(),
+            scope: scope.into(),
+            subject: None,
+        }
+    }
+
+    pub fn with_subject(mut self, subject: impl Into<String>) -> Self {
+        self.subject = Some(subject.into());
+        self
+    }
+
+    /// State key for the entry; the parts are hashed so scopes and ids with
+    /// arbitrary characters stay within the key charset.
+    pub fn state_key(&self) -> String {
+        let mut hasher = Sha256::new();
+        for part in [
+            self.tenant.as_str(),
+            self.client_id.as_str(),
+            self.scope.as_str(),
+            self.subject.as_deref().unwrap_or_default(),
+        ] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        let hex: String = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        format!("{TOKEN_CACHE_PREFIX}.{hex}")
+    }
+}
+
+/// Access token returned by a token endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessToken {
+    pub access_token: String,
+    pub expires_in: Option<u64>,
+}
+
+impl AccessToken {
+    /// Reads `access_token` and `expires_in` (number or numeric string) from
+    /// an OAuth token response.
+    pub fn from_response(body: &Value) -> Option<Self> {
+        let access_token = body.get("access_token")?.as_str()?.to_string();
+        let expires_in = match body.get("expires_in") {
+            Some(Value::Number(n)) => n.as_u64(),
+            Some(Value::String(s)) => s.trim().parse().ok(),
+            _ => None,
+        };
+        Some(Self {
+            access_token,
+            expires_in,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedToken {
+    access_token: String,
+    expires_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenCache {
+    pub margin_secs: u64,
+}
+
+impl Default for TokenCache {
+    fn default() -> Self {
+        Self {
+            margin_secs: DEFAULT_EXPIRY_MARGIN_SECS,
+        }
+    }
+}
+
+impl TokenCache {
+    pub fn new(margin_secs: u64) -> Self {
+        Self { margin_secs }
+    }
+
+    /// Cached token for `key` that is still valid at `now` plus the margin.
+    pub fn cached(
+        &self,
+        store: &mut impl StateStore,
+        key: &TokenCacheKey,
+        now: u64,
+    ) -> Option<String> {
+        store
+            .read(&key.state_key())
+            .ok()
+            .flatten()
+            .and_then(|bytes| serde_json::from_slice::<CachedToken>(&bytes).ok())
+            .filter(|entry| now.saturating_add(self.margin_secs) < entry.expires_at)
+            .map(|entry| entry.access_token)
+    }
+
+    /// Stores `token` for `key`. Tokens without `expires_in` are not cached.
+    pub fn store(
+        &self,
+        store: &mut impl StateStore,
+        key: &TokenCacheKey,
+        token: &AccessToken,
+        now: u64,
+    ) {
+        let Some(expires_in) = token.expires_in else {
+            return;
+        };
+        let entry = CachedToken {
+            access_token: token.access_token.clone(),
+            expires_at: now.saturating_add(expires_in),
+        };
+        if let Ok(bytes) = serde_json::to_vec(&entry) {
+            let _ = store.write(&key.state_key(), &bytes);
+        }
+    }
+
+    /// Drops the entry for `key`, e.g. after the API rejected it with a 401.
+    pub fn invalidate(&self, store: &mut impl StateStore, key: &TokenCacheKey) {
+        let _ = store.delete(&key.state_key());
+    }
+
+    /// Returns the cached token for `key`, or runs `fetch` and caches its
+    /// result.
+    pub fn get_or_fetch<E>(
+        &self,
+        store: &mut impl StateStore,
+        key: &TokenCacheKey,
+        now: u64,
+        fetch: impl FnOnce() -> Result<AccessToken, E>,
+    ) -> Result<String, E> {
+        if let Some(token) = self.cached(store, key, now) {
+            return Ok(token);
+        }
+        let token = fetch()?;
+        self.store(store, key, &token, now);
+        Ok(token.access_token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MemoryStore;
+    use serde_json::json;
+
+    fn grant(token: &str) -> Result<AccessToken, String> {
+        Ok(
+            AccessToken::from_response(&json!({"access_token": token, "expires_in": 3600}))
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn reuses_token_until_margin() {
+        let mut store = MemoryStore::default();
+        let cache = TokenCache::default();
+        let key = TokenCacheKey::new("t1", "client", "scope");
+        assert_eq!(
+            cache.get_or_fetch(&mut store, &key, 1_000, || grant("a")),
+            Ok("a".to_string())
+        );
+        assert_eq!(
+            cache.get_or_fetch(&mut store, &key, 4_299, || grant("b")),
+            Ok("a".to_string())
+        );
+        assert_eq!(
+            cache.get_or_fetch(&mut store, &key, 4_300, || grant("c")),
+            Ok("c".to_string())
+        );
+    }
+
+    #[test]
+    fn invalidate_forces_exchange() {
+        let mut store = MemoryStore::default();
+        let cache = TokenCache::default();
+        let key = TokenCacheKey::new("t1", "client", "scope");
+        cache
+            .get_or_fetch(&mut store, &key, 0, || grant("a"))
+            .unwrap();
+        cache.invalidate(&mut store, &key);
+        assert_eq!(
+            cache.get_or_fetch(&mut store, &key, 1, || grant("b")),
+            Ok("b".to_string())
+        );
+    }
+
+    #[test]
+    fn keys_separate_subjects_and_skip_unknown_expiry() {
+        let base = TokenCacheKey::new("t1", "client", "scope");
+        assert_ne!(
+            base.state_key(),
+            base.clone().with_subject("user-1").state_key()
+        );
+        let mut store = MemoryStore::default();
+        let token = AccessToken::from_response(&json!({"access_token": "x"})).unwrap();
+        TokenCache::default().store(&mut store, &base, &token, 0);
+        assert!(store.entries.is_empty());
+        let token =
+            AccessToken::from_response(&json!({"access_token": "x", "expires_in": "60"})).unwrap();
+        assert_eq!(token.expires_in, Some(60));
+    }
+}
@@ -0,0 +1,421 @@
+//! Webhook authenticity checks shared by ingress components and providers.
+//!
+//! Every check takes raw header values rather than a header collection, since
+//! ingress components and `ingest_http` carry headers in different shapes.
+//! Comparisons against secrets are constant-time.
+
+use crate::ProviderError;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use greentic_types::messaging::universal_dto::HttpInV1;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+pub const DEFAULT_MAX_SKEW_SECS: u64 = 300;
+
+pub const SLACK_SIGNATURE_HEADER: &str = "x-slack-signature";
+pub const SLACK_TIMESTAMP_HEADER: &str = "x-slack-request-timestamp";
+pub const TELEGRAM_SECRET_TOKEN_HEADER: &str = "x-telegram-bot-api-secret-token";
+pub const META_SIGNATURE_HEADER: &str = "x-hub-signature-256";
+pub const WEBEX_SIGNATURE_HEADER: &str = "x-spark-signature";
+pub const WEBEX_SIGNATURE_HEADER_ALT: &str = "x-webex-signature";
+pub const TEAMS_AUTHORIZATION_HEADER: &str = "authorization";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HmacAlgorithm {
+    Sha1,
+    Sha256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureEncoding {
+    Hex,
+    Base64,
+}
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum WebhookAuthError {
+    #[error("missing {0} header")]
+    MissingHeader(&'static str),
+    #[error("malformed signature")]
+    MalformedSignature,
+    #[error("signature mismatch")]
+    SignatureMismatch,
+    #[error("invalid timestamp")]
+    InvalidTimestamp,
+    #[error("timestamp outside allowed window")]
+    StaleTimestamp,
+    #[error("token mismatch")]
+    TokenMismatch,
+    #[error("invalid secret")]
+    InvalidSecret,
+}
+
+impl From<WebhookAuthError> for ProviderError {
+    fn from(err: WebhookAuthError) -> Self {
+        ProviderError::auth_failed(err.to_string())
+    }
+}
+
+/// Accepted clock skew between the sender's timestamp and local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimestampWindow {
+    pub max_skew_secs: u64,
+}
+
+impl Default for TimestampWindow {
+    fn default() -> Self {
+        Self {
+            max_skew_secs: DEFAULT_MAX_SKEW_SECS,
+        }
+    }
+}
+
+impl TimestampWindow {
+    pub fn new(max_skew_secs: u64) -> Self {
+        Self { max_skew_secs }
+    }
+
+    /// Checks a unix-seconds timestamp in either direction from `now`.
+    pub fn check(&self, timestamp: &str, now: u64) -> Result<(), WebhookAuthError> {
+        let ts = timestamp
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| WebhookAuthError::InvalidTimestamp)?;
+        if ts.abs_diff(now) > self.max_skew_secs {
+            return Err(WebhookAuthError::StaleTimestamp);
+        }
+        Ok(())
+    }
+}
+
+/// Case-insensitive header lookup on an `ingest_http` request.
+pub fn request_header<'a>(request: &'a HttpInV1, name: &str) -> Option<&'a str> {
+    request
+        .headers
+        .iter()
+        .find(|header| header.name.eq_ignore_ascii_case(name))
+        .map(|header| header.value.as_str())
+}
+
+pub fn now_unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn sign(
+    algorithm: HmacAlgorithm,
+    secret: &[u8],
+    message: &[u8],
+    encoding: SignatureEncoding,
+) -> String {
+    let digest = match algorithm {
+        HmacAlgorithm::Sha1 => {
+            let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("hmac accepts any key size");
+            mac.update(message);
+            mac.finalize().into_bytes().to_vec()
+        }
+        HmacAlgorithm::Sha256 => {
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(secret).expect("hmac accepts any key size");
+            mac.update(message);
+            mac.finalize().into_bytes().to_vec()
+        }
+    };
+    match encoding {
+        SignatureEncoding::Hex => hex_encode(&digest),
+        SignatureEncoding::Base64 => STANDARD.encode(digest),
+    }
+}
+
+/// Verifies an encoded HMAC of `message` in constant time.
+pub fn verify_signature(
+    algorithm: HmacAlgorithm,
+    secret: &[u8],
+    message: &[u8],
+    signature: &str,
+    encoding: SignatureEncoding,
+) -> Result<(), WebhookAuthError> {
+    let expected = match encoding {
+        SignatureEncoding::Hex => hex_decode(signature.trim()),
+        SignatureEncoding::Base64 => STANDARD.decode(signature.trim()).ok(),
+    }
+    .ok_or(WebhookAuthError::MalformedSignature)?;
+    let verified = match algorithm {
+        HmacAlgorithm::Sha1 => {
+            let mut mac = Hmac::<Sha1>::new_from_slice(secret)
+                .map_err(|_| WebhookAuthError::InvalidSecret)?;
+            mac.update(message);
+            mac.verify_slice(&expected)
+        }
+        HmacAlgorithm::Sha256 => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret)
+                .map_err(|_| WebhookAuthError::InvalidSecret)?;
+            mac.update(message);
+            mac.verify_slice(&expected)
+        }
+    };
+    verified.map_err(|_| WebhookAuthError::SignatureMismatch)
+}
+
+/// Compares a shared-secret token carried in a header.
+pub fn verify_shared_token(
+    expected: &str,
+    provided: Option<&str>,
+    header: &'static str,
+) -> Result<(), WebhookAuthError> {
+    let provided = provided.ok_or(WebhookAuthError::MissingHeader(header))?;
+    if constant_time_eq(expected.as_bytes(), provided.trim().as_bytes()) {
+        Ok(())
+    } else {
+        Err(WebhookAuthError::TokenMismatch)
+    }
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Slack signs `v0:{timestamp}:{body}` and sends `v0=<hex>`.
+pub fn verify_slack(
+    signing_secret: &[u8],
+    signature: Option<&str>,
+    timestamp: Option<&str>,
+    body: &[u8],
+    window: &TimestampWindow,
+    now: u64,
+) -> Result<(), WebhookAuthError> {
+    let signature = signature.ok_or(WebhookAuthError::MissingHeader(SLACK_SIGNATURE_HEADER))?;
+    let timestamp = timestamp.ok_or(WebhookAuthError::MissingHeader(SLACK_TIMESTAMP_HEADER))?;
+    window.check(timestamp, now)?;
+    let hex = signature
+        .trim()
+        .strip_prefix("v0=")
+        .ok_or(WebhookAuthError::MalformedSignature)?;
+    let mut basestring = format!("v0:{}:", timestamp.trim()).into_bytes();
+    basestring.extend_from_slice(body);
+    verify_signature(
+        HmacAlgorithm::Sha256,
+        signing_secret,
+        &basestring,
+        hex,
+        SignatureEncoding::Hex,
+    )
+}
+
+/// Meta (WhatsApp Cloud API) sends `X-Hub-Signature-256: sha256=<hex>` keyed
+/// with the app secret.
+pub fn verify_meta(
+    app_secret: &[u8],
+    signature: Option<&str>,
+    body: &[u8],
+) -> Result<(), WebhookAuthError> {
+    let signature = signature.ok_or(WebhookAuthError::MissingHeader(META_SIGNATURE_HEADER))?;
+    let hex = signature
+        .trim()
+        .strip_prefix("sha256=")
+        .ok_or(WebhookAuthError::MalformedSignature)?;
+    verify_signature(
+        HmacAlgorithm::Sha256,
+        app_secret,
+        body,
+        hex,
+        SignatureEncoding::Hex,
+    )
+}
+
+/// Webex sends a hex HMAC-SHA1 in `X-Spark-Signature`. A `SHA-256=<hex>`
+/// segment, when present, is checked instead.
+pub fn verify_webex(
+    secret: &[u8],
+    signature: Option<&str>,
+    body: &[u8],
+) -> Result<(), WebhookAuthError> {
+    let signature = signature.ok_or(WebhookAuthError::MissingHeader(WEBEX_SIGNATURE_HEADER))?;
+    let sha256 = signature
+        .split(',')
+        .find_map(|segment| segment.trim().strip_prefix("SHA-256="));
+    match sha256 {
+        Some(hex) => verify_signature(
+            HmacAlgorithm::Sha256,
+            secret,
+            body,
+            hex.trim().trim_matches('"'),
+            SignatureEncoding::Hex,
+        ),
+        None => verify_signature(
+            HmacAlgorithm::Sha1,
+            secret,
+            body,
+            signature,
+            SignatureEncoding::Hex,
+        ),
+    }
+}
+
+/// Teams outgoing webhooks send `Authorization: HMAC <base64>` keyed with the
+/// base64-decoded security token issued when the webhook was created.
+pub fn verify_teams_outgoing(
+    security_token: &str,
+    authorization: Option<&str>,
+    body: &[u8],
+) -> Result<(), WebhookAuthError> {
+    let authorization =
+        authorization.ok_or(WebhookAuthError::MissingHeader(TEAMS_AUTHORIZATION_HEADER))?;
+    let signature = authorization
+        .trim()
+        .strip_prefix("HMAC ")
+        .ok_or(WebhookAuthError::MalformedSignature)?;
+    let key = STANDARD
+        .decode(security_token.trim())
+        .map_err(|_| WebhookAuthError::InvalidSecret)?;
+    verify_signature(
+        HmacAlgorithm::Sha256,
+        &key,
+        body,
+        signature,
+        SignatureEncoding::Base64,
+    )
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        use std::fmt::Write;
+        let _ = write!(&mut out, "{byte:02x}");
+    }
+    out
+}
+
+fn hex_decode(input: &str) -> Option<Vec<u8>> {
+    if !input.len().is_multiple_of(2) {
+        return None;
+    }
+    input
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hmac_round_trips_in_both_encodings() {
+        for algorithm in [HmacAlgorithm::Sha1, HmacAlgorithm::Sha256] {
+            for encoding in [SignatureEncoding::Hex, SignatureEncoding::Base64] {
+                let sig = sign(algorithm, b"secret", b"payload", encoding);
+                assert!(verify_signature(algorithm, b"secret", b"payload", &sig, encoding).is_ok());
+                assert_eq!(
+                    verify_signature(algorithm, b"other", b"payload", &sig, encoding),
+                    Err(WebhookAuthError::SignatureMismatch)
+                );
+            }
+        }
+        assert_eq!(
+            verify_signature(
+                HmacAlgorithm::Sha256,
+                b"secret",
+                b"payload",
+                "zz",
+                SignatureEncoding::Hex
+            ),
+            Err(WebhookAuthError::MalformedSignature)
+        );
+    }
+
+    #[test]
+    fn slack_signature_respects_window() {
+        let now = 1_700_000_000;
+        let body = br#"{"type":"event_callback"}"#;
+        let ts = now.to_string();
+        let mut base = format!("v0:{ts}:").into_bytes();
+        base.extend_from_slice(body);
+        let sig = format!(
+            "v0={}",
+            sign(HmacAlgorithm::Sha256, b"s", &base, SignatureEncoding::Hex)
+        );
+        let window = TimestampWindow::default();
+        assert!(verify_slack(b"s", Some(&sig), Some(&ts), body, &window, now).is_ok());
+        assert_eq!(
+            verify_slack(b"s", Some(&sig), Some(&ts), body, &window, now + 301),
+            Err(WebhookAuthError::StaleTimestamp)
+        );
+        assert!(
+            verify_slack(
+                b"s",
+                Some(&sig),
+                Some(&ts),
+                body,
+                &TimestampWindow::new(600),
+                now + 301
+            )
+            .is_ok()
+        );
+        assert_eq!(
+            verify_slack(b"s", None, Some(&ts), body, &window, now),
+            Err(WebhookAuthError::MissingHeader(SLACK_SIGNATURE_HEADER))
+        );
+    }
+
+    #[test]
+    fn provider_schemes_verify() {
+        let body = b"{}";
+        let meta = format!(
+            "sha256={}",
+            sign(HmacAlgorithm::Sha256, b"app", body, SignatureEncoding::Hex)
+        );
+        assert!(verify_meta(b"app", Some(&meta), body).is_ok());
+
+        let webex = sign(HmacAlgorithm::Sha1, b"hook", body, SignatureEncoding::Hex);
+        assert!(verify_webex(b"hook", Some(&webex), body).is_ok());
+        let webex256 = format!(
+            "SHA-256={}",
+            sign(HmacAlgorithm::Sha256, b"hook", body, SignatureEncoding::Hex)
+        );
+        assert!(verify_webex(b"hook", Some(&webex256), body).is_ok());
+
+        let token = STANDARD.encode(b"teams-key");
+        let teams = format!(
+            "HMAC {}",
+            sign(
+                HmacAlgorithm::Sha256,
+                b"teams-key",
+                body,
+                SignatureEncoding::Base64
+            )
+        );
+        assert!(verify_teams_outgoing(&token, Some(&teams), body).is_ok());
+        assert!(verify_teams_outgoing(&token, Some("HMAC AAAA"), body).is_err());
+    }
+
+    #[test]
+    fn shared_token_compares_exactly() {
+        let header = TELEGRAM_SECRET_TOKEN_HEADER;
+        assert!(verify_shared_token("abc", Some("abc"), header).is_ok());
+        assert_eq!(
+            verify_shared_token("abc", Some("abd"), header),
+            Err(WebhookAuthError::TokenMismatch)
+        );
+        assert_eq!(
+            verify_shared_token("abc", None, header),
+            Err(WebhookAuthError::MissingHeader(header))
+        );
+        let err: ProviderError = WebhookAuthError::TokenMismatch.into();
+        assert_eq!(err.to_string(), "auth failed: token mismatch");
+    }
+}
@@ -11,6 +11,7 @@ use greentic_interfaces_wasmtime::host_helpers::v1::{
     HostFns, add_all_v1_to_linker, http_client, secrets_store, state_store,
 };
 use greentic_interfaces_wasmtime::http_client_client_v1_1::greentic::http::http_client as http_client_client_alias;
+use provider_common::webhook_auth::{self, HmacAlgorithm, SignatureEncoding};
 use serde_json::json;
 use wasmtime::{
     Config, Engine,
@@ -349,3 +350,29 @@ pub fn default_secret_values() -> HashMap<String, Vec<u8>> {
     }
     map
 }
+
+/// Slack signature headers for `body`, signed with the default
+/// `SLACK_SIGNING_SECRET` and the current timestamp.
+pub fn slack_signature_headers(body: &[u8]) -> Vec<(String, String)> {
+    let secret = SECRET_PAIRS
+        .iter()
+        .find(|(key, _)| *key == "SLACK_SIGNING_SECRET")
+        .map(|(_, value)| *value)
+        .unwrap_or_default();
+    let timestamp = webhook_auth::now_unix_secs().to_string();
+    let mut basestring = format!("v0:{timestamp}:").into_bytes();
+    basestring.extend_from_slice(body);
+    let signature = webhook_auth::sign(
+        HmacAlgorithm::Sha256,
+        secret.as_bytes(),
+        &basestring,
+        SignatureEncoding::Hex,
+    );
+    vec![
+        (
+            webhook_auth::SLACK_SIGNATURE_HEADER.to_string(),
+            format!("v0={signature}"),
+        ),
+        (webhook_auth::SLACK_TIMESTAMP_HEADER.to_string(), timestamp),
+    ]
+}
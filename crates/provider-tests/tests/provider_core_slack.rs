@@ -134,7 +134,7 @@ impl bindings::greentic::http::http_client::Host for HostState {
         Ok(bindings::greentic::http::http_client::Response {
             status: 200,
             headers: vec![],
-            body: Some(serde_json::to_vec(&json!({"ts":"123.456"})).expect("resp bytes")),
+            body: Some(serde_json::to_vec(&json!({"ok":true,"ts":"123.456"})).expect("resp bytes")),
         })
     }
 }
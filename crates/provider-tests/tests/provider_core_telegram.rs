@@ -463,9 +463,10 @@ fn invoke_reply_smoke_test() -> Result<()> {
     let body_json: Value =
         serde_json::from_slice(last_req.body.as_ref().expect("body set")).context("decode body")?;
     assert_eq!(
-        body_json.get("reply_to_message_id"),
-        Some(&Value::String("42".into()))
+        body_json.pointer("/reply_parameters/message_id"),
+        Some(&json!(42))
     );
+    assert!(body_json.get("reply_to_message_id").is_none());
 
     Ok(())
 }
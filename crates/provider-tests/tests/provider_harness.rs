@@ -17,6 +17,7 @@ use wasmtime::component::{Component, ComponentExportIndex, Instance, Linker, Typ
 
 use provider_tests::harness::{
     TestHostState, add_wasi_to_linker, add_wasmtime_hosts, component_path, new_engine,
+    slack_signature_headers,
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -184,6 +185,7 @@ fn test_message_from_plan(plan: &RenderPlan) -> ChannelMessageEnvelope {
 }
 
 struct ProviderHarness {
+    provider: ProviderId,
     _instance: Instance,
     store: Store<TestHostState>,
     invoke: TypedFunc<(String, Vec<u8>), (Vec<u8>,)>,
@@ -215,6 +217,7 @@ impl ProviderHarness {
             .expect("invoke func");
 
         Self {
+            provider,
             _instance: instance,
             store,
             invoke,
@@ -258,7 +261,8 @@ impl ProviderHarness {
     }
 
     fn handle_webhook(&mut self, headers: &Value, body: &Value) -> Value {
-        let headers = headers
+        let body_json = serde_json::to_vec(body).unwrap_or_default();
+        let mut headers = headers
             .as_object()
             .map(|map| {
                 map.iter()
@@ -271,7 +275,13 @@ impl ProviderHarness {
                     .collect::<Vec<_>>()
             })
             .unwrap_or_default();
-        let body_json = serde_json::to_vec(body).unwrap_or_default();
+        if matches!(self.provider, ProviderId::Slack) {
+            headers.extend(
+                slack_signature_headers(&body_json)
+                    .into_iter()
+                    .map(|(name, value)| json!({ "name": name, "value": value })),
+            );
+        }
         let out = self.call_json(
             "ingest_http",
             json!({
@@ -312,7 +312,7 @@ mod slack {
 
         let secret = "signing-secret";
         let body = r#"{"type":"event_callback"}"#;
-        let timestamp = "1700000000";
+        let timestamp = provider_common::webhook_auth::now_unix_secs().to_string();
         let basestring = format!("v0:{timestamp}:{body}");
         let signature = slack_signature(secret, &basestring);
         let headers = json!({
@@ -307,6 +307,51 @@ fn validate_value_against_schema(value: &Value, schema: &SchemaIr, path: &str) -
             }
             Ok(())
         }
+        SchemaIr::Number { .. } => {
+            if !value.is_number() {
+                return Err(anyhow!("{path} must be number"));
+            }
+            Ok(())
+        }
+        SchemaIr::Integer {
+            minimum, maximum, ..
+        } => {
+            let number = value
+                .as_i64()
+                .ok_or_else(|| anyhow!("{path} must be integer"))?;
+            if minimum.is_some_and(|min| number < min) || maximum.is_some_and(|max| number > max) {
+                return Err(anyhow!("{path} is out of range"));
+            }
+            Ok(())
+        }
+        SchemaIr::Array { items, .. } => {
+            let values = value
+                .as_array()
+                .ok_or_else(|| anyhow!("{path} must be array"))?;
+            for (idx, item) in values.iter().enumerate() {
+                validate_value_against_schema(item, items, &format!("{path}[{idx}]"))?;
+            }
+            Ok(())
+        }
+        SchemaIr::Enum { values, .. } => {
+            let text = value
+                .as_str()
+                .ok_or_else(|| anyhow!("{path} must be string"))?;
+            if !values.iter().any(|allowed| allowed.value == text) {
+                return Err(anyhow!("{path} must be one of the enum values"));
+            }
+            Ok(())
+        }
+        SchemaIr::OneOf { variants, .. } => {
+            if variants
+                .iter()
+                .any(|variant| validate_value_against_schema(value, variant, path).is_ok())
+            {
+                Ok(())
+            } else {
+                Err(anyhow!("{path} matches no oneOf variant"))
+            }
+        }
     }
 }
 
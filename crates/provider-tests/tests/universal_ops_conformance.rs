@@ -16,7 +16,7 @@ use greentic_types::{Actor, EnvId, MessageMetadata, TenantCtx, TenantId};
 use provider_common::component_v0_6::{canonical_cbor_bytes, decode_cbor};
 use provider_tests::harness::{
     TestHostState, add_wasi_to_linker, add_wasmtime_hosts, component_path, default_secret_values,
-    ensure_components_built, new_engine,
+    ensure_components_built, new_engine, slack_signature_headers,
 };
 use serde::Deserialize;
 use serde_json::{Value, json};
@@ -118,7 +118,7 @@ const PROVIDERS: &[ProviderSpec] = &[
         id: ProviderId::Slack,
         provider_type: "messaging.slack.api",
         fixture: "slack.json",
-        ingest_supported: false,
+        ingest_supported: true,
         challenge_fixture: None,
         challenge_response: None,
         skip_universal_ops: true,
@@ -525,7 +525,15 @@ fn run_provider_checks(spec: &ProviderSpec) -> Result<()> {
     let mut harness = ProviderHarness::new(spec)?;
     if spec.ingest_supported {
         let fixture = load_http_fixture(spec.fixture)?;
-        let http_in = http_input_from_fixture(fixture);
+        let mut http_in = http_input_from_fixture(fixture);
+        if spec.id == ProviderId::Slack {
+            let body = STANDARD.decode(&http_in.body_b64)?;
+            http_in.headers.extend(
+                slack_signature_headers(&body)
+                    .into_iter()
+                    .map(|(name, value)| Header { name, value }),
+            );
+        }
         let ingest_bytes = serde_json::to_vec(&http_in)?;
         let ingest_out = harness.call("ingest_http", ingest_bytes)?;
         let ingest_value: Value = serde_json::from_slice(&ingest_out)?;